@@ -0,0 +1,115 @@
+//! Benchmarks for the work every hook does per file: finding the file's crate,
+//! grouping the files by crate, checking the excludes, and (for the ratchet)
+//! comparing the suppressions to the shamefile.  They run on synthetic repos of
+//! 1k, 10k, and 100k files, with 100 files per crate.
+//!
+//! The budget is 10µs of overhead per file for everything but the ratchet, so
+//! that a 100k file commit spends at most a second or so deciding what to run.
+//! The ratchet also parses every file, so it gets 100µs per file.
+//!
+//! Run them with `cargo bench`.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use salt_spray::config::Config;
+use salt_spray::ratchet::{ratchet, Options};
+use salt_spray::{find_manifest, group_by_crate, target_kind};
+
+const FILES_PER_CRATE: usize = 100;
+const SIZES: &[usize] = &[1_000, 10_000, 100_000];
+
+/// Builds a repo with `files` files spread over crates of FILES_PER_CRATE each,
+/// returning its root and the (repo relative) names of the files.  Only the
+/// manifests exist on disk unless `write_sources` is set, since most of the hot
+/// paths never open the files themselves.
+fn synthetic_repo(files: usize, write_sources: bool) -> (PathBuf, Vec<String>) {
+    let root = env::temp_dir().join(format!("salt-spray-bench-{}-{}", files, write_sources));
+    let mut names = Vec::with_capacity(files);
+    drop(fs::remove_dir_all(&root));
+    fs::create_dir_all(root.join(".git")).unwrap();
+    fs::write(
+        root.join("salt-spray.toml"),
+        "exclude = [\"**/generated/**\", \"vendor/**\"]\n",
+    )
+    .unwrap();
+    for c in 0..files.div_ceil(FILES_PER_CRATE) {
+        let crate_dir = format!("crates/c{}", c);
+        fs::create_dir_all(root.join(&crate_dir).join("src/m")).unwrap();
+        let manifest = format!("[package]\nname = \"c{}\"\nversion = \"0.1.0\"\n", c);
+        fs::write(root.join(&crate_dir).join("Cargo.toml"), manifest).unwrap();
+        for f in 0..FILES_PER_CRATE.min(files - names.len()) {
+            let name = format!("{}/src/m/f{}.rs", crate_dir, f);
+            if write_sources {
+                let source = format!("#[allow(dead_code)]\nfn f{}() {{}}\n", f);
+                fs::write(root.join(&name), source).unwrap();
+            }
+            names.push(name);
+        }
+    }
+    (root, names)
+}
+
+fn manifests_and_grouping(c: &mut Criterion) {
+    let mut group = c.benchmark_group("per-file overhead");
+    group.sample_size(10);
+    for &size in SIZES {
+        let (root, files) = synthetic_repo(size, false);
+        env::set_current_dir(&root).unwrap();
+        let config = Config::load().unwrap();
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(
+            BenchmarkId::new("find_manifest", size),
+            &files,
+            |b, files| b.iter(|| files.iter().filter_map(find_manifest).count()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("group_by_crate", size),
+            &files,
+            |b, files| b.iter(|| group_by_crate(files)),
+        );
+        group.bench_with_input(BenchmarkId::new("target_kind", size), &files, |b, files| {
+            b.iter(|| files.iter().filter_map(target_kind).count())
+        });
+        group.bench_with_input(BenchmarkId::new("is_excluded", size), &files, |b, files| {
+            b.iter(|| {
+                files
+                    .iter()
+                    .filter(|f| config.is_excluded(f.as_ref()))
+                    .count()
+            })
+        });
+        drop(fs::remove_dir_all(&root));
+    }
+    group.finish();
+}
+
+fn shamefile_comparison(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ratchet");
+    group.sample_size(10);
+    // 100k files takes minutes per sample, which is more than this is worth
+    for &size in &SIZES[..2] {
+        let (root, files) = synthetic_repo(size, true);
+        env::set_current_dir(&root).unwrap();
+        let config = Config::load().unwrap();
+        let accept = Options {
+            click: true,
+            accept: true,
+            ..Default::default()
+        };
+        // The first run writes the shamefile the rest compare against
+        ratchet(&files, &config, &accept);
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::new("compare", size), &files, |b, files| {
+            b.iter(|| ratchet(files, &config, &Options::default()))
+        });
+        drop(fs::remove_dir_all(&root));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, manifests_and_grouping, shamefile_comparison);
+criterion_main!(benches);
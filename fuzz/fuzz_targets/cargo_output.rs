@@ -0,0 +1,22 @@
+//! Feeds salt-clip what cargo and clippy might print: the JSON messages on stdout
+//! (with their rendered diagnostics, which quote source lines that can hold
+//! anything) and cargo's own messages on stderr.
+//!
+//! Run it with `cargo +nightly fuzz run cargo_output` from the repo root.
+
+#![no_main]
+
+use std::path::Path;
+
+use libfuzzer_sys::fuzz_target;
+
+use salt_spray::clippy::cargo_messages;
+use salt_spray::diagnostic::{parse_messages, unparsed_lines};
+
+fuzz_target!(|output: &str| {
+    cargo_messages(output);
+    unparsed_lines(output);
+    for diagnostic in parse_messages(output) {
+        diagnostic.relative_to_repo(Path::new("crates/a"));
+    }
+});
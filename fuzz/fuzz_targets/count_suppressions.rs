@@ -0,0 +1,14 @@
+//! Feeds the warning ratchet's attribute counting Rust source, which only has to
+//! parse for it to be counted, however odd its attributes are.
+//!
+//! Run it with `cargo +nightly fuzz run count_suppressions` from the repo root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use salt_spray::ratchet::count_suppressions;
+
+fuzz_target!(|contents: &str| {
+    drop(count_suppressions(contents));
+});
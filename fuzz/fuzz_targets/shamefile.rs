@@ -0,0 +1,19 @@
+//! Feeds the warning ratchet shamefiles, which people edit by hand (and resolve
+//! merge conflicts in), so they can hold just about anything.  Whatever's read has
+//! to come back out as a shamefile that reads the same way.
+//!
+//! Run it with `cargo +nightly fuzz run shamefile` from the repo root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use salt_spray::ratchet::rewrite_shamefile;
+
+fuzz_target!(|contents: &str| {
+    if let Ok(rewritten) = rewrite_shamefile(contents) {
+        let again = rewrite_shamefile(&rewritten)
+            .unwrap_or_else(|e| panic!("the rewritten shamefile doesn't read: {}", e));
+        assert_eq!(rewritten, again, "rewriting the shamefile isn't stable");
+    }
+});
@@ -0,0 +1,98 @@
+//! Splitting the hooks' command lines into flags and files.
+//!
+//! pre-commit runs a hook with the `args:` from .pre-commit-config.yaml followed by
+//! the files being committed, so the two have to be kept strictly apart.  Anything
+//! starting with `-` is a flag, and has to be one the hook knows (a misspelled
+//! flag is an error rather than a file that doesn't exist).  Everything else, and
+//! everything after a `--`, is a file.
+//!
+//! Flags are named the way the hooks declare them: names ending in `=` (e.g.
+//! `--env-args=`) take a value, given either as `--env-args=X=1` or as the next
+//! argument (`--env-args X=1`), the rest (e.g. `--stream`) are switches.
+//!
+//! Every hook also takes `--root=PATH`, for running it from somewhere other than
+//! the repo (e.g. on a CI runner that checks out into a directory of its own).
+//! The hook then works from PATH just as if it had been started there: that's
+//! the repo root, salt-spray.toml and .therug.yaml are found there, and the files
+//! on the command line are relative to it, the way pre-commit gives them.
+
+use std::path::Path;
+use std::process;
+
+use crate::{closest, set_repo_root};
+
+/// The flags every hook accepts, on top of its own.  `--version` (and
+/// `--features`) are handled by [`crate::exit_if_version_asked`] before the rest
+/// of the command line is looked at.
+const ALWAYS_KNOWN: [&str; 2] = ["--version", "--features"];
+
+/// The flag every hook accepts that says where the repo root is, which
+/// [`parse_args`] handles itself rather than handing it back.
+pub const ROOT: &str = "--root=";
+
+/// Splits the arguments into the flags and the files, given the flags the tool
+/// knows.  Each flag comes back the way it's declared, with any value after it,
+/// e.g. `--env-args=X=1` or `--stream`, however it was spelled on the command
+/// line.  Unknown flags, values given to switches, and missing values are reported
+/// and exit the process, and so does a `--root=` that can't be moved to.
+pub fn parse_args<I: IntoIterator<Item = String>>(
+    tool: &str,
+    args: I,
+    known: &[&str],
+) -> (Vec<String>, Vec<String>) {
+    let mut flags = Vec::new();
+    let mut files = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            files.extend(args.by_ref());
+            break;
+        } else if !arg.starts_with('-') || arg == "-" {
+            files.push(arg);
+            continue;
+        }
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        if ROOT.strip_suffix('=') == Some(name) {
+            let root = value.or_else(|| args.next()).unwrap_or_else(|| {
+                fail(tool, &format!("{} needs a value, e.g. {}=...", name, name))
+            });
+            if let Err(e) = set_repo_root(Path::new(&root)) {
+                fail(tool, &e);
+            }
+        } else if known.contains(&name) || ALWAYS_KNOWN.contains(&name) {
+            if let Some(value) = value {
+                fail(
+                    tool,
+                    &format!("{} doesn't take a value, but was given {}", name, value),
+                );
+            }
+            flags.push(name.to_string());
+        } else if known.iter().any(|k| k.strip_suffix('=') == Some(name)) {
+            let value = value.or_else(|| args.next()).unwrap_or_else(|| {
+                fail(tool, &format!("{} needs a value, e.g. {}=...", name, name))
+            });
+            flags.push(format!("{}={}", name, value));
+        } else {
+            let names = known.iter().map(|k| k.trim_end_matches('='));
+            let suggestion = match closest(name, names.clone()) {
+                Some(flag) => format!(", did you mean {}?", flag),
+                None if known.is_empty() => ", it takes no flags".to_string(),
+                None => format!(", expected one of {}", names.collect::<Vec<_>>().join(", ")),
+            };
+            let hint = "(files starting with - go after a --)";
+            fail(
+                tool,
+                &format!("{} isn't a flag it knows{} {}", name, suggestion, hint),
+            );
+        }
+    }
+    (flags, files)
+}
+
+fn fail(tool: &str, message: &str) -> ! {
+    eprintln!("{}: {}", tool, message);
+    process::exit(-1);
+}
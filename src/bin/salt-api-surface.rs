@@ -0,0 +1,349 @@
+//! `salt-api-surface` keeps a snapshot of each library crate's public API in
+//! .api-surface.yaml, so that API changes show up in code review as changes to
+//! that file.  It's a much lighter-weight alternative to full semver checking.
+//!
+//! For each library crate touched by a commit, it walks the crate from its root
+//! (following `pub mod`s) and records every public item: functions with their
+//! signatures, structs with their public fields, enums with their variants,
+//! traits with their items, the public methods of impl blocks, trait impls,
+//! `pub use`s, and exported macros.  If that differs from the snapshot, the
+//! snapshot is updated (so it needs to be staged before retrying), just like
+//! `salt-geiger`.
+//!
+//! Items marked `#[doc(hidden)]` aren't part of the API as far as it's concerned.
+
+#![forbid(unsafe_code)]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use quote::ToTokens;
+use serde::{Deserialize, Serialize};
+use syn::{Attribute, Fields, ImplItem, Item, TraitItem};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::Violation;
+use salt_spray::config::Config;
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::{
+    child_dir, group_by_crate, is_public, library_of, module_file, package_name, parse_source, say,
+};
+
+const SNAPSHOT: &str = ".api-surface.yaml";
+
+/// The public items of each crate, keyed by package name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Snapshot {
+    crates: BTreeMap<String, BTreeSet<String>>,
+}
+
+fn load_snapshot() -> Snapshot {
+    match fs::read_to_string(SNAPSHOT) {
+        Ok(contents) => serde_yaml::from_str(&contents).unwrap_or_else(|e| {
+            eprintln!("Unable to parse {}: {}", SNAPSHOT, e);
+            process::exit(-1);
+        }),
+        Err(_) => Default::default(),
+    }
+}
+
+fn save_snapshot(snapshot: &Snapshot) {
+    // unwrap here is safe since the snapshot is just maps of strings
+    let contents = serde_yaml::to_string(snapshot).unwrap();
+    if let Err(e) = fs::write(SNAPSHOT, contents) {
+        eprintln!("Unable to write {}: {}", SNAPSHOT, e);
+        process::exit(-1);
+    }
+}
+
+fn tokens<T: ToTokens>(t: &T) -> String {
+    t.to_token_stream().to_string()
+}
+
+/// Joins the parts of a declaration with spaces, leaving out the empty ones (like
+/// missing generics).
+fn words(parts: &[&str]) -> String {
+    let parts: Vec<&str> = parts.iter().copied().filter(|p| !p.is_empty()).collect();
+    parts.join(" ")
+}
+
+fn is_hidden(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .any(|a| a.path.is_ident("doc") && a.tokens.to_string().replace(' ', "") == "(hidden)")
+}
+
+fn is_exported_macro(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|a| a.path.is_ident("macro_export"))
+}
+
+/// Walks a crate's public modules, collecting a line for each public item.
+struct Walker {
+    crate_name: String,
+    lines: BTreeSet<String>,
+}
+
+impl Walker {
+    fn walk_file(&mut self, module: &str, file: &Path) {
+        let ast = match fs::read_to_string(file).map(|c| parse_source(&c)) {
+            Ok(Ok(ast)) => ast,
+            _ => {
+                eprintln!("Unable to parse {}, skipping it", file.display());
+                return;
+            }
+        };
+        self.walk_items(module, &ast.items, &child_dir(file));
+    }
+
+    fn add(&mut self, module: &str, declaration: String) {
+        self.lines.insert(format!("{}: {}", module, declaration));
+    }
+
+    fn add_fields(&mut self, owner: &str, fields: &Fields) {
+        for (i, field) in fields.iter().enumerate() {
+            if is_public(&field.vis) && !is_hidden(&field.attrs) {
+                let name = field
+                    .ident
+                    .as_ref()
+                    .map(|i| i.to_string())
+                    .unwrap_or_else(|| i.to_string());
+                self.add(owner, format!("pub {}: {}", name, tokens(&field.ty)));
+            }
+        }
+    }
+
+    fn walk_items(&mut self, module: &str, items: &[Item], dir: &Path) {
+        for item in items {
+            match item {
+                Item::Fn(f) if is_public(&f.vis) && !is_hidden(&f.attrs) => {
+                    self.add(module, format!("pub {}", tokens(&f.sig)));
+                }
+                Item::Struct(s) if is_public(&s.vis) && !is_hidden(&s.attrs) => {
+                    let header = words(&[
+                        "pub struct",
+                        &s.ident.to_string(),
+                        &tokens(&s.generics),
+                        &tokens(&s.generics.where_clause),
+                    ]);
+                    self.add(module, header);
+                    self.add_fields(&format!("{}::{}", module, s.ident), &s.fields);
+                }
+                Item::Union(u) if is_public(&u.vis) && !is_hidden(&u.attrs) => {
+                    let header = words(&["pub union", &u.ident.to_string(), &tokens(&u.generics)]);
+                    self.add(module, header);
+                    let fields = Fields::Named(u.fields.clone());
+                    self.add_fields(&format!("{}::{}", module, u.ident), &fields);
+                }
+                Item::Enum(e) if is_public(&e.vis) && !is_hidden(&e.attrs) => {
+                    let header = words(&["pub enum", &e.ident.to_string(), &tokens(&e.generics)]);
+                    self.add(module, header);
+                    let owner = format!("{}::{}", module, e.ident);
+                    for variant in e.variants.iter().filter(|v| !is_hidden(&v.attrs)) {
+                        let declaration =
+                            words(&[&variant.ident.to_string(), &tokens(&variant.fields)]);
+                        self.add(&owner, declaration);
+                    }
+                }
+                Item::Trait(t) if is_public(&t.vis) && !is_hidden(&t.attrs) => {
+                    let supertraits = if t.supertraits.is_empty() {
+                        String::new()
+                    } else {
+                        format!(": {}", tokens(&t.supertraits))
+                    };
+                    let header = words(&[
+                        "pub",
+                        if t.unsafety.is_some() { "unsafe" } else { "" },
+                        "trait",
+                        &t.ident.to_string(),
+                        &tokens(&t.generics),
+                        &supertraits,
+                    ]);
+                    self.add(module, header);
+                    let owner = format!("{}::{}", module, t.ident);
+                    for trait_item in t.items.iter() {
+                        let declaration = match trait_item {
+                            TraitItem::Method(m) => tokens(&m.sig),
+                            TraitItem::Const(c) => format!("const {}: {}", c.ident, tokens(&c.ty)),
+                            TraitItem::Type(t) if t.bounds.is_empty() => {
+                                format!("type {}", t.ident)
+                            }
+                            TraitItem::Type(t) => {
+                                format!("type {}: {}", t.ident, tokens(&t.bounds))
+                            }
+                            _ => continue,
+                        };
+                        self.add(&owner, declaration);
+                    }
+                }
+                Item::Const(c) if is_public(&c.vis) && !is_hidden(&c.attrs) => {
+                    self.add(module, format!("pub const {}: {}", c.ident, tokens(&c.ty)));
+                }
+                Item::Static(s) if is_public(&s.vis) && !is_hidden(&s.attrs) => {
+                    let mutability = if s.mutability.is_some() { "mut " } else { "" };
+                    let declaration =
+                        format!("pub static {}{}: {}", mutability, s.ident, tokens(&s.ty));
+                    self.add(module, declaration);
+                }
+                Item::Type(t) if is_public(&t.vis) && !is_hidden(&t.attrs) => {
+                    let declaration = words(&[
+                        "pub type",
+                        &t.ident.to_string(),
+                        &tokens(&t.generics),
+                        "=",
+                        &tokens(&t.ty),
+                    ]);
+                    self.add(module, declaration);
+                }
+                Item::Use(u) if is_public(&u.vis) && !is_hidden(&u.attrs) => {
+                    self.add(module, format!("pub use {}", tokens(&u.tree)));
+                }
+                Item::Macro(m) if is_exported_macro(&m.attrs) && !is_hidden(&m.attrs) => {
+                    if let Some(ident) = &m.ident {
+                        // Exported macros always live at the root of the crate
+                        let root = self.crate_name.clone();
+                        self.add(&root, format!("macro_rules! {}", ident));
+                    }
+                }
+                Item::Impl(i) if !is_hidden(&i.attrs) => self.walk_impl(module, i),
+                Item::Mod(m) if is_public(&m.vis) && !is_hidden(&m.attrs) => {
+                    self.add(module, format!("pub mod {}", m.ident));
+                    let child = format!("{}::{}", module, m.ident);
+                    match &m.content {
+                        Some((_, items)) => {
+                            self.walk_items(&child, items, &dir.join(m.ident.to_string()))
+                        }
+                        None => match module_file(dir, m) {
+                            Some(file) => self.walk_file(&child, &file),
+                            None => eprintln!(
+                                "Unable to find the file for module {} in {}",
+                                child,
+                                dir.display()
+                            ),
+                        },
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Trait impls are part of the API as a whole, inherent impls by their public
+    /// methods and constants.
+    fn walk_impl(&mut self, module: &str, i: &syn::ItemImpl) {
+        let header = match &i.trait_ {
+            Some((negative, path, _)) => words(&[
+                "impl",
+                &tokens(&i.generics),
+                &format!(
+                    "{}{}",
+                    if negative.is_some() { "!" } else { "" },
+                    tokens(path)
+                ),
+                "for",
+                &tokens(&i.self_ty),
+            ]),
+            None => words(&["impl", &tokens(&i.generics), &tokens(&i.self_ty)]),
+        };
+        if i.trait_.is_some() {
+            self.add(module, header);
+            return;
+        }
+        for impl_item in i.items.iter() {
+            let member = match impl_item {
+                ImplItem::Method(m) if is_public(&m.vis) && !is_hidden(&m.attrs) => {
+                    format!("pub {}", tokens(&m.sig))
+                }
+                ImplItem::Const(c) if is_public(&c.vis) && !is_hidden(&c.attrs) => {
+                    format!("pub const {}: {}", c.ident, tokens(&c.ty))
+                }
+                _ => continue,
+            };
+            self.add(module, format!("{} {{ {} }}", header, member));
+        }
+    }
+}
+
+/// The public API of the crate with the given manifest, or None if it doesn't have
+/// a library target.
+fn surface_of(manifest: &Path) -> Option<BTreeSet<String>> {
+    let (crate_name, root) = library_of(manifest)?;
+    let mut walker = Walker {
+        crate_name: crate_name.clone(),
+        lines: BTreeSet::new(),
+    };
+    walker.walk_file(&crate_name, &root);
+    Some(walker.lines)
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-api-surface");
+    let mut summary = Summary::new("salt-api-surface");
+    let known = [PORCELAIN, OUTPUT, COLOR];
+    let (flags, files) = parse_args("salt-api-surface", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut report = Report::from_flags("salt-api-surface", &flags, &config).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+
+    let files: Vec<String> = files
+        .into_iter()
+        .filter(|f| !config.is_excluded(Path::new(f)))
+        .collect();
+    let mut snapshot = load_snapshot();
+    let mut updated = false;
+    for (cargo_toml, crate_files) in group_by_crate(&files).iter() {
+        let observed = match surface_of(Path::new(cargo_toml)) {
+            Some(observed) => observed,
+            None => continue,
+        };
+        let name = package_name(cargo_toml).unwrap_or_else(|| cargo_toml.to_string());
+        summary.crates += 1;
+        summary.files += crate_files.len();
+
+        match snapshot.crates.get(&name) {
+            Some(expected) if *expected == observed => continue,
+            Some(expected) => {
+                let message = format!("the public API of {} changed", name);
+                report.record(&Violation::new(SNAPSHOT, message).for_lint("api-surface"));
+                for removed in expected.difference(&observed) {
+                    say!("  - {}", removed);
+                }
+                for added in observed.difference(expected) {
+                    say!("  + {}", added);
+                }
+            }
+            None => say!(
+                "Started tracking the public API of {} ({} items)",
+                name,
+                observed.len()
+            ),
+        }
+        snapshot.crates.insert(name, observed);
+        updated = true;
+    }
+
+    let exit_code = if updated {
+        save_snapshot(&snapshot);
+        say!(
+            "Please review the changes to {}, then `git add` it and retry your commit.",
+            SNAPSHOT
+        );
+        2
+    } else {
+        0
+    };
+    report.finish(&summary);
+    summary.print();
+    process::exit(exit_code);
+}
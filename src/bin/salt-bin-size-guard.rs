@@ -0,0 +1,314 @@
+//! `salt-bin-size-guard` is a ratchet for the size of release binaries, for the
+//! embedded and CLI crates where every kilobyte shows.
+//!
+//! For each crate touched by a commit whose policy sets `track-binary-size`, it
+//! builds the crate's binaries (stripped, with the configured profile) and
+//! compares their sizes to the ones stored in .bin-size.yaml .  A binary that grew
+//! by more than the tolerance is a regression, and rejects the commit.  One that
+//! shrank updates .bin-size.yaml (so it needs to be staged before retrying), just
+//! like `salt-geiger`, while one that grew by less than the tolerance is left at
+//! its old size so that small regressions can't add up.
+//!
+//! Mark crates in salt-spray.toml through their tier's policy, e.g.
+//!
+//! ```toml
+//! [tier-policy.tier1]
+//! track-binary-size = true
+//!
+//! [bin-size-guard]
+//! tolerance = 0.02            # binaries may grow by 2% before it's a regression
+//! profile = "release"
+//! sizes = "target/sizes.yaml" # read the sizes from here instead of building
+//! advisory = false
+//! ```
+//!
+//! or with `track-binary-size = true` in the `[policy]` of a crate's own config.
+//! Since building release binaries takes a while, CI jobs that have built them
+//! already can hand over their sizes with `--sizes=<file>` (or `sizes`), in the
+//! same form as .bin-size.yaml:
+//!
+//! ```yaml
+//! crates:
+//!   my-cli:
+//!     my-cli: 1482752
+//! ```
+//!
+//! To accept a regression anyway, rerun with `UPDATE_ANYWAY=1`.
+
+#![forbid(unsafe_code)]
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{self, Path, PathBuf};
+use std::process;
+
+use serde::{Deserialize, Serialize};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::Violation;
+use salt_spray::config::Config;
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::toolchain::{self, CARGO_PATH};
+use salt_spray::{
+    find_repo_root, group_by_crate, load_baseline, package_name, read_manifest, save_baseline, say,
+};
+
+const BASELINE: &str = ".bin-size.yaml";
+
+static SIZES: &str = "--sizes=";
+
+/// The size in bytes of each binary of each crate, keyed by package name and then
+/// by binary.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Sizes {
+    crates: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+/// One line of cargo's JSON output.  Everything but artifacts is ignored.
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    manifest_path: Option<PathBuf>,
+    target: Option<CargoTarget>,
+    executable: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct CargoTarget {
+    name: String,
+    kind: Vec<String>,
+}
+
+/// Does the crate have any binaries, going by cargo's autodiscovery and its
+/// `[[bin]]` tables?
+fn has_binaries(manifest: &Path) -> bool {
+    let src = manifest.with_file_name("src");
+    let declared = read_manifest(manifest).is_some_and(|t| t.contains_key("bin"));
+    declared || src.join("main.rs").is_file() || src.join("bin").is_dir()
+}
+
+/// Builds the crate's binaries, stripped, returning the size of each.
+fn build_sizes(
+    manifest: &Path,
+    config: &Config,
+    profile: &str,
+) -> Result<BTreeMap<String, u64>, String> {
+    let manifest = path::absolute(manifest).unwrap_or_else(|_| manifest.to_path_buf());
+    // Stripping through the profile works without any other tools
+    let strip = format!(
+        "CARGO_PROFILE_{}_STRIP",
+        profile.to_uppercase().replace('-', "_")
+    );
+    let output = toolchain::cargo_for(&manifest, config)
+        .env(strip, "symbols")
+        .args([
+            "build",
+            "--bins",
+            "--message-format=json",
+            "--profile",
+            profile,
+        ])
+        .arg("--manifest-path")
+        .arg(&manifest)
+        .output()
+        .map_err(|e| format!("Unable to run cargo: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Unable to build the binaries of {}:\n{}",
+            manifest.display(),
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        ));
+    }
+
+    let canonical = manifest.canonicalize().ok();
+    let mut result = BTreeMap::new();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for line in stdout.lines().filter(|l| l.starts_with('{')) {
+        let message = match serde_json::from_str::<CargoMessage>(line) {
+            Ok(message) if message.reason == "compiler-artifact" => message,
+            _ => continue,
+        };
+        let ours = message.manifest_path.and_then(|m| m.canonicalize().ok()) == canonical;
+        match (message.target, message.executable) {
+            (Some(target), Some(executable)) if ours && target.kind.iter().any(|k| k == "bin") => {
+                let size = fs::metadata(&executable)
+                    .map_err(|e| format!("Unable to read {}: {}", executable.display(), e))?
+                    .len();
+                result.insert(target.name, size);
+            }
+            _ => (),
+        }
+    }
+    Ok(result)
+}
+
+/// How much bigger `is` is than `was`, as a fraction of `was`.
+fn growth(was: u64, is: u64) -> f64 {
+    if was == 0 {
+        f64::INFINITY
+    } else {
+        (is as f64 - was as f64) / was as f64
+    }
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-bin-size-guard");
+    let mut summary = Summary::new("salt-bin-size-guard");
+    let known = [PORCELAIN, OUTPUT, SIZES, CARGO_PATH, COLOR];
+    let (flags, files) = parse_args("salt-bin-size-guard", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    if let Some(path) = flags.iter().find_map(|f| f.strip_prefix(CARGO_PATH)) {
+        toolchain::set_cargo_path(path);
+    }
+    let mut report =
+        Report::from_flags("salt-bin-size-guard", &flags, &config).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+    let settings = &config.bin_size_guard;
+
+    // Sizes measured elsewhere stand in for building
+    let sizes_file = flags
+        .iter()
+        .find_map(|f| f.strip_prefix(SIZES))
+        .map(PathBuf::from)
+        .or_else(|| {
+            let root = find_repo_root().unwrap_or_default();
+            settings.sizes.as_ref().map(|s| root.join(s))
+        });
+    let measured = sizes_file.as_ref().map(|path| {
+        if !path.exists() {
+            eprintln!("{} doesn't exist", path.display());
+            process::exit(-1);
+        }
+        load_baseline::<Sizes>(path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        })
+    });
+
+    let mut baseline: Sizes = load_baseline(Path::new(BASELINE)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let update_anyway = env::var("UPDATE_ANYWAY").map(|v| v == "1").unwrap_or(false);
+    let mut updated = false;
+    for (cargo_toml, crate_files) in group_by_crate(&files).iter() {
+        let manifest = Path::new(cargo_toml);
+        let policy = config.policy_for(manifest);
+        if !policy.track_binary_size || !has_binaries(manifest) {
+            continue;
+        }
+        let name = package_name(cargo_toml).unwrap_or_else(|| cargo_toml.to_string());
+        summary.crates += 1;
+        summary.files += crate_files.len();
+
+        let observed = match &measured {
+            Some(measured) => match measured.crates.get(&name) {
+                Some(observed) => observed.clone(),
+                None => {
+                    // unwrap here is safe since measured is only Some when the file is
+                    let file = sizes_file.as_ref().unwrap();
+                    eprintln!("{} has no sizes for {}, skipping it", file.display(), name);
+                    continue;
+                }
+            },
+            None => {
+                say!("Building the binaries of {}", name);
+                let crate_config = config.for_crate(manifest).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    config.clone()
+                });
+                match build_sizes(manifest, &crate_config, &settings.profile) {
+                    Ok(observed) => observed,
+                    Err(e) => {
+                        report.record(&Violation::new(cargo_toml, e).for_lint("bin-size-guard"));
+                        summary.violations += 1;
+                        continue;
+                    }
+                }
+            }
+        };
+
+        if observed.is_empty() {
+            continue;
+        }
+        let expected = baseline.crates.entry(name.clone()).or_default();
+        for (binary, is) in observed {
+            let was = match expected.get(&binary) {
+                Some(was) => *was,
+                None => {
+                    say!(
+                        "Started tracking the size of {} ({}): {} bytes",
+                        binary,
+                        name,
+                        is
+                    );
+                    expected.insert(binary, is);
+                    updated = true;
+                    continue;
+                }
+            };
+            let grew = growth(was, is);
+            if grew > settings.tolerance {
+                let message = format!(
+                    "{} grew from {} to {} bytes ({:+.1}%), more than the {:.1}% it may grow by",
+                    binary,
+                    was,
+                    is,
+                    grew * 100.0,
+                    settings.tolerance * 100.0
+                );
+                let mut violation = Violation::new(cargo_toml, message)
+                    .for_lint("bin-size-guard")
+                    .suggesting("shrink it, or rerun with UPDATE_ANYWAY=1 to accept the new size");
+                if settings.advisory || policy.advisory {
+                    violation = violation.advisory();
+                } else {
+                    summary.violations += 1;
+                }
+                report.record(&violation);
+                if update_anyway {
+                    expected.insert(binary, is);
+                    updated = true;
+                }
+            } else if is < was {
+                say!(
+                    "Thanks for shrinking {} ({})!  It's now {} bytes, down from {}",
+                    binary,
+                    name,
+                    is,
+                    was
+                );
+                expected.insert(binary, is);
+                updated = true;
+            }
+        }
+    }
+
+    if updated {
+        save_baseline(Path::new(BASELINE), &baseline).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+    }
+    let exit_code = if summary.violations > 0 {
+        1
+    } else if updated {
+        say!("Please run `git add {}` and retry your commit.", BASELINE);
+        2
+    } else {
+        0
+    };
+    report.finish(&summary);
+    summary.print();
+    process::exit(exit_code);
+}
@@ -0,0 +1,298 @@
+//! `salt-cfg-guard` catches `cfg` predicates that can never be true because of a
+//! typo, like `#[cfg(featur = "serde")]` or `#[cfg(feature = "sedre")]`.  Rather
+//! than failing to compile, code behind such a predicate is silently left out.
+//!
+//! It checks `#[cfg(...)]`, `#[cfg_attr(...)]`, and `cfg!(...)` in the files being
+//! committed against the cfgs rustc knows about, the features declared in the
+//! crate's Cargo.toml, the cfgs listed in its `[lints.rust.unexpected_cfgs]`
+//! check-cfg, and the `allowed` list in the `[cfg-guard]` section of
+//! salt-spray.toml.
+
+#![forbid(unsafe_code)]
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::visit::{self, Visit};
+use syn::{Lit, Meta, NestedMeta, Token};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::Violation;
+use salt_spray::config::Config;
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::{
+    closest, find_manifest, parse_source, read_manifest, BUILTIN_CFG_KEYS, BUILTIN_CFG_NAMES,
+};
+
+static CHECK_CFG_NAME: Lazy<Regex> = Lazy::new(|| {
+    // The name ends up in capture group #1, e.g. cfg(tokio_unstable, values(none()))
+    Regex::new(r"^\s*cfg\(\s*([A-Za-z_][A-Za-z0-9_]*)").unwrap()
+});
+
+/// The cfgs and features a crate may use.
+#[derive(Debug, Default)]
+struct Known {
+    features: BTreeSet<String>,
+    custom: BTreeSet<String>,
+}
+
+impl Known {
+    fn for_crate(manifest: &Path, config: &Config) -> Known {
+        let mut result = Known::default();
+        result
+            .custom
+            .extend(config.cfg_guard.allowed.iter().cloned());
+        let table = match read_manifest(manifest) {
+            Some(table) => table,
+            None => return result,
+        };
+
+        if let Some(features) = table.get("features").and_then(|f| f.as_table()) {
+            result.features.extend(features.keys().cloned());
+        }
+        // Optional dependencies are implicitly features too
+        let mut dependency_tables: Vec<&toml::Value> =
+            table.get("dependencies").into_iter().collect();
+        if let Some(targets) = table.get("target").and_then(|t| t.as_table()) {
+            dependency_tables.extend(targets.values().filter_map(|t| t.get("dependencies")));
+        }
+        for dependencies in dependency_tables.iter().filter_map(|d| d.as_table()) {
+            for (name, dependency) in dependencies.iter() {
+                if dependency.get("optional").and_then(|o| o.as_bool()) == Some(true) {
+                    result.features.insert(name.clone());
+                }
+            }
+        }
+
+        let check_cfg = table
+            .get("lints")
+            .and_then(|l| l.get("rust"))
+            .and_then(|r| r.get("unexpected_cfgs"))
+            .and_then(|u| u.get("check-cfg"))
+            .and_then(|c| c.as_array());
+        for cfg in check_cfg.into_iter().flatten().filter_map(|c| c.as_str()) {
+            if let Some(captures) = CHECK_CFG_NAME.captures(cfg) {
+                // unwrap here is safe since the capture group is mandatory
+                result
+                    .custom
+                    .insert(captures.get(1).unwrap().as_str().to_string());
+            }
+        }
+        result
+    }
+
+    fn is_name(&self, name: &str) -> bool {
+        BUILTIN_CFG_NAMES.contains(&name) || self.custom.contains(name)
+    }
+
+    fn is_key(&self, key: &str) -> bool {
+        BUILTIN_CFG_KEYS.contains(&key) || self.custom.contains(key)
+    }
+
+    /// The closest known cfg, if there is one that's close enough to be a typo.
+    fn suggestion(&self, name: &str) -> Option<String> {
+        let candidates = BUILTIN_CFG_NAMES
+            .iter()
+            .chain(BUILTIN_CFG_KEYS.iter())
+            .copied();
+        closest(
+            name,
+            candidates.chain(self.custom.iter().map(String::as_str)),
+        )
+    }
+}
+
+fn did_you_mean(suggestion: Option<String>) -> String {
+    suggestion
+        .map(|s| format!(", did you mean `{}`?", s))
+        .unwrap_or_default()
+}
+
+/// Walks a file's syntax tree checking every cfg predicate in it.
+struct CfgVisitor<'a> {
+    filename: &'a str,
+    manifest: &'a Path,
+    known: &'a Known,
+    problems: Vec<Violation>,
+}
+
+impl<'a> CfgVisitor<'a> {
+    fn report(&mut self, span: proc_macro2::Span, problem: String) {
+        let violation = Violation::new(self.filename, problem)
+            .at(span)
+            .for_lint("cfg-guard");
+        self.problems.push(violation);
+    }
+
+    fn check_predicate(&mut self, predicate: &NestedMeta) {
+        let span = predicate.span();
+        match predicate {
+            NestedMeta::Meta(Meta::List(list))
+                if ["all", "any", "not"].iter().any(|o| list.path.is_ident(o)) =>
+            {
+                for nested in list.nested.iter() {
+                    self.check_predicate(nested);
+                }
+            }
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("feature") => {
+                if let Lit::Str(feature) = &nv.lit {
+                    let feature = feature.value();
+                    if !self.known.features.contains(&feature) {
+                        let suggestion =
+                            closest(&feature, self.known.features.iter().map(String::as_str));
+                        let problem = format!(
+                            "feature `{}` isn't declared in {}{}",
+                            feature,
+                            self.manifest.display(),
+                            did_you_mean(suggestion)
+                        );
+                        self.report(span, problem);
+                    }
+                }
+            }
+            NestedMeta::Meta(Meta::NameValue(nv)) => {
+                let key = path_string(&nv.path);
+                if !self.known.is_key(&key) {
+                    let problem = format!(
+                        "unknown cfg `{}`{}",
+                        key,
+                        did_you_mean(self.known.suggestion(&key))
+                    );
+                    self.report(span, problem);
+                }
+            }
+            NestedMeta::Meta(Meta::Path(path)) => {
+                let name = path_string(path);
+                if !self.known.is_name(&name) {
+                    let problem = format!(
+                        "unknown cfg `{}`{}",
+                        name,
+                        did_you_mean(self.known.suggestion(&name))
+                    );
+                    self.report(span, problem);
+                }
+            }
+            NestedMeta::Meta(Meta::List(list)) => {
+                let name = path_string(&list.path);
+                let problem = format!(
+                    "unknown cfg operator `{}`, expected all, any, or not{}",
+                    name,
+                    did_you_mean(closest(&name, ["all", "any", "not"].into_iter()))
+                );
+                self.report(span, problem);
+            }
+            NestedMeta::Lit(_) => {
+                self.report(span, "a cfg predicate can't be a literal".to_string())
+            }
+        }
+    }
+}
+
+fn path_string(path: &syn::Path) -> String {
+    let segments: Vec<String> = path.segments.iter().map(|s| s.ident.to_string()).collect();
+    segments.join("::")
+}
+
+impl<'a, 'ast> Visit<'ast> for CfgVisitor<'a> {
+    fn visit_attribute(&mut self, node: &'ast syn::Attribute) {
+        if node.path.is_ident("cfg") || node.path.is_ident("cfg_attr") {
+            // Only the first argument of cfg_attr is a predicate
+            if let Ok(Meta::List(list)) = node.parse_meta() {
+                if let Some(predicate) = list.nested.first() {
+                    self.check_predicate(predicate);
+                }
+            }
+        }
+        visit::visit_attribute(self, node);
+    }
+
+    fn visit_macro(&mut self, node: &'ast syn::Macro) {
+        if node.path.is_ident("cfg") {
+            let arguments =
+                node.parse_body_with(Punctuated::<NestedMeta, Token![,]>::parse_terminated);
+            if let Some(predicate) = arguments.ok().as_ref().and_then(|a| a.first()) {
+                self.check_predicate(predicate);
+            }
+        }
+        visit::visit_macro(self, node);
+    }
+}
+
+/// Checks one file, returning the problems found.
+fn check_file(filename: &str, config: &Config) -> Vec<Violation> {
+    let manifest = match find_manifest(filename) {
+        Some(manifest) => manifest,
+        None => return Vec::new(),
+    };
+    let ast = match fs::read_to_string(filename).map(|c| parse_source(&c)) {
+        Ok(Ok(ast)) => ast,
+        Ok(Err(_)) => {
+            eprintln!("Unable to parse {}, skipping it", filename);
+            return Vec::new();
+        }
+        Err(e) => {
+            return vec![Violation::new(
+                filename,
+                format!("Unable to read {}: {}", filename, e),
+            )]
+        }
+    };
+    let config = config.for_crate(&manifest).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        config.clone()
+    });
+    let known = Known::for_crate(&manifest, &config);
+    let mut visitor = CfgVisitor {
+        filename,
+        manifest: &manifest,
+        known: &known,
+        problems: Vec::new(),
+    };
+    visitor.visit_file(&ast);
+    visitor.problems
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-cfg-guard");
+    let mut summary = Summary::new("salt-cfg-guard");
+    let known = [PORCELAIN, OUTPUT, COLOR];
+    let (flags, files) = parse_args("salt-cfg-guard", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut report = Report::from_flags("salt-cfg-guard", &flags, &config).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+
+    let mut crates = BTreeSet::new();
+    for arg in files {
+        if arg.ends_with(".rs") && !config.is_excluded(Path::new(&arg)) {
+            let violations = check_file(&arg, &config);
+            report.record_all(&violations);
+            summary.violations += violations.len();
+            summary.count_lints(&violations);
+            crates.extend(find_manifest(&arg));
+            summary.files += 1;
+        }
+    }
+    if summary.violations > 0 {
+        eprintln!("\nCustom cfgs can be allowed in the [cfg-guard] section of salt-spray.toml.");
+    }
+    summary.crates = crates.len();
+    report.finish(&summary);
+    summary.print();
+    process::exit(summary.exit_code());
+}
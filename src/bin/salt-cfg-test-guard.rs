@@ -0,0 +1,270 @@
+//! `salt-cfg-test-guard` is a lightweight "tests required" policy: a commit that
+//! adds a public function to a library should also add (or change) a test that
+//! refers to it.
+//!
+//! It's a heuristic.  A new `pub fn` counts as tested when its name shows up more
+//! often in the commit's test code than it did at HEAD, where test code is
+//! everything under a crate's `tests/` directory plus any `#[cfg(test)]` modules
+//! and `#[test]` functions.  Functions that already existed at HEAD are left
+//! alone, and a new one can opt out with a comment containing the configured
+//! marker (`salt-cfg-test-guard: skip` by default) just above it.  The tests are
+//! counted over the whole commit, so the hook is `require_serial`: split into
+//! batches, a function's tests could land in a batch other than its own.  With
+//!
+//! ```toml
+//! [cfg-test-guard]
+//! advisory = true
+//! ```
+//!
+//! untested functions are reported but don't fail the commit.
+
+#![forbid(unsafe_code)]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::spanned::Spanned;
+use syn::{ImplItem, Item};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::{Span, Violation};
+use salt_spray::config::Config;
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::{find_manifest, is_public, is_test_code, parse_source, read_committed};
+
+/// A public function, e.g. `pub fn parse` or `pub fn Point::new`.
+struct PublicFn {
+    description: String,
+    name: String,
+    /// The lines (1-based, inclusive) from the function's first attribute to its name.
+    lines: (usize, usize),
+    /// Where its name is.
+    span: Span,
+}
+
+/// How many times each identifier appears in the test code of some files.
+type References = BTreeMap<String, usize>;
+
+fn count_idents(tokens: TokenStream, counts: &mut References) {
+    for token in tokens {
+        match token {
+            TokenTree::Ident(ident) => *counts.entry(ident.to_string()).or_default() += 1,
+            TokenTree::Group(group) => count_idents(group.stream(), counts),
+            _ => (),
+        }
+    }
+}
+
+/// Where the file is within its crate, e.g. `src/lib.rs`.
+fn within_crate(filename: &str) -> Option<PathBuf> {
+    let manifest = find_manifest(filename)?;
+    let within = Path::new(filename).strip_prefix(manifest.parent()?).ok()?;
+    Some(within.to_path_buf())
+}
+
+/// Is the file part of a library, as opposed to a binary, test, example, etc.?
+fn is_library_file(filename: &str) -> bool {
+    match within_crate(filename) {
+        Some(within) => {
+            within.starts_with("src")
+                && within != Path::new("src/main.rs")
+                && !within.starts_with("src/bin")
+        }
+        None => false,
+    }
+}
+
+fn is_test_file(filename: &str) -> bool {
+    within_crate(filename).is_some_and(|within| within.starts_with("tests"))
+}
+
+/// Counts the identifiers in the test code among the items.
+fn count_test_references(items: &[Item], counts: &mut References) {
+    for item in items {
+        match item {
+            Item::Fn(f) if is_test_code(&f.attrs) => count_idents(f.to_token_stream(), counts),
+            Item::Impl(i) if is_test_code(&i.attrs) => count_idents(i.to_token_stream(), counts),
+            Item::Mod(m) if is_test_code(&m.attrs) => count_idents(m.to_token_stream(), counts),
+            Item::Mod(m) => {
+                if let Some((_, items)) = &m.content {
+                    count_test_references(items, counts);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Counts the identifiers in the test code of a version of the given file.
+fn test_references(filename: &str, contents: &str, counts: &mut References) {
+    let ast = match parse_source(contents) {
+        Ok(ast) => ast,
+        Err(_) => return,
+    };
+    if is_test_file(filename) {
+        count_idents(ast.to_token_stream(), counts);
+    } else {
+        count_test_references(&ast.items, counts);
+    }
+}
+
+fn public_fn(description: String, item: &dyn Spanned, name: &syn::Ident) -> PublicFn {
+    PublicFn {
+        description,
+        name: name.to_string(),
+        lines: (item.span().start().line, name.span().start().line),
+        span: name.span().into(),
+    }
+}
+
+/// Walks the items (and inline modules) collecting the public functions that
+/// aren't test code, including those in inherent impls.
+fn collect_public_fns(prefix: &str, items: &[Item], result: &mut Vec<PublicFn>) {
+    for item in items {
+        match item {
+            Item::Fn(f) if is_public(&f.vis) && !is_test_code(&f.attrs) => {
+                let description = format!("pub fn {}{}", prefix, f.sig.ident);
+                result.push(public_fn(description, item, &f.sig.ident));
+            }
+            Item::Impl(i) if i.trait_.is_none() && !is_test_code(&i.attrs) => {
+                let ty = i.self_ty.to_token_stream().to_string().replace(' ', "");
+                for impl_item in i.items.iter() {
+                    if let ImplItem::Method(m) = impl_item {
+                        if is_public(&m.vis) && !is_test_code(&m.attrs) {
+                            let description = format!("pub fn {}{}::{}", prefix, ty, m.sig.ident);
+                            result.push(public_fn(description, impl_item, &m.sig.ident));
+                        }
+                    }
+                }
+            }
+            Item::Mod(m) if !is_test_code(&m.attrs) => {
+                if let Some((_, items)) = &m.content {
+                    collect_public_fns(&format!("{}{}::", prefix, m.ident), items, result);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn public_fns_in(contents: &str) -> Option<Vec<PublicFn>> {
+    let ast = parse_source(contents).ok()?;
+    let mut result = Vec::new();
+    collect_public_fns("", &ast.items, &mut result);
+    Some(result)
+}
+
+/// Returns the public functions the file gained since HEAD, leaving out the ones
+/// that opted out.
+fn new_public_fns(filename: &str, contents: &str, config: &Config) -> Vec<PublicFn> {
+    let fns = match public_fns_in(contents) {
+        Some(fns) => fns,
+        None => {
+            eprintln!("Unable to parse {}, skipping it", filename);
+            return Vec::new();
+        }
+    };
+    let existing: BTreeSet<String> = read_committed("HEAD", filename)
+        .and_then(|old| public_fns_in(&old))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|f| f.description)
+        .collect();
+
+    let lines: Vec<&str> = contents.lines().collect();
+    let marker = &config.cfg_test_guard.marker;
+    fns.into_iter()
+        .filter(|f| !existing.contains(&f.description))
+        .filter(|f| {
+            let (first, last) = f.lines;
+            // The marker may be on the line above the fn or anywhere in its attributes
+            !lines
+                .get(first.saturating_sub(2)..last.min(lines.len()))
+                .is_some_and(|ls| ls.iter().any(|l| l.contains(marker.as_str())))
+        })
+        .collect()
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-cfg-test-guard");
+    let mut summary = Summary::new("salt-cfg-test-guard");
+    let known = [PORCELAIN, OUTPUT, COLOR];
+    let (flags, args) = parse_args("salt-cfg-test-guard", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut report =
+        Report::from_flags("salt-cfg-test-guard", &flags, &config).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+
+    let files: Vec<String> = args.into_iter().filter(|a| a.ends_with(".rs")).collect();
+
+    // Tests anywhere in the commit count, including ones in excluded files
+    let mut before = References::new();
+    let mut after = References::new();
+    let mut new_fns = Vec::new();
+    let mut crates = BTreeSet::new();
+    for file in files.iter() {
+        let contents = match fs::read_to_string(file) {
+            Ok(contents) => contents,
+            Err(e) => {
+                eprintln!("Unable to read {}: {}", file, e);
+                continue;
+            }
+        };
+        test_references(file, &contents, &mut after);
+        if let Some(old) = read_committed("HEAD", file) {
+            test_references(file, &old, &mut before);
+        }
+        if is_library_file(file) && !config.is_excluded(Path::new(file)) {
+            new_fns.extend(
+                new_public_fns(file, &contents, &config)
+                    .into_iter()
+                    .map(|f| (file, f)),
+            );
+            crates.extend(find_manifest(file));
+            summary.files += 1;
+        }
+    }
+
+    let count = |refs: &References, name: &str| refs.get(name).copied().unwrap_or(0);
+    let mut untested = Vec::new();
+    for (file, f) in new_fns.iter() {
+        if count(&after, &f.name) <= count(&before, &f.name) {
+            let message = format!(
+                "{} is new, but no test in this commit refers to it",
+                f.description
+            );
+            let mut violation = Violation::new(file, message)
+                .at(f.span)
+                .for_lint("cfg-test-guard")
+                .suggesting(format!(
+                    "add a test that uses it, or a `// {}` comment if it doesn't need one",
+                    config.cfg_test_guard.marker
+                ));
+            if config.cfg_test_guard.advisory {
+                violation = violation.advisory();
+            }
+            untested.push(violation);
+        }
+    }
+    report.record_all(&untested);
+    summary.violations = untested.iter().filter(|v| !v.advisory).count();
+    summary.count_lints(&untested);
+    summary.crates = crates.len();
+    report.finish(&summary);
+    summary.print();
+    process::exit(summary.exit_code());
+}
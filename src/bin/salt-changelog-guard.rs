@@ -0,0 +1,166 @@
+//! `salt-changelog-guard` keeps the release notes of a multi-crate repo current.
+//! A commit that changes a crate whose policy sets `changelog` has to add to that
+//! crate's CHANGELOG.md (next to its Cargo.toml) too, or stage a changeset file
+//! that mentions the crate by name.
+//!
+//! Mark crates in salt-spray.toml through their tier's policy, e.g.
+//!
+//! ```toml
+//! [tier-policy.tier1]
+//! changelog = true
+//! ```
+//!
+//! or with `changelog = true` in the `[policy]` of a crate's own config.  Commits
+//! that don't deserve an entry can say so with a tag in their message:
+//!
+//! ```toml
+//! [changelog-guard]
+//! changesets = [".changeset/*.md"]
+//! skip-tag = "[skip changelog]"
+//! advisory = false
+//! ```
+//!
+//! Since it needs the commit message, the hook runs at pre-commit's `commit-msg`
+//! stage, which hands it the file the message is in.  Without one it goes by the
+//! staged files alone.
+
+#![forbid(unsafe_code)]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::Violation;
+use salt_spray::config::{glob_matches, Config};
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::{find_manifest, find_repo_root, package_name, read_committed, say};
+
+const CHANGELOG: &str = "CHANGELOG.md";
+
+/// The (repo relative) paths of the staged changes.
+fn staged_files(root: &Path) -> Result<Vec<String>, String> {
+    let output = Command::new("git")
+        .args(["diff", "--cached", "--name-only", "-z"])
+        .current_dir(root)
+        .output()
+        .map_err(|e| format!("Unable to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Unable to list the staged changes: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|f| !f.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-changelog-guard");
+    let mut summary = Summary::new("salt-changelog-guard");
+    let known = [PORCELAIN, OUTPUT, COLOR];
+    let (flags, args) = parse_args("salt-changelog-guard", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut report =
+        Report::from_flags("salt-changelog-guard", &flags, &config).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+    let root = find_repo_root().unwrap_or_else(|| {
+        eprintln!("salt-changelog-guard has to run inside of a git repo");
+        process::exit(-1);
+    });
+
+    let settings = &config.changelog_guard;
+    let message = args.first().and_then(|f| fs::read_to_string(f).ok());
+    if message.is_some_and(|m| m.contains(&settings.skip_tag)) {
+        say!(
+            "The commit message says {}, so no changelog entries are needed",
+            settings.skip_tag
+        );
+        summary.print();
+        process::exit(0);
+    }
+
+    let staged = staged_files(&root).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let changesets: Vec<String> = staged
+        .iter()
+        .filter(|f| {
+            settings
+                .changesets
+                .iter()
+                .any(|g| glob_matches(g, Path::new(f)))
+        })
+        .filter_map(|f| read_committed("", f))
+        .collect();
+
+    // The crates with changes, other than to their changelogs
+    let mut changed: BTreeMap<PathBuf, BTreeSet<PathBuf>> = BTreeMap::new();
+    for file in staged.iter().map(|f| root.join(f)) {
+        if let Some(manifest) = find_manifest(&file) {
+            let entry = changed.entry(manifest.clone()).or_default();
+            if file != manifest.with_file_name(CHANGELOG) {
+                entry.insert(file);
+            }
+        }
+    }
+
+    let mut missing = Vec::new();
+    for (manifest, files) in changed.iter() {
+        if files.is_empty() || !config.policy_for(manifest).changelog {
+            continue;
+        }
+        summary.crates += 1;
+        summary.files += files.len();
+        let changelog = manifest.with_file_name(CHANGELOG);
+        let name = package_name(manifest).unwrap_or_else(|| manifest.display().to_string());
+        let has_entry = staged.iter().any(|f| root.join(f) == changelog)
+            || changesets.iter().any(|c| c.contains(&name));
+        if !has_entry {
+            let relative = changelog.strip_prefix(&root).unwrap_or(&changelog);
+            let message = format!(
+                "{} changed, but neither it nor a changeset ({}) says how",
+                name,
+                settings.changesets.join(", ")
+            );
+            let violation = Violation::new(&relative.to_string_lossy(), message)
+                .for_lint("changelog-guard")
+                .suggesting(format!(
+                    "add an entry, or put {} in the commit message if the change doesn't need one",
+                    settings.skip_tag
+                ));
+            missing.push(violation);
+        }
+    }
+
+    if settings.advisory {
+        missing = missing.into_iter().map(Violation::advisory).collect();
+    }
+    report.record_all(&missing);
+    summary.count_lints(&missing);
+    if !missing.is_empty() {
+        if settings.advisory {
+            eprintln!("Changelogs are advisory, so this won't block the commit.");
+        } else {
+            summary.violations = missing.len();
+        }
+    }
+    report.finish(&summary);
+    summary.print();
+    process::exit(summary.exit_code());
+}
@@ -0,0 +1,135 @@
+//! `salt-check` is a `cargo check` plugin for `pre-commit`, `salt-clip` without
+//! the lints.  Clippy can take a long time on a big workspace, so this is for
+//! failing fast on code that doesn't compile, e.g. with salt-check at the
+//! pre-commit stage and salt-clip at pre-push.
+//!
+//! It checks each crate the commit touches once, the same way salt-clip lints
+//! them (only the targets the files are in, each workspace in its own
+//! environment, for the crate's cross target if it has one), and only reports the
+//! errors and warnings in the files being committed.  Warnings for lints the
+//! crate's `[lints]` table sets to `warn` don't block the commit, the rest do.
+//!
+//! It takes the same `--env-args=`, `--env-file=`, `--stream`, `--verbose`,
+//! `--with-reverse-deps`, `--deny-future-incompat`, and `--jobs=N` as salt-clip,
+//! see there.
+
+#![forbid(unsafe_code)]
+
+use std::env;
+use std::path::Path;
+use std::process;
+
+use salt_spray::args::parse_args;
+use salt_spray::clippy::{check_crate, read_env_file, reverse_dependencies, Options};
+use salt_spray::config::Config;
+use salt_spray::jobs::{self, JOBS};
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::toolchain::{self, CARGO_PATH};
+use salt_spray::{group_by_workspace, say};
+
+static ENV_ARGS: &str = "--env-args=";
+static ENV_FILE: &str = "--env-file=";
+static STREAM: &str = "--stream";
+static VERBOSE: &str = "--verbose";
+static WITH_REVERSE_DEPS: &str = "--with-reverse-deps";
+static DENY_FUTURE_INCOMPAT: &str = "--deny-future-incompat";
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-check");
+    let mut summary = Summary::new("salt-check");
+    let known = [
+        ENV_ARGS,
+        ENV_FILE,
+        STREAM,
+        VERBOSE,
+        WITH_REVERSE_DEPS,
+        DENY_FUTURE_INCOMPAT,
+        COLOR,
+        OUTPUT,
+        PORCELAIN,
+        CARGO_PATH,
+        JOBS,
+    ];
+    let (flags, args) = parse_args("salt-check", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut options = Options::default();
+    let mut with_reverse_deps = false;
+    for arg in flags.iter() {
+        if let Some(assignments) = arg.strip_prefix(ENV_ARGS) {
+            let assignments = assignments.split(';').filter(|a| !a.is_empty());
+            options.env_args.extend(assignments.map(str::to_string));
+        } else if let Some(path) = arg.strip_prefix(ENV_FILE) {
+            let assignments = read_env_file(Path::new(path)).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(-1);
+            });
+            options.env_args.extend(assignments);
+        } else if arg == STREAM {
+            options.stream = true;
+        } else if arg == VERBOSE {
+            options.verbose = true;
+        } else if arg == WITH_REVERSE_DEPS {
+            with_reverse_deps = true;
+        } else if arg == DENY_FUTURE_INCOMPAT {
+            options.deny_future_incompat = true;
+        } else if let Some(path) = arg.strip_prefix(CARGO_PATH) {
+            toolchain::set_cargo_path(path);
+        } else if let Some(jobs) = arg.strip_prefix(JOBS) {
+            jobs::set_jobs(jobs).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(-1);
+            });
+        }
+    }
+    let mut report = Report::from_flags("salt-check", &flags, &config).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let files: Vec<String> = args
+        .into_iter()
+        .filter(|a| !config.is_excluded(Path::new(a)))
+        .collect();
+
+    // Like clippy, cargo check goes a whole crate at a time
+    let mut files_by_workspace = group_by_workspace(&files);
+    if with_reverse_deps {
+        for (workspace, files_by_crate) in files_by_workspace.iter_mut() {
+            let changed = files_by_crate.keys().cloned().collect();
+            let dependents = reverse_dependencies(workspace, &changed, &config);
+            let dependents = dependents.unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(-1);
+            });
+            for (cargo_toml, files) in dependents {
+                say!(
+                    "Also checking {}, since it depends on the changes",
+                    cargo_toml
+                );
+                files_by_crate.insert(cargo_toml, files);
+            }
+        }
+    }
+    for (workspace, files_by_crate) in files_by_workspace.iter() {
+        if files_by_workspace.len() > 1 {
+            eprintln!("\n== {} ==", workspace.display());
+        }
+        for (cargo_toml, files) in files_by_crate.iter() {
+            let violations = check_crate(cargo_toml, files, &options, &config);
+            report.record_all(&violations);
+            summary.violations += violations.iter().filter(|v| !v.advisory).count();
+            summary.count_lints(&violations);
+            summary.files += files.len();
+        }
+        summary.crates += files_by_crate.len();
+    }
+    report.finish(&summary);
+    summary.print();
+    process::exit(summary.exit_code());
+}
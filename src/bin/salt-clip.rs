@@ -2,116 +2,315 @@
 //!
 //! It tries to be smarter about how it handles monorepos (and other situations
 //! where Cargo workspaces are used) than some other rustfmt pre-commit wrappers.
+//!
+//! Clippy can be adopted a few lint groups at a time: with
+//! `--only-groups=correctness,suspicious` only lints in those groups block the
+//! commit, and with `--skip-groups=pedantic,nursery` lints in those groups don't.
+//! Either way the rest are still shown.
+//!
+//! Only the targets that the commit's files are in get built, e.g. just `--lib`
+//! when only the library changed, rather than every binary of the crate too.
+//!
+//! Changing a crate can cause lints in the crates that depend on it (e.g. when
+//! something they use gets deprecated), so `--with-reverse-deps` also lints every
+//! crate in the workspace that depends on the changed ones, all of its files.
+//!
+//! Clippy can be given environment variables with `--env-args=NAME=value;...`
+//! and `--env-file=path` (a `NAME=value` per line), as many of each as needed.
+//! They're set in order, so later ones can refer to earlier ones as `$NAME`, e.g.
+//! `--env-file=sdk.env --env-args='CC=$SDK_ROOT/bin/gcc'`, and `${NAME:-default}`
+//! stands for the default when NAME is unset or empty.  A variable that's already
+//! set is overridden, unless salt-spray.toml says otherwise:
+//!
+//! ```toml
+//! [clip.env-policy]
+//! PATH = "append-path"  # add to the end of it, as a list of paths
+//! CC = "preserve"       # keep it, only setting it when it isn't set
+//! ```
+//!
+//! Cargo's progress (all those "Compiling foo" lines) is noise in pre-commit's
+//! logs, so it's left out.  With `--stream` a terminal gets a spinner while cargo
+//! works and every run ends with a one line summary, and with `--verbose` every
+//! progress line is shown, streamed or not.
+//!
+//! What cargo itself has to say (e.g. warnings about unused manifest keys) is
+//! forwarded in its own section, apart from the lints.  With
+//! `--deny-future-incompat` its reports of dependencies that a future Rust will
+//! reject block the commit too.
+//!
+//! Crates can be treated differently depending on whether they can be published:
+//!
+//! ```toml
+//! [publish]
+//! skip-unpublished = true          # leave crates with publish = false alone
+//! clippy-args = ["-D", "missing_docs"]  # but hold the published ones to more
+//! ```
+//!
+//! Crates that are built for another target (e.g. the firmware in an embedded
+//! monorepo) are linted for it too, so the lints match the real build:
+//!
+//! ```toml
+//! [cross."firmware"]
+//! triple = "thumbv7em-none-eabihf"
+//! rustflags = "-C link-arg=-Tlink.x"  # if cargo's config doesn't have them
+//! ```
+//!
+//! `--jobs=N` is passed on to cargo, see `salt_spray::jobs`.
+//!
+//! With `--report-file=clip.json` (or `report-file` under `[clip]` in
+//! salt-spray.toml) it also writes one JSON report of the whole run there, with
+//! each crate's status, how long it took, and its diagnostics, so that tooling
+//! looking at pre-push or CI runs over many crates doesn't have to pick them out
+//! of the logs.  The path is relative to the repo root unless it's absolute.  Each
+//! run replaces the report, so it's only of the whole commit because the hook is
+//! `require_serial` (see the baseline below); a config that overrides that gets a
+//! report of just the last batch.
+//!
+//! A legacy crate with thousands of warnings can adopt clippy with `--baseline`,
+//! which records the counts of each lint in each file in .clippy-baseline.yaml and
+//! only fails files that gain more, see `salt_spray::clippy::Baseline`.  When
+//! fixes lower the counts the baseline is updated, and so needs to be staged
+//! before retrying.  Each run rewrites the baseline from the files it was given,
+//! which is why the hook has `require_serial: true`: in parallel batches, the last
+//! one to finish would drop the others' counts.
 
 #![forbid(unsafe_code)]
 
-use std::collections::{BTreeSet, HashMap};
 use std::env;
 use std::ffi::OsStr; // intentionally unused since this file is its own test data
-use std::process::{self, Command, Output};
+use std::path::Path;
+use std::process;
+use std::time::Instant;
 
-use once_cell::sync::Lazy;
-use regex::Regex;
-
-use salt_spray::{find_manifest, find_repo_root};
+use salt_spray::args::parse_args;
+use salt_spray::clippy::{
+    lint_crate, lint_groups, read_env_file, reverse_dependencies, write_report, Baseline, CrateRun,
+    Options, BASELINE,
+};
+use salt_spray::config::Config;
+use salt_spray::jobs::{self, JOBS};
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::toolchain::{self, CARGO_PATH};
+use salt_spray::{group_by_workspace, is_publishable, say};
 
 static CLIPPY_ENV_ARGS: &str = "--env-args=";
-static CLIPPY_FILE_IDENTIFICATION: Lazy<Regex> = Lazy::new(|| {
-    // The filename ends up in capture group #1
-    Regex::new(r"-->\s+([^:]+)").unwrap()
-});
-static ENV_VAR_REFERENCE: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"(\$[A-Z_]+)(?:\W|$)").unwrap()
-});
-
-fn resolve_env_vars(s: &str) -> String {
-    let mut result = s.to_string();
-    if let Some(rr) = find_repo_root() {
-        result = result.replace("$REPO_ROOT", &rr.to_string_lossy());
-    }
+static CLIPPY_ENV_FILE: &str = "--env-file=";
+static CLIPPY_STREAM: &str = "--stream";
+static ONLY_GROUPS: &str = "--only-groups=";
+static SKIP_GROUPS: &str = "--skip-groups=";
+static WITH_REVERSE_DEPS: &str = "--with-reverse-deps";
+static DENY_FUTURE_INCOMPAT: &str = "--deny-future-incompat";
+static BASELINE_FLAG: &str = "--baseline";
+static VERBOSE: &str = "--verbose";
+static REPORT_FILE: &str = "--report-file=";
 
-    while let Some(captures) = ENV_VAR_REFERENCE.captures(&result) {
-        // unwrap here is safe since the capture group is mandatory
-        let first_variable_name = captures.get(1).unwrap().as_str();
-        if let Ok(val) = env::var(&first_variable_name[1..]) {
-            result = result.replace(first_variable_name, &val);
-        } else {
-            eprintln!("Unrecognized environment variable: {}", first_variable_name);
-            process::exit(-1);
-        }
-    }
-    result
+/// Splits a comma separated list of lint groups, e.g. `style,clippy::perf`.
+fn parse_groups(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|g| g.trim().trim_start_matches("clippy::").replace('-', "_"))
+        .filter(|g| !g.is_empty())
+        .collect()
 }
 
-/// Parses `args` and loads them into the pending command.
-fn load_env_args(cmd: &mut Command, args: &Option<String>) {
-    if let Some(args) = args {
-        for assignment in args.split(';') {
-            if let Some((name, val)) = assignment.split_once('=') {
-                let val = resolve_env_vars(val);
-                cmd.env(name, val);
-            }
-        }
+/// Makes sure clippy has the lint groups the options name.  This has to wait
+/// until the arguments are parsed, since it depends on which cargo is used.
+fn check_groups(options: &Options) {
+    let groups: Vec<&String> = options
+        .only_groups
+        .iter()
+        .chain(options.skip_groups.iter())
+        .collect();
+    let known = lint_groups();
+    if groups.is_empty() {
+        return;
+    } else if known.is_empty() {
+        eprintln!(
+            "Clippy's lint groups are unknown, so {} and {} have no effect",
+            ONLY_GROUPS, SKIP_GROUPS
+        );
+        return;
     }
-}
-
-/// Runs Clippy on a crate, but only outputs lints for files in the given set.
-fn lint_crate(cargo_toml: &str, files: &BTreeSet<String>, args: &Option<String>) -> i32 {
-    let mut result = 0;
-    let mut cmd = Command::new("cargo");
-    load_env_args(&mut cmd, args);
-    cmd.args(["clippy", "--no-deps", "--quiet", "--manifest-path", cargo_toml]);
-
-    match cmd.output() {
-        Ok(Output { stderr, .. }) => {
-            let stderr = String::from_utf8_lossy(&stderr);
-            for found_lint in stderr.split("\n\n") {
-                if let Some(captures) = CLIPPY_FILE_IDENTIFICATION.captures(found_lint) {
-                    // unwrap here is safe since the capture group is mandatory
-                    let project_relative_filename = captures.get(1).unwrap().as_str();
-                    if files.iter().any(|s| s.ends_with(project_relative_filename)) {
-                        eprintln!("\n{}", found_lint);
-                        result += 1;
-                    // } else {
-                    //     eprintln!("None of the files ended with {}", project_relative_filename);
-                    }
-                // } else {
-                //     eprintln!("Bad\n{}", found_lint);
-                }
-            }
-        }
-        e => eprintln!("{:?}", e),
+    if let Some(group) = groups.into_iter().find(|g| !known.contains_key(*g)) {
+        let names: Vec<&str> = known.keys().map(String::as_str).collect();
+        eprintln!(
+            "{} isn't a clippy lint group, expected one of {}",
+            group,
+            names.join(", ")
+        );
+        process::exit(-1);
     }
-    result
 }
 
 /// Do the thing
 fn main() {
-    let mut args = env::args();
-    drop(args.next());
+    salt_spray::exit_if_version_asked("salt-clip");
+    let mut summary = Summary::new("salt-clip");
+    let known = [
+        CLIPPY_ENV_ARGS,
+        CLIPPY_ENV_FILE,
+        CLIPPY_STREAM,
+        VERBOSE,
+        ONLY_GROUPS,
+        SKIP_GROUPS,
+        WITH_REVERSE_DEPS,
+        DENY_FUTURE_INCOMPAT,
+        BASELINE_FLAG,
+        COLOR,
+        OUTPUT,
+        PORCELAIN,
+        CARGO_PATH,
+        JOBS,
+        REPORT_FILE,
+    ];
+    let (flags, args) = parse_args("salt-clip", env::args().skip(1), &known);
 
-    let mut clippy_env_args = None;
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut options = Options::default();
+    let mut with_reverse_deps = false;
+    let mut baseline = None;
+    let mut report_file = config.clip.report_file.clone();
+
+    for arg in flags.iter() {
+        if let Some(assignments) = arg.strip_prefix(CLIPPY_ENV_ARGS) {
+            let assignments = assignments.split(';').filter(|a| !a.is_empty());
+            options.env_args.extend(assignments.map(str::to_string));
+        } else if let Some(path) = arg.strip_prefix(CLIPPY_ENV_FILE) {
+            let assignments = read_env_file(Path::new(path)).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(-1);
+            });
+            options.env_args.extend(assignments);
+        } else if arg == CLIPPY_STREAM {
+            options.stream = true;
+        } else if arg == VERBOSE {
+            options.verbose = true;
+        } else if let Some(list) = arg.strip_prefix(ONLY_GROUPS) {
+            options.only_groups = parse_groups(list);
+        } else if let Some(list) = arg.strip_prefix(SKIP_GROUPS) {
+            options.skip_groups = parse_groups(list);
+        } else if arg == WITH_REVERSE_DEPS {
+            with_reverse_deps = true;
+        } else if arg == DENY_FUTURE_INCOMPAT {
+            options.deny_future_incompat = true;
+        } else if arg == BASELINE_FLAG {
+            baseline = Some(Baseline::load().unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(-1);
+            }));
+        } else if arg == PORCELAIN {
+            summary::set_porcelain(true);
+        } else if let Some(path) = arg.strip_prefix(CARGO_PATH) {
+            toolchain::set_cargo_path(path);
+        } else if let Some(jobs) = arg.strip_prefix(JOBS) {
+            jobs::set_jobs(jobs).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(-1);
+            });
+        } else if let Some(path) = arg.strip_prefix(REPORT_FILE) {
+            report_file = Some(path.to_string());
+        }
+    }
+    let mut report = Report::from_flags("salt-clip", &flags, &config).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let files: Vec<String> = args
+        .into_iter()
+        .filter(|a| !config.is_excluded(Path::new(a)))
+        .collect();
+    check_groups(&options);
 
     // Clippy can only operate on whole crates at a time, so rather than lint
     // each crate for each file within it, we group the file names first and
-    // only run clippy once for each crate.
-    let mut files_by_crate: HashMap<String, BTreeSet<String>> = HashMap::new();
-    for mut arg in args {
-        if arg.starts_with(CLIPPY_ENV_ARGS) {
-            clippy_env_args = Some(arg.split_off(CLIPPY_ENV_ARGS.len()));
-        } else if let Some(manifest_path) = find_manifest(&arg) {
-            let manifest_path = manifest_path.to_string_lossy().to_string();
-            let files = files_by_crate.entry(manifest_path).or_default();
-            files.insert(arg);
+    // only run clippy once for each crate.  The crates are grouped by workspace
+    // too, since each workspace gets linted in its own environment.
+    let mut files_by_workspace = group_by_workspace(&files);
+    if with_reverse_deps {
+        for (workspace, files_by_crate) in files_by_workspace.iter_mut() {
+            let changed = files_by_crate.keys().cloned().collect();
+            let dependents = reverse_dependencies(workspace, &changed, &config);
+            let dependents = dependents.unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(-1);
+            });
+            for (cargo_toml, files) in dependents {
+                say!(
+                    "Also linting {}, since it depends on the changes",
+                    cargo_toml
+                );
+                files_by_crate.insert(cargo_toml, files);
+            }
+        }
+    }
+    if config.publish.skip_unpublished {
+        for files_by_crate in files_by_workspace.values_mut() {
+            files_by_crate.retain(|cargo_toml, _| {
+                let publishable = is_publishable(cargo_toml);
+                if !publishable {
+                    say!("Skipping {}, since it has publish = false", cargo_toml);
+                }
+                publishable
+            });
         }
+        files_by_workspace.retain(|_, files_by_crate| !files_by_crate.is_empty());
     }
 
     // pre-commit looks for either changes to files or our return code to indicate
     // that the commit should not be allowed.  Since we can't automatically fix
     // issues, we instead exit with the violation count.
     let mut violation_count = 0;
-    for (cargo_toml, files) in files_by_crate.iter() {
-        violation_count += lint_crate(cargo_toml, files, &clippy_env_args);
+    let mut runs = Vec::new();
+    for (workspace, files_by_crate) in files_by_workspace.iter() {
+        if files_by_workspace.len() > 1 {
+            eprintln!("\n== {} ==", workspace.display());
+        }
+        for (cargo_toml, files) in files_by_crate.iter() {
+            let start = Instant::now();
+            let mut violations = lint_crate(cargo_toml, files, &options, &config);
+            if let Some(baseline) = baseline.as_mut() {
+                violations = baseline.apply(cargo_toml, files, violations);
+            }
+            for violation in violations.iter() {
+                report.record(violation);
+                if !violation.advisory {
+                    violation_count += 1;
+                }
+            }
+            summary.count_lints(&violations);
+            summary.files += files.len();
+            if report_file.is_some() {
+                runs.push(CrateRun {
+                    manifest: cargo_toml.clone(),
+                    files: files.clone(),
+                    duration: start.elapsed(),
+                    violations,
+                });
+            }
+        }
+        summary.crates += files_by_crate.len();
+    }
+    let updated = baseline.map(|b| b.save()).transpose().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    summary.violations = violation_count as usize;
+    if updated == Some(true) {
+        say!("Please run `git add {}` and retry your commit.", BASELINE);
+        if violation_count == 0 {
+            violation_count = 2;
+        }
+    }
+    if let Some(path) = report_file {
+        if let Err(e) = write_report(&path, &runs, &summary) {
+            eprintln!("{}", e);
+        }
     }
-    process::exit(violation_count);
+    report.finish(&summary);
+    summary.print();
+    process::exit(violation_count.min(255));
 }
@@ -0,0 +1,283 @@
+//! `salt-deprecation-guard` is a ratchet for the uses of deprecated items, so that
+//! deprecations get migrated away from rather than picking up new callers while
+//! the old ones are being fixed.
+//!
+//! For each crate touched by a commit whose policy sets `track-deprecated`, it
+//! runs `cargo check` and counts the `deprecated` warnings rustc gives for the
+//! crate's own code, by the item they're about (e.g. `old_api::frobnicate`), and
+//! compares those counts to the ones stored in .deprecated.yaml .  An item whose
+//! uses went up rejects the commit, with every use of it in the committed files
+//! pointed out (since there's no telling which of them is new).  Uses that went
+//! down (or away) update .deprecated.yaml (so it needs to be staged before
+//! retrying), just like `salt-panic-guard`.
+//!
+//! Mark crates in salt-spray.toml through their tier's policy, e.g.
+//!
+//! ```toml
+//! [tier-policy.tier1]
+//! track-deprecated = true
+//! ```
+//!
+//! or with `track-deprecated = true` in the `[policy]` of a crate's own config.
+//! Only the library and binaries are checked, as `cargo check` does by default, so
+//! tests of deprecated items don't count against them.  Uses under an
+//! `#[allow(deprecated)]` don't get a warning to count, which is what the warning
+//! ratchet is for.
+//!
+//! To accept growth anyway, rerun with `UPDATE_ANYWAY=1`.
+
+#![forbid(unsafe_code)]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::path::{self, Path};
+use std::process;
+
+use serde::{Deserialize, Serialize};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::{Span, Violation};
+use salt_spray::config::Config;
+use salt_spray::diagnostic::{parse_messages, Diagnostic};
+use salt_spray::jobs::{self, JOBS};
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::toolchain::{self, CARGO_PATH};
+use salt_spray::{
+    find_workspace_root, group_by_crate, load_baseline, package_name, repo_relative, save_baseline,
+    say,
+};
+
+const BASELINE: &str = ".deprecated.yaml";
+
+/// How many times each crate uses each deprecated item, keyed by package name and
+/// then by item.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Baseline {
+    crates: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+/// The deprecated item a warning is about, e.g. `old_api::frobnicate` out of "use
+/// of deprecated function `old_api::frobnicate`: use `frob` instead", or the
+/// whole message if it doesn't name one.
+fn item_of(diagnostic: &Diagnostic) -> String {
+    let mut quoted = diagnostic.message.split('`');
+    match (quoted.next(), quoted.next(), quoted.next()) {
+        (Some(_), Some(item), Some(_)) => item.to_string(),
+        _ => diagnostic.message.clone(),
+    }
+}
+
+/// Checks the crate, returning rustc's `deprecated` warnings about its own code,
+/// with their paths relative to the repo root.
+fn deprecated_uses(manifest: &Path, config: &Config) -> Result<Vec<Diagnostic>, String> {
+    let absolute = path::absolute(manifest).unwrap_or_else(|_| manifest.to_path_buf());
+    let mut cmd = toolchain::cargo_for(manifest, config);
+    cmd.args(["check", "--message-format=json", "--manifest-path"])
+        .arg(&absolute);
+    if let Some(jobs) = jobs::limit() {
+        cmd.args(["--jobs", &jobs.to_string()]);
+    }
+    let output = cmd
+        .output()
+        .map_err(|e| format!("Unable to run cargo: {}", e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let diagnostics = parse_messages(&stdout);
+    if !output.status.success() {
+        let errors: Vec<&str> = diagnostics
+            .iter()
+            .filter(|d| d.level == "error")
+            .map(|d| d.rendered.as_str())
+            .collect();
+        let why = if errors.is_empty() {
+            String::from_utf8_lossy(&output.stderr)
+                .trim_end()
+                .to_string()
+        } else {
+            errors.join("\n")
+        };
+        return Err(format!(
+            "Unable to check {}, so its uses of deprecated items can't be counted:\n{}",
+            manifest.display(),
+            why
+        ));
+    }
+
+    // Warnings about path dependencies in the same workspace come out too
+    let workspace = find_workspace_root(manifest);
+    let crate_dir = repo_relative(manifest.parent().unwrap_or(Path::new(".")));
+    Ok(diagnostics
+        .into_iter()
+        .filter(|d| d.code.as_deref() == Some("deprecated"))
+        .map(|d| d.relative_to_repo(&workspace))
+        .filter(|d| {
+            d.location
+                .as_ref()
+                .is_some_and(|(file, _, _)| Path::new(file).starts_with(&crate_dir))
+        })
+        .collect())
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-deprecation-guard");
+    let mut summary = Summary::new("salt-deprecation-guard");
+    let known = [PORCELAIN, OUTPUT, CARGO_PATH, JOBS, COLOR];
+    let (flags, files) = parse_args("salt-deprecation-guard", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    if let Some(path) = flags.iter().find_map(|f| f.strip_prefix(CARGO_PATH)) {
+        toolchain::set_cargo_path(path);
+    }
+    if let Some(jobs) = flags.iter().find_map(|f| f.strip_prefix(JOBS)) {
+        jobs::set_jobs(jobs).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+    }
+    let mut report =
+        Report::from_flags("salt-deprecation-guard", &flags, &config).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+
+    let mut baseline: Baseline = load_baseline(Path::new(BASELINE)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let update_anyway = env::var("UPDATE_ANYWAY").map(|v| v == "1").unwrap_or(false);
+    let mut updated = false;
+    for (cargo_toml, crate_files) in group_by_crate(&files).iter() {
+        let manifest = Path::new(cargo_toml);
+        let policy = config.policy_for(manifest);
+        if !policy.track_deprecated {
+            continue;
+        }
+        let name = package_name(cargo_toml).unwrap_or_else(|| cargo_toml.to_string());
+        summary.crates += 1;
+        summary.files += crate_files.len();
+
+        say!("Checking {} for uses of deprecated items", name);
+        let crate_config = config.for_crate(manifest).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            config.clone()
+        });
+        let uses = match deprecated_uses(manifest, &crate_config) {
+            Ok(uses) => uses,
+            Err(e) => {
+                let mut violation = Violation::new(cargo_toml, e).for_lint("deprecation-guard");
+                if policy.advisory {
+                    violation = violation.advisory();
+                } else {
+                    summary.violations += 1;
+                }
+                report.record(&violation);
+                continue;
+            }
+        };
+        let mut observed: BTreeMap<String, usize> = BTreeMap::new();
+        for diagnostic in uses.iter() {
+            *observed.entry(item_of(diagnostic)).or_default() += 1;
+        }
+
+        let expected = match baseline.crates.get(&name) {
+            Some(expected) => expected.clone(),
+            None => {
+                let total: usize = observed.values().sum();
+                say!(
+                    "Started tracking the uses of deprecated items in {}: {}",
+                    name,
+                    total
+                );
+                baseline.crates.insert(name, observed);
+                updated = true;
+                continue;
+            }
+        };
+        let committed: BTreeSet<String> = crate_files
+            .iter()
+            .map(|f| repo_relative(f).to_string_lossy().replace('\\', "/"))
+            .collect();
+        let mut grew = false;
+        for (item, is) in observed.iter() {
+            let was = expected.get(item).copied().unwrap_or(0);
+            if is <= &was {
+                continue;
+            }
+            grew = true;
+            let message = format!(
+                "{} uses the deprecated `{}` {} time(s), up from {} in {}",
+                name, item, is, was, BASELINE
+            );
+            let suggestion = "use what replaces it, or rerun with UPDATE_ANYWAY=1 to accept the \
+                              new uses";
+            let mut violations: Vec<Violation> = uses
+                .iter()
+                .filter(|d| item_of(d) == *item && d.is_in(&committed))
+                .map(|d| {
+                    // unwrap here is safe since deprecated_uses only keeps located ones
+                    let (file, line, column) = d.location.clone().unwrap();
+                    Violation::new(&file, format!("{}\n{}", message, d.rendered))
+                        .at(Span::new(line, column, 0))
+                })
+                .collect();
+            if violations.is_empty() {
+                violations.push(Violation::new(cargo_toml, message.clone()));
+            }
+            for violation in violations {
+                let mut violation = violation
+                    .for_lint("deprecation-guard")
+                    .suggesting(suggestion);
+                if policy.advisory {
+                    violation = violation.advisory();
+                }
+                report.record(&violation);
+            }
+        }
+        if grew && policy.advisory {
+            eprintln!(
+                "{} is in an advisory tier, so this won't block the commit.",
+                name
+            );
+        } else if grew {
+            summary.violations += 1;
+            if update_anyway {
+                baseline.crates.insert(name, observed);
+                updated = true;
+            }
+        } else if observed != expected {
+            let (was, is): (usize, usize) = (expected.values().sum(), observed.values().sum());
+            say!(
+                "Thanks for moving {} off deprecated items!  It now uses them {} time(s), down \
+                 from {}",
+                name,
+                is,
+                was
+            );
+            baseline.crates.insert(name, observed);
+            updated = true;
+        }
+    }
+
+    if updated {
+        save_baseline(Path::new(BASELINE), &baseline).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+    }
+    let exit_code = if summary.violations > 0 {
+        1
+    } else if updated {
+        say!("Please run `git add {}` and retry your commit.", BASELINE);
+        2
+    } else {
+        0
+    };
+    report.finish(&summary);
+    summary.print();
+    process::exit(exit_code);
+}
@@ -0,0 +1,176 @@
+//! `salt-derive-guard` makes sure that new public structs and enums derive the
+//! traits your team has agreed every type should have (just Debug by default,
+//! see the `[derive-guard]` section of salt-spray.toml).
+//!
+//! Types that already existed at HEAD are left alone, so adopting the hook doesn't
+//! mean fixing the whole codebase at once.  A new type can also opt out with a
+//! comment containing the configured marker (`salt-derive-guard: skip` by default)
+//! just above it.
+
+#![forbid(unsafe_code)]
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use syn::spanned::Spanned;
+use syn::{Attribute, Ident, Item};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::{Span, Violation};
+use salt_spray::config::Config;
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::{find_manifest, parse_source, public_types_in, read_committed};
+
+/// A public struct or enum, along with what it derives.
+struct PublicType {
+    /// e.g. "pub struct foo::Bar"
+    description: String,
+    derives: BTreeSet<String>,
+    /// The lines (1-based, inclusive) from the type's first attribute to its name.
+    lines: (usize, usize),
+    /// Where its name is.
+    span: Span,
+}
+
+/// Returns the names of everything the attributes derive.
+fn derives_of(attrs: &[Attribute]) -> BTreeSet<String> {
+    let mut result = BTreeSet::new();
+    for attr in attrs.iter().filter(|a| a.path.is_ident("derive")) {
+        if let Ok(syn::Meta::List(list)) = attr.parse_meta() {
+            for nested in list.nested {
+                if let syn::NestedMeta::Meta(syn::Meta::Path(path)) = nested {
+                    // Only the last segment matters for e.g. serde::Serialize
+                    if let Some(segment) = path.segments.last() {
+                        result.insert(segment.ident.to_string());
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+fn public_type(
+    kind: &str,
+    prefix: &str,
+    item: &Item,
+    ident: &Ident,
+    attrs: &[Attribute],
+) -> PublicType {
+    PublicType {
+        description: format!("pub {} {}{}", kind, prefix, ident),
+        derives: derives_of(attrs),
+        lines: (item.span().start().line, ident.span().start().line),
+        span: ident.span().into(),
+    }
+}
+
+/// The file's public structs and enums, or None if it doesn't parse.
+fn types_in(contents: &str) -> Option<Vec<PublicType>> {
+    let ast = parse_source(contents).ok()?;
+    let types = public_types_in(&ast.items)
+        .into_iter()
+        .filter_map(|(prefix, item)| match item {
+            Item::Struct(s) => Some(public_type("struct", &prefix, item, &s.ident, &s.attrs)),
+            Item::Enum(e) => Some(public_type("enum", &prefix, item, &e.ident, &e.attrs)),
+            _ => None,
+        });
+    Some(types.collect())
+}
+
+/// Checks one file, returning the violations found.
+fn check_file(filename: &str, config: &Config) -> Vec<Violation> {
+    let contents = match fs::read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(e) => {
+            return vec![Violation::new(
+                filename,
+                format!("Unable to read {}: {}", filename, e),
+            )]
+        }
+    };
+    let types = match types_in(&contents) {
+        Some(types) => types,
+        None => {
+            eprintln!("Unable to parse {}, skipping it", filename);
+            return Vec::new();
+        }
+    };
+    let existing: BTreeSet<String> = read_committed("HEAD", filename)
+        .and_then(|old| types_in(&old))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|t| t.description)
+        .collect();
+
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut result = Vec::new();
+    for t in types.iter().filter(|t| !existing.contains(&t.description)) {
+        let (first, last) = t.lines;
+        // The marker may be on the line above the type or anywhere in its attributes
+        let opted_out = lines
+            .get(first.saturating_sub(2)..last.min(lines.len()))
+            .map(|ls| ls.iter().any(|l| l.contains(&config.derive_guard.marker)))
+            .unwrap_or(false);
+        if opted_out {
+            continue;
+        }
+        let missing: Vec<&str> = config
+            .derive_guard
+            .required
+            .iter()
+            .filter(|r| !t.derives.contains(*r))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            let message = format!("{} must derive {}", t.description, missing.join(", "));
+            let violation = Violation::new(filename, message)
+                .at(t.span)
+                .for_lint("derive-guard")
+                .suggesting(format!(
+                    "add the missing derives, or a `// {}` comment if it can't have them",
+                    config.derive_guard.marker
+                ));
+            result.push(violation);
+        }
+    }
+    result
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-derive-guard");
+    let mut summary = Summary::new("salt-derive-guard");
+    let known = [PORCELAIN, OUTPUT, COLOR];
+    let (flags, files) = parse_args("salt-derive-guard", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut report = Report::from_flags("salt-derive-guard", &flags, &config).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+
+    let mut crates = BTreeSet::new();
+    for arg in files {
+        if arg.ends_with(".rs") && !config.is_excluded(Path::new(&arg)) {
+            let violations = check_file(&arg, &config);
+            report.record_all(&violations);
+            summary.violations += violations.len();
+            summary.count_lints(&violations);
+            crates.extend(find_manifest(&arg));
+            summary.files += 1;
+        }
+    }
+    summary.crates = crates.len();
+    report.finish(&summary);
+    summary.print();
+    process::exit(summary.exit_code());
+}
@@ -0,0 +1,245 @@
+//! `salt-doc-example-guard` nudges library crates toward example-driven docs.
+//! In the library files of a commit, each new public function, method (of an
+//! inherent impl), struct, enum, union, trait, and type alias needs an example in
+//! its doc comment: a fenced block of Rust, the kind rustdoc runs as a doctest, e.g.
+//!
+//! ````rust
+//! /// Adds two numbers.
+//! ///
+//! /// ```
+//! /// assert_eq!(my_crate::add(1, 2), 3);
+//! /// ```
+//! pub fn add(a: u64, b: u64) -> u64 {
+//!     a + b
+//! }
+//! ````
+//!
+//! Blocks fenced as some other language (e.g. ```` ```text ````) don't count, but
+//! the ones rustdoc won't run (` ```ignore `, ` ```no_run `, and so on) do.  Items
+//! that already existed at HEAD are left alone, and so are `#[doc(hidden)]` ones,
+//! test code, and items whose docs come from elsewhere (e.g.
+//! `#[doc = include_str!("...")]`), since what's in those can't be seen.
+//!
+//! It only checks the crates whose policy asks for it, e.g.
+//!
+//! ```toml
+//! [tier-policy.tier1]
+//! require-doc-examples = true
+//! ```
+//!
+//! or with `require-doc-examples = true` in the `[policy]` of a crate's own config.
+//! Crates in advisory tiers get told about missing examples, but they don't fail
+//! the commit.
+
+#![forbid(unsafe_code)]
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use syn::{Attribute, Lit, Meta, NestedMeta};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::{Span, Violation};
+use salt_spray::config::Config;
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::{
+    attrs_of, find_manifest, is_test_code, parse_source, public_items_in, read_committed,
+    target_kind,
+};
+
+/// The words a fence's info string can have that still leave it Rust, as far as
+/// rustdoc is concerned.
+static RUSTDOC_ATTRIBUTES: [&str; 7] = [
+    "rust",
+    "ignore",
+    "should_panic",
+    "no_run",
+    "compile_fail",
+    "test_harness",
+    "standalone_crate",
+];
+
+/// A public item, along with whether its docs have an example.
+struct PublicItem {
+    /// e.g. "pub fn foo::Bar::parse"
+    description: String,
+    /// None when the docs come from somewhere that can't be looked at.
+    has_example: Option<bool>,
+    /// Where its name is.
+    span: Span,
+}
+
+/// Is this test code, or hidden from the docs?
+fn is_skipped(attrs: &[Attribute]) -> bool {
+    is_test_code(attrs)
+        || attrs
+            .iter()
+            .filter(|a| a.path.is_ident("doc"))
+            .any(|attr| match attr.parse_meta() {
+                Ok(Meta::List(list)) => list
+                    .nested
+                    .iter()
+                    .any(|n| matches!(n, NestedMeta::Meta(Meta::Path(p)) if p.is_ident("hidden"))),
+                _ => false,
+            })
+}
+
+/// Would rustdoc take a block fenced with this info string (what follows the
+/// backticks) for Rust?
+fn is_rust_fence(info: &str) -> bool {
+    info.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .all(|word| {
+            let error_code = word.len() == 5
+                && word.starts_with('E')
+                && word[1..].chars().all(|c| c.is_ascii_digit());
+            RUSTDOC_ATTRIBUTES.contains(&word) || word.starts_with("edition") || error_code
+        })
+}
+
+/// Does the doc comment have a Rust code block in it?
+fn has_example(docs: &str) -> bool {
+    let mut fence: Option<&str> = None;
+    for line in docs.lines().map(str::trim_start) {
+        let marker = ["```", "~~~"].into_iter().find(|m| line.starts_with(*m));
+        match (fence, marker) {
+            (None, Some(marker)) if is_rust_fence(line.trim_start_matches(&marker[..1])) => {
+                return true;
+            }
+            (None, Some(marker)) => fence = Some(marker),
+            (Some(open), Some(marker)) if open == marker => fence = None,
+            _ => (),
+        }
+    }
+    false
+}
+
+/// Whether the doc comment the attributes make up has an example, or None if
+/// any of it comes from somewhere else.
+fn example_in(attrs: &[Attribute]) -> Option<bool> {
+    let mut docs = String::new();
+    for attr in attrs.iter().filter(|a| a.path.is_ident("doc")) {
+        match attr.parse_meta() {
+            Ok(Meta::NameValue(nv)) => match nv.lit {
+                Lit::Str(s) => {
+                    docs.push_str(&s.value());
+                    docs.push('\n');
+                }
+                _ => return None,
+            },
+            Ok(_) => (),
+            // e.g. #[doc = include_str!("README.md")]
+            Err(_) => return None,
+        }
+    }
+    Some(has_example(&docs))
+}
+
+/// The kinds of public items that should have an example.
+const EXEMPLIFIED: [&str; 6] = ["fn", "struct", "enum", "union", "trait", "type"];
+
+/// The file's public items that should have an example, or None if it doesn't
+/// parse.
+fn items_in(contents: &str) -> Option<Vec<PublicItem>> {
+    let ast = parse_source(contents).ok()?;
+    let items = public_items_in(&ast.items)
+        .into_iter()
+        .filter(|item| EXEMPLIFIED.contains(&item.kind) && !is_skipped(item.attrs))
+        .filter(|item| !item.within.iter().any(|i| is_skipped(attrs_of(i))))
+        .map(|item| PublicItem {
+            description: item.description,
+            has_example: example_in(item.attrs),
+            span: item.span,
+        });
+    Some(items.collect())
+}
+
+/// Checks one file, returning the new public items without examples.
+fn check_file(filename: &str) -> Vec<Violation> {
+    let contents = match fs::read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(e) => {
+            return vec![Violation::new(
+                filename,
+                format!("Unable to read {}: {}", filename, e),
+            )]
+        }
+    };
+    let items = match items_in(&contents) {
+        Some(items) => items,
+        None => {
+            eprintln!("Unable to parse {}, skipping it", filename);
+            return Vec::new();
+        }
+    };
+    let existing: BTreeSet<String> = read_committed("HEAD", filename)
+        .and_then(|old| items_in(&old))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|i| i.description)
+        .collect();
+    items
+        .into_iter()
+        .filter(|i| i.has_example == Some(false) && !existing.contains(&i.description))
+        .map(|i| {
+            Violation::new(
+                filename,
+                format!("{} has no example in its docs", i.description),
+            )
+            .at(i.span)
+            .for_lint("doc-example-guard")
+            .suggesting("add a ``` block to its doc comment showing how it's used")
+        })
+        .collect()
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-doc-example-guard");
+    let mut summary = Summary::new("salt-doc-example-guard");
+    let known = [PORCELAIN, OUTPUT, COLOR];
+    let (flags, files) = parse_args("salt-doc-example-guard", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut report =
+        Report::from_flags("salt-doc-example-guard", &flags, &config).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+
+    let mut crates = BTreeSet::new();
+    for file in files.iter().filter(|f| f.ends_with(".rs")) {
+        let manifest = match find_manifest(file) {
+            Some(manifest) => manifest,
+            None => continue,
+        };
+        if config.is_excluded(Path::new(file)) || target_kind(file) != Some("lib") {
+            continue;
+        }
+        let policy = config.policy_for(&manifest);
+        if !policy.require_doc_examples {
+            continue;
+        }
+        summary.files += 1;
+        crates.insert(manifest);
+        let mut violations = check_file(file);
+        if policy.advisory {
+            violations = violations.into_iter().map(Violation::advisory).collect();
+        }
+        report.record_all(&violations);
+        summary.violations += violations.iter().filter(|v| !v.advisory).count();
+        summary.count_lints(&violations);
+    }
+    summary.crates = crates.len();
+    report.finish(&summary);
+    summary.print();
+    process::exit(summary.exit_code());
+}
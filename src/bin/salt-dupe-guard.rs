@@ -0,0 +1,277 @@
+//! `salt-dupe-guard` catches copy-pasted code, so that the copies in a big repo
+//! don't drift apart as each gets fixed separately.  Each new function in the
+//! files of a commit (one that wasn't in its file at HEAD) is compared with every
+//! other function (and method) in its workspace, and rejected if it's too much like
+//! one of them.
+//!
+//! The comparison is of tokens, not text, so a copy that's been reformatted or had
+//! its variables renamed still counts, see `salt_spray::dupe`.  Test code is left
+//! alone, since tests are meant to be repetitive, and so are the files of test,
+//! bench, and example targets.
+//!
+//! ```toml
+//! [dupe-guard]
+//! threshold = 0.8          # how alike, from 0 to 1, is too alike
+//! min-tokens = 50          # shorter functions are left alone
+//! allow = ["*::fmt"]       # globs of the functions that may look like others
+//! advisory = false
+//! ```
+//!
+//! Crates whose policy is advisory (or every crate, with `advisory = true`) get
+//! told about duplicates, but they don't fail the commit.
+
+#![forbid(unsafe_code)]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use quote::ToTokens;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use syn::{Block, ImplItem, Item, Signature, TraitItem};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::{Span, Violation};
+use salt_spray::config::{glob_matches, Config, DupeGuardConfig};
+use salt_spray::dupe::Fingerprints;
+use salt_spray::jobs::{self, JOBS};
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::{
+    find_manifest, find_workspace_root, is_test_code, parse_source, read_committed, repo_relative,
+    rust_files_in, target_kind,
+};
+
+/// A function (or method), boiled down.
+struct Function {
+    /// e.g. "parse" or "Parser::parse"
+    name: String,
+    file: String,
+    /// Where its name is.
+    span: Span,
+    code: Fingerprints,
+}
+
+impl Function {
+    fn is(&self, other: &Function) -> bool {
+        self.file == other.file && self.span == other.span
+    }
+}
+
+/// Walks the items (and inline modules) boiling down every function and method
+/// other than tests.
+struct Collector<'a> {
+    file: &'a str,
+    functions: Vec<Function>,
+}
+
+impl Collector<'_> {
+    fn push(&mut self, name: String, sig: &Signature, block: &Block) {
+        let mut tokens = sig.to_token_stream();
+        block.to_tokens(&mut tokens);
+        self.functions.push(Function {
+            name,
+            file: self.file.to_string(),
+            span: sig.ident.span().into(),
+            code: Fingerprints::of(tokens),
+        });
+    }
+
+    fn collect(&mut self, prefix: &str, items: &[Item]) {
+        for item in items {
+            match item {
+                Item::Fn(f) if !is_test_code(&f.attrs) => {
+                    let name = format!("{}{}", prefix, f.sig.ident);
+                    self.push(name, &f.sig, &f.block);
+                }
+                Item::Impl(i) if !is_test_code(&i.attrs) => {
+                    let ty = i.self_ty.to_token_stream().to_string().replace(' ', "");
+                    for item in i.items.iter() {
+                        if let ImplItem::Method(m) = item {
+                            if !is_test_code(&m.attrs) {
+                                let name = format!("{}{}::{}", prefix, ty, m.sig.ident);
+                                self.push(name, &m.sig, &m.block);
+                            }
+                        }
+                    }
+                }
+                Item::Trait(t) if !is_test_code(&t.attrs) => {
+                    for item in t.items.iter() {
+                        if let TraitItem::Method(m) = item {
+                            if let (Some(block), false) = (&m.default, is_test_code(&m.attrs)) {
+                                let name = format!("{}{}::{}", prefix, t.ident, m.sig.ident);
+                                self.push(name, &m.sig, block);
+                            }
+                        }
+                    }
+                }
+                Item::Mod(m) if !is_test_code(&m.attrs) => {
+                    if let Some((_, items)) = &m.content {
+                        self.collect(&format!("{}{}::", prefix, m.ident), items);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+fn functions_in(file: &str, contents: &str) -> Option<Vec<Function>> {
+    let ast = parse_source(contents).ok()?;
+    let mut collector = Collector {
+        file,
+        functions: Vec::new(),
+    };
+    collector.collect("", &ast.items);
+    Some(collector.functions)
+}
+
+/// Is the file part of a test, bench, or example target?
+fn is_test_target<P: AsRef<Path> + ?Sized>(file: &P) -> bool {
+    matches!(target_kind(file), Some("test" | "bench" | "example"))
+}
+
+/// Every function in the workspace (outside of test targets), parsed `--jobs=`
+/// files at a time.
+fn functions_under(workspace: &Path, config: &Config) -> Vec<Function> {
+    let mut files = rust_files_in(workspace);
+    files.retain(|f| !config.is_excluded(f) && !is_test_target(f));
+    let parse = |path: &PathBuf| {
+        let file = repo_relative(path).to_string_lossy().replace('\\', "/");
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| functions_in(&file, &contents))
+            .unwrap_or_default()
+    };
+    let functions: Vec<Vec<Function>> =
+        match ThreadPoolBuilder::new().num_threads(jobs::jobs()).build() {
+            Ok(pool) => pool.install(|| files.par_iter().map(parse).collect()),
+            // Without threads it's slower, but no different
+            Err(_) => files.iter().map(parse).collect(),
+        };
+    functions.into_iter().flatten().collect()
+}
+
+/// Checks one file's new functions against the rest of the workspace.
+fn check_file(filename: &str, others: &[Function], settings: &DupeGuardConfig) -> Vec<Violation> {
+    let file = repo_relative(filename).to_string_lossy().replace('\\', "/");
+    let contents = match fs::read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(e) => {
+            return vec![Violation::new(
+                filename,
+                format!("Unable to read {}: {}", filename, e),
+            )]
+        }
+    };
+    let functions = match functions_in(&file, &contents) {
+        Some(functions) => functions,
+        None => {
+            eprintln!("Unable to parse {}, skipping it", filename);
+            return Vec::new();
+        }
+    };
+    let existing: BTreeSet<String> = read_committed("HEAD", filename)
+        .and_then(|old| functions_in(&file, &old))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|f| f.name)
+        .collect();
+    let mut result = Vec::new();
+    for function in functions.iter() {
+        let allowed = settings
+            .allow
+            .iter()
+            .any(|glob| glob_matches(glob, Path::new(&function.name)));
+        if function.code.tokens < settings.min_tokens
+            || allowed
+            || existing.contains(&function.name)
+        {
+            continue;
+        }
+        // Its own copy in the workspace is the one that was just parsed
+        let closest = others
+            .iter()
+            .filter(|other| !other.is(function) && other.code.tokens >= settings.min_tokens)
+            .map(|other| (function.code.similarity(&other.code), other))
+            .filter(|(similarity, _)| *similarity >= settings.threshold)
+            .max_by(|(a, _), (b, _)| a.total_cmp(b));
+        if let Some((similarity, other)) = closest {
+            let message = format!(
+                "`{}` is {:.0}% like `{}` in {}:{}",
+                function.name,
+                similarity * 100.0,
+                other.name,
+                other.file,
+                other.span.line
+            );
+            result.push(
+                Violation::new(filename, message)
+                    .at(function.span)
+                    .for_lint("dupe-guard")
+                    .suggesting(
+                        "call it (or pull out what they share) instead, or add the function to \
+                         [dupe-guard] allow",
+                    ),
+            );
+        }
+    }
+    result
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-dupe-guard");
+    let mut summary = Summary::new("salt-dupe-guard");
+    let known = [PORCELAIN, OUTPUT, COLOR, JOBS];
+    let (flags, files) = parse_args("salt-dupe-guard", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let settings = &config.dupe_guard;
+    if let Some(jobs) = flags.iter().find_map(|f| f.strip_prefix(JOBS)) {
+        jobs::set_jobs(jobs).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+    }
+    let mut report = Report::from_flags("salt-dupe-guard", &flags, &config).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+
+    // Each workspace only gets parsed once, however many of its files are committed
+    let mut workspaces: BTreeMap<PathBuf, Vec<Function>> = BTreeMap::new();
+    let mut crates = BTreeSet::new();
+    for file in files.iter().filter(|f| f.ends_with(".rs")) {
+        let manifest = match find_manifest(file) {
+            Some(manifest) => manifest,
+            None => continue,
+        };
+        if config.is_excluded(Path::new(file)) || is_test_target(file) {
+            continue;
+        }
+        summary.files += 1;
+        let others = workspaces
+            .entry(find_workspace_root(&manifest))
+            .or_insert_with_key(|workspace| functions_under(workspace, &config));
+        let mut problems = check_file(file, others, settings);
+        if settings.advisory || config.policy_for(&manifest).advisory {
+            problems = problems.into_iter().map(Violation::advisory).collect();
+        }
+        crates.insert(manifest);
+        report.record_all(&problems);
+        summary.violations += problems.iter().filter(|p| !p.advisory).count();
+        summary.count_lints(&problems);
+    }
+    summary.crates = crates.len();
+    report.finish(&summary);
+    summary.print();
+    process::exit(summary.exit_code());
+}
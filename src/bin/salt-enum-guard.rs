@@ -0,0 +1,197 @@
+//! `salt-enum-guard` makes sure that new public enums in library crates are
+//! `#[non_exhaustive]`, so that adding a variant to one later isn't a breaking
+//! change.  The same goes for new public structs that other crates can build
+//! (those whose fields are all public), since they can't gain fields otherwise.
+//!
+//! Types that already existed at HEAD are left alone, so adopting the hook doesn't
+//! mean fixing the whole codebase at once.  A new type that's exhaustive on purpose
+//! (e.g. `Ordering`-like enums that will never grow) can opt out with a comment
+//! containing the configured marker just above it:
+//!
+//! ```toml
+//! [enum-guard]
+//! structs = true                         # structs too, not just enums
+//! publishable-only = false               # or leave publish = false crates alone
+//! marker = "salt-enum-guard: exhaustive"
+//! advisory = false
+//! ```
+//!
+//! Crates whose policy is advisory (or every crate, with `advisory = true`) get
+//! told about exhaustive types, but they don't fail the commit.
+
+#![forbid(unsafe_code)]
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use syn::spanned::Spanned;
+use syn::{Attribute, Fields, Ident, Item};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::{Span, Violation};
+use salt_spray::config::{Config, EnumGuardConfig};
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::{
+    find_manifest, is_public, is_publishable, parse_source, public_types_in, read_committed,
+    read_manifest, target_kind,
+};
+
+/// A public enum, or a public struct that other crates can build.
+struct PublicType {
+    /// e.g. "pub enum foo::Bar"
+    description: String,
+    non_exhaustive: bool,
+    /// The lines (1-based, inclusive) from the type's first attribute to its name.
+    lines: (usize, usize),
+    /// Where its name is.
+    span: Span,
+}
+
+fn is_non_exhaustive(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|a| a.path.is_ident("non_exhaustive"))
+}
+
+/// Can code outside of the crate build the struct, i.e. are all of its fields
+/// public?
+fn is_buildable(fields: &Fields) -> bool {
+    fields.iter().all(|f| is_public(&f.vis))
+}
+
+fn public_type(
+    kind: &str,
+    prefix: &str,
+    item: &Item,
+    ident: &Ident,
+    attrs: &[Attribute],
+) -> PublicType {
+    PublicType {
+        description: format!("pub {} {}{}", kind, prefix, ident),
+        non_exhaustive: is_non_exhaustive(attrs),
+        lines: (item.span().start().line, ident.span().start().line),
+        span: ident.span().into(),
+    }
+}
+
+/// The file's types that the policy covers, or None if it doesn't parse.
+fn types_in(contents: &str, settings: &EnumGuardConfig) -> Option<Vec<PublicType>> {
+    let ast = parse_source(contents).ok()?;
+    let types = public_types_in(&ast.items)
+        .into_iter()
+        .filter_map(|(prefix, item)| match item {
+            Item::Enum(e) => Some(public_type("enum", &prefix, item, &e.ident, &e.attrs)),
+            Item::Struct(s) if settings.structs && is_buildable(&s.fields) => {
+                Some(public_type("struct", &prefix, item, &s.ident, &s.attrs))
+            }
+            _ => None,
+        });
+    Some(types.collect())
+}
+
+/// Is the file part of a library that the policy covers?
+fn is_covered(filename: &str, settings: &EnumGuardConfig) -> bool {
+    let manifest = match find_manifest(filename) {
+        Some(manifest) => manifest,
+        None => return false,
+    };
+    // Crates that are only binaries fall back to the lib kind for their modules
+    let has_lib = manifest.with_file_name("src").join("lib.rs").exists()
+        || read_manifest(&manifest).is_some_and(|t| t.contains_key("lib"));
+    target_kind(filename) == Some("lib")
+        && has_lib
+        && (!settings.publishable_only || is_publishable(&manifest))
+}
+
+/// Checks one file, returning its problems.
+fn check_file(filename: &str, settings: &EnumGuardConfig) -> Vec<Violation> {
+    let contents = match fs::read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(e) => {
+            return vec![Violation::new(
+                filename,
+                format!("Unable to read {}: {}", filename, e),
+            )]
+        }
+    };
+    let types = match types_in(&contents, settings) {
+        Some(types) => types,
+        None => {
+            eprintln!("Unable to parse {}, skipping it", filename);
+            return Vec::new();
+        }
+    };
+    let existing: BTreeSet<String> = read_committed("HEAD", filename)
+        .and_then(|old| types_in(&old, settings))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|t| t.description)
+        .collect();
+
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut result = Vec::new();
+    for t in types.iter().filter(|t| !existing.contains(&t.description)) {
+        let (first, last) = t.lines;
+        // The marker may be on the line above the type or anywhere in its attributes
+        let opted_out = lines
+            .get(first.saturating_sub(2)..last.min(lines.len()))
+            .map(|ls| ls.iter().any(|l| l.contains(&settings.marker)))
+            .unwrap_or(false);
+        if !t.non_exhaustive && !opted_out {
+            let message = format!(
+                "{} must be #[non_exhaustive], so that it can grow without breaking its users",
+                t.description
+            );
+            let violation = Violation::new(filename, message)
+                .at(t.span)
+                .for_lint("enum-guard")
+                .suggesting(format!(
+                    "add #[non_exhaustive] above it, or a `// {}` comment if it's exhaustive on purpose",
+                    settings.marker
+                ));
+            result.push(violation);
+        }
+    }
+    result
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-enum-guard");
+    let mut summary = Summary::new("salt-enum-guard");
+    let known = [PORCELAIN, OUTPUT, COLOR];
+    let (flags, files) = parse_args("salt-enum-guard", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let settings = &config.enum_guard;
+    let mut report = Report::from_flags("salt-enum-guard", &flags, &config).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+
+    let mut crates = BTreeSet::new();
+    for file in files.iter().filter(|f| f.ends_with(".rs")) {
+        if config.is_excluded(Path::new(file)) || !is_covered(file, settings) {
+            continue;
+        }
+        summary.files += 1;
+        crates.extend(find_manifest(file));
+        let mut problems = check_file(file, settings);
+        if settings.advisory || config.policy_for_file(Path::new(file)).advisory {
+            problems = problems.into_iter().map(Violation::advisory).collect();
+        }
+        report.record_all(&problems);
+        summary.violations += problems.iter().filter(|p| !p.advisory).count();
+        summary.count_lints(&problems);
+    }
+    summary.crates = crates.len();
+    report.finish(&summary);
+    summary.print();
+    process::exit(summary.exit_code());
+}
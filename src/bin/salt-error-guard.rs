@@ -0,0 +1,491 @@
+//! `salt-error-guard` holds library crates to their error handling conventions.
+//! In the library files of a commit, the public functions (and the methods of
+//! public traits and inherent impls) that are part of the crate's API, i.e. that
+//! aren't in a private module,
+//!
+//! - that return a `Result` have to return it with the crate's own error type,
+//!   once `error-type` names one (the crate's own `Result<T>` alias will do too),
+//! - can't have `Box<dyn Error>` anywhere in their signatures, since callers can't
+//!   match on what went wrong, and
+//! - in crates that are `strict`, can't use `anyhow::Result` or `anyhow::Error`,
+//!   which are for applications rather than libraries.
+//!
+//! Each crate has its own error type, so it's mostly set in the crate's own
+//! salt-spray.toml:
+//!
+//! ```toml
+//! [error-guard]
+//! error-type = "crate::Error"
+//! strict = true
+//! doc = "docs/errors.md"  # where the conventions are, for the problems to point to
+//! advisory = false
+//! ```
+//!
+//! Functions that already broke a convention at HEAD are left alone, so adopting
+//! the hook doesn't mean fixing the whole codebase at once.  Crates whose policy is
+//! advisory (or every crate, with `advisory = true`) get told about problems, but
+//! they don't fail the commit.
+
+#![forbid(unsafe_code)]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use quote::ToTokens;
+use syn::spanned::Spanned;
+use syn::visit::{self, Visit};
+use syn::{
+    GenericArgument, ImplItem, Item, PathArguments, ReturnType, Signature, TraitItem, Type,
+    TypeParamBound, TypePath, UseTree,
+};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::{Span, Violation};
+use salt_spray::config::{Config, ErrorGuardConfig};
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::{
+    child_dir, find_manifest, is_public, library_of, module_file, parse_source, read_committed,
+    target_kind,
+};
+
+/// A public function breaking one of the conventions.
+struct Problem {
+    /// e.g. "pub fn foo::Bar::parse"
+    function: String,
+    /// Which convention, e.g. "error-type".
+    convention: &'static str,
+    message: String,
+    suggestion: String,
+    /// Where the type at fault is.
+    span: Span,
+}
+
+impl Problem {
+    /// What identifies the problem across versions of the file.
+    fn key(&self) -> (String, &'static str) {
+        (self.function.clone(), self.convention)
+    }
+}
+
+/// The type as it would be written, more or less.
+fn tidy<T: ToTokens>(t: &T) -> String {
+    let mut result = t.to_token_stream().to_string();
+    for (spaced, tight) in [(" :: ", "::"), (":: ", "::"), (" <", "<"), ("< ", "<")] {
+        result = result.replace(spaced, tight);
+    }
+    result
+        .replace(" >", ">")
+        .replace(" ,", ",")
+        .replace("& ", "&")
+}
+
+/// The path of a type without its generics, e.g. `crate::error::Error`.
+fn bare_path(ty: &TypePath) -> String {
+    let segments: Vec<String> = ty
+        .path
+        .segments
+        .iter()
+        .map(|s| s.ident.to_string())
+        .collect();
+    segments.join("::")
+}
+
+/// The names that the file brings in from anyhow, and whether it brings in all of
+/// them with a glob.
+#[derive(Default)]
+struct AnyhowImports {
+    names: BTreeSet<String>,
+    glob: bool,
+}
+
+impl AnyhowImports {
+    fn collect(tree: &UseTree, from_anyhow: bool, result: &mut AnyhowImports) {
+        match tree {
+            UseTree::Path(p) => {
+                Self::collect(&p.tree, from_anyhow || p.ident == "anyhow", result);
+            }
+            UseTree::Name(n) if from_anyhow => {
+                result.names.insert(n.ident.to_string());
+            }
+            UseTree::Rename(r) if from_anyhow => {
+                result.names.insert(r.rename.to_string());
+            }
+            UseTree::Glob(_) if from_anyhow => result.glob = true,
+            UseTree::Group(g) => {
+                for tree in g.items.iter() {
+                    Self::collect(tree, from_anyhow, result);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Is the type anyhow's, whether it's spelled out or imported?
+    fn is_anyhow(&self, ty: &TypePath) -> bool {
+        let segments = &ty.path.segments;
+        match segments.first() {
+            Some(first) if segments.len() > 1 => first.ident == "anyhow",
+            Some(only) => {
+                let name = only.ident.to_string();
+                self.names.contains(&name) || (self.glob && (name == "Result" || name == "Error"))
+            }
+            None => false,
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for AnyhowImports {
+    fn visit_item_use(&mut self, node: &'ast syn::ItemUse) {
+        Self::collect(&node.tree, false, self);
+    }
+}
+
+/// Finds the types in a signature that break the conventions other than the
+/// error type of its `Result`.
+struct SignatureVisitor<'a> {
+    anyhow: &'a AnyhowImports,
+    strict: bool,
+    /// The `Box<dyn Error>`s.
+    boxed: Vec<&'a TypePath>,
+    /// anyhow's types, in strict crates.
+    anyhow_types: Vec<&'a TypePath>,
+}
+
+/// Is the type a boxed trait object with `Error` among its traits?
+fn is_boxed_error(ty: &TypePath) -> bool {
+    let last = match ty.path.segments.last() {
+        Some(last) if last.ident == "Box" => last,
+        _ => return false,
+    };
+    let args = match &last.arguments {
+        PathArguments::AngleBracketed(args) => &args.args,
+        _ => return false,
+    };
+    args.iter().any(|arg| match arg {
+        GenericArgument::Type(Type::TraitObject(object)) => object.bounds.iter().any(|b| {
+            matches!(b, TypeParamBound::Trait(t)
+                if t.path.segments.last().is_some_and(|s| s.ident == "Error"))
+        }),
+        _ => false,
+    })
+}
+
+impl<'a> Visit<'a> for SignatureVisitor<'a> {
+    fn visit_type_path(&mut self, node: &'a TypePath) {
+        if is_boxed_error(node) {
+            self.boxed.push(node);
+        } else if self.strict && self.anyhow.is_anyhow(node) {
+            self.anyhow_types.push(node);
+        }
+        visit::visit_type_path(self, node);
+    }
+}
+
+/// The error type a `Result` return type has, if it's a `Result` at all: None for
+/// the crate's own `Result<T>` alias, otherwise its path as written (or the path
+/// of the alias, e.g. `io::Result` for some other crate's).
+fn result_error(ty: &Type) -> Option<Option<String>> {
+    let path = match ty {
+        Type::Path(path) => path,
+        _ => return None,
+    };
+    let last = path.path.segments.last().filter(|s| s.ident == "Result")?;
+    let types: Vec<&Type> = match &last.arguments {
+        PathArguments::AngleBracketed(args) => args
+            .args
+            .iter()
+            .filter_map(|a| match a {
+                GenericArgument::Type(t) => Some(t),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    let foreign = path.path.segments.len() > 1
+        && !["crate", "self", "super"].contains(&path.path.segments[0].ident.to_string().as_str());
+    match types.get(1) {
+        Some(Type::Path(error)) => Some(Some(bare_path(error))),
+        Some(error) => Some(Some(tidy(error))),
+        None if foreign => Some(Some(bare_path(path))),
+        None => Some(None),
+    }
+}
+
+/// Is the error type (as written) the designated one?  It may be written without
+/// the modules it's in, e.g. `Error` for `crate::error::Error` (where it's been
+/// imported).
+fn is_designated(error: &str, designated: &str) -> bool {
+    let local = |mut path: &str| {
+        for prefix in ["crate::", "self::", "super::"] {
+            path = path.trim_start_matches(prefix);
+        }
+        path.to_string()
+    };
+    let (error, designated) = (local(error), local(designated));
+    error == designated || designated.ends_with(&format!("::{}", error))
+}
+
+struct Checker<'a> {
+    settings: &'a ErrorGuardConfig,
+    anyhow: &'a AnyhowImports,
+    /// ", see docs/errors.md", or nothing without a doc.
+    see: String,
+    problems: Vec<Problem>,
+}
+
+impl Checker<'_> {
+    fn check_signature(&mut self, function: String, sig: &Signature) {
+        let mut visitor = SignatureVisitor {
+            anyhow: self.anyhow,
+            strict: self.settings.strict,
+            boxed: Vec::new(),
+            anyhow_types: Vec::new(),
+        };
+        visitor.visit_signature(sig);
+        if let Some(boxed) = visitor.boxed.first() {
+            self.problems.push(Problem {
+                function: function.clone(),
+                convention: "box-dyn-error",
+                message: format!(
+                    "{} has {} in its signature, which its callers can't match on",
+                    function,
+                    tidy(boxed)
+                ),
+                suggestion: format!("use the crate's own error type instead{}", self.see),
+                span: boxed.span().into(),
+            });
+        }
+        if let Some(anyhow) = visitor.anyhow_types.first() {
+            self.problems.push(Problem {
+                function: function.clone(),
+                convention: "anyhow",
+                message: format!(
+                    "{} uses {}, but anyhow is for applications and this is a strict library",
+                    function,
+                    tidy(anyhow)
+                ),
+                suggestion: format!("use the crate's own error type instead{}", self.see),
+                span: anyhow.span().into(),
+            });
+            // The anyhow::Result is reported already, whatever its error type
+            return;
+        }
+        let designated = match &self.settings.error_type {
+            Some(designated) => designated,
+            None => return,
+        };
+        let output = match &sig.output {
+            ReturnType::Type(_, output) => output,
+            ReturnType::Default => return,
+        };
+        // A Box<dyn Error> is reported already, wherever it is
+        if let Some(Some(error)) = result_error(output) {
+            if !is_designated(&error, designated) && visitor.boxed.is_empty() {
+                self.problems.push(Problem {
+                    function: function.clone(),
+                    convention: "error-type",
+                    message: format!(
+                        "{} returns {}, but the crate's errors are {}",
+                        function,
+                        tidy(output),
+                        designated
+                    ),
+                    suggestion: format!(
+                        "return Result<_, {}> or the crate's own Result<_> alias{}",
+                        designated, self.see
+                    ),
+                    span: output.span().into(),
+                });
+            }
+        }
+    }
+
+    /// Walks the items (and inline modules) checking every public function.
+    fn check_items(&mut self, prefix: &str, items: &[Item]) {
+        for item in items {
+            match item {
+                Item::Fn(f) if is_public(&f.vis) => {
+                    self.check_signature(format!("pub fn {}{}", prefix, f.sig.ident), &f.sig);
+                }
+                // Trait impls have their signatures from the trait
+                Item::Impl(i) if i.trait_.is_none() => {
+                    let ty = tidy(&i.self_ty);
+                    for item in i.items.iter() {
+                        if let ImplItem::Method(m) = item {
+                            if is_public(&m.vis) {
+                                let function = format!("pub fn {}{}::{}", prefix, ty, m.sig.ident);
+                                self.check_signature(function, &m.sig);
+                            }
+                        }
+                    }
+                }
+                Item::Trait(t) if is_public(&t.vis) => {
+                    for item in t.items.iter() {
+                        if let TraitItem::Method(m) = item {
+                            let function = format!("fn {}{}::{}", prefix, t.ident, m.sig.ident);
+                            self.check_signature(function, &m.sig);
+                        }
+                    }
+                }
+                Item::Mod(m) if is_public(&m.vis) => {
+                    if let Some((_, items)) = &m.content {
+                        let prefix = format!("{}{}::", prefix, m.ident);
+                        self.check_items(&prefix, items);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// The files of the crate's library whose modules are public, i.e. the root and
+/// the files of its `pub mod`s (and theirs), as salt-api-surface walks them.
+fn public_files(manifest: &Path) -> BTreeSet<PathBuf> {
+    let mut result = BTreeSet::new();
+    if let Some((_, root)) = library_of(manifest) {
+        walk_file(&root, &mut result);
+    }
+    result
+}
+
+fn walk_file(file: &Path, result: &mut BTreeSet<PathBuf>) {
+    if !result.insert(canonical(file)) {
+        return;
+    }
+    if let Ok(Ok(ast)) = fs::read_to_string(file).map(|c| parse_source(&c)) {
+        walk_items(&ast.items, &child_dir(file), result);
+    }
+}
+
+fn walk_items(items: &[Item], dir: &Path, result: &mut BTreeSet<PathBuf>) {
+    for item in items {
+        match item {
+            Item::Mod(m) if is_public(&m.vis) => match &m.content {
+                Some((_, items)) => walk_items(items, &dir.join(m.ident.to_string()), result),
+                None => {
+                    if let Some(file) = module_file(dir, m) {
+                        walk_file(&file, result);
+                    }
+                }
+            },
+            _ => (),
+        }
+    }
+}
+
+/// The path, made comparable however it was spelled.
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn problems_in(contents: &str, settings: &ErrorGuardConfig) -> Option<Vec<Problem>> {
+    let ast = parse_source(contents).ok()?;
+    let mut anyhow = AnyhowImports::default();
+    anyhow.visit_file(&ast);
+    let mut checker = Checker {
+        settings,
+        anyhow: &anyhow,
+        see: match &settings.doc {
+            Some(doc) => format!(", see {}", doc),
+            None => String::new(),
+        },
+        problems: Vec::new(),
+    };
+    checker.check_items("", &ast.items);
+    Some(checker.problems)
+}
+
+/// Checks one file against its crate's conventions, returning its problems.
+fn check_file(filename: &str, settings: &ErrorGuardConfig) -> Vec<Violation> {
+    let contents = match fs::read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(e) => {
+            return vec![Violation::new(
+                filename,
+                format!("Unable to read {}: {}", filename, e),
+            )]
+        }
+    };
+    let problems = match problems_in(&contents, settings) {
+        Some(problems) => problems,
+        None => {
+            eprintln!("Unable to parse {}, skipping it", filename);
+            return Vec::new();
+        }
+    };
+    let existing: BTreeSet<(String, &str)> = read_committed("HEAD", filename)
+        .and_then(|old| problems_in(&old, settings))
+        .unwrap_or_default()
+        .iter()
+        .map(Problem::key)
+        .collect();
+    problems
+        .into_iter()
+        .filter(|p| !existing.contains(&p.key()))
+        .map(|p| {
+            Violation::new(filename, p.message)
+                .at(p.span)
+                .for_lint("error-guard")
+                .suggesting(p.suggestion)
+        })
+        .collect()
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-error-guard");
+    let mut summary = Summary::new("salt-error-guard");
+    let known = [PORCELAIN, OUTPUT, COLOR];
+    let (flags, files) = parse_args("salt-error-guard", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut report = Report::from_flags("salt-error-guard", &flags, &config).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+
+    let mut crates = BTreeSet::new();
+    let mut public = BTreeMap::new();
+    for file in files.iter().filter(|f| f.ends_with(".rs")) {
+        let manifest = match find_manifest(file) {
+            Some(manifest) => manifest,
+            None => continue,
+        };
+        if config.is_excluded(Path::new(file)) || target_kind(file) != Some("lib") {
+            continue;
+        }
+        let public = public
+            .entry(manifest.clone())
+            .or_insert_with(|| public_files(&manifest));
+        if !public.contains(&canonical(Path::new(file))) {
+            continue;
+        }
+        let crate_config = config.for_crate(&manifest).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            config.clone()
+        });
+        let settings = &crate_config.error_guard;
+        summary.files += 1;
+        crates.insert(manifest);
+        let mut problems = check_file(file, settings);
+        if settings.advisory || config.policy_for_file(Path::new(file)).advisory {
+            problems = problems.into_iter().map(Violation::advisory).collect();
+        }
+        report.record_all(&problems);
+        summary.violations += problems.iter().filter(|p| !p.advisory).count();
+        summary.count_lints(&problems);
+    }
+    summary.crates = crates.len();
+    report.finish(&summary);
+    summary.print();
+    process::exit(summary.exit_code());
+}
@@ -0,0 +1,369 @@
+//! `salt-feature-flag-guard` keeps feature flags from outliving their welcome.
+//! Every custom cfg (like `#[cfg(new_checkout)]`, set with `--cfg new_checkout`)
+//! and every Cargo feature that's a flag, that the files of a commit gate code
+//! on, has to be in a registry that says who owns it and when it's due to go:
+//!
+//! ```yaml
+//! cfgs:
+//!   new_checkout:
+//!     owner: "@payments"
+//!     expires: 2026-12-31
+//!     description: The checkout rewrite, on for 10% of traffic
+//! features:
+//!   flag-fast-search:
+//!     owner: jo@example.com
+//!     expires: 2026-11-30
+//! ```
+//!
+//! A flag that isn't registered, or whose expiry date has passed, fails the
+//! commit, and one that expires within `warn-days` gets a warning.  Only the
+//! features the `features` globs match are flags, since most features are
+//! there for good, and cfgs that aren't flags can be ignored:
+//!
+//! ```toml
+//! [feature-flag-guard]
+//! registry = ".feature-flags.yaml"  # relative to the repo root
+//! features = ["flag-*"]
+//! ignore = ["docsrs", "loom"]
+//! warn-days = 14
+//! ```
+//!
+//! It looks at `#[cfg(...)]`, `#[cfg_attr(...)]`, and `cfg!(...)`, the same as
+//! `salt-cfg-guard`, which catches the typos.  Crates in advisory tiers get told
+//! about their flags, but they don't fail the commit.
+
+#![forbid(unsafe_code)]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::visit::{self, Visit};
+use syn::{Lit, Meta, NestedMeta, Token};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::Violation;
+use salt_spray::config::{days_since_epoch, glob_matches, Config, FeatureFlagGuardConfig};
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::{
+    find_manifest, find_repo_root, parse_source, BUILTIN_CFG_KEYS, BUILTIN_CFG_NAMES,
+};
+
+/// The feature flags, with who owns them and when they expire.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Registry {
+    cfgs: BTreeMap<String, Flag>,
+    features: BTreeMap<String, Flag>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Flag {
+    owner: String,
+    /// As YYYY-MM-DD.
+    expires: String,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+impl Flag {
+    /// Who the flag belongs to, along with what it's for if the registry says.
+    fn about(&self) -> String {
+        match &self.description {
+            Some(description) => format!("{}, owned by {}", description.trim(), self.owner),
+            None => format!("owned by {}", self.owner),
+        }
+    }
+}
+
+impl Registry {
+    /// Loads the registry, or an empty one if there isn't one yet.
+    fn load(path: &Path) -> Result<Registry, String> {
+        let registry: Registry = match fs::read_to_string(path) {
+            Ok(contents) => serde_yaml::from_str(&contents)
+                .map_err(|e| format!("Unable to parse {}: {}", path.display(), e))?,
+            Err(_) => return Ok(Default::default()),
+        };
+        let flags = registry.cfgs.iter().chain(registry.features.iter());
+        for (name, flag) in flags {
+            if flag.owner.trim().is_empty() {
+                return Err(format!("{}: `{}` has no owner", path.display(), name));
+            } else if days_since_epoch(&flag.expires).is_none() {
+                return Err(format!(
+                    "{}: `{}` expires on {}, which isn't a YYYY-MM-DD date",
+                    path.display(),
+                    name,
+                    flag.expires
+                ));
+            }
+        }
+        Ok(registry)
+    }
+}
+
+/// Which kind of flag something gates on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Cfg,
+    Feature,
+}
+
+impl Kind {
+    fn describe(self, name: &str) -> String {
+        match self {
+            Kind::Cfg => format!("cfg `{}`", name),
+            Kind::Feature => format!("feature `{}`", name),
+        }
+    }
+
+    /// The list in the registry that flags of this kind go in.
+    fn section(self) -> &'static str {
+        match self {
+            Kind::Cfg => "cfgs",
+            Kind::Feature => "features",
+        }
+    }
+}
+
+/// Walks a file's syntax tree collecting the flags its cfg predicates use.
+struct FlagVisitor<'a> {
+    settings: &'a FeatureFlagGuardConfig,
+    /// The flags, each with where it's first used.
+    flags: BTreeMap<(Kind, String), proc_macro2::Span>,
+}
+
+impl<'a> FlagVisitor<'a> {
+    fn found(&mut self, kind: Kind, name: String, span: proc_macro2::Span) {
+        self.flags.entry((kind, name)).or_insert(span);
+    }
+
+    fn is_custom(&self, name: &str, builtin: &[&str]) -> bool {
+        !builtin.contains(&name) && !self.settings.ignore.iter().any(|i| i == name)
+    }
+
+    fn check_predicate(&mut self, predicate: &NestedMeta) {
+        let span = predicate.span();
+        match predicate {
+            NestedMeta::Meta(Meta::List(list)) => {
+                for nested in list.nested.iter() {
+                    self.check_predicate(nested);
+                }
+            }
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("feature") => {
+                if let Lit::Str(feature) = &nv.lit {
+                    let feature = feature.value();
+                    let is_flag = self
+                        .settings
+                        .features
+                        .iter()
+                        .any(|glob| glob_matches(glob, Path::new(&feature)));
+                    if is_flag {
+                        self.found(Kind::Feature, feature, span);
+                    }
+                }
+            }
+            NestedMeta::Meta(Meta::NameValue(nv)) => {
+                if let Some(key) = nv.path.get_ident().map(|i| i.to_string()) {
+                    if self.is_custom(&key, BUILTIN_CFG_KEYS) {
+                        self.found(Kind::Cfg, key, span);
+                    }
+                }
+            }
+            NestedMeta::Meta(Meta::Path(path)) => {
+                if let Some(name) = path.get_ident().map(|i| i.to_string()) {
+                    if self.is_custom(&name, BUILTIN_CFG_NAMES) {
+                        self.found(Kind::Cfg, name, span);
+                    }
+                }
+            }
+            NestedMeta::Lit(_) => (),
+        }
+    }
+}
+
+impl<'a, 'ast> Visit<'ast> for FlagVisitor<'a> {
+    fn visit_attribute(&mut self, node: &'ast syn::Attribute) {
+        if node.path.is_ident("cfg") || node.path.is_ident("cfg_attr") {
+            // Only the first argument of cfg_attr is a predicate
+            if let Ok(Meta::List(list)) = node.parse_meta() {
+                if let Some(predicate) = list.nested.first() {
+                    self.check_predicate(predicate);
+                }
+            }
+        }
+        visit::visit_attribute(self, node);
+    }
+
+    fn visit_macro(&mut self, node: &'ast syn::Macro) {
+        if node.path.is_ident("cfg") {
+            let arguments =
+                node.parse_body_with(Punctuated::<NestedMeta, Token![,]>::parse_terminated);
+            if let Some(predicate) = arguments.ok().as_ref().and_then(|a| a.first()) {
+                self.check_predicate(predicate);
+            }
+        }
+        visit::visit_macro(self, node);
+    }
+}
+
+/// Checks one file's flags against the registry, returning the problems found.
+fn check_file(
+    filename: &str,
+    settings: &FeatureFlagGuardConfig,
+    registry: &Registry,
+    registry_path: &str,
+    today: i64,
+) -> Vec<Violation> {
+    let ast = match fs::read_to_string(filename).map(|c| parse_source(&c)) {
+        Ok(Ok(ast)) => ast,
+        Ok(Err(_)) => {
+            eprintln!("Unable to parse {}, skipping it", filename);
+            return Vec::new();
+        }
+        Err(e) => {
+            return vec![Violation::new(
+                filename,
+                format!("Unable to read {}: {}", filename, e),
+            )]
+        }
+    };
+    let mut visitor = FlagVisitor {
+        settings,
+        flags: BTreeMap::new(),
+    };
+    visitor.visit_file(&ast);
+
+    let mut result = Vec::new();
+    for ((kind, name), span) in visitor.flags {
+        let registered = match kind {
+            Kind::Cfg => registry.cfgs.get(&name),
+            Kind::Feature => registry.features.get(&name),
+        };
+        let flag = match registered {
+            Some(flag) => flag,
+            None => {
+                let problem = format!(
+                    "{} is a feature flag, but it isn't in {}",
+                    kind.describe(&name),
+                    registry_path
+                );
+                let suggestion = format!(
+                    "add it under `{}:` in {} with an owner and an expiry date",
+                    kind.section(),
+                    registry_path
+                );
+                result.push(
+                    Violation::new(filename, problem)
+                        .at(span)
+                        .suggesting(suggestion),
+                );
+                continue;
+            }
+        };
+        // unwrap here is safe since Registry::load checked every date
+        let left = days_since_epoch(&flag.expires).unwrap() - today;
+        if left < 0 {
+            let problem = format!(
+                "the feature flag {} ({}) expired on {}",
+                kind.describe(&name),
+                flag.about(),
+                flag.expires
+            );
+            let suggestion = format!(
+                "remove the flag, or have its owner push back its expiry date in {}",
+                registry_path
+            );
+            result.push(
+                Violation::new(filename, problem)
+                    .at(span)
+                    .suggesting(suggestion),
+            );
+        } else if left < settings.warn_days {
+            let problem = format!(
+                "the feature flag {} ({}) expires on {}, in {} day(s)",
+                kind.describe(&name),
+                flag.about(),
+                flag.expires,
+                left
+            );
+            result.push(Violation::new(filename, problem).at(span).advisory());
+        }
+    }
+    result
+        .into_iter()
+        .map(|v| v.for_lint("feature-flag-guard"))
+        .collect()
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-feature-flag-guard");
+    let mut summary = Summary::new("salt-feature-flag-guard");
+    let known = [PORCELAIN, OUTPUT, COLOR];
+    let (flags, files) = parse_args("salt-feature-flag-guard", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut report =
+        Report::from_flags("salt-feature-flag-guard", &flags, &config).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+
+    // There's one registry for the whole repo, so it's where the repo's config says
+    let registry_path = &config.feature_flag_guard.registry;
+    let root = find_repo_root().unwrap_or_default();
+    let registry = Registry::load(&root.join(registry_path)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let today = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| (d.as_secs() / 86400) as i64)
+        .unwrap_or_default();
+
+    let mut crates = BTreeSet::new();
+    for file in files.iter().filter(|f| f.ends_with(".rs")) {
+        let manifest = match find_manifest(file) {
+            Some(manifest) => manifest,
+            None => continue,
+        };
+        if config.is_excluded(Path::new(file)) {
+            continue;
+        }
+        let crate_config = config.for_crate(&manifest).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            config.clone()
+        });
+        summary.files += 1;
+        crates.insert(manifest.clone());
+        let mut violations = check_file(
+            file,
+            &crate_config.feature_flag_guard,
+            &registry,
+            registry_path,
+            today,
+        );
+        if config.policy_for(&manifest).advisory {
+            violations = violations.into_iter().map(Violation::advisory).collect();
+        }
+        report.record_all(&violations);
+        summary.violations += violations.iter().filter(|v| !v.advisory).count();
+        summary.count_lints(&violations);
+    }
+    summary.crates = crates.len();
+    report.finish(&summary);
+    summary.print();
+    process::exit(summary.exit_code());
+}
@@ -0,0 +1,411 @@
+//! `salt-fmt-imports` sorts each file's imports into groups (the standard library,
+//! external crates, the workspace's other crates, and the crate itself, in that
+//! order by default) separated by blank lines, which rustfmt only does on nightly
+//! (`group_imports`).  It can also merge or split `use` trees, like nightly's
+//! `imports_granularity`:
+//!
+//! ```toml
+//! [fmt-imports]
+//! groups = ["std", "external", "workspace", "crate"]
+//! granularity = "module"  # or "preserve" (the default), "crate", or "item"
+//! ```
+//!
+//! Only runs of plain `use`s are touched: a `pub use`, an attribute, or a comment
+//! ends the run, so nothing but whitespace is ever lost.  Files are rewritten in
+//! place, so run it before salt-spray, which then tidies up any long lines.
+
+#![forbid(unsafe_code)]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use proc_macro2::LineColumn;
+use syn::spanned::Spanned;
+use syn::{Item, ItemUse, UseTree, Visibility};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::Violation;
+use salt_spray::config::{Config, ImportGranularity, ImportGroup};
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+#[cfg(feature = "metadata")]
+use salt_spray::toolchain;
+use salt_spray::{find_manifest, find_workspace_root, parse_source};
+
+/// The names the workspace's crates are imported by, according to cargo metadata.
+#[cfg(feature = "metadata")]
+fn workspace_crates(workspace: &Path) -> BTreeSet<String> {
+    let output = toolchain::cargo()
+        .args([
+            "metadata",
+            "--no-deps",
+            "--format-version",
+            "1",
+            "--manifest-path",
+        ])
+        .arg(workspace.join("Cargo.toml"))
+        .output();
+    let metadata: serde_json::Value = match output.map(|o| serde_json::from_slice(&o.stdout)) {
+        Ok(Ok(metadata)) => metadata,
+        _ => return BTreeSet::new(),
+    };
+    let mut result = BTreeSet::new();
+    for package in metadata["packages"].as_array().into_iter().flatten() {
+        let targets = package["targets"].as_array().into_iter().flatten();
+        let names = targets.map(|t| &t["name"]).chain([&package["name"]]);
+        result.extend(
+            names
+                .filter_map(|n| n.as_str())
+                .map(|n| n.replace('-', "_")),
+        );
+    }
+    result
+}
+
+/// Without the `metadata` feature the workspace's crates can't be told apart from
+/// external ones, so they're all grouped as external.
+#[cfg(not(feature = "metadata"))]
+fn workspace_crates(_workspace: &Path) -> BTreeSet<String> {
+    BTreeSet::new()
+}
+
+/// Every path a `use` tree imports, e.g. `["std", "io", "Read"]`.  Renames and
+/// globs end up in the last segment (`Foo as Bar`, `*`), and a leading `::` is an
+/// empty first segment.
+fn flatten(tree: &UseTree, prefix: &mut Vec<String>, result: &mut Vec<Vec<String>>) {
+    let mut leaf = |last: String| {
+        let mut path = prefix.clone();
+        path.push(last);
+        result.push(path);
+    };
+    match tree {
+        UseTree::Path(p) => {
+            prefix.push(p.ident.to_string());
+            flatten(&p.tree, prefix, result);
+            prefix.pop();
+        }
+        UseTree::Name(n) => leaf(n.ident.to_string()),
+        UseTree::Rename(r) => leaf(format!("{} as {}", r.ident, r.rename)),
+        UseTree::Glob(_) => leaf("*".to_string()),
+        UseTree::Group(g) => {
+            for tree in g.items.iter() {
+                flatten(tree, prefix, result);
+            }
+        }
+    }
+}
+
+fn paths_of(item: &ItemUse) -> Vec<Vec<String>> {
+    let mut prefix = Vec::new();
+    if item.leading_colon.is_some() {
+        prefix.push(String::new());
+    }
+    let mut result = Vec::new();
+    flatten(&item.tree, &mut prefix, &mut result);
+    result
+}
+
+/// The first segment of the path, i.e. the crate (or `crate`, `self`, `super`).
+fn root_of(path: &[String]) -> &str {
+    let mut segments = path.iter().filter(|s| !s.is_empty());
+    segments.next().map(String::as_str).unwrap_or("")
+}
+
+fn group_of(path: &[String], workspace: &BTreeSet<String>) -> ImportGroup {
+    match root_of(path) {
+        "std" | "core" | "alloc" | "proc_macro" => ImportGroup::Std,
+        "crate" | "self" | "super" => ImportGroup::Crate,
+        root if workspace.contains(root) => ImportGroup::Workspace,
+        _ => ImportGroup::External,
+    }
+}
+
+/// Imported paths merged into a tree, for rendering back into `use`s.
+#[derive(Default)]
+struct Tree {
+    children: BTreeMap<String, Tree>,
+    /// Whether a path ends here, as opposed to only passing through.
+    ends_here: bool,
+}
+
+impl Tree {
+    fn insert(&mut self, path: &[String]) {
+        match path.split_first() {
+            Some((first, rest)) => self.children.entry(first.clone()).or_default().insert(rest),
+            None => self.ends_here = true,
+        }
+    }
+
+    /// Renders what's below this node, e.g. `["fs", "io::{self, Read}"]`.
+    fn render(&self) -> Vec<String> {
+        let mut result = Vec::new();
+        for (name, child) in self.children.iter() {
+            if child.children.is_empty() {
+                result.push(name.clone());
+                continue;
+            }
+            let mut inner = child.render();
+            if child.ends_here {
+                inner.insert(0, "self".to_string());
+                inner.dedup();
+            }
+            match inner.as_slice() {
+                [only] if only != "self" => result.push(format!("{}::{}", name, only)),
+                _ => result.push(format!("{}::{{{}}}", name, inner.join(", "))),
+            }
+        }
+        // Like rustfmt, self goes first
+        result.sort_by_key(|s| s != "self");
+        result
+    }
+}
+
+/// The `use`s (without the `use ` and `;`) the paths make at the given granularity.
+fn merge(paths: &[Vec<String>], granularity: ImportGranularity) -> Vec<String> {
+    let mut trees: BTreeMap<Vec<String>, Tree> = BTreeMap::new();
+    for path in paths {
+        let key = match granularity {
+            ImportGranularity::Preserve | ImportGranularity::Crate => {
+                let leading = path.first().is_some_and(|s| s.is_empty()) as usize;
+                path[..(leading + 1).min(path.len())].to_vec()
+            }
+            ImportGranularity::Module => path[..path.len() - 1].to_vec(),
+            ImportGranularity::Item => path.clone(),
+        };
+        trees.entry(key).or_default().insert(path);
+    }
+    trees.values().flat_map(Tree::render).collect()
+}
+
+/// Turns a run of `use`s back into source: one group after another, each sorted,
+/// with blank lines in between.
+fn render_run(
+    run: &[(&ItemUse, &str)],
+    indent: &str,
+    config: &Config,
+    workspace: &BTreeSet<String>,
+) -> String {
+    let imports = &config.fmt_imports;
+    let mut groups: BTreeMap<ImportGroup, Vec<String>> = BTreeMap::new();
+    if imports.granularity == ImportGranularity::Preserve {
+        for (item, text) in run {
+            let group = paths_of(item)
+                .first()
+                .map_or(ImportGroup::External, |p| group_of(p, workspace));
+            groups.entry(group).or_default().push(text.to_string());
+        }
+    } else {
+        let mut paths_by_group: BTreeMap<ImportGroup, Vec<Vec<String>>> = BTreeMap::new();
+        for path in run.iter().flat_map(|(item, _)| paths_of(item)) {
+            paths_by_group
+                .entry(group_of(&path, workspace))
+                .or_default()
+                .push(path);
+        }
+        for (group, paths) in paths_by_group {
+            let uses = merge(&paths, imports.granularity)
+                .into_iter()
+                .map(|u| format!("use {};", u));
+            groups.insert(group, uses.collect());
+        }
+    }
+
+    let order = imports.groups.iter().chain(ImportGroup::ALL.iter());
+    let mut seen = BTreeSet::new();
+    let mut blocks = Vec::new();
+    for group in order.filter(|g| seen.insert(**g)) {
+        if let Some(mut uses) = groups.remove(group) {
+            uses.sort();
+            uses.dedup();
+            blocks.push(uses.join(&format!("\n{}", indent)));
+        }
+    }
+    blocks.join(&format!("\n\n{}", indent))
+}
+
+/// Where things are in a file, for turning spans into byte offsets.
+struct Source<'a> {
+    contents: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> Source<'a> {
+    fn new(contents: &'a str) -> Self {
+        let newlines = contents.match_indices('\n').map(|(i, _)| i + 1);
+        Source {
+            contents,
+            line_starts: [0].into_iter().chain(newlines).collect(),
+        }
+    }
+
+    fn offset(&self, at: LineColumn) -> usize {
+        let start = self
+            .line_starts
+            .get(at.line - 1)
+            .copied()
+            .unwrap_or(self.contents.len());
+        self.contents[start..]
+            .char_indices()
+            .nth(at.column)
+            .map_or(self.contents.len(), |(i, _)| start + i)
+    }
+
+    /// What's on the line before the offset.
+    fn before_on_line(&self, offset: usize) -> &'a str {
+        let line_start = self.contents[..offset].rfind('\n').map_or(0, |i| i + 1);
+        &self.contents[line_start..offset]
+    }
+}
+
+/// What a file's imports should be replaced with: where, and by what.
+type Replacements = Vec<(usize, usize, String)>;
+
+/// Pushes a replacement for the run if reorganizing it changes anything.
+fn flush(
+    source: &Source,
+    run: &mut Vec<(&ItemUse, &str)>,
+    (start, end): (usize, usize),
+    config: &Config,
+    workspace: &BTreeSet<String>,
+    replacements: &mut Replacements,
+) {
+    if run.is_empty() {
+        return;
+    }
+    let indent = source.before_on_line(start);
+    let rendered = render_run(run, indent, config, workspace);
+    if rendered != source.contents[start..end] {
+        replacements.push((start, end, rendered));
+    }
+    run.clear();
+}
+
+/// Finds the runs of plain `use`s among the items (and in inline modules), and
+/// pushes a replacement for each run that changes.
+fn reorganize(
+    source: &Source,
+    items: &[Item],
+    config: &Config,
+    workspace: &BTreeSet<String>,
+    replacements: &mut Replacements,
+) {
+    let mut run: Vec<(&ItemUse, &str)> = Vec::new();
+    let mut span = (0, 0);
+    for item in items {
+        let item_use = match item {
+            Item::Use(u) if matches!(u.vis, Visibility::Inherited) && u.attrs.is_empty() => u,
+            _ => {
+                flush(source, &mut run, span, config, workspace, replacements);
+                if let Item::Mod(m) = item {
+                    if let Some((_, items)) = &m.content {
+                        reorganize(source, items, config, workspace, replacements);
+                    }
+                }
+                continue;
+            }
+        };
+        let start = source.offset(item.span().start());
+        let end = source.offset(item.span().end());
+        let text = &source.contents[start..end];
+        let plain = !text.contains("//") && !text.contains("/*");
+        let adjacent = run.is_empty() || source.contents[span.1..start].trim().is_empty();
+        let indented = source.before_on_line(start).trim().is_empty();
+        if !adjacent || !plain || !indented {
+            flush(source, &mut run, span, config, workspace, replacements);
+        }
+        if plain && indented {
+            if run.is_empty() {
+                span.0 = start;
+            }
+            span.1 = end;
+            run.push((item_use, text));
+        }
+    }
+    flush(source, &mut run, span, config, workspace, replacements);
+}
+
+/// Returns the file's contents with its imports reorganized.
+fn reorganized(contents: &str, config: &Config, workspace: &BTreeSet<String>) -> Option<String> {
+    let ast = parse_source(contents).ok()?;
+    let source = Source::new(contents);
+    let mut replacements = Vec::new();
+    reorganize(&source, &ast.items, config, workspace, &mut replacements);
+    let mut result = contents.to_string();
+    replacements.sort();
+    for (start, end, text) in replacements.into_iter().rev() {
+        result.replace_range(start..end, &text);
+    }
+    Some(result)
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-fmt-imports");
+    let mut summary = Summary::new("salt-fmt-imports");
+    let known = [PORCELAIN, OUTPUT, COLOR];
+    let (flags, files) = parse_args("salt-fmt-imports", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut report = Report::from_flags("salt-fmt-imports", &flags, &config).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+
+    let mut workspaces: BTreeMap<PathBuf, BTreeSet<String>> = BTreeMap::new();
+    let mut crates = BTreeSet::new();
+    for arg in files {
+        if !arg.ends_with(".rs") || config.is_excluded(Path::new(&arg)) {
+            continue;
+        }
+        summary.files += 1;
+        let manifest = find_manifest(&arg);
+        let workspace = match &manifest {
+            Some(manifest) => {
+                let root = find_workspace_root(manifest);
+                workspaces
+                    .entry(root.clone())
+                    .or_insert_with(|| workspace_crates(&root))
+            }
+            None => workspaces.entry(PathBuf::new()).or_default(),
+        };
+        crates.extend(manifest);
+
+        let contents = match fs::read_to_string(&arg) {
+            Ok(contents) => contents,
+            Err(e) => {
+                report.record(&Violation::new(
+                    &arg,
+                    format!("Unable to read {}: {}", arg, e),
+                ));
+                summary.violations += 1;
+                continue;
+            }
+        };
+        match reorganized(&contents, &config, workspace) {
+            Some(result) if result != contents => {
+                if let Err(e) = fs::write(&arg, result) {
+                    report.record(&Violation::new(
+                        &arg,
+                        format!("Unable to write {}: {}", arg, e),
+                    ));
+                    summary.violations += 1;
+                } else {
+                    eprintln!("Reorganized the imports in {}", arg);
+                }
+            }
+            Some(_) => (),
+            None => eprintln!("Unable to parse {}, skipping it", arg),
+        }
+    }
+    summary.crates = crates.len();
+    report.finish(&summary);
+    summary.print();
+    process::exit(summary.exit_code());
+}
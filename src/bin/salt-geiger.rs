@@ -0,0 +1,231 @@
+//! `salt-geiger` is a ratchet for unsafe code, in the spirit of cargo-geiger.
+//!
+//! For each crate touched by a commit whose policy sets `forbid-unsafe-growth`, it
+//! counts the unsafe blocks, functions, impls, and traits in the whole crate and
+//! compares them to the counts stored in .geiger.yaml .  A commit that increases
+//! any of them is rejected, and one that decreases them updates .geiger.yaml (so
+//! it needs to be staged before retrying), just like `warning-ratchet`.
+//!
+//! Mark crates in salt-spray.toml through their tier's policy, e.g.
+//!
+//! ```toml
+//! [tier-policy.tier1]
+//! forbid-unsafe-growth = true
+//! ```
+//!
+//! or with `forbid-unsafe-growth = true` in the `[policy]` of a crate's own config.
+
+#![forbid(unsafe_code)]
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use serde::{Deserialize, Serialize};
+use syn::visit::{self, Visit};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::Violation;
+use salt_spray::config::Config;
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::{
+    crate_files_in, group_by_crate, load_baseline, package_name, parse_source, save_baseline, say,
+};
+
+const BASELINE: &str = ".geiger.yaml";
+
+/// How much unsafe code a crate has.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct UnsafeCounts {
+    expressions: usize,
+    functions: usize,
+    impls: usize,
+    traits: usize,
+}
+
+impl UnsafeCounts {
+    fn kinds(&self) -> [(&'static str, usize); 4] {
+        [
+            ("unsafe block(s)", self.expressions),
+            ("unsafe fn(s)", self.functions),
+            ("unsafe impl(s)", self.impls),
+            ("unsafe trait(s)", self.traits),
+        ]
+    }
+
+    fn add(&mut self, other: &UnsafeCounts) {
+        self.expressions += other.expressions;
+        self.functions += other.functions;
+        self.impls += other.impls;
+        self.traits += other.traits;
+    }
+}
+
+impl fmt::Display for UnsafeCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kinds: Vec<String> = self
+            .kinds()
+            .iter()
+            .map(|(k, n)| format!("{} {}", n, k))
+            .collect();
+        write!(f, "{}", kinds.join(", "))
+    }
+}
+
+impl<'ast> Visit<'ast> for UnsafeCounts {
+    fn visit_expr_unsafe(&mut self, node: &'ast syn::ExprUnsafe) {
+        self.expressions += 1;
+        visit::visit_expr_unsafe(self, node);
+    }
+
+    fn visit_item_fn(&mut self, node: &'ast syn::ItemFn) {
+        self.functions += node.sig.unsafety.is_some() as usize;
+        visit::visit_item_fn(self, node);
+    }
+
+    fn visit_impl_item_method(&mut self, node: &'ast syn::ImplItemMethod) {
+        self.functions += node.sig.unsafety.is_some() as usize;
+        visit::visit_impl_item_method(self, node);
+    }
+
+    fn visit_trait_item_method(&mut self, node: &'ast syn::TraitItemMethod) {
+        self.functions += node.sig.unsafety.is_some() as usize;
+        visit::visit_trait_item_method(self, node);
+    }
+
+    fn visit_item_impl(&mut self, node: &'ast syn::ItemImpl) {
+        self.impls += node.unsafety.is_some() as usize;
+        visit::visit_item_impl(self, node);
+    }
+
+    fn visit_item_trait(&mut self, node: &'ast syn::ItemTrait) {
+        self.traits += node.unsafety.is_some() as usize;
+        visit::visit_item_trait(self, node);
+    }
+}
+
+/// The unsafe counts of each crate, keyed by package name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Baseline {
+    crates: BTreeMap<String, UnsafeCounts>,
+}
+
+/// Counts the unsafe code in the whole crate, returning the counts and how many
+/// files were looked at.
+fn count_crate(cargo_toml: &str, config: &Config) -> (UnsafeCounts, usize) {
+    let mut files = Path::new(cargo_toml)
+        .parent()
+        .map(crate_files_in)
+        .unwrap_or_default();
+    files.retain(|f| !config.is_excluded(f));
+
+    let mut result = UnsafeCounts::default();
+    for file in files.iter() {
+        let ast = fs::read_to_string(file)
+            .ok()
+            .and_then(|c| parse_source(&c).ok());
+        match ast {
+            Some(ast) => {
+                let mut counts = UnsafeCounts::default();
+                counts.visit_file(&ast);
+                result.add(&counts);
+            }
+            None => eprintln!("Unable to parse {}, skipping it", file.display()),
+        }
+    }
+    (result, files.len())
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-geiger");
+    let mut summary = Summary::new("salt-geiger");
+    let known = [PORCELAIN, OUTPUT, COLOR];
+    let (flags, files) = parse_args("salt-geiger", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut report = Report::from_flags("salt-geiger", &flags, &config).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+
+    let mut baseline: Baseline = load_baseline(Path::new(BASELINE)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut updated = false;
+    for cargo_toml in group_by_crate(&files).keys() {
+        if !config
+            .policy_for(Path::new(cargo_toml))
+            .forbid_unsafe_growth
+        {
+            continue;
+        }
+        let name = package_name(cargo_toml).unwrap_or_else(|| cargo_toml.to_string());
+        let (observed, file_count) = count_crate(cargo_toml, &config);
+        summary.crates += 1;
+        summary.files += file_count;
+
+        let expected = match baseline.crates.get(&name) {
+            Some(expected) => *expected,
+            None => {
+                say!("Started tracking the unsafe code in {}: {}", name, observed);
+                baseline.crates.insert(name, observed);
+                updated = true;
+                continue;
+            }
+        };
+        let mut grew = false;
+        for ((kind, was), (_, is)) in expected.kinds().iter().zip(observed.kinds().iter()) {
+            if is > was {
+                let message = format!(
+                    "{} went from {} to {} {}, but may not add unsafe code",
+                    name, was, is, kind
+                );
+                report.record(&Violation::general(message).for_lint("geiger"));
+                grew = true;
+            }
+        }
+        if grew {
+            summary.violations += 1;
+            if env::var("UPDATE_ANYWAY").map(|v| v == "1").unwrap_or(false) {
+                baseline.crates.insert(name, observed);
+                updated = true;
+            }
+        } else if observed != expected {
+            say!(
+                "Thanks for removing unsafe code from {}!  It now has {}",
+                name,
+                observed
+            );
+            baseline.crates.insert(name, observed);
+            updated = true;
+        }
+    }
+
+    if updated {
+        save_baseline(Path::new(BASELINE), &baseline).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+    }
+    let exit_code = if summary.violations > 0 {
+        1
+    } else if updated {
+        say!("Please run `git add {}` and retry your commit.", BASELINE);
+        2
+    } else {
+        0
+    };
+    report.finish(&summary);
+    summary.print();
+    process::exit(exit_code);
+}
@@ -0,0 +1,267 @@
+//! `salt-mod-decl-guard` keeps the `mod` declarations of a crate and its files in
+//! sync, catching refactors that silently drop a module from compilation.
+//!
+//! For each crate touched by a commit it starts from the crate's targets (the
+//! library, the binaries, tests, examples, and benches, and the build script,
+//! whether they're declared in Cargo.toml or found the way cargo finds them) and
+//! follows every `mod` declaration (and `include!`) from there, the way rustc would,
+//! `#[path]`s included.  It then reports
+//!
+//! - `mod` declarations whose file doesn't exist, and
+//! - Rust files in the crate that no `mod` declaration reaches, which cargo never
+//!   compiles, so they rot without anyone noticing.
+//!
+//! Excluded and generated files don't count as orphans.  Crates whose policy is
+//! advisory get told about problems, but they don't fail the commit.  Either way
+//! they go wherever `--output=` (or salt-spray.toml) says, see `salt_spray::sink`.
+
+#![forbid(unsafe_code)]
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use syn::spanned::Spanned;
+use syn::{Attribute, Item, Lit, LitStr, Meta};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::Violation;
+use salt_spray::config::Config;
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::{crate_files_in, group_by_crate, parse_source, read_manifest};
+
+/// The directories cargo looks in for targets, other than src/.
+const TARGET_DIRS: [&str; 3] = ["tests", "examples", "benches"];
+
+/// What walking a crate's modules found.
+#[derive(Default)]
+struct Modules {
+    /// Every file some target reaches, canonicalized.
+    reachable: BTreeSet<PathBuf>,
+    /// The declarations whose file is missing.
+    missing: Vec<Violation>,
+}
+
+/// The value of a `#[path = "..."]` among the attributes, if there is one.
+fn path_attr(attrs: &[Attribute]) -> Option<String> {
+    attrs
+        .iter()
+        .filter(|a| a.path.is_ident("path"))
+        .find_map(|a| match a.parse_meta() {
+            Ok(Meta::NameValue(nv)) => match nv.lit {
+                Lit::Str(s) => Some(s.value()),
+                _ => None,
+            },
+            _ => None,
+        })
+}
+
+impl Modules {
+    /// Walks the file and the modules it declares.  `mod_rs` is whether its child
+    /// modules live next to it (as for crate roots and mod.rs files) rather than in
+    /// a directory named after it.
+    fn walk_file(&mut self, file: &Path, mod_rs: bool) {
+        let canonical = match file.canonicalize() {
+            Ok(canonical) => canonical,
+            Err(_) => return,
+        };
+        if !self.reachable.insert(canonical) {
+            return;
+        }
+        let ast = match fs::read_to_string(file).ok().map(|c| parse_source(&c)) {
+            Some(Ok(ast)) => ast,
+            _ => {
+                eprintln!("Unable to parse {}, skipping it", file.display());
+                return;
+            }
+        };
+        let here = file.parent().unwrap_or(Path::new("")).to_path_buf();
+        let children = if mod_rs {
+            here.clone()
+        } else {
+            // unwrap here is safe since the file was just read, so it has a name
+            here.join(file.file_stem().unwrap())
+        };
+        self.walk_items(file, &ast.items, &here, &children);
+    }
+
+    /// Walks the items of the file, where `here` is the directory `#[path]`s are
+    /// relative to and `children` the one the modules declared here live in.
+    fn walk_items(&mut self, file: &Path, items: &[Item], here: &Path, children: &Path) {
+        for item in items {
+            match item {
+                Item::Mod(m) => {
+                    let name = m.ident.to_string();
+                    let path = path_attr(&m.attrs);
+                    if let Some((_, items)) = &m.content {
+                        let dir = children.join(path.as_deref().unwrap_or(&name));
+                        self.walk_items(file, items, &dir, &dir);
+                        continue;
+                    }
+                    // rustc looks for the children of a #[path] file next to it, like
+                    // for a mod.rs, whatever the file is called
+                    let from_path = path.is_some();
+                    let candidates = match path {
+                        Some(path) => vec![here.join(path)],
+                        None => vec![
+                            children.join(format!("{}.rs", name)),
+                            children.join(&name).join("mod.rs"),
+                        ],
+                    };
+                    match candidates.iter().find(|c| c.is_file()) {
+                        Some(found) => {
+                            let mod_rs =
+                                from_path || found.file_name().is_some_and(|n| n == "mod.rs");
+                            self.walk_file(found, mod_rs);
+                        }
+                        None => {
+                            let message = format!(
+                                "`mod {};` needs {}, which doesn't exist",
+                                name,
+                                candidates
+                                    .iter()
+                                    .map(|c| c.display().to_string())
+                                    .collect::<Vec<_>>()
+                                    .join(" or ")
+                            );
+                            let violation = Violation::new(&file.to_string_lossy(), message)
+                                .at(m.span())
+                                .for_lint("mod-decl-guard");
+                            self.missing.push(violation);
+                        }
+                    }
+                }
+                Item::Macro(m) if m.mac.path.is_ident("include") => {
+                    // Only literal paths, ones built with concat! or env! can't be followed
+                    if let Ok(included) = m.mac.parse_body::<LitStr>() {
+                        let included = file
+                            .parent()
+                            .unwrap_or(Path::new(""))
+                            .join(included.value());
+                        if let Ok(canonical) = included.canonicalize() {
+                            self.reachable.insert(canonical);
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// The root files of the crate's targets, i.e. the ones cargo hands to rustc.
+fn target_roots(cargo_toml: &Path) -> Vec<PathBuf> {
+    let dir = cargo_toml.parent().unwrap_or(Path::new(""));
+    let manifest = read_manifest(cargo_toml).unwrap_or_default();
+    let mut roots = vec![
+        dir.join("src/lib.rs"),
+        dir.join("src/main.rs"),
+        dir.join("build.rs"),
+    ];
+    if let Some(build) = manifest
+        .get("package")
+        .and_then(|p| p.get("build"))
+        .and_then(|b| b.as_str())
+    {
+        roots.push(dir.join(build));
+    }
+    for target in ["lib", "bin", "test", "example", "bench"] {
+        let tables = match manifest.get(target) {
+            Some(toml::Value::Array(tables)) => tables.clone(),
+            Some(table) => vec![table.clone()],
+            None => Vec::new(),
+        };
+        for table in tables.iter() {
+            if let Some(path) = table.get("path").and_then(|p| p.as_str()) {
+                roots.push(dir.join(path));
+            }
+        }
+    }
+    // Cargo finds foo.rs and foo/main.rs in these on its own
+    let autodiscovered = ["src/bin"]
+        .iter()
+        .chain(TARGET_DIRS.iter())
+        .filter_map(|d| fs::read_dir(dir.join(d)).ok())
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path());
+    for path in autodiscovered {
+        if path.is_dir() {
+            roots.push(path.join("main.rs"));
+        } else if path.extension().is_some_and(|e| e == "rs") {
+            roots.push(path);
+        }
+    }
+    roots.retain(|r| r.is_file());
+    roots
+}
+
+/// Checks the crate, returning its problems and how many files it has.
+fn check_crate(cargo_toml: &Path, config: &Config) -> (Vec<Violation>, usize) {
+    let mut modules = Modules::default();
+    for root in target_roots(cargo_toml) {
+        modules.walk_file(&root, true);
+    }
+    let mut files = cargo_toml.parent().map(crate_files_in).unwrap_or_default();
+    files.retain(|f| !config.is_excluded(f));
+    let orphans = files.iter().filter(|f| {
+        f.canonicalize()
+            .is_ok_and(|c| !modules.reachable.contains(&c))
+    });
+    let mut problems = modules.missing;
+    problems.extend(orphans.map(|f| {
+        let message = "no `mod` declaration reaches it, so it isn't compiled";
+        Violation::new(&f.to_string_lossy(), message)
+            .for_lint("mod-decl-guard")
+            .suggesting("declare it, or delete it")
+    }));
+    (problems, files.len())
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-mod-decl-guard");
+    let mut summary = Summary::new("salt-mod-decl-guard");
+    let known = [PORCELAIN, OUTPUT, COLOR];
+    let (flags, files) = parse_args("salt-mod-decl-guard", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut report =
+        Report::from_flags("salt-mod-decl-guard", &flags, &config).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+
+    for cargo_toml in group_by_crate(&files).keys() {
+        let cargo_toml = Path::new(cargo_toml);
+        let (mut problems, file_count) = check_crate(cargo_toml, &config);
+        summary.crates += 1;
+        summary.files += file_count;
+        let advisory = config.policy_for(cargo_toml).advisory;
+        if advisory {
+            problems = problems.into_iter().map(Violation::advisory).collect();
+        }
+        report.record_all(&problems);
+        summary.count_lints(&problems);
+        if problems.is_empty() {
+            continue;
+        } else if advisory {
+            eprintln!(
+                "{} is in an advisory tier, so this won't block the commit.",
+                cargo_toml.display()
+            );
+        } else {
+            summary.violations += problems.len();
+        }
+    }
+    report.finish(&summary);
+    summary.print();
+    process::exit(summary.exit_code());
+}
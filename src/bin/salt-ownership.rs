@@ -0,0 +1,265 @@
+//! `salt-ownership` keeps the repo's CODEOWNERS file complete by rejecting commits
+//! that add files nobody owns, i.e. files that no CODEOWNERS entry matches (or
+//! whose last matching entry lists no owners).  Files that already existed at HEAD
+//! are left alone, so adopting the hook doesn't mean assigning the whole repo at
+//! once.
+//!
+//! The CODEOWNERS file is looked for where GitHub and GitLab look for it, or can
+//! be set in the `[ownership]` section of salt-spray.toml, which can also make the
+//! hook advisory:
+//!
+//! ```toml
+//! [ownership]
+//! codeowners = "tools/CODEOWNERS"
+//! advisory = true
+//! ```
+//!
+//! GitLab's `[Section]` headers are skipped, along with any default owners on them.
+
+#![forbid(unsafe_code)]
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+use regex::Regex;
+
+use salt_spray::args::parse_args;
+use salt_spray::check::Violation;
+use salt_spray::config::Config;
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::{find_manifest, find_repo_root, repo_relative};
+
+/// Where GitHub and GitLab look for the CODEOWNERS file, in the order they do.
+static LOCATIONS: &[&str] = &[
+    ".github/CODEOWNERS",
+    ".gitlab/CODEOWNERS",
+    "CODEOWNERS",
+    "docs/CODEOWNERS",
+];
+
+/// One entry in a CODEOWNERS file.
+struct Rule {
+    pattern: String,
+    regex: Regex,
+    owners: Vec<String>,
+    line: usize,
+}
+
+/// Translates a CODEOWNERS pattern, which is a .gitignore pattern, into a regex
+/// matching repo relative paths.
+fn pattern_to_regex(pattern: &str) -> Option<Regex> {
+    let directory = pattern.ends_with('/');
+    let trimmed = pattern.trim_end_matches('/');
+    // A slash anywhere but the end ties the pattern to the root of the repo
+    let anchored = trimmed.contains('/');
+    let trimmed = trimmed.trim_start_matches('/');
+    if trimmed.is_empty() {
+        return None;
+    }
+
+    let mut regex = String::from(if anchored { "^" } else { "^(?:.*/)?" });
+    let mut chars = trimmed.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    regex.push_str(&regex::escape(&escaped.to_string()));
+                }
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    // A directory owns everything under it, but like on GitHub `docs/*` only owns
+    // the files directly in docs
+    if directory {
+        regex.push_str("/.*");
+    } else if !trimmed.ends_with("/*") {
+        regex.push_str("(?:/.*)?");
+    }
+    regex.push('$');
+    Regex::new(&regex).ok()
+}
+
+/// Parses a CODEOWNERS file, complaining about (and skipping) entries it can't
+/// make sense of.
+fn parse_codeowners(path: &Path, contents: &str) -> Vec<Rule> {
+    let mut result = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        let is_section = line.starts_with('[') || line.starts_with("^[");
+        if line.is_empty() || line.starts_with('#') || is_section {
+            continue;
+        }
+        // Comments can also follow an entry, as long as they're set off by a space
+        let line = match line.find(" #") {
+            Some(start) => &line[..start],
+            None => line,
+        };
+        let mut words = line.split_whitespace();
+        // unwrap here is safe since the line has something other than whitespace
+        let pattern = words.next().unwrap().to_string();
+        match pattern_to_regex(&pattern) {
+            Some(regex) => result.push(Rule {
+                pattern,
+                regex,
+                owners: words.map(str::to_string).collect(),
+                line: i + 1,
+            }),
+            None => eprintln!(
+                "{}:{}: unable to make sense of the pattern `{}`, skipping it",
+                path.display(),
+                i + 1,
+                pattern
+            ),
+        }
+    }
+    result
+}
+
+/// The entry that decides who owns the (repo relative) path: the last one
+/// matching it.
+fn rule_for<'a>(rules: &'a [Rule], path: &str) -> Option<&'a Rule> {
+    rules.iter().rev().find(|r| r.regex.is_match(path))
+}
+
+/// Finds the CODEOWNERS file to check against, if there is one.
+fn find_codeowners(config: &Config, root: &Path) -> Option<PathBuf> {
+    match &config.ownership.codeowners {
+        Some(path) => Some(root.join(path)),
+        None => LOCATIONS.iter().map(|l| root.join(l)).find(|p| p.is_file()),
+    }
+}
+
+/// The (repo relative) files the commit adds.  Renames count as additions, since
+/// the file may well have moved somewhere nobody owns.
+fn added_files(root: &Path) -> Result<BTreeSet<String>, String> {
+    let output = Command::new("git")
+        .args([
+            "diff",
+            "--cached",
+            "--name-only",
+            "--no-renames",
+            "--diff-filter=A",
+            "-z",
+        ])
+        .current_dir(root)
+        .output()
+        .map_err(|e| format!("Unable to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Unable to list the files being added: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|f| !f.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-ownership");
+    let mut summary = Summary::new("salt-ownership");
+    let known = [PORCELAIN, OUTPUT, COLOR];
+    let (flags, files) = parse_args("salt-ownership", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut report = Report::from_flags("salt-ownership", &flags, &config).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let root = find_repo_root().unwrap_or_else(|| {
+        eprintln!("salt-ownership has to run inside of a git repo");
+        process::exit(-1);
+    });
+
+    let codeowners = match find_codeowners(&config, &root) {
+        Some(codeowners) => codeowners,
+        None => {
+            eprintln!("There's no CODEOWNERS file, so nothing to check.");
+            summary.print();
+            process::exit(0);
+        }
+    };
+    let codeowners_name = repo_relative(&codeowners);
+    let rules = match fs::read_to_string(&codeowners) {
+        Ok(contents) => parse_codeowners(&codeowners_name, &contents),
+        Err(e) => {
+            eprintln!("Unable to read {}: {}", codeowners.display(), e);
+            process::exit(-1);
+        }
+    };
+    let added = added_files(&root).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+
+    let suggestion = format!("add an entry covering it to {}", codeowners_name.display());
+    let mut unowned = Vec::new();
+    let mut crates = BTreeSet::new();
+    for file in files.iter().filter(|f| !config.is_excluded(Path::new(f))) {
+        summary.files += 1;
+        crates.extend(find_manifest(file));
+        let relative = repo_relative(file).to_string_lossy().replace('\\', "/");
+        if !added.contains(&relative) {
+            continue;
+        }
+        match rule_for(&rules, &relative) {
+            Some(rule) if !rule.owners.is_empty() => (),
+            Some(rule) => {
+                let message = format!(
+                    "new file has no owner, since {}:{} (`{}`) doesn't list any",
+                    codeowners_name.display(),
+                    rule.line,
+                    rule.pattern
+                );
+                unowned.push(Violation::new(file, message));
+            }
+            None => {
+                let message = format!("new file has no owner in {}", codeowners_name.display());
+                unowned.push(Violation::new(file, message));
+            }
+        }
+    }
+
+    let advisory = config.ownership.advisory;
+    let unowned: Vec<Violation> = unowned
+        .into_iter()
+        .map(|v| v.for_lint("ownership").suggesting(&suggestion))
+        .map(|v| if advisory { v.advisory() } else { v })
+        .collect();
+    report.record_all(&unowned);
+    summary.count_lints(&unowned);
+    if !unowned.is_empty() {
+        if advisory {
+            eprintln!("Ownership is advisory, so this won't block the commit.");
+        } else {
+            summary.violations = unowned.len();
+        }
+    }
+    summary.crates = crates.len();
+    report.finish(&summary);
+    summary.print();
+    process::exit(summary.exit_code());
+}
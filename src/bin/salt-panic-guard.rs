@@ -0,0 +1,259 @@
+//! `salt-panic-guard` is a ratchet for the ways library code can panic.
+//!
+//! For each library crate touched by a commit whose policy sets
+//! `forbid-panic-growth`, it counts the `panic!`s (and `todo!`s and
+//! `unimplemented!`s), `.unwrap()`s, `.expect(...)`s, and `assert!`s (and
+//! `assert_eq!`s and `assert_ne!`s) in the library, leaving out test code:
+//! `#[cfg(test)]` modules, `#[test]` functions, and the binaries, tests,
+//! examples, and benches.  Those counts are compared to the ones stored in
+//! .panics.yaml .  A commit that increases any of them is rejected, and one that
+//! decreases them updates .panics.yaml (so it needs to be staged before
+//! retrying), just like `salt-geiger`.
+//!
+//! Mark crates in salt-spray.toml through their tier's policy, e.g.
+//!
+//! ```toml
+//! [tier-policy.tier1]
+//! forbid-panic-growth = true
+//! ```
+//!
+//! or with `forbid-panic-growth = true` in the `[policy]` of a crate's own config.
+//! Growth in advisory tiers is reported but doesn't fail the commit.
+
+#![forbid(unsafe_code)]
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use serde::{Deserialize, Serialize};
+use syn::visit::{self, Visit};
+use syn::Item;
+
+use salt_spray::args::parse_args;
+use salt_spray::check::Violation;
+use salt_spray::config::Config;
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::{
+    attrs_of, group_by_crate, is_test_code, library_files_in, load_baseline, package_name,
+    parse_source, read_manifest, save_baseline, say,
+};
+
+const BASELINE: &str = ".panics.yaml";
+
+/// How many ways a library has to panic.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct PanicCounts {
+    panics: usize,
+    unwraps: usize,
+    expects: usize,
+    asserts: usize,
+}
+
+impl PanicCounts {
+    fn kinds(&self) -> [(&'static str, usize); 4] {
+        [
+            ("panic!(s)", self.panics),
+            ("unwrap(s)", self.unwraps),
+            ("expect(s)", self.expects),
+            ("assert!(s)", self.asserts),
+        ]
+    }
+
+    fn add(&mut self, other: &PanicCounts) {
+        self.panics += other.panics;
+        self.unwraps += other.unwraps;
+        self.expects += other.expects;
+        self.asserts += other.asserts;
+    }
+}
+
+impl fmt::Display for PanicCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kinds: Vec<String> = self
+            .kinds()
+            .iter()
+            .map(|(k, n)| format!("{} {}", n, k))
+            .collect();
+        write!(f, "{}", kinds.join(", "))
+    }
+}
+
+impl<'ast> Visit<'ast> for PanicCounts {
+    fn visit_item(&mut self, node: &'ast Item) {
+        if !is_test_code(attrs_of(node)) {
+            visit::visit_item(self, node);
+        }
+    }
+
+    fn visit_impl_item_method(&mut self, node: &'ast syn::ImplItemMethod) {
+        if !is_test_code(&node.attrs) {
+            visit::visit_impl_item_method(self, node);
+        }
+    }
+
+    fn visit_expr_method_call(&mut self, node: &'ast syn::ExprMethodCall) {
+        match node.method.to_string().as_str() {
+            "unwrap" if node.args.is_empty() => self.unwraps += 1,
+            "expect" if node.args.len() == 1 => self.expects += 1,
+            _ => (),
+        }
+        visit::visit_expr_method_call(self, node);
+    }
+
+    fn visit_macro(&mut self, node: &'ast syn::Macro) {
+        let name = node.path.segments.last().map(|s| s.ident.to_string());
+        match name.as_deref() {
+            Some("panic" | "todo" | "unimplemented") => self.panics += 1,
+            Some("assert" | "assert_eq" | "assert_ne") => self.asserts += 1,
+            _ => (),
+        }
+        visit::visit_macro(self, node);
+    }
+}
+
+/// The panic counts of each library, keyed by package name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Baseline {
+    crates: BTreeMap<String, PanicCounts>,
+}
+
+/// Where the crate's library target is, if it has one.
+fn library_root(cargo_toml: &str) -> Option<PathBuf> {
+    let dir = Path::new(cargo_toml).parent()?;
+    let manifest = read_manifest(Path::new(cargo_toml))?;
+    let path = manifest
+        .get("lib")
+        .and_then(|lib| lib.get("path"))
+        .and_then(|path| path.as_str())
+        .unwrap_or("src/lib.rs");
+    Some(dir.join(path)).filter(|root| root.is_file())
+}
+
+/// Counts the ways the crate's library can panic, returning the counts and how
+/// many files were looked at, or None if the crate has no library.
+fn count_crate(cargo_toml: &str, config: &Config) -> Option<(PanicCounts, usize)> {
+    let root = library_root(cargo_toml)?;
+    let mut files = root.parent().map(library_files_in).unwrap_or_default();
+    files.retain(|f| !config.is_excluded(f));
+
+    let mut result = PanicCounts::default();
+    for file in files.iter() {
+        let ast = fs::read_to_string(file)
+            .ok()
+            .and_then(|c| parse_source(&c).ok());
+        match ast {
+            Some(ast) => {
+                let mut counts = PanicCounts::default();
+                counts.visit_file(&ast);
+                result.add(&counts);
+            }
+            None => eprintln!("Unable to parse {}, skipping it", file.display()),
+        }
+    }
+    Some((result, files.len()))
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-panic-guard");
+    let mut summary = Summary::new("salt-panic-guard");
+    let known = [PORCELAIN, OUTPUT, COLOR];
+    let (flags, files) = parse_args("salt-panic-guard", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut report = Report::from_flags("salt-panic-guard", &flags, &config).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+
+    let mut baseline: Baseline = load_baseline(Path::new(BASELINE)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut updated = false;
+    for cargo_toml in group_by_crate(&files).keys() {
+        let policy = config.policy_for(Path::new(cargo_toml));
+        if !policy.forbid_panic_growth {
+            continue;
+        }
+        let (observed, file_count) = match count_crate(cargo_toml, &config) {
+            Some(counted) => counted,
+            None => continue,
+        };
+        let name = package_name(cargo_toml).unwrap_or_else(|| cargo_toml.to_string());
+        summary.crates += 1;
+        summary.files += file_count;
+
+        let expected = match baseline.crates.get(&name) {
+            Some(expected) => *expected,
+            None => {
+                say!("Started tracking the panics in {}: {}", name, observed);
+                baseline.crates.insert(name, observed);
+                updated = true;
+                continue;
+            }
+        };
+        let mut grew = false;
+        for ((kind, was), (_, is)) in expected.kinds().iter().zip(observed.kinds().iter()) {
+            if is > was {
+                let message = format!(
+                    "{} went from {} to {} {}, but its library may not gain ways to panic",
+                    name, was, is, kind
+                );
+                let mut violation = Violation::general(message).for_lint("panic-guard");
+                if policy.advisory {
+                    violation = violation.advisory();
+                }
+                report.record(&violation);
+                grew = true;
+            }
+        }
+        if grew && policy.advisory {
+            eprintln!(
+                "{} is in an advisory tier, so this won't block the commit.",
+                name
+            );
+        } else if grew {
+            summary.violations += 1;
+            if env::var("UPDATE_ANYWAY").map(|v| v == "1").unwrap_or(false) {
+                baseline.crates.insert(name, observed);
+                updated = true;
+            }
+        } else if observed != expected {
+            say!(
+                "Thanks for making {} less likely to panic!  It now has {}",
+                name,
+                observed
+            );
+            baseline.crates.insert(name, observed);
+            updated = true;
+        }
+    }
+
+    if updated {
+        save_baseline(Path::new(BASELINE), &baseline).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+    }
+    let exit_code = if summary.violations > 0 {
+        1
+    } else if updated {
+        say!("Please run `git add {}` and retry your commit.", BASELINE);
+        2
+    } else {
+        0
+    };
+    report.finish(&summary);
+    summary.print();
+    process::exit(exit_code);
+}
@@ -0,0 +1,111 @@
+//! `salt-rustdoc-links` is a `pre-commit` hook that catches broken intra-doc links
+//! before CI's doc build does.
+//!
+//! Like `salt-clip` it documents each affected crate once (private items included,
+//! so links in internal docs get checked too), and only reports the broken links
+//! that live in the files being committed.
+
+#![forbid(unsafe_code)]
+
+use std::collections::BTreeSet;
+use std::env;
+use std::path::Path;
+use std::process::{self, Output};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::Violation;
+use salt_spray::config::Config;
+use salt_spray::output::{self, COLOR};
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::toolchain::{self, CARGO_PATH, RUSTDOCFLAGS};
+use salt_spray::{diagnostics_for, group_by_workspace};
+
+static BROKEN_LINKS: &str = "-D rustdoc::broken_intra_doc_links";
+
+/// The one of the files that the (human readable) diagnostic points into.
+fn file_of<'a>(diagnostic: &str, files: &'a BTreeSet<String>) -> Option<&'a String> {
+    let (_, at) = diagnostic.split_once("--> ")?;
+    let path = at.split(':').next()?;
+    files.iter().find(|f| f.ends_with(path))
+}
+
+/// Documents a crate, returning the broken links in files from the given set.
+fn check_crate(cargo_toml: &str, files: &BTreeSet<String>, config: &Config) -> Vec<Violation> {
+    let mut result = Vec::new();
+    let manifest = Path::new(cargo_toml);
+    let config = config.for_crate(manifest).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        config.clone()
+    });
+    let mut cmd = toolchain::cargo_for(manifest, &config);
+    // On top of whatever rustdoc flags the crate is already documented with
+    let flags = toolchain::encoded_flags(manifest, &config, None, RUSTDOCFLAGS, BROKEN_LINKS);
+    cmd.env("CARGO_ENCODED_RUSTDOCFLAGS", flags);
+    cmd.args([
+        "doc",
+        "--no-deps",
+        "--document-private-items",
+        "--quiet",
+        "--manifest-path",
+    ]);
+    cmd.arg(std::path::absolute(manifest).unwrap_or_else(|_| manifest.to_path_buf()));
+
+    match cmd.output() {
+        Ok(Output { stderr, .. }) => {
+            let stderr = output::clean(&stderr);
+            for broken_link in diagnostics_for(&stderr, files) {
+                let file = file_of(broken_link, files).map_or(cargo_toml, String::as_str);
+                let violation = Violation::new(file, broken_link);
+                result.push(violation.for_lint("rustdoc::broken_intra_doc_links"));
+            }
+        }
+        Err(e) => result.push(Violation::new(
+            cargo_toml,
+            format!("Unable to run cargo doc: {}", e),
+        )),
+    }
+    result
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-rustdoc-links");
+    let mut summary = Summary::new("salt-rustdoc-links");
+    let known = [PORCELAIN, OUTPUT, CARGO_PATH, COLOR];
+    let (flags, args) = parse_args("salt-rustdoc-links", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    if let Some(path) = flags.iter().find_map(|f| f.strip_prefix(CARGO_PATH)) {
+        toolchain::set_cargo_path(path);
+    }
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut report =
+        Report::from_flags("salt-rustdoc-links", &flags, &config).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+    let files: Vec<String> = args
+        .into_iter()
+        .filter(|a| !config.is_excluded(Path::new(a)))
+        .collect();
+    let files_by_workspace = group_by_workspace(&files);
+
+    for (workspace, files_by_crate) in files_by_workspace.iter() {
+        if files_by_workspace.len() > 1 {
+            eprintln!("\n== {} ==", workspace.display());
+        }
+        for (cargo_toml, files) in files_by_crate.iter() {
+            let violations = check_crate(cargo_toml, files, &config);
+            report.record_all(&violations);
+            summary.violations += violations.iter().filter(|v| !v.advisory).count();
+            summary.files += files.len();
+        }
+        summary.crates += files_by_crate.len();
+    }
+    report.finish(&summary);
+    summary.print();
+    process::exit(summary.exit_code());
+}
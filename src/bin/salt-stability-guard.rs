@@ -0,0 +1,389 @@
+//! `salt-stability-guard` keeps unstable API out of crates that promise a stable
+//! one.  In the library files of a commit, each new public item (function, method
+//! of an inherent impl, type, trait, const, static, module, or re-export) is
+//! rejected if it
+//!
+//! - is `#[doc(hidden)]`, which leaves it usable by anyone while the docs (and so
+//!   semver, as far as readers of them know) pretend it isn't there,
+//! - has a name starting with `__`, the other way of saying "public, but don't
+//!   use this", or
+//! - mentions an unstable feature in its `#[cfg]` without requiring it, e.g.
+//!   `#[cfg(any(feature = "unstable", docsrs))]`, which lets it in without the
+//!   feature.
+//!
+//! Items that are behind an unstable feature (`#[cfg(feature = "unstable")]`, or
+//! `all(...)` with it, on the item or on a module or impl it's in, including the
+//! `mod foo;` a file is declared with) are left alone, since that's where unstable
+//! API is meant to go.  So are items that already
+//! existed at HEAD, test code, and what's in `#[doc(hidden)]` (or `__`) modules,
+//! which are covered by the module being let in.  An unstable feature that's on by
+//! default (directly, or through a feature `default` turns on) is rejected too,
+//! since then so is everything behind it.
+//!
+//! It only checks the crates whose policy asks for it, e.g.
+//!
+//! ```toml
+//! [tier-policy.tier1]
+//! stable-api = true
+//!
+//! [stability-guard]
+//! unstable-features = ["unstable"]  # the default
+//! ```
+//!
+//! or with `stable-api = true` in the `[policy]` of a crate's own config (which can
+//! have its own `[stability-guard]` too).  Crates in advisory tiers get told about
+//! what it finds, but it doesn't fail the commit.
+
+#![forbid(unsafe_code)]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use syn::{Attribute, Item, Lit, Meta, NestedMeta};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::{Span, Violation};
+use salt_spray::config::{Config, TierPolicy};
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::{
+    attrs_of, default_features, find_manifest, is_test_code, parse_source, public_items_in,
+    read_committed, target_kind,
+};
+
+/// How much of an item is behind an unstable feature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Gate {
+    /// Its cfgs don't mention an unstable feature at all.
+    Ungated,
+    /// Its cfgs mention one, but let it in without it.
+    Partly,
+    /// It's only there with an unstable feature.
+    Gated,
+}
+
+/// A public item, along with what it does to hide itself.
+struct PublicItem {
+    /// e.g. "pub fn foo::Bar::parse"
+    description: String,
+    /// Whether it's `#[doc(hidden)]`, itself or through the impl it's in.
+    hidden: bool,
+    /// Whether its name (or one of the names it re-exports) starts with `__`.
+    underscored: bool,
+    gate: Gate,
+    /// Where its name is.
+    span: Span,
+}
+
+fn is_hidden(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|a| a.path.is_ident("doc"))
+        .any(|attr| match attr.parse_meta() {
+            Ok(Meta::List(list)) => list
+                .nested
+                .iter()
+                .any(|n| matches!(n, NestedMeta::Meta(Meta::Path(p)) if p.is_ident("hidden"))),
+            _ => false,
+        })
+}
+
+/// Is the predicate `feature = "..."` for one of the unstable features?
+fn is_unstable_feature(meta: &Meta, unstable: &[String]) -> bool {
+    match meta {
+        Meta::NameValue(nv) if nv.path.is_ident("feature") => {
+            matches!(&nv.lit, Lit::Str(s) if unstable.contains(&s.value()))
+        }
+        _ => false,
+    }
+}
+
+/// Is the cfg predicate only true with one of the unstable features?
+fn requires(meta: &Meta, unstable: &[String]) -> bool {
+    match meta {
+        Meta::List(list) if list.path.is_ident("all") => list
+            .nested
+            .iter()
+            .any(|n| matches!(n, NestedMeta::Meta(m) if requires(m, unstable))),
+        _ => is_unstable_feature(meta, unstable),
+    }
+}
+
+/// Does the cfg predicate ask for one of the unstable features somewhere?  What's
+/// under a `not(...)` doesn't count, since that's the stable side of the gate.
+fn mentions(meta: &Meta, unstable: &[String]) -> bool {
+    match meta {
+        Meta::List(list) if list.path.is_ident("any") || list.path.is_ident("all") => list
+            .nested
+            .iter()
+            .any(|n| matches!(n, NestedMeta::Meta(m) if mentions(m, unstable))),
+        _ => is_unstable_feature(meta, unstable),
+    }
+}
+
+fn gate_of(attrs: &[Attribute], unstable: &[String]) -> Gate {
+    let mut gate = Gate::Ungated;
+    for attr in attrs.iter().filter(|a| a.path.is_ident("cfg")) {
+        let predicate = match attr.parse_meta() {
+            Ok(Meta::List(list)) => list.nested.into_iter().next(),
+            _ => None,
+        };
+        if let Some(NestedMeta::Meta(meta)) = predicate {
+            if requires(&meta, unstable) {
+                return Gate::Gated;
+            } else if mentions(&meta, unstable) {
+                gate = Gate::Partly;
+            }
+        }
+    }
+    gate
+}
+
+/// The file's public items, each with what hides or gates it (the modules and
+/// impl it's in included, on top of the given gate of the file's module), or None
+/// if it doesn't parse.
+fn items_in(contents: &str, unstable: &[String], gate: Gate) -> Option<Vec<PublicItem>> {
+    let ast = parse_source(contents).ok()?;
+    let mut result = Vec::new();
+    for item in public_items_in(&ast.items) {
+        let within = &item.within;
+        if is_test_code(item.attrs) || within.iter().any(|i| is_test_code(attrs_of(i))) {
+            continue;
+        }
+        // What's in a hidden module was let in along with it
+        let in_hidden_module = within.iter().any(|i| {
+            matches!(i, Item::Mod(m) if is_hidden(&m.attrs) || m.ident.to_string().starts_with("__"))
+        });
+        if in_hidden_module {
+            continue;
+        }
+        let in_hidden_impl = within
+            .iter()
+            .any(|i| matches!(i, Item::Impl(i) if is_hidden(&i.attrs)));
+        let gate = within
+            .iter()
+            .map(|i| gate_of(attrs_of(i), unstable))
+            .fold(gate.max(gate_of(item.attrs, unstable)), Gate::max);
+        result.push(PublicItem {
+            description: item.description,
+            hidden: in_hidden_impl || is_hidden(item.attrs),
+            underscored: item.names.iter().any(|n| n.starts_with("__")),
+            gate,
+            span: item.span,
+        });
+    }
+    Some(result)
+}
+
+/// The file a module's file is declared in (with `mod foo;`), along with the
+/// module's name, going by where rustc looks for the files of modules.  Crate
+/// roots have none, and nor do modules with a `#[path]`, as far as this knows.
+fn declaring_file(path: &Path) -> Option<(PathBuf, String)> {
+    let stem = path.file_stem()?.to_str()?;
+    let dir = path.parent()?;
+    let (name, dir) = match stem {
+        "lib" | "main" => return None,
+        "mod" => (dir.file_name()?.to_str()?, dir.parent()?),
+        _ => (stem, dir),
+    };
+    let mut candidates = vec![dir.join("mod.rs"), dir.join("lib.rs"), dir.join("main.rs")];
+    if let (Some(parent), Some(dir_name)) = (dir.parent(), dir.file_name()) {
+        let mut file = dir_name.to_os_string();
+        file.push(".rs");
+        candidates.push(parent.join(file));
+    }
+    let file = candidates.into_iter().find(|c| c.is_file())?;
+    Some((file, name.to_string()))
+}
+
+/// Whether the module in the given file is hidden, and what gate it's behind, by
+/// way of its `mod foo;` declaration (and the ones of the modules it's in).
+fn declared_gate(path: &Path, unstable: &[String]) -> (bool, Gate) {
+    let (file, name) = match declaring_file(path) {
+        Some(declaring) => declaring,
+        None => return (false, Gate::Ungated),
+    };
+    let declaration = fs::read_to_string(&file)
+        .ok()
+        .and_then(|contents| parse_source(&contents).ok())
+        .and_then(|ast| {
+            ast.items.into_iter().find_map(|item| match item {
+                Item::Mod(m) if m.content.is_none() && m.ident == name => Some(m),
+                _ => None,
+            })
+        });
+    match declaration {
+        Some(m) => {
+            let (hidden, gate) = declared_gate(&file, unstable);
+            let hidden = hidden || is_hidden(&m.attrs) || name.starts_with("__");
+            (hidden, gate.max(gate_of(&m.attrs, unstable)))
+        }
+        None => (false, Gate::Ungated),
+    }
+}
+
+/// How to put an item behind the first of the unstable features (if there are
+/// any), or else make it private.
+fn gating_suggestion(unstable: &[String]) -> String {
+    match unstable.first() {
+        Some(feature) => format!(
+            "put it behind #[cfg(feature = \"{}\")], or make it private",
+            feature
+        ),
+        None => "make it private".to_string(),
+    }
+}
+
+/// Checks one file, returning what's wrong with its new public items.
+fn check_file(filename: &str, unstable: &[String]) -> Vec<Violation> {
+    let contents = match fs::read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(e) => {
+            return vec![Violation::new(
+                filename,
+                format!("Unable to read {}: {}", filename, e),
+            )]
+        }
+    };
+    // What's in a gated (or hidden) module was let in along with it
+    let (hidden, gate) = declared_gate(Path::new(filename), unstable);
+    if hidden || gate == Gate::Gated {
+        return Vec::new();
+    }
+    let items = match items_in(&contents, unstable, gate) {
+        Some(items) => items,
+        None => {
+            eprintln!("Unable to parse {}, skipping it", filename);
+            return Vec::new();
+        }
+    };
+    let existing: BTreeSet<String> = read_committed("HEAD", filename)
+        .and_then(|old| items_in(&old, unstable, gate))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|i| i.description)
+        .collect();
+    let mut result = Vec::new();
+    for item in items {
+        if item.gate == Gate::Gated || existing.contains(&item.description) {
+            continue;
+        }
+        let mut problems = Vec::new();
+        if item.hidden {
+            problems.push(format!(
+                "{} is #[doc(hidden)], which leaves it in the public API without its docs",
+                item.description
+            ));
+        }
+        if item.underscored {
+            problems.push(format!(
+                "{} has a `__` name, which leaves it in the public API while saying it isn't",
+                item.description
+            ));
+        }
+        if item.gate == Gate::Partly {
+            problems.push(format!(
+                "{} mentions an unstable feature in its #[cfg], but it's there without the feature too",
+                item.description
+            ));
+        }
+        result.extend(problems.into_iter().map(|problem| {
+            Violation::new(filename, problem)
+                .at(item.span)
+                .for_lint("stability-guard")
+                .suggesting(gating_suggestion(unstable))
+        }));
+    }
+    result
+}
+
+/// Checks that none of the unstable features are on by default.
+fn check_manifest(manifest: &Path, unstable: &[String]) -> Vec<Violation> {
+    let defaults = default_features(manifest);
+    unstable
+        .iter()
+        .filter(|f| defaults.contains(*f))
+        .map(|feature| {
+            Violation::new(
+                &manifest.to_string_lossy(),
+                format!(
+                    "The `{}` feature is on by default, so the unstable API behind it is too",
+                    feature
+                ),
+            )
+            .for_lint("stability-guard")
+            .suggesting(format!("take `{}` out of `default`", feature))
+        })
+        .collect()
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-stability-guard");
+    let mut summary = Summary::new("salt-stability-guard");
+    let known = [PORCELAIN, OUTPUT, COLOR];
+    let (flags, files) = parse_args("salt-stability-guard", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut report =
+        Report::from_flags("salt-stability-guard", &flags, &config).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+
+    // The crates with a stable API, along with their unstable features
+    let mut crates: BTreeMap<PathBuf, (TierPolicy, Vec<String>)> = BTreeMap::new();
+    let mut checked = Vec::new();
+    for file in files.iter() {
+        let manifest = match find_manifest(file) {
+            Some(manifest) => manifest,
+            None => continue,
+        };
+        let is_library = file.ends_with(".rs") && target_kind(file) == Some("lib");
+        let is_manifest = Path::new(file).file_name() == Some("Cargo.toml".as_ref());
+        if config.is_excluded(Path::new(file)) || !(is_library || is_manifest) {
+            continue;
+        }
+        let policy = config.policy_for(&manifest);
+        if !policy.stable_api {
+            continue;
+        }
+        let unstable = &crates
+            .entry(manifest.clone())
+            .or_insert_with(|| {
+                let crate_config = config.for_crate(&manifest).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    config.clone()
+                });
+                (policy, crate_config.stability_guard.unstable_features)
+            })
+            .1;
+        if is_library {
+            summary.files += 1;
+            checked.push((manifest, check_file(file, unstable)));
+        }
+    }
+    for (manifest, (_, unstable)) in crates.iter() {
+        checked.push((manifest.clone(), check_manifest(manifest, unstable)));
+    }
+
+    for (manifest, mut violations) in checked {
+        if crates[&manifest].0.advisory {
+            violations = violations.into_iter().map(Violation::advisory).collect();
+        }
+        report.record_all(&violations);
+        summary.violations += violations.iter().filter(|v| !v.advisory).count();
+        summary.count_lints(&violations);
+    }
+    summary.crates = crates.len();
+    report.finish(&summary);
+    summary.print();
+    process::exit(summary.exit_code());
+}
@@ -0,0 +1,290 @@
+//! `salt-submodule-guard` checks the submodule pointer bumps in a commit, for
+//! monorepos that vendor components as git submodules.  Each submodule the commit
+//! bumps (or adds) has to
+//!
+//! 1. point at a commit that's on the submodule's allowed branch, so nobody
+//!    vendors a commit that only exists on their laptop or a feature branch, and
+//! 2. come with a changelog entry, i.e. the commit has to add a line mentioning
+//!    the submodule (by its path or its directory name) to one of the changelogs.
+//!
+//! The allowed branch is the one .gitmodules gives the submodule, or else the one
+//! in the `[submodule-guard]` section of salt-spray.toml, or else whichever
+//! branch the submodule's remote has checked out.  The check goes by what the
+//! submodule last fetched from `origin`, unless `fetch` is set:
+//!
+//! ```toml
+//! [submodule-guard]
+//! branch = "release"
+//! changelog = ["CHANGELOG.md", "vendor/**/CHANGES.md"]
+//! fetch = true
+//! advisory = false
+//! ```
+
+#![forbid(unsafe_code)]
+
+use std::collections::BTreeMap;
+use std::env;
+use std::path::Path;
+use std::process::{self, Command, Output};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::Violation;
+use salt_spray::config::{glob_matches, Config};
+use salt_spray::find_repo_root;
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+
+/// The mode git gives submodules (gitlinks) in the index.
+static GITLINK: &str = "160000";
+
+/// A staged change to a submodule's pointer.
+struct Bump {
+    path: String,
+    commit: String,
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<Output, String> {
+    Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| format!("Unable to run git: {}", e))
+}
+
+/// Runs git, returning what it printed if it succeeded.
+fn git_stdout(dir: &Path, args: &[&str]) -> Option<String> {
+    match git(dir, args) {
+        Ok(output) if output.status.success() => {
+            Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        }
+        _ => None,
+    }
+}
+
+/// The staged changes, as the submodules being bumped and every path being
+/// changed.
+fn staged_changes(root: &Path) -> Result<(Vec<Bump>, Vec<String>), String> {
+    let output = git(
+        root,
+        &[
+            "diff",
+            "--cached",
+            "--raw",
+            "-z",
+            "--no-renames",
+            "--no-abbrev",
+        ],
+    )?;
+    if !output.status.success() {
+        return Err(format!(
+            "Unable to list the staged changes: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    // Each change is ":old-mode new-mode old-sha new-sha status" then its path
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut fields = stdout.split('\0').filter(|f| !f.is_empty());
+    let mut bumps = Vec::new();
+    let mut paths = Vec::new();
+    while let (Some(change), Some(path)) = (fields.next(), fields.next()) {
+        let change: Vec<&str> = change.trim_start_matches(':').split(' ').collect();
+        if let [_, new_mode, _, new_commit, _] = change[..] {
+            if new_mode == GITLINK {
+                bumps.push(Bump {
+                    path: path.to_string(),
+                    commit: new_commit.to_string(),
+                });
+            }
+        }
+        paths.push(path.to_string());
+    }
+    Ok((bumps, paths))
+}
+
+/// The names of the submodules in .gitmodules, by path.
+fn submodule_names(root: &Path) -> BTreeMap<String, String> {
+    let listing = git_stdout(
+        root,
+        &[
+            "config",
+            "-f",
+            ".gitmodules",
+            "--get-regexp",
+            r"^submodule\..*\.path$",
+        ],
+    )
+    .unwrap_or_default();
+    listing
+        .lines()
+        .filter_map(|line| {
+            let (key, path) = line.split_once(' ')?;
+            let name = key.strip_prefix("submodule.")?.strip_suffix(".path")?;
+            Some((path.to_string(), name.to_string()))
+        })
+        .collect()
+}
+
+/// The branch the submodule's commits have to be on, or None for whatever its
+/// remote has checked out.
+fn allowed_branch(root: &Path, name: Option<&String>, config: &Config) -> Option<String> {
+    let from_gitmodules = name.and_then(|name| {
+        let key = format!("submodule.{}.branch", name);
+        git_stdout(root, &["config", "-f", ".gitmodules", "--get", &key])
+    });
+    match from_gitmodules {
+        // "." means the same branch as the superproject
+        Some(branch) if branch == "." => git_stdout(root, &["rev-parse", "--abbrev-ref", "HEAD"]),
+        Some(branch) => Some(branch),
+        None => config.submodule_guard.branch.clone(),
+    }
+}
+
+/// What's wrong with the commit a submodule is being bumped to, if anything.
+fn branch_problem(dir: &Path, commit: &str, branch: Option<&str>, fetch: bool) -> Option<String> {
+    if !dir.join(".git").exists() {
+        return Some("the submodule isn't checked out, so the bump can't be verified".to_string());
+    }
+    if fetch {
+        let mut args = vec!["fetch", "--quiet", "origin"];
+        args.extend(branch);
+        if git_stdout(dir, &args).is_none() {
+            eprintln!(
+                "Unable to fetch origin in {}, going by what it has",
+                dir.display()
+            );
+        }
+    }
+    let remote = match branch {
+        Some(branch) => format!("refs/remotes/origin/{}", branch),
+        None => "refs/remotes/origin/HEAD".to_string(),
+    };
+    let described = branch.unwrap_or("its default branch");
+    let exists = format!("{}^{{commit}}", commit);
+    if git_stdout(dir, &["cat-file", "-e", &exists]).is_none() {
+        return Some("that commit isn't in the submodule, has it been pushed?".to_string());
+    } else if git_stdout(dir, &["rev-parse", "--verify", "--quiet", &remote]).is_none() {
+        return Some(format!(
+            "the submodule has no origin/{} to check it against",
+            described
+        ));
+    }
+    match git(dir, &["merge-base", "--is-ancestor", commit, &remote]) {
+        Ok(output) if output.status.success() => None,
+        Ok(output) if output.status.code() == Some(1) => {
+            Some(format!("that commit isn't on {}", described))
+        }
+        Ok(output) => Some(format!(
+            "unable to check that commit against {}: {}",
+            described,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Err(e) => Some(e),
+    }
+}
+
+/// The lines the commit adds to the changelogs.
+fn changelog_additions(root: &Path, paths: &[String], config: &Config) -> Vec<String> {
+    let mut result = Vec::new();
+    let changelogs = paths.iter().filter(|p| {
+        let path = Path::new(p.as_str());
+        config
+            .submodule_guard
+            .changelog
+            .iter()
+            .any(|g| glob_matches(g, path))
+    });
+    for changelog in changelogs {
+        let diff =
+            git_stdout(root, &["diff", "--cached", "-U0", "--", changelog]).unwrap_or_default();
+        result.extend(
+            diff.lines()
+                .filter(|l| l.starts_with('+') && !l.starts_with("+++"))
+                .map(|l| l[1..].to_string()),
+        );
+    }
+    result
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-submodule-guard");
+    let mut summary = Summary::new("salt-submodule-guard");
+    let known = [PORCELAIN, OUTPUT, COLOR];
+    let (flags, _) = parse_args("salt-submodule-guard", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut report =
+        Report::from_flags("salt-submodule-guard", &flags, &config).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+    let root = find_repo_root().unwrap_or_else(|| {
+        eprintln!("salt-submodule-guard has to run inside of a git repo");
+        process::exit(-1);
+    });
+
+    let (bumps, paths) = staged_changes(&root).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let names = submodule_names(&root);
+    let additions = changelog_additions(&root, &paths, &config);
+
+    let mut problems = Vec::new();
+    for bump in bumps.iter() {
+        summary.files += 1;
+        let short = &bump.commit[..bump.commit.len().min(12)];
+        let name = names.get(&bump.path);
+        let branch = allowed_branch(&root, name, &config);
+        let dir = root.join(&bump.path);
+        let mut complaints: Vec<String> = Vec::new();
+        complaints.extend(branch_problem(
+            &dir,
+            &bump.commit,
+            branch.as_deref(),
+            config.submodule_guard.fetch,
+        ));
+
+        let dir_name = Path::new(&bump.path)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| bump.path.clone());
+        if !additions
+            .iter()
+            .any(|l| l.contains(&bump.path) || l.contains(&dir_name))
+        {
+            complaints.push(format!(
+                "no changelog ({}) gained an entry mentioning {}",
+                config.submodule_guard.changelog.join(", "),
+                bump.path
+            ));
+        }
+
+        for complaint in complaints {
+            let message = format!("bumped to {}, but {}", short, complaint);
+            let violation = Violation::new(&bump.path, message).for_lint("submodule-guard");
+            problems.push(if config.submodule_guard.advisory {
+                violation.advisory()
+            } else {
+                violation
+            });
+        }
+    }
+
+    report.record_all(&problems);
+    summary.count_lints(&problems);
+    if !problems.is_empty() {
+        if config.submodule_guard.advisory {
+            eprintln!("Submodule bumps are advisory, so this won't block the commit.");
+        } else {
+            summary.violations = problems.len();
+        }
+    }
+    report.finish(&summary);
+    summary.print();
+    process::exit(summary.exit_code());
+}
@@ -0,0 +1,218 @@
+//! `salt-test-name-guard` keeps tests where they belong.  In the files in a commit
+//! it rejects
+//!
+//! - `#[test]` functions outside of `#[cfg(test)]` modules, other than in the
+//!   integration tests under `tests/`, since the test itself is left out of builds
+//!   that aren't tests but the imports and helpers it uses aren't, so they end up
+//!   as dead code (and warnings) there,
+//! - `#[test]` functions in the main module of a binary (`src/main.rs` and the
+//!   like), wherever they are in it, since tests of code that only a binary can
+//!   reach tend to be tests of its argument parsing; move the logic into the
+//!   library and test it there, or test the binary from `tests/`, and
+//! - when a pattern is configured, tests whose names don't match it:
+//!
+//! ```toml
+//! [test-name-guard]
+//! pattern = "^(it_|test_)"
+//! advisory = false
+//! ```
+//!
+//! Attribute macros like `#[tokio::test]` count as `#[test]`.  Crates whose policy
+//! is advisory (or every crate, with `advisory = true`) get told about problems,
+//! but they don't fail the commit.
+
+#![forbid(unsafe_code)]
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use regex::Regex;
+use syn::{Attribute, Item, Meta};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::Violation;
+use salt_spray::config::Config;
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::{find_manifest, needs_test, parse_source, read_manifest, target_kind};
+
+/// Is this `#[test]`, or an attribute macro like `#[tokio::test]`?
+fn is_test(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .any(|a| a.path.segments.last().is_some_and(|s| s.ident == "test"))
+}
+
+/// Is there a `#[cfg(test)]` (or the like) among the attributes?
+fn is_cfg_test(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|a| a.path.is_ident("cfg"))
+        .any(|a| match a.parse_meta() {
+            Ok(Meta::List(list)) => list.nested.first().is_some_and(needs_test),
+            _ => false,
+        })
+}
+
+/// Is the file the root of one of its crate's binaries?
+fn is_bin_root(filename: &str) -> bool {
+    let manifest = match find_manifest(filename) {
+        Some(manifest) => manifest,
+        None => return false,
+    };
+    let dir = manifest.parent().unwrap_or(Path::new(""));
+    let within = match Path::new(filename).strip_prefix(dir) {
+        Ok(within) => within,
+        Err(_) => return false,
+    };
+    let autodiscovered = within == Path::new("src/main.rs")
+        || within.parent() == Some(Path::new("src/bin"))
+        || (within.starts_with("src/bin")
+            && within.file_name().is_some_and(|n| n == "main.rs")
+            && within.components().count() == 4);
+    // Binaries declared in Cargo.toml can be anywhere
+    let manifest = read_manifest(&manifest).unwrap_or_default();
+    let declared = manifest
+        .get("bin")
+        .and_then(|b| b.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|b| b.get("path")?.as_str())
+        .any(|path| within == Path::new(path));
+    autodiscovered || declared
+}
+
+/// Where the tests in a file may go.
+struct Placement {
+    /// Whether tests have to be inside of a `#[cfg(test)]` module.
+    cfg_test: bool,
+    /// Whether the file is a binary's main module, which may not have any tests.
+    bin_root: bool,
+}
+
+/// Walks the items, pushing their problems onto `problems`.  `in_cfg_test` is
+/// whether they're inside of a `#[cfg(test)]` module.
+fn check_items(
+    file: &str,
+    items: &[Item],
+    in_cfg_test: bool,
+    placement: &Placement,
+    pattern: Option<&Regex>,
+    problems: &mut Vec<Violation>,
+) {
+    for item in items {
+        match item {
+            Item::Fn(f) if is_test(&f.attrs) => {
+                let name = f.sig.ident.to_string();
+                let problem = |message: String, suggestion: &str| {
+                    Violation::new(file, message)
+                        .at(f.sig.ident.span())
+                        .for_lint("test-name-guard")
+                        .suggesting(suggestion)
+                };
+                if placement.bin_root {
+                    problems.push(problem(
+                        format!("test {} is in a binary's main module", name),
+                        "test the library (or the binary, from tests/) instead",
+                    ));
+                } else if placement.cfg_test && !in_cfg_test && !is_cfg_test(&f.attrs) {
+                    problems.push(problem(
+                        format!("test {} isn't in a #[cfg(test)] module", name),
+                        "move it into one (or under tests/)",
+                    ));
+                }
+                if let Some(pattern) = pattern.filter(|p| !p.is_match(&name)) {
+                    problems.push(problem(
+                        format!("test {} doesn't match the naming pattern", name),
+                        &format!("rename it to match {}", pattern.as_str()),
+                    ));
+                }
+            }
+            Item::Mod(m) => {
+                if let Some((_, items)) = &m.content {
+                    let in_cfg_test = in_cfg_test || is_cfg_test(&m.attrs);
+                    check_items(file, items, in_cfg_test, placement, pattern, problems);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Checks the tests in the file, returning its problems.
+fn check_file(file: &str, pattern: Option<&Regex>) -> Vec<Violation> {
+    let ast = match fs::read_to_string(file).ok().map(|c| parse_source(&c)) {
+        Some(Ok(ast)) => ast,
+        _ => {
+            eprintln!("Unable to parse {}, skipping it", file);
+            return Vec::new();
+        }
+    };
+    let placement = Placement {
+        cfg_test: target_kind(file) != Some("test"),
+        bin_root: is_bin_root(file),
+    };
+    let mut problems = Vec::new();
+    let in_cfg_test = is_cfg_test(&ast.attrs);
+    check_items(
+        file,
+        &ast.items,
+        in_cfg_test,
+        &placement,
+        pattern,
+        &mut problems,
+    );
+    problems
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-test-name-guard");
+    let mut summary = Summary::new("salt-test-name-guard");
+    let known = [PORCELAIN, OUTPUT, COLOR];
+    let (flags, args) = parse_args("salt-test-name-guard", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut report =
+        Report::from_flags("salt-test-name-guard", &flags, &config).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+    let settings = &config.test_name_guard;
+    let pattern = settings.pattern.as_deref().map(|p| {
+        Regex::new(p).unwrap_or_else(|e| {
+            eprintln!(
+                "The pattern in [test-name-guard] isn't a valid regex: {}",
+                e
+            );
+            process::exit(-1);
+        })
+    });
+
+    let mut crates = BTreeSet::new();
+    for file in args.iter().filter(|a| a.ends_with(".rs")) {
+        if config.is_excluded(Path::new(file)) {
+            continue;
+        }
+        summary.files += 1;
+        crates.extend(find_manifest(file));
+        let mut problems = check_file(file, pattern.as_ref());
+        if settings.advisory || config.policy_for_file(Path::new(file)).advisory {
+            problems = problems.into_iter().map(Violation::advisory).collect();
+        }
+        report.record_all(&problems);
+        summary.violations += problems.iter().filter(|p| !p.advisory).count();
+        summary.count_lints(&problems);
+    }
+    summary.crates = crates.len();
+    report.finish(&summary);
+    summary.print();
+    process::exit(summary.exit_code());
+}
@@ -0,0 +1,640 @@
+//! `salt-workspace-dep-guard` keeps a workspace's dependencies in one place.  For
+//! each Cargo.toml in a commit that belongs to a workspace, it rejects
+//!
+//! - dependencies that two or more of the workspace's members have (or that are in
+//!   `[workspace.dependencies]` already) but that the manifest spells out for
+//!   itself, rather than inheriting them with `workspace = true`, and
+//! - version requirements that differ from the ones the other members (or
+//!   `[workspace.dependencies]`) have for the same dependency, e.g. `serde = "1"`
+//!   in one crate and `serde = "1.0.190"` in another.
+//!
+//! ```toml
+//! [workspace-dep-guard]
+//! min-members = 2       # how many members have to share a dependency
+//! ignore = ["cc"]       # dependencies members may spell out for themselves
+//! advisory = false
+//! ```
+//!
+//! Dependencies are told apart by the name they're depended on as, and every
+//! dependency table counts, including the dev and build ones and those under
+//! `[target.'cfg(...)']`.  With `--fix` it makes the members inherit what they
+//! can: a dependency missing from `[workspace.dependencies]` is moved there (with
+//! its version, path, git repository and such), and the member keeps only what's
+//! its own, i.e. the features it turns on and whether it's optional.  Dependencies
+//! whose spec doesn't match the one in `[workspace.dependencies]` are left for you
+//! to reconcile, as are differing versions.  Either way the manifests it changed
+//! need to be staged before retrying.
+//!
+//! Crates whose policy is advisory (or every crate, with `advisory = true`) get
+//! told about problems, but they don't fail the commit.
+
+#![forbid(unsafe_code)]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use toml_edit::{Document, DocumentMut, InlineTable, Item, Value};
+
+use salt_spray::args::parse_args;
+use salt_spray::check::{Span, Violation};
+use salt_spray::config::{Config, WorkspaceDepGuardConfig};
+use salt_spray::manifest::is_manifest;
+use salt_spray::output::COLOR;
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::{
+    find_workspace_root, package_name, read_manifest, repo_relative, say, workspace_members,
+};
+
+static FIX: &str = "--fix";
+
+/// The tables of a manifest that hold dependencies, at the top level and under
+/// each `[target.'cfg(...)']`.
+const DEPENDENCY_TABLES: [&str; 5] = [
+    "dependencies",
+    "dev-dependencies",
+    "dev_dependencies",
+    "build-dependencies",
+    "build_dependencies",
+];
+
+/// The keys of a dependency's spec that say where it comes from, which are the
+/// ones `[workspace.dependencies]` takes over when it's inherited.
+const SOURCE_KEYS: [&str; 8] = [
+    "version", "path", "git", "branch", "tag", "rev", "registry", "package",
+];
+
+/// Where a dependency comes from, and whether it wants its default features.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Spec {
+    /// The source keys of the spec, normalized so that specs meaning the same thing
+    /// compare equal: versions lose their `^`, and paths are made absolute.
+    source: BTreeMap<String, String>,
+    /// Its `default-features`, if it says.
+    default_features: Option<bool>,
+}
+
+/// One of the workspace's members depending on a crate.
+struct Use {
+    /// The (canonical) path of the member's manifest.
+    manifest: PathBuf,
+    /// The member's package name, for telling it apart in messages.
+    member: String,
+    /// The keys leading to the dependency table it's in, e.g. `["dependencies"]`.
+    table: Vec<String>,
+    /// The name it's depended on as.
+    name: String,
+    /// Whether it's inherited from `[workspace.dependencies]`.
+    inherited: bool,
+    spec: Spec,
+    /// Where its name is in the manifest.
+    span: Option<Span>,
+}
+
+/// What the workspace's members depend on, and what it has for them to inherit.
+struct Workspace {
+    /// The (canonical) path of the workspace's root manifest.
+    manifest: PathBuf,
+    /// `[workspace.dependencies]`, by name.
+    inheritable: BTreeMap<String, Spec>,
+    /// The dependencies of all of its members.
+    uses: Vec<Use>,
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn parse(manifest: &Path) -> Result<Document<String>, String> {
+    let contents = fs::read_to_string(manifest)
+        .map_err(|e| format!("Unable to read {}: {}", manifest.display(), e))?;
+    Document::parse(contents).map_err(|e| format!("Unable to parse {}: {}", manifest.display(), e))
+}
+
+/// Spells a version requirement the one way, since `1.2` already means `^1.2`.
+fn normalize_version(version: &str) -> String {
+    let version = version.trim();
+    version
+        .strip_prefix('^')
+        .unwrap_or(version)
+        .trim()
+        .to_string()
+}
+
+/// Reads a dependency's spec, with paths relative to `dir`, returning whether it's
+/// inherited along with it.
+fn read_spec(item: &toml::Value, dir: &Path) -> (bool, Spec) {
+    let mut spec = Spec::default();
+    if let Some(version) = item.as_str() {
+        spec.source
+            .insert("version".to_string(), normalize_version(version));
+        return (false, spec);
+    }
+    let table = match item.as_table() {
+        Some(table) => table,
+        None => return (false, spec),
+    };
+    for key in SOURCE_KEYS {
+        let value = match table.get(key) {
+            Some(value) => value,
+            None => continue,
+        };
+        let raw = match value.as_str() {
+            Some(raw) => raw.to_string(),
+            None => value.to_string(),
+        };
+        let normalized = match key {
+            "version" => normalize_version(&raw),
+            "path" => canonical(&dir.join(raw)).to_string_lossy().to_string(),
+            _ => raw,
+        };
+        spec.source.insert(key.to_string(), normalized);
+    }
+    spec.default_features = table
+        .get("default-features")
+        .or_else(|| table.get("default_features"))
+        .and_then(toml::Value::as_bool);
+    let inherited = table.get("workspace").and_then(toml::Value::as_bool) == Some(true);
+    (inherited, spec)
+}
+
+/// The dependency tables of a manifest, each with the keys leading to it.
+fn dependency_tables(manifest: &toml::Table) -> Vec<(Vec<String>, &toml::Table)> {
+    let mut result = Vec::new();
+    for key in DEPENDENCY_TABLES {
+        if let Some(table) = manifest.get(key).and_then(toml::Value::as_table) {
+            result.push((vec![key.to_string()], table));
+        }
+    }
+    let targets = manifest.get("target").and_then(toml::Value::as_table);
+    for (target, item) in targets.into_iter().flatten() {
+        for key in DEPENDENCY_TABLES {
+            if let Some(table) = item.get(key).and_then(toml::Value::as_table) {
+                let keys = vec!["target".to_string(), target.to_string(), key.to_string()];
+                result.push((keys, table));
+            }
+        }
+    }
+    result
+}
+
+/// The name of a dependency table, as it'd be written in its header.
+fn table_name(keys: &[String]) -> String {
+    match keys {
+        [target, cfg, table] if target == "target" => format!("target.'{}'.{}", cfg, table),
+        _ => keys.join("."),
+    }
+}
+
+/// Where the bytes in `range` are in `contents`.
+fn span_at(contents: &str, range: Range<usize>) -> Option<Span> {
+    let before = contents.get(..range.start)?;
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    let length = contents.get(range)?.chars().count();
+    Some(Span::new(line, column, length))
+}
+
+/// Where the name of the dependency in the table with the given keys is in the
+/// manifest, which only toml_edit keeps track of.
+fn key_span(doc: &Document<String>, keys: &[String], name: &str) -> Option<Span> {
+    let mut table = doc.as_table();
+    for key in keys {
+        table = table.get(key)?.as_table()?;
+    }
+    span_at(doc.raw(), table.key(name)?.span()?)
+}
+
+/// Everything the member with the given manifest depends on.
+fn read_uses(manifest: &Path, doc: &Document<String>) -> Vec<Use> {
+    let path = canonical(manifest);
+    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    let member = package_name(manifest).unwrap_or_else(|| manifest.display().to_string());
+    let table = match read_manifest(manifest) {
+        Some(table) => table,
+        None => return Vec::new(),
+    };
+    let mut result = Vec::new();
+    for (keys, table) in dependency_tables(&table) {
+        for (name, item) in table.iter() {
+            let (inherited, spec) = read_spec(item, &dir);
+            let span = key_span(doc, &keys, name);
+            result.push(Use {
+                manifest: path.clone(),
+                member: member.clone(),
+                table: keys.clone(),
+                name: name.to_string(),
+                inherited,
+                spec,
+                span,
+            });
+        }
+    }
+    // In the order they're in the manifest, rather than toml's sorted one
+    result.sort_by_key(|u| u.span.map(|s| (s.line, s.column)));
+    result
+}
+
+/// Reads the workspace with the given root, or None if it's a workspace of one.
+fn read_workspace(root: &Path) -> Result<Option<Workspace>, String> {
+    let manifest = root.join("Cargo.toml");
+    let table =
+        read_manifest(&manifest).ok_or_else(|| format!("Unable to read {}", manifest.display()))?;
+    let workspace = match table.get("workspace").and_then(toml::Value::as_table) {
+        Some(workspace) => workspace,
+        None => return Ok(None),
+    };
+    let dir = canonical(root);
+    let inheritable = workspace
+        .get("dependencies")
+        .and_then(toml::Value::as_table)
+        .into_iter()
+        .flatten()
+        .map(|(name, item)| (name.to_string(), read_spec(item, &dir).1))
+        .collect();
+    let mut uses = Vec::new();
+    for member in workspace_members(root) {
+        match parse(&member) {
+            Ok(doc) => uses.extend(read_uses(&member, &doc)),
+            Err(e) => eprintln!("{}, skipping it", e),
+        }
+    }
+    Ok(Some(Workspace {
+        manifest: canonical(&manifest),
+        inheritable,
+        uses,
+    }))
+}
+
+/// The dependencies of the member with the given manifest that it should inherit
+/// but doesn't, each with how many members depend on it.
+fn uninherited<'a>(
+    workspace: &'a Workspace,
+    manifest: &Path,
+    settings: &WorkspaceDepGuardConfig,
+) -> Vec<(&'a Use, usize)> {
+    let mut members: BTreeMap<&str, BTreeSet<&Path>> = BTreeMap::new();
+    for dependency in &workspace.uses {
+        let users = members.entry(&dependency.name).or_default();
+        users.insert(&dependency.manifest);
+    }
+    workspace
+        .uses
+        .iter()
+        .filter(|u| u.manifest == manifest && !u.inherited)
+        .filter(|u| !settings.ignore.contains(&u.name))
+        .map(|u| (u, members[u.name.as_str()].len()))
+        .filter(|(u, users)| {
+            *users >= settings.min_members || workspace.inheritable.contains_key(&u.name)
+        })
+        .collect()
+}
+
+fn not_inherited(file: &str, workspace: &Workspace, dependency: &Use, users: usize) -> Violation {
+    let table = table_name(&dependency.table);
+    let message = if workspace.inheritable.contains_key(&dependency.name) {
+        format!(
+            "{} is in [workspace.dependencies], but [{}] doesn't inherit it",
+            dependency.name, table
+        )
+    } else {
+        format!(
+            "{} of the workspace's members depend on {}, but [{}] doesn't inherit it from \
+             [workspace.dependencies]",
+            users, dependency.name, table
+        )
+    };
+    let mut violation = Violation::new(file, message)
+        .for_lint("workspace-dep-guard")
+        .suggesting(format!(
+            "use `{} = {{ workspace = true }}`, or rerun with --fix",
+            dependency.name
+        ));
+    if let Some(span) = dependency.span {
+        violation = violation.at(span);
+    }
+    violation
+}
+
+/// The member's dependencies whose versions differ from the ones required
+/// elsewhere in the workspace.
+fn mismatched_versions(
+    file: &str,
+    workspace: &Workspace,
+    manifest: &Path,
+    settings: &WorkspaceDepGuardConfig,
+) -> Vec<Violation> {
+    let mut result = Vec::new();
+    let ours = workspace
+        .uses
+        .iter()
+        .filter(|u| u.manifest == manifest && !u.inherited)
+        .filter(|u| !settings.ignore.contains(&u.name));
+    for dependency in ours {
+        let version = match dependency.spec.source.get("version") {
+            Some(version) => version,
+            None => continue,
+        };
+        let mut elsewhere = BTreeSet::new();
+        if let Some(theirs) = workspace
+            .inheritable
+            .get(&dependency.name)
+            .and_then(|s| s.source.get("version"))
+            .filter(|v| *v != version)
+        {
+            elsewhere.insert(format!("\"{}\" in [workspace.dependencies]", theirs));
+        }
+        let others = workspace
+            .uses
+            .iter()
+            .filter(|u| u.name == dependency.name && !u.inherited);
+        for other in others {
+            let theirs = match other.spec.source.get("version") {
+                Some(theirs) if theirs != version => theirs,
+                _ => continue,
+            };
+            if other.manifest == manifest {
+                let table = table_name(&other.table);
+                elsewhere.insert(format!("\"{}\" in [{}]", theirs, table));
+            } else {
+                elsewhere.insert(format!("\"{}\" by {}", theirs, other.member));
+            }
+        }
+        if elsewhere.is_empty() {
+            continue;
+        }
+        let elsewhere: Vec<String> = elsewhere.into_iter().collect();
+        let message = format!(
+            "{} is required as \"{}\" here, but as {}",
+            dependency.name,
+            version,
+            elsewhere.join(", ")
+        );
+        let mut violation = Violation::new(file, message)
+            .for_lint("workspace-dep-guard")
+            .suggesting("require the one version, ideally in [workspace.dependencies]");
+        if let Some(span) = dependency.span {
+            violation = violation.at(span);
+        }
+        result.push(violation);
+    }
+    result
+}
+
+/// The entry for a dependency in `[workspace.dependencies]`, with its path (if it
+/// has one) relative to the workspace's root.
+fn workspace_entry(spec: &Spec, root: &Path) -> Result<Item, String> {
+    if spec.default_features != Some(false) && spec.source.len() == 1 {
+        if let Some(version) = spec.source.get("version") {
+            return Ok(toml_edit::value(version.as_str()));
+        }
+    }
+    let mut entry = InlineTable::new();
+    for key in SOURCE_KEYS {
+        let value = match spec.source.get(key) {
+            Some(value) => value,
+            None => continue,
+        };
+        if key == "path" {
+            let relative = Path::new(value)
+                .strip_prefix(root)
+                .map_err(|_| "its path is outside of the workspace".to_string())?;
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            entry.insert(
+                key,
+                if relative.is_empty() { "." } else { &relative }.into(),
+            );
+        } else {
+            entry.insert(key, value.as_str().into());
+        }
+    }
+    if spec.default_features == Some(false) {
+        entry.insert("default-features", false.into());
+    }
+    entry.fmt();
+    Ok(Item::Value(Value::InlineTable(entry)))
+}
+
+/// Makes a dependency inherit from `[workspace.dependencies]`, keeping what it
+/// says about how it's used (the features it turns on, whether it's optional).
+fn make_inherited(item: &mut Item, default_features: Option<bool>) {
+    let moved = |key: &str| {
+        SOURCE_KEYS.contains(&key) || key == "default-features" || key == "default_features"
+    };
+    if let Item::Table(table) = item {
+        table.retain(|key, _| !moved(key));
+        table.insert("workspace", toml_edit::value(true));
+        if let Some(default_features) = default_features {
+            table.insert("default-features", toml_edit::value(default_features));
+        }
+        return;
+    }
+    let mut spec = InlineTable::new();
+    spec.insert("workspace", true.into());
+    if let Some(old) = item.as_inline_table() {
+        for (key, value) in old.iter().filter(|(key, _)| !moved(key)) {
+            spec.insert(key, value.clone());
+        }
+    }
+    if let Some(default_features) = default_features {
+        spec.insert("default-features", default_features.into());
+    }
+    spec.fmt();
+    let mut value = Value::InlineTable(spec);
+    if let Some(old) = item.as_value() {
+        *value.decor_mut() = old.decor().clone();
+    }
+    *item = Item::Value(value);
+}
+
+/// The manifests being fixed, with what they held before.
+type Manifests = BTreeMap<PathBuf, (String, DocumentMut)>;
+
+fn manifest_mut<'a>(
+    manifests: &'a mut Manifests,
+    path: &Path,
+) -> Result<&'a mut DocumentMut, String> {
+    if !manifests.contains_key(path) {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        let doc = contents
+            .parse()
+            .map_err(|e| format!("Unable to parse {}: {}", path.display(), e))?;
+        manifests.insert(path.to_path_buf(), (contents, doc));
+    }
+    // unwrap here is safe since it was just inserted if it wasn't there already
+    Ok(&mut manifests.get_mut(path).unwrap().1)
+}
+
+/// Makes a dependency inherit from `[workspace.dependencies]`, adding it there
+/// first if neither the workspace nor `added` has it yet.
+fn inherit(
+    workspace: &Workspace,
+    dependency: &Use,
+    added: &mut BTreeMap<String, Spec>,
+    manifests: &mut Manifests,
+) -> Result<(), String> {
+    let name = dependency.name.as_str();
+    let ours = &dependency.spec;
+    let entry = match workspace.inheritable.get(name).or_else(|| added.get(name)) {
+        Some(entry) => entry.clone(),
+        None => {
+            let theirs = workspace
+                .uses
+                .iter()
+                .filter(|u| u.name == name && !u.inherited);
+            // Whichever member went first would otherwise decide for all of them
+            if theirs.clone().any(|u| u.spec.source != ours.source) {
+                return Err("the members don't agree on where it comes from".to_string());
+            }
+            // The members that want default features can turn them back on
+            let entry = Spec {
+                source: ours.source.clone(),
+                default_features: theirs
+                    .clone()
+                    .any(|u| u.spec.default_features == Some(false))
+                    .then_some(false),
+            };
+            let root = workspace.manifest.parent().unwrap_or(Path::new(""));
+            let item = workspace_entry(&entry, root)?;
+            let doc = manifest_mut(manifests, &workspace.manifest)?;
+            let deps = doc["workspace"]
+                .as_table_mut()
+                .map(|w| w.entry("dependencies").or_insert(toml_edit::table()))
+                .and_then(Item::as_table_mut)
+                .ok_or("[workspace.dependencies] isn't a table")?;
+            deps.insert(name, item);
+            added.insert(name.to_string(), entry.clone());
+            entry
+        }
+    };
+    if entry.source != ours.source {
+        return Err("its spec doesn't match the one in [workspace.dependencies]".to_string());
+    }
+    // Members can turn default features back on, but not off
+    let default_features = if entry.default_features == Some(false) {
+        (ours.default_features != Some(false)).then_some(true)
+    } else if ours.default_features == Some(false) {
+        return Err("it turns off default features, and the workspace doesn't".to_string());
+    } else {
+        None
+    };
+
+    let mut item = manifest_mut(manifests, &dependency.manifest)?.as_item_mut();
+    for key in &dependency.table {
+        item = &mut item[key.as_str()];
+    }
+    make_inherited(&mut item[name], default_features);
+    say!(
+        "Made [{}] of {} inherit {} from the workspace",
+        table_name(&dependency.table),
+        dependency.member,
+        name
+    );
+    Ok(())
+}
+
+/// Do the thing
+fn main() {
+    salt_spray::exit_if_version_asked("salt-workspace-dep-guard");
+    let mut summary = Summary::new("salt-workspace-dep-guard");
+    let known = [PORCELAIN, OUTPUT, COLOR, FIX];
+    let (flags, args) = parse_args("salt-workspace-dep-guard", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|f| f == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let mut report = Report::from_flags("salt-workspace-dep-guard", &flags, &config)
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+    let fix = flags.iter().any(|f| f == FIX);
+    let settings = &config.workspace_dep_guard;
+
+    let mut by_workspace: BTreeMap<PathBuf, Vec<&String>> = BTreeMap::new();
+    for file in args.iter().filter(|a| is_manifest(a)) {
+        if !config.is_excluded(Path::new(file)) {
+            let root = find_workspace_root(Path::new(file));
+            by_workspace.entry(root).or_default().push(file);
+        }
+    }
+
+    let mut manifests = Manifests::new();
+    for (root, files) in by_workspace {
+        let workspace = match read_workspace(&root) {
+            Ok(Some(workspace)) => workspace,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("{}, skipping its workspace", e);
+                continue;
+            }
+        };
+        let mut added = BTreeMap::new();
+        for file in files {
+            let manifest = canonical(Path::new(file));
+            if !workspace.uses.iter().any(|u| u.manifest == manifest) {
+                continue;
+            }
+            summary.crates += 1;
+            summary.files += 1;
+            let mut problems = Vec::new();
+            let dependencies = uninherited(&workspace, &manifest, settings);
+            if fix {
+                for (dependency, users) in &dependencies {
+                    if let Err(why) = inherit(&workspace, dependency, &mut added, &mut manifests) {
+                        let violation = not_inherited(file, &workspace, dependency, *users);
+                        problems.push(
+                            violation.suggesting(format!("inherit it by hand, since {}", why)),
+                        );
+                    }
+                }
+            } else {
+                problems.extend(
+                    dependencies
+                        .iter()
+                        .map(|(u, users)| not_inherited(file, &workspace, u, *users)),
+                );
+            }
+            problems.extend(mismatched_versions(file, &workspace, &manifest, settings));
+            if settings.advisory || config.policy_for(Path::new(file)).advisory {
+                problems = problems.into_iter().map(Violation::advisory).collect();
+            }
+            report.record_all(&problems);
+            summary.violations += problems.iter().filter(|p| !p.advisory).count();
+            summary.count_lints(&problems);
+        }
+    }
+
+    let mut changed = Vec::new();
+    for (path, (contents, doc)) in &manifests {
+        let fixed = doc.to_string();
+        if fixed == *contents {
+            continue;
+        }
+        if let Err(e) = fs::write(path, fixed) {
+            eprintln!("Unable to write {}: {}", path.display(), e);
+            process::exit(-1);
+        }
+        changed.push(repo_relative(path).display().to_string());
+    }
+    let exit_code = if summary.violations > 0 {
+        1
+    } else if !changed.is_empty() {
+        say!(
+            "Please run `git add {}` and retry your commit.",
+            changed.join(" ")
+        );
+        2
+    } else {
+        0
+    };
+    report.finish(&summary);
+    summary.print();
+    process::exit(exit_code);
+}
@@ -0,0 +1,9 @@
+//! `salt` runs all of the checks (fmt, clippy, and the warning ratchet) in one go,
+//! through the common [`salt_spray::check::Check`] interface.
+
+#![forbid(unsafe_code)]
+
+/// Do the thing
+fn main() {
+    salt_spray::check::cli_main(salt_spray::check::builtin_checks())
+}
@@ -8,252 +8,399 @@
 //! user know why.  If OTOH the new total has decreased the ratchet clicks and updates
 //! .therug.yaml to have fewer warnings swept under it.
 //!
+//! In CI, run it with `--mode=ci` (rather than the default `--mode=commit`) so
+//! that it never touches .therug.yaml and fails if it's out of date in either
+//! direction, which catches commits that skipped the hook.
+//!
+//! Directories among the files (e.g. `warning-ratchet src`, or `args: [.]` in a
+//! hook with `pass_filenames: false`) stand for every Rust file under them, and
+//! the manifests and cargo configs, leaving out what git ignores.  It says how
+//! many Rust files it found in each.
+//!
+//! `--since=<rev>` has it work out which files to look at by itself, from `git
+//! diff --name-only <rev>...HEAD` (what changed since HEAD forked from the
+//! revision, so not what's changed on the revision's branch since), so that in CI
+//! it can check just a pull request's files without pre-commit, e.g.
+//! `warning-ratchet --mode=ci --since=origin/main`.
+//!
+//! With `--commit-msg` it runs as a `commit-msg` hook instead, taking the file
+//! the commit message is in (which pre-commit hands that stage's hooks) rather
+//! than a list of files, and checking the staged files.  That's how it sees the
+//! approvals `[ratchet.approval]` asks for, see `salt_spray::ratchet`, e.g.
+//!
+//! ```yaml
+//! - id: warning-ratchet
+//!   stages: [commit-msg]
+//!   args: [--commit-msg]
+//! ```
+//!
+//! With `--prepare-commit-msg` it doesn't ratchet at all, it only adds a
+//! trailer for each lint the staged .therug.yaml has fewer suppressions of than
+//! HEAD's to the commit message in the file it's given, e.g. `Lint-Debt:
+//! dead_code -2`, when `debt-trailer` under `[ratchet]` names one:
+//!
+//! ```yaml
+//! - id: lint-debt-trailer
+//!   stages: [prepare-commit-msg]
+//! ```
+//!
+//! With `--strict` it also rejects new `#![allow(...)]`s on whole files and
+//! modules, the same as `forbid-inner-allows = true` under `[ratchet]` in
+//! salt-spray.toml.
+//!
+//! With `--report=sarif` it also writes the suppressions that made it reject the
+//! files to warning-ratchet.sarif (or wherever `--report-file=` says), so that
+//! GitHub code scanning and other SARIF consumers can show them inline on pull
+//! requests.  Since the ratchet only counts, every allow of an increased lint in
+//! the file it increased in is reported.  `--report=sarif-all` reports every
+//! suppression in the files as well, as notes.  Reports need the `json` feature.
+//!
+//! The files are parsed as many at a time as there are CPUs, or as `--jobs=N`
+//! says, see `salt_spray::jobs`.
+//!
+//! `warning-ratchet show [--lint=dead_code] [--file=src/lib.rs] [--top=10]` lists
+//! what's in .therug.yaml instead, most suppressed first.
+//!
+//! The ratchet itself lives in `salt_spray::ratchet`, this is just the hook.
+//!
 
 // #![allow(unused_must_use, unused_imports)] // Leave these in; this file is its own test data.
 #![allow(unused_variables)]
 
-use std::cmp::{max, min};
-use std::collections::BTreeMap;
 use std::env;
-use std::ffi::OsStr;
-use std::fs::File;
-use std::io::{Read, Write};
-use std::path::Path;
+use std::fs;
+use std::process::{self, Command};
 
-use proc_macro2::Span;
-use serde::{Deserialize, Serialize};
-use syn::{Attribute, Ident, Item};
+use salt_spray::args::parse_args;
+use salt_spray::config::Config;
+use salt_spray::jobs::{self, JOBS};
+use salt_spray::output::COLOR;
+use salt_spray::ratchet::{approver_in, debt_paid, query, ratchet, Mode, Options, Outcome, Query};
+#[cfg(feature = "json")]
+use salt_spray::sarif::{self, Finding};
+use salt_spray::say;
+use salt_spray::sink::{self, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
 
-#[allow(dead_code)]
-const SHAMEFILE: &str = ".therug.yaml";
+static ACCEPT: &str = "--accept";
+static EXPLAIN: &str = "--explain";
+static MODE: &str = "--mode=";
+static STRICT: &str = "--strict";
+static REPORT: &str = "--report=";
+static REPORT_FILE: &str = "--report-file=";
+static SINCE: &str = "--since=";
+static COMMIT_MSG: &str = "--commit-msg";
+static PREPARE_COMMIT_MSG: &str = "--prepare-commit-msg";
+static SHOW: &str = "show";
+static SHOW_LINT: &str = "--lint=";
+static SHOW_FILE: &str = "--file=";
+static SHOW_TOP: &str = "--top=";
 
-#[allow(dead_code, unsafe_code, unused_mut, unused_imports)]
-#[derive(Debug, Default, Serialize, Deserialize)]
-struct SupressedLints {
-    lints: BTreeMap<String, BTreeMap<String, usize>>,
-}
+/// `warning-ratchet show`, which prints (a filtered view of) the shamefile.
+fn show<I: Iterator<Item = String>>(args: I) -> ! {
+    let known = [SHOW_LINT, SHOW_FILE, SHOW_TOP];
+    let (flags, rest) = parse_args("warning-ratchet show", args, &known);
+    if let Some(arg) = rest.first() {
+        eprintln!("Unrecognized argument to {}: {}", SHOW, arg);
+        process::exit(-1);
+    }
+    let mut filter = Query::default();
+    for arg in flags {
+        if let Some(lint) = arg.strip_prefix(SHOW_LINT) {
+            filter.lint = Some(lint.to_string());
+        } else if let Some(file) = arg.strip_prefix(SHOW_FILE) {
+            filter.file = Some(file.to_string());
+        } else if let Some(top) = arg.strip_prefix(SHOW_TOP) {
+            filter.top = Some(top.parse().unwrap_or_else(|_| {
+                eprintln!("{} needs a number, not {}", SHOW_TOP, top);
+                process::exit(-1);
+            }));
+        }
+    }
 
-enum Relationship {
-    Expected,
-    ProperSubset,
-    NotASubset,
+    let entries = query(&filter).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let width = entries
+        .iter()
+        .map(|e| e.count.to_string().len())
+        .max()
+        .unwrap_or(1);
+    for entry in entries.iter() {
+        println!(
+            "{:>width$}  allow({})  {}",
+            entry.count,
+            entry.lint,
+            entry.key,
+            width = width
+        );
+    }
+    let total: usize = entries.iter().map(|e| e.count).sum();
+    println!("{} suppression(s) in {} place(s)", total, entries.len());
+    process::exit(0);
 }
 
-fn read_file<S: AsRef<OsStr>>(filename: S) -> Option<String> {
-    File::open(filename.as_ref())
-        .map(|mut file| {
-            let mut result = String::new();
-            file.read_to_string(&mut result).unwrap();
-            result
-        })
-        .ok()
+/// The files changed since HEAD forked from the given revision (and still around),
+/// relative to the working directory.
+fn changed_since(rev: &str) -> Result<Vec<String>, String> {
+    let range = format!("{}...HEAD", rev);
+    changed_files(&[&range], &format!("changed since {}", rev))
 }
 
-#[allow(unused_mut)]
-fn look_under_therug() -> SupressedLints {
-    // TODO (mrd): this should probably only be a default, test an env var first
-    match read_file(SHAMEFILE) {
-        Some(contents) => serde_yaml::from_str(&contents).expect(&contents),
-        None => Default::default(),
-    }
+/// The files with staged changes (that are still around), relative to the working
+/// directory.
+fn staged_files() -> Result<Vec<String>, String> {
+    changed_files(&["--cached"], "being committed")
 }
 
-fn sweep_under_therug(lints: &SupressedLints) {
-    let contents = serde_yaml::to_string(&lints).unwrap();
-    // TODO (mrd): this should probably only be a default, test an env var first
-    let mut file = File::create(SHAMEFILE).unwrap();
-    file.write_all(contents.as_bytes()).unwrap();
+/// The files `git diff` with the given arguments lists (leaving out deleted ones),
+/// relative to the working directory.
+fn changed_files(args: &[&str], what: &str) -> Result<Vec<String>, String> {
+    let output = Command::new("git")
+        .args(["diff", "--name-only", "--relative", "--diff-filter=d", "-z"])
+        .args(args)
+        .arg("--")
+        .output()
+        .map_err(|e| format!("Unable to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Unable to list the files {}: {}",
+            what,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|f| !f.is_empty())
+        .map(str::to_string)
+        .collect())
 }
 
-fn find_supressed_lints<S: AsRef<OsStr>>(filenames: &Vec<S>) -> SupressedLints {
-    let mut result = SupressedLints::default();
-    for name in filenames {
-        if Path::new(&name).extension().map(|e| e == "rs").unwrap_or(false) {
-            result.load_suppressed_lints_from(&name.as_ref().to_string_lossy());
+/// `warning-ratchet --prepare-commit-msg`, which adds the trailers for the lint
+/// debt the commit pays down to its message, see `salt_spray::ratchet::debt_paid`.
+fn prepare_commit_msg(file: &str, config: &Config) -> ! {
+    let trailer = match &config.ratchet.debt_trailer {
+        Some(trailer) => trailer,
+        None => process::exit(0),
+    };
+    let paid = debt_paid(config);
+    if paid.is_empty() {
+        process::exit(0);
+    }
+    // git knows where trailers go, e.g. ahead of the comments in the template
+    let mut cmd = Command::new("git");
+    cmd.args([
+        "interpret-trailers",
+        "--in-place",
+        "--if-exists=addIfDifferent",
+    ]);
+    for value in paid.iter() {
+        cmd.arg(format!("--trailer={}: {}", trailer, value));
+    }
+    match cmd.arg(file).output() {
+        Ok(output) if output.status.success() => (),
+        Ok(output) => {
+            eprintln!(
+                "Unable to add the {} trailers to {}: {}",
+                trailer,
+                file,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            process::exit(-1);
+        }
+        Err(e) => {
+            eprintln!("Unable to run git: {}", e);
+            process::exit(-1);
         }
     }
-    result
+    say!("Thanks for paying down lint debt: {}", paid.join(", "));
+    process::exit(0);
 }
 
-// #[allow(unsafe_code)]
-fn count_lints_in_attrs(
-    result: &mut BTreeMap<String, usize>,
-    attrs: &Vec<Attribute>,
-    item_count: usize,
-) {
-    let item_count = max(item_count, 1);
-    let allow = Ident::new("allow", Span::call_site());
-
-    for attr in attrs {
-        if attr.path.get_ident() == Some(&allow) {
-            if let Ok(syn::Meta::List(lints)) = attr.parse_meta() {
-                for lint in lints.nested {
-                    // hooray metaprogramming  :-/
-                    if let syn::NestedMeta::Meta(syn::Meta::Path(lint)) = lint {
-                        if let Some(lint) = lint.get_ident() {
-                            let lint = lint.to_string();
-                            if result.contains_key(&lint) {
-                                *result.get_mut(&lint).unwrap() += item_count;
-                            } else {
-                                result.insert(lint, item_count);
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
+/// Which suppressions to report.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Report {
+    New,
+    All,
 }
 
-fn count_lints_in_items(result: &mut BTreeMap<String, usize>, items: &Vec<Item>) {
-    for item in items {
-        match item {
-            Item::Const(c) => count_lints_in_attrs(result, &c.attrs, 1),
-            Item::Enum(c) => count_lints_in_attrs(result, &c.attrs, 1),
-            Item::ExternCrate(c) => count_lints_in_attrs(result, &c.attrs, 1),
-            Item::Fn(c) => count_lints_in_attrs(result, &c.attrs, 1),
-            Item::ForeignMod(c) => count_lints_in_attrs(result, &c.attrs, c.items.len()),
-            Item::Impl(c) => count_lints_in_attrs(result, &c.attrs, c.items.len()),
-            Item::Macro(c) => count_lints_in_attrs(result, &c.attrs, 1),
-            Item::Macro2(c) => count_lints_in_attrs(result, &c.attrs, 1),
-            Item::Mod(c) => {
-                if let Some((_, items)) = &c.content {
-                    count_lints_in_attrs(result, &c.attrs, items.len());
-                    count_lints_in_items(result, items);
-                } else {
-                    count_lints_in_attrs(result, &c.attrs, 1);
-                }
-            }
-            Item::Static(c) => count_lints_in_attrs(result, &c.attrs, 1),
-            Item::Struct(c) => count_lints_in_attrs(result, &c.attrs, 1),
-            Item::Trait(c) => count_lints_in_attrs(result, &c.attrs, 1),
-            Item::TraitAlias(c) => count_lints_in_attrs(result, &c.attrs, 1),
-            Item::Type(c) => count_lints_in_attrs(result, &c.attrs, 1),
-            Item::Union(c) => count_lints_in_attrs(result, &c.attrs, 1),
-            Item::Use(c) => count_lints_in_attrs(result, &c.attrs, 1),
-            Item::Verbatim(_) => (),
-            _ => (),
-        }
+/// Writes the SARIF report of the outcome to the file.
+#[cfg(feature = "json")]
+fn write_report(outcome: &Outcome, report: Report, path: &str) {
+    let new = outcome.new_suppressions();
+    let mut findings: Vec<Finding> = new
+        .iter()
+        .map(|s| Finding {
+            rule: s.lint.clone(),
+            message: format!(
+                "allow({}) in {} is more than .therug.yaml allows, fix the lint instead",
+                s.lint, s.key
+            ),
+            file: s.file.clone(),
+            line: s.line,
+            error: true,
+        })
+        .collect();
+    if report == Report::All {
+        findings.extend(
+            outcome
+                .suppressions
+                .iter()
+                .filter(|s| !new.contains(s))
+                .map(|s| Finding {
+                    rule: s.lint.clone(),
+                    message: format!("allow({}) is tracked in .therug.yaml", s.lint),
+                    file: s.file.clone(),
+                    line: s.line,
+                    error: false,
+                }),
+        );
+    }
+    if let Err(e) = fs::write(path, sarif::render("warning-ratchet", &findings)) {
+        eprintln!("Unable to write {}: {}", path, e);
+        process::exit(-1);
     }
 }
 
-fn count_suppressed_lints(ast: syn::File) -> BTreeMap<String, usize> {
-    let mut result = BTreeMap::<String, usize>::default();
-    count_lints_in_attrs(&mut result, &ast.attrs, ast.items.len());
-    count_lints_in_items(&mut result, &ast.items);
-    result
+#[cfg(not(feature = "json"))]
+fn write_report(_: &Outcome, _: Report, _: &str) {
+    eprintln!(
+        "{} needs salt-spray to be built with the json feature",
+        REPORT
+    );
+    process::exit(-1);
 }
 
 fn main() {
-    let mut args = env::args();
+    salt_spray::exit_if_version_asked("warning-ratchet");
+    let mut summary = Summary::new("warning-ratchet");
+    let mut args = env::args().peekable();
     drop(args.next());
-    let relevant_files: Vec<String> = args.collect();
-    let observed_supressed_lints = find_supressed_lints(&relevant_files);
-    let mut expected_supressed_lints = look_under_therug();
-
-    match observed_supressed_lints.vis_a_vis(&expected_supressed_lints) {
-        Relationship::Expected => (),
-        Relationship::ProperSubset => {
-            expected_supressed_lints.shrink_around(&observed_supressed_lints, &relevant_files);
-            sweep_under_therug(&expected_supressed_lints);
-            println!(
-                "Thanks for enabling more lints!  Please run `git add {}` and retry your commit.",
-                SHAMEFILE
-            );
-            std::process::exit(2);
-        }
-        Relationship::NotASubset => {
-            // For the most part NotASubset is handled by the eprintln calls below
-            if env::var("UPDATE_ANYWAY").map(|v| v == "1").unwrap_or(false) {
-                expected_supressed_lints.grow_around(&observed_supressed_lints);
-                sweep_under_therug(&expected_supressed_lints);
-            }
-            std::process::exit(1);
-        }
+    if args.peek().map(String::as_str) == Some(SHOW) {
+        drop(args.next());
+        show(args);
     }
-}
-
-#[allow(unsafe_code)]
-impl SupressedLints {
-    fn vis_a_vis(&self, other: &SupressedLints) -> Relationship {
-        let mut result = Relationship::Expected;
-
-        // Is everything in self also in other?
-        for (file, lints) in self.lints.iter() {
-            if let Some(olints) = other.lints.get(file) {
-                for (lint, count) in lints {
-                    if let Some(ocount) = olints.get(lint) {
-                        if *count < *ocount {
-                            result = Relationship::ProperSubset;
-                        } else if *count > *ocount {
-                            eprintln!("Cannot allow({}) count to increase in {}", lint, file);
-                            return Relationship::NotASubset;
-                        }
-                    } else {
-                        eprintln!("Cannot add allow({}) to {}", lint, file);
-                        return Relationship::NotASubset;
-                    }
+    let mut options = Options {
+        click: true,
+        ..Default::default()
+    };
+    let mut explain = false;
+    let mut report = None;
+    let mut report_file = "warning-ratchet.sarif".to_string();
+    let mut message = None;
+    let mut prepare = None;
+    let known = [
+        PORCELAIN,
+        ACCEPT,
+        EXPLAIN,
+        STRICT,
+        MODE,
+        REPORT,
+        REPORT_FILE,
+        SINCE,
+        COMMIT_MSG,
+        PREPARE_COMMIT_MSG,
+        OUTPUT,
+        COLOR,
+        JOBS,
+    ];
+    let (flags, mut relevant_files) = parse_args("warning-ratchet", args, &known);
+    for arg in flags.iter() {
+        if arg == PORCELAIN {
+            summary::set_porcelain(true);
+        } else if arg == ACCEPT {
+            options.accept = true;
+        } else if arg == EXPLAIN {
+            explain = true;
+        } else if arg == STRICT {
+            options.strict = true;
+        } else if let Some(mode) = arg.strip_prefix(MODE) {
+            options.mode = match mode {
+                "commit" => Mode::Commit,
+                "ci" => Mode::Ci,
+                _ => {
+                    eprintln!("{} must be either commit or ci", MODE);
+                    process::exit(-1);
                 }
-            } else if lints.len() > 0 {
-                eprintln!("Cannot surpress new lints in {}", file);
-                return Relationship::NotASubset;
-            }
-        }
-
-        // Is there anything in other that is not in self?
-        for (ofile, olints) in other.lints.iter() {
-            if let Some(lints) = self.lints.get(ofile) {
-                for (lint, count) in olints {
-                    if !lints.contains_key(lint) && *count > 0 {
-                        println!("No longer have {} to worry about in {}.", lint, ofile);
-                        result = Relationship::ProperSubset;
-                    }
+            };
+        } else if let Some(format) = arg.strip_prefix(REPORT) {
+            report = match format {
+                "sarif" => Some(Report::New),
+                "sarif-all" => Some(Report::All),
+                _ => {
+                    eprintln!("{} must be either sarif or sarif-all", REPORT);
+                    process::exit(-1);
                 }
-            }
-            // No else here because pre-commit chunks the filenames before
-            // invoking us, so on the second invocation we expect to have
-            // lints that other does not.
+            };
+        } else if let Some(path) = arg.strip_prefix(REPORT_FILE) {
+            report_file = path.to_string();
+        } else if let Some(rev) = arg.strip_prefix(SINCE) {
+            let changed = changed_since(rev).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(-1);
+            });
+            relevant_files.extend(changed);
+            options.since = Some(rev.to_string());
+        } else if let Some(jobs) = arg.strip_prefix(JOBS) {
+            jobs::set_jobs(jobs).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(-1);
+            });
+        } else if arg == COMMIT_MSG {
+            // pre-commit hands commit-msg hooks the message file, not the files
+            let file = relevant_files.first().cloned().unwrap_or_else(|| {
+                eprintln!("{} needs the file the commit message is in", COMMIT_MSG);
+                process::exit(-1);
+            });
+            message = Some(fs::read_to_string(&file).unwrap_or_else(|e| {
+                eprintln!("Unable to read {}: {}", file, e);
+                process::exit(-1);
+            }));
+            relevant_files = staged_files().unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(-1);
+            });
+        } else if arg == PREPARE_COMMIT_MSG {
+            // Likewise, with the source of the message after it
+            prepare = Some(relevant_files.first().cloned().unwrap_or_else(|| {
+                eprintln!(
+                    "{} needs the file the commit message is in",
+                    PREPARE_COMMIT_MSG
+                );
+                process::exit(-1);
+            }));
         }
-        result
     }
-
-    fn shrink_around(&mut self, other: &SupressedLints, examined_files: &Vec<String>) {
-        // TODO: remove keys that are now missing?
-        for (key, val) in self.lints.iter_mut() {
-            if let Some(oval) = other.lints.get(key) {
-                for (lint, count) in val.iter_mut() {
-                    if let Some(ocount) = oval.get(lint) {
-                        *count = min(*count, *ocount);
-                    } else {
-                        *count = 0;
-                    }
-                }
-            } else if examined_files.contains(key) {
-                val.clear();
-            }
-        }
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(-1);
+    });
+    if let Some(file) = prepare {
+        prepare_commit_msg(&file, &config);
     }
+    options.approved_by = message.and_then(|m| approver_in(&m, &config));
+    let mut sinks =
+        sink::Report::from_flags("warning-ratchet", &flags, &config).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
 
-    fn grow_around(&mut self, other: &SupressedLints) {
-        for (okey, oval) in other.lints.iter() {
-            if let Some(val) = self.lints.get_mut(okey) {
-                for (lint, ocount) in oval {
-                    if let Some(count) = val.get_mut(lint) {
-                        *count = max(*count, *ocount);
-                    } else {
-                        val.insert(lint.to_string(), *ocount);
-                    }
-                }
-            } else {
-                self.lints.insert(okey.to_string(), oval.clone());
-            }
-        }
+    let outcome = ratchet(&relevant_files, &config, &options);
+    if let Some(report) = report {
+        write_report(&outcome, report, &report_file);
     }
-
-    fn load_suppressed_lints_from(&mut self, filename: &str) {
-        if let Some(contents) = read_file(filename) {
-            let ast = syn::parse_file(&contents).unwrap();
-            self.lints.insert(filename.to_string(), count_suppressed_lints(ast));
+    sinks.record_all(&outcome.violations);
+    if explain {
+        for increase in outcome.increases.iter() {
+            eprintln!("\n{}", increase.explain());
         }
     }
+    summary.files = outcome.files;
+    summary.crates = outcome.crates;
+    summary.violations = outcome.violations.iter().filter(|v| !v.advisory).count();
+    summary.count_lints(&outcome.violations);
+    sinks.finish(&summary);
+    summary.print();
+    std::process::exit(outcome.exit_code());
 }
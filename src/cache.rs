@@ -0,0 +1,328 @@
+//! The one directory the hooks keep files in, `target/salt-spray/` under the repo
+//! root, with a subdirectory for each kind of file (so far just `debug`, see
+//! [`crate::debug`]).  Anything new the hooks keep on disk goes there too, so
+//! that it's all found, measured, and cleaned up in one place.
+//!
+//! `salt cache status` says how much of each kind there is, `salt cache clear
+//! [kind...]` deletes it (all of it, without any kinds), and `salt cache gc
+//! [--max-age=30d]` deletes what's older than the max age and then the oldest files
+//! until the rest fit in the max size.  The hooks collect the garbage themselves
+//! whenever they add to the directory, with the limits from salt-spray.toml:
+//!
+//! ```toml
+//! [cache]
+//! max-size = "100M"  # K, M, and G are powers of 1024, plain numbers are bytes
+//! max-age = "30d"    # d, h, and m are days, hours, and minutes
+//! ```
+//!
+//! There's no max age unless one is given.  Deleting the directory (or `cargo
+//! clean`) is always fine, it only holds what the hooks can do without.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime};
+
+use crate::args::parse_args;
+use crate::config::{CacheConfig, Config};
+use crate::find_repo_root;
+
+/// Where the cache is, relative to the repo root.
+pub const CACHE_DIR: &str = "target/salt-spray";
+
+/// What's kept where `crate::debug` dumps what couldn't be parsed.
+pub const DEBUG: &str = "debug";
+
+/// The kinds of file the hooks keep, with what each of them is.
+static KINDS: [(&str, &str); 1] = [(DEBUG, "what the hooks couldn't parse")];
+
+static MAX_AGE: &str = "--max-age=";
+
+/// The directory the cache is in.
+pub fn root() -> PathBuf {
+    find_repo_root().unwrap_or_default().join(CACHE_DIR)
+}
+
+/// The directory the given kind of file is kept in.
+pub fn dir(kind: &str) -> PathBuf {
+    root().join(kind)
+}
+
+/// Reads a size like `100M`, `512K`, or `1G`, or a plain number of bytes.
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let (number, unit) = match size.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => size.split_at(i),
+        None => (size, ""),
+    };
+    let scale = match unit
+        .trim()
+        .to_ascii_uppercase()
+        .trim_end_matches(['B', 'I'])
+    {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => 0,
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .filter(|_| scale > 0)
+        .map(|n| n.saturating_mul(scale))
+        .ok_or_else(|| format!("{} isn't a size, e.g. 100M", size))
+}
+
+/// Reads an age like `30d`, `12h`, or `90m`.
+pub fn parse_age(age: &str) -> Result<Duration, String> {
+    let age = age.trim();
+    let (number, scale) = [('d', 24 * 60 * 60), ('h', 60 * 60), ('m', 60)]
+        .into_iter()
+        .find_map(|(unit, scale)| Some((age.strip_suffix(unit)?, scale)))
+        .unwrap_or((age, 0));
+    number
+        .parse::<u64>()
+        .ok()
+        .filter(|_| scale > 0)
+        .map(|n| Duration::from_secs(n.saturating_mul(scale)))
+        .ok_or_else(|| format!("{} isn't an age, e.g. 30d, 12h, or 90m", age))
+}
+
+/// A file in the cache.
+struct Entry {
+    path: PathBuf,
+    /// The subdirectory it's in, which says what kind of file it is.
+    kind: String,
+    size: u64,
+    modified: SystemTime,
+}
+
+fn collect(dir: &Path, kind: &str, result: &mut Vec<Entry>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        if metadata.is_dir() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            collect(&path, if kind.is_empty() { &name } else { kind }, result);
+        } else {
+            result.push(Entry {
+                path,
+                kind: kind.to_string(),
+                size: metadata.len(),
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            });
+        }
+    }
+}
+
+/// Every file in the cache, oldest first.
+fn entries() -> Vec<Entry> {
+    let mut result = Vec::new();
+    collect(&root(), "", &mut result);
+    result.sort_by_key(|e| e.modified);
+    result
+}
+
+/// The size in a form people read, e.g. `1.5 MiB`.
+fn human(bytes: u64) -> String {
+    let units = ["bytes", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < units.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} bytes", bytes)
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+/// How long ago the time was, roughly.
+fn ago(time: SystemTime) -> String {
+    let seconds = SystemTime::now()
+        .duration_since(time)
+        .unwrap_or_default()
+        .as_secs();
+    match seconds {
+        s if s >= 2 * 24 * 60 * 60 => format!("{} days ago", s / (24 * 60 * 60)),
+        s if s >= 2 * 60 * 60 => format!("{} hours ago", s / (60 * 60)),
+        s if s >= 2 * 60 => format!("{} minutes ago", s / 60),
+        _ => "just now".to_string(),
+    }
+}
+
+/// What was taken out of the cache.
+#[derive(Debug, Default)]
+pub struct Removed {
+    /// How many files.
+    pub files: usize,
+    /// How many bytes they had.
+    pub bytes: u64,
+}
+
+impl Removed {
+    fn remove(&mut self, entry: &Entry) {
+        match fs::remove_file(&entry.path) {
+            Ok(()) => {
+                self.files += 1;
+                self.bytes += entry.size;
+            }
+            // Another hook may have got there first
+            Err(e) if e.kind() == ErrorKind::NotFound => (),
+            Err(e) => eprintln!("Unable to remove {}: {}", entry.path.display(), e),
+        }
+    }
+}
+
+/// Deletes what's older than `max_age`, and then the oldest files until the rest
+/// take up at most `max_size` bytes, apart from the file it's keeping.
+fn gc(max_size: u64, max_age: Option<Duration>, keeping: Option<&Path>) -> Removed {
+    let mut removed = Removed::default();
+    let now = SystemTime::now();
+    let mut kept = Vec::new();
+    for entry in entries() {
+        if keeping == Some(entry.path.as_path()) {
+            continue;
+        }
+        let age = now.duration_since(entry.modified).unwrap_or_default();
+        if max_age.is_some_and(|max| age > max) {
+            removed.remove(&entry);
+        } else {
+            kept.push(entry);
+        }
+    }
+    let mut total: u64 = kept.iter().map(|e| e.size).sum();
+    for entry in kept {
+        if total <= max_size {
+            break;
+        }
+        total -= entry.size;
+        removed.remove(&entry);
+    }
+    removed
+}
+
+/// The limits from the config, or why they can't be read.
+fn limits(config: &CacheConfig) -> Result<(u64, Option<Duration>), String> {
+    let max_size = parse_size(&config.max_size).map_err(|e| format!("[cache] max-size: {}", e))?;
+    let max_age = match &config.max_age {
+        Some(age) => Some(parse_age(age).map_err(|e| format!("[cache] max-age: {}", e))?),
+        None => None,
+    };
+    Ok((max_size, max_age))
+}
+
+/// Collects the garbage with the limits from salt-spray.toml, for after adding
+/// the file to the cache, which stays whatever the limits say.  Problems are only
+/// worth a warning, since the file is there either way.
+pub fn trim(added: &Path) {
+    let config = Config::load().unwrap_or_default();
+    match limits(&config.cache) {
+        Ok((max_size, max_age)) => drop(gc(max_size, max_age, Some(added))),
+        Err(e) => eprintln!("{}", e),
+    }
+}
+
+fn status(config: &CacheConfig) {
+    let entries = entries();
+    let mut kinds: Vec<&str> = KINDS.iter().map(|(kind, _)| *kind).collect();
+    for entry in entries.iter() {
+        if !kinds.contains(&entry.kind.as_str()) {
+            kinds.push(&entry.kind);
+        }
+    }
+    let age = match &config.max_age {
+        Some(age) => format!(", max age {}", age),
+        None => String::new(),
+    };
+    println!(
+        "{} (max size {}{}):",
+        root().display(),
+        config.max_size,
+        age
+    );
+    let width = kinds.iter().map(|k| k.len().max(1)).max().unwrap_or(0);
+    for kind in kinds {
+        let of_kind: Vec<&Entry> = entries.iter().filter(|e| e.kind == kind).collect();
+        let bytes: u64 = of_kind.iter().map(|e| e.size).sum();
+        let oldest = match of_kind.first() {
+            Some(e) => format!(", the oldest written {}", ago(e.modified)),
+            None => String::new(),
+        };
+        let what = match KINDS.iter().find(|(k, _)| *k == kind) {
+            Some((_, what)) => format!("  ({})", what),
+            None => String::new(),
+        };
+        let name = if kind.is_empty() { "." } else { kind };
+        println!(
+            "  {:width$}  {} file(s), {}{}{}",
+            name,
+            of_kind.len(),
+            human(bytes),
+            oldest,
+            what
+        );
+    }
+    let bytes: u64 = entries.iter().map(|e| e.size).sum();
+    println!("  {} file(s), {} in all", entries.len(), human(bytes));
+}
+
+fn clear(kinds: &[String]) -> Removed {
+    let mut removed = Removed::default();
+    for entry in entries() {
+        if kinds.is_empty() || kinds.contains(&entry.kind) {
+            removed.remove(&entry);
+        }
+    }
+    if kinds.is_empty() {
+        drop(fs::remove_dir_all(root()));
+    }
+    removed
+}
+
+/// The `salt cache` command line: `salt cache status`, `salt cache clear
+/// [kind...]`, or `salt cache gc [--max-age=...]`.
+pub fn cli_main<I: IntoIterator<Item = String>>(args: I) -> ! {
+    let (flags, words) = parse_args("salt cache", args, &[MAX_AGE]);
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    let fail = |e: String| -> ! {
+        eprintln!("salt cache: {}", e);
+        process::exit(-1);
+    };
+    let removed = match words.first().map(String::as_str) {
+        Some("status") if words.len() == 1 => {
+            status(&config.cache);
+            process::exit(0);
+        }
+        Some("clear") => clear(&words[1..]),
+        Some("gc") if words.len() == 1 => {
+            let (max_size, mut max_age) = limits(&config.cache).unwrap_or_else(|e| fail(e));
+            if let Some(age) = flags.iter().find_map(|f| f.strip_prefix(MAX_AGE)) {
+                max_age = Some(parse_age(age).unwrap_or_else(|e| fail(e)));
+            }
+            gc(max_size, max_age, None)
+        }
+        _ => fail("expected status, clear [kind...], or gc [--max-age=...]".to_string()),
+    };
+    println!(
+        "Removed {} file(s), {}, from {}",
+        removed.files,
+        human(removed.bytes),
+        root().display()
+    );
+    process::exit(0);
+}
@@ -0,0 +1,377 @@
+//! A common interface for the checks, so that they can all be driven by the
+//! `salt` command, and so that other crates can plug in checks of their own
+//! without forking the hooks.  A custom check is just a type implementing
+//! [`Check`], registered alongside the built in ones:
+//!
+//! ```no_run
+//! use salt_spray::check::{self, Check, Violation};
+//!
+//! struct NoTodos;
+//!
+//! impl Check for NoTodos {
+//!     fn name(&self) -> &str {
+//!         "no-todos"
+//!     }
+//!
+//!     fn run(&self, files: &[String]) -> Vec<Violation> {
+//!         files
+//!             .iter()
+//!             .filter(|f| std::fs::read_to_string(f).unwrap_or_default().contains("TODO"))
+//!             .map(|f| Violation::new(f, "TODOs must be resolved before committing"))
+//!             .collect()
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let mut checks = check::builtin_checks();
+//!     checks.push(Box::new(NoTodos));
+//!     check::cli_main(checks);
+//! }
+//! ```
+
+use std::env;
+use std::fmt;
+use std::path::Path;
+use std::process;
+
+use crate::args::parse_args;
+use crate::config::Config;
+use crate::jobs::{self, JOBS};
+use crate::output::COLOR;
+use crate::say;
+use crate::sink::{Report, OUTPUT};
+use crate::summary::{self, Summary, PORCELAIN};
+use crate::toolchain::{self, CARGO_PATH};
+
+static FIX: &str = "--fix";
+static LIST: &str = "--list";
+static ONLY: &str = "--only=";
+static WATCH: &str = "watch";
+static SELFTEST: &str = "selftest";
+static CACHE: &str = "cache";
+
+/// Where in its file a violation is.  Lines and columns are 1-based, and the end
+/// is just past the last character.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    /// The line it starts on.
+    pub line: usize,
+    /// The column (in characters) it starts at.
+    pub column: usize,
+    /// The line it ends on.
+    pub end_line: usize,
+    /// The column just past the end of it.
+    pub end_column: usize,
+}
+
+impl Span {
+    /// A span of the given length on one line.
+    pub fn new(line: usize, column: usize, length: usize) -> Self {
+        Span {
+            line,
+            column,
+            end_line: line,
+            end_column: column + length.max(1),
+        }
+    }
+
+    /// The whole of one line, or as near as can be told without reading it.
+    pub fn line(line: usize) -> Self {
+        Span {
+            line,
+            column: 1,
+            end_line: line,
+            end_column: 1,
+        }
+    }
+}
+
+#[cfg(feature = "syn")]
+impl From<proc_macro2::Span> for Span {
+    fn from(span: proc_macro2::Span) -> Self {
+        let (start, end) = (span.start(), span.end());
+        Span {
+            line: start.line,
+            column: start.column + 1,
+            end_line: end.line,
+            end_column: end.column + 1,
+        }
+    }
+}
+
+/// Something a check found wrong.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    /// The file the problem is in, if it is in one particular file.
+    pub file: Option<String>,
+    /// Where in the file the problem is, if it's somewhere in particular.
+    pub span: Option<Span>,
+    /// A human readable description of the problem.
+    pub message: String,
+    /// What to do about it, if there's something more specific to say than the
+    /// message does.
+    pub suggestion: Option<String>,
+    /// Advisory violations are shown to the user but don't fail the run, which
+    /// makes them warnings rather than errors.
+    pub advisory: bool,
+    /// The lint (or error code, or other rule) behind the problem, if it has one.
+    pub lint: Option<String>,
+}
+
+impl Violation {
+    /// A (blocking) violation in the given file.
+    pub fn new<S: Into<String>>(file: &str, message: S) -> Self {
+        Violation {
+            file: Some(file.to_string()),
+            span: None,
+            message: message.into(),
+            suggestion: None,
+            advisory: false,
+            lint: None,
+        }
+    }
+
+    /// A (blocking) violation that isn't about any one file.
+    pub fn general<S: Into<String>>(message: S) -> Self {
+        Violation {
+            file: None,
+            span: None,
+            message: message.into(),
+            suggestion: None,
+            advisory: false,
+            lint: None,
+        }
+    }
+
+    /// The same violation, but advisory.
+    pub fn advisory(self) -> Self {
+        Violation {
+            advisory: true,
+            ..self
+        }
+    }
+
+    /// The same violation, but blamed on the given lint.
+    pub fn for_lint<S: Into<String>>(self, lint: S) -> Self {
+        Violation {
+            lint: Some(lint.into()),
+            ..self
+        }
+    }
+
+    /// The same violation, but at the given place in its file.
+    pub fn at<S: Into<Span>>(self, span: S) -> Self {
+        Violation {
+            span: Some(span.into()),
+            ..self
+        }
+    }
+
+    /// The same violation, with a suggestion of what to do about it.
+    pub fn suggesting<S: Into<String>>(self, suggestion: S) -> Self {
+        Violation {
+            suggestion: Some(suggestion.into()),
+            ..self
+        }
+    }
+
+    /// Whether it's an `error` or (for advisory violations) a `warning`.
+    pub fn severity(&self) -> &'static str {
+        if self.advisory {
+            "warning"
+        } else {
+            "error"
+        }
+    }
+
+    /// The violation as a JSON object, the way the `json-lines` sink and
+    /// salt-clip's `--report-file=` write it, without any colors the message was
+    /// passed on with.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "file": self.file,
+            "span": self.span.map(|s| serde_json::json!({
+                "line": s.line,
+                "column": s.column,
+                "end_line": s.end_line,
+                "end_column": s.end_column,
+            })),
+            "message": crate::output::sanitize(&self.message, false),
+            "suggestion": self.suggestion,
+            "severity": self.severity(),
+            "advisory": self.advisory,
+            "lint": self.lint,
+        })
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.file, &self.span) {
+            // Multi-line messages (e.g. rustc diagnostics) already say where they are
+            _ if self.message.contains('\n') => write!(f, "{}", self.message),
+            (Some(file), Some(span)) => write!(f, "{}:{}: {}", file, span.line, self.message),
+            (Some(file), None) => write!(f, "{}: {}", file, self.message),
+            (None, _) => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// A check that can be run over the files in a commit.
+pub trait Check {
+    /// A short name for the check, used to pick it on the command line.
+    fn name(&self) -> &str;
+
+    /// Does the check want to look at the given file?  By default checks look at
+    /// Rust source files.
+    fn wants(&self, file: &Path) -> bool {
+        file.extension().map(|e| e == "rs").unwrap_or(false)
+    }
+
+    /// Checks the files without modifying anything.
+    fn run(&self, files: &[String]) -> Vec<Violation>;
+
+    /// Can the check fix (some of) what it finds?
+    fn can_fix(&self) -> bool {
+        false
+    }
+
+    /// Fixes what it can, and returns whatever it couldn't fix.  Checks that can't
+    /// fix anything just run.
+    fn fix(&self, files: &[String]) -> Vec<Violation> {
+        self.run(files)
+    }
+}
+
+/// The checks that ship with salt-spray: fmt, clippy, and the warning ratchet
+/// (clippy only with the `json` feature, and the ratchet only with `syn`).
+pub fn builtin_checks() -> Vec<Box<dyn Check>> {
+    vec![
+        Box::new(crate::fmt::FmtCheck::default()),
+        #[cfg(feature = "json")]
+        Box::new(crate::clippy::ClippyCheck::default()),
+        #[cfg(feature = "syn")]
+        Box::new(crate::ratchet::RatchetCheck),
+    ]
+}
+
+/// Runs the given checks over the files, fixing what they can if `fix` is set.
+/// Files excluded by the config are never handed to any check.
+pub fn run_checks(checks: &[Box<dyn Check>], files: &[String], fix: bool) -> Vec<Violation> {
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        Default::default()
+    });
+    let mut result = Vec::new();
+    for check in checks {
+        let wanted: Vec<String> = files
+            .iter()
+            .filter(|f| check.wants(Path::new(f)) && !config.is_excluded(Path::new(f)))
+            .cloned()
+            .collect();
+        if wanted.is_empty() {
+            continue;
+        }
+        say!("Running {} on {} file(s)", check.name(), wanted.len());
+        if fix && check.can_fix() {
+            result.extend(check.fix(&wanted));
+        } else {
+            result.extend(check.run(&wanted));
+        }
+    }
+    result
+}
+
+/// The `salt` command line:
+/// `salt [--fix] [--only=name,...] [--list] [--cargo-path=...] [--color=...]
+/// [--output=...] files...`.  Exits with the number of blocking violations, which
+/// go wherever `--output` (or salt-spray.toml) says, see [`crate::sink`].
+///
+/// `salt watch [--only=name,...] [--cargo-path=...] [dirs...]` instead re-runs the
+/// checks on files as they're saved, see [`crate::watch`].
+///
+/// `salt selftest [--only=hook-id,...]` runs each hook on a crate of its own to
+/// check that they're installed and work, see [`crate::selftest`].
+///
+/// `salt cache status|clear|gc` looks after the files the hooks keep, see
+/// [`crate::cache`].
+pub fn cli_main(checks: Vec<Box<dyn Check>>) -> ! {
+    crate::exit_if_version_asked("salt");
+    let mut summary = Summary::new("salt");
+    let mut args = env::args().peekable();
+    drop(args.next());
+    let watch = args.peek().map(String::as_str) == Some(WATCH);
+    if watch {
+        drop(args.next());
+    }
+    let selftest = args.peek().map(String::as_str) == Some(SELFTEST);
+    if selftest {
+        drop(args.next());
+    }
+    if args.peek().map(String::as_str) == Some(CACHE) {
+        drop(args.next());
+        crate::cache::cli_main(args);
+    }
+
+    let known = [FIX, PORCELAIN, CARGO_PATH, COLOR, JOBS, LIST, ONLY, OUTPUT];
+    let (flags, files) = parse_args("salt", args, &known);
+    let mut fix = false;
+    let mut only: Option<Vec<String>> = None;
+    for arg in flags.iter() {
+        if arg == FIX {
+            fix = true;
+        } else if arg == PORCELAIN {
+            summary::set_porcelain(true);
+        } else if let Some(path) = arg.strip_prefix(CARGO_PATH) {
+            toolchain::set_cargo_path(path);
+        } else if let Some(jobs) = arg.strip_prefix(JOBS) {
+            jobs::set_jobs(jobs).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(-1);
+            });
+        } else if arg == LIST {
+            for check in checks.iter() {
+                let fixes = if check.can_fix() { " (can fix)" } else { "" };
+                println!("{}{}", check.name(), fixes);
+            }
+            process::exit(0);
+        } else if let Some(names) = arg.strip_prefix(ONLY) {
+            only = Some(names.split(',').map(str::to_string).collect());
+        }
+    }
+    // A config that doesn't load is reported when the checks run
+    let config = Config::load().unwrap_or_default();
+    let mut report = Report::from_flags("salt", &flags, &config).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    if selftest {
+        crate::selftest::selftest(only.as_deref());
+    }
+
+    let checks: Vec<Box<dyn Check>> = checks
+        .into_iter()
+        .filter(|c| {
+            only.as_ref()
+                .is_none_or(|o| o.iter().any(|n| n == c.name()))
+        })
+        .collect();
+    if watch {
+        crate::watch::watch(&checks, &files);
+    }
+    let violations = run_checks(&checks, &files, fix);
+    report.record_all(&violations);
+
+    summary.files = files.len();
+    summary.crates = files
+        .iter()
+        .filter_map(crate::find_manifest)
+        .collect::<std::collections::BTreeSet<_>>()
+        .len();
+    summary.violations = violations.iter().filter(|v| !v.advisory).count();
+    summary.count_lints(&violations);
+    report.finish(&summary);
+    summary.print();
+    process::exit(summary.exit_code());
+}
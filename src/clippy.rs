@@ -0,0 +1,982 @@
+//! Running clippy over the crates touched by a commit, and keeping only the lints
+//! that point into the files being committed.  This is the guts of `salt-clip`,
+//! and with `cargo check` in clippy's place, of `salt-check`.
+//!
+//! For crates with more warnings than anyone can fix at once there's a
+//! [`Baseline`], which records how many of each lint every file has in
+//! .clippy-baseline.yaml (next to .therug.yaml) so that only new ones fail.
+
+use std::cmp::min;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::check::{Check, Violation};
+use crate::config::{Config, EnvPolicy};
+use crate::debug;
+use crate::diagnostic::{parse_messages, unparsed_lines, Diagnostic};
+use crate::jobs;
+use crate::output;
+use crate::summary::Summary;
+use crate::toolchain;
+use crate::toolchain::RUSTFLAGS;
+pub use crate::toolchain::{lint_groups, LintGroups};
+use crate::{
+    cargo_target, default_features, find_manifest, find_repo_root, find_workspace_root,
+    group_by_crate, is_proc_macro, is_publishable, library_of, lint_levels, package_name,
+    read_manifest, repo_relative, say,
+};
+
+static CARGO_PROGRESS: Lazy<Regex> = Lazy::new(|| {
+    // Lines like "    Checking foo v0.1.0", as opposed to diagnostics
+    Regex::new(r"^\s*[A-Z][a-z]+\s").unwrap()
+});
+static ENV_VAR_REFERENCE: Lazy<Regex> = Lazy::new(|| {
+    // $NAME, ${NAME}, or ${NAME:-default}
+    Regex::new(r"\$(?:([A-Z_][A-Z0-9_]*)|\{([A-Za-z_][A-Za-z0-9_]*)(?::-([^}]*))?\})").unwrap()
+});
+
+/// Replaces the `$VARS` (or `${VARS}`) in the value, with the ones set earlier on
+/// the command line (or in an env file) taking precedence over the environment.
+/// `${VAR:-default}` is the default when the variable is unset or empty.  What's
+/// put in isn't looked at again, so a value can refer to itself, e.g. `$PATH`.
+pub fn resolve_env_vars(s: &str, earlier: &BTreeMap<String, String>) -> Result<String, String> {
+    let lookup = |name: &str| {
+        let repo_root = find_repo_root().filter(|_| name == "REPO_ROOT");
+        repo_root
+            .map(|r| r.to_string_lossy().into_owned())
+            .or_else(|| earlier.get(name).cloned())
+            .or_else(|| env::var(name).ok())
+    };
+    let mut result = String::new();
+    let mut rest = 0;
+    for captures in ENV_VAR_REFERENCE.captures_iter(s) {
+        // unwrap here is safe since the whole match is always there
+        let reference = captures.get(0).unwrap();
+        result.push_str(&s[rest..reference.start()]);
+        rest = reference.end();
+        let name = captures.get(1).or_else(|| captures.get(2));
+        // unwrap here is safe since one name or the other always matches
+        let value = lookup(name.unwrap().as_str());
+        let value = match captures.get(3) {
+            Some(default) if value.as_deref().unwrap_or_default().is_empty() => {
+                resolve_env_vars(default.as_str(), earlier)?
+            }
+            _ => value.ok_or_else(|| {
+                format!("Unrecognized environment variable: {}", reference.as_str())
+            })?,
+        };
+        result.push_str(&value);
+    }
+    result.push_str(&s[rest..]);
+    Ok(result)
+}
+
+/// What the variable is for the pending command: what it was set to, or else
+/// what it inherits.
+fn current_value(cmd: &Command, name: &str) -> Option<OsString> {
+    match cmd.get_envs().find(|(key, _)| *key == OsStr::new(name)) {
+        Some((_, value)) => value.map(OsStr::to_os_string),
+        None => env::var_os(name),
+    }
+}
+
+/// Loads the `NAME=value` assignments into the pending command, in order, each
+/// merged with what the variable already is as its policy says.
+fn load_env_args(
+    cmd: &mut Command,
+    args: &[String],
+    policies: &BTreeMap<String, EnvPolicy>,
+) -> Result<(), String> {
+    let mut earlier = BTreeMap::new();
+    for assignment in args {
+        if let Some((name, val)) = assignment.split_once('=') {
+            let val = resolve_env_vars(val, &earlier)?;
+            let policy = policies.get(name).copied().unwrap_or_default();
+            let val = match (policy, current_value(cmd, name)) {
+                (EnvPolicy::Preserve, Some(current)) => current,
+                (EnvPolicy::AppendPath, Some(current)) if !current.is_empty() => {
+                    let paths = env::split_paths(&current).chain(env::split_paths(&val));
+                    env::join_paths(paths)
+                        .map_err(|e| format!("Unable to append {} to {}: {}", val, name, e))?
+                }
+                _ => OsString::from(val),
+            };
+            cmd.env(name, &val);
+            earlier.insert(name.to_string(), val.to_string_lossy().into_owned());
+        }
+    }
+    Ok(())
+}
+
+/// Reads the `NAME=value` assignments in an env file, one per line.  Blank lines
+/// and `#` comments are skipped, as are `export`s in front of the names and quotes
+/// around the values, so files written for the shell work as they are.
+pub fn read_env_file(path: &Path) -> Result<Vec<String>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    let mut result = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+        let (name, val) = line.split_once('=').ok_or_else(|| {
+            format!(
+                "{}:{}: expected NAME=value, not {}",
+                path.display(),
+                number + 1,
+                line
+            )
+        })?;
+        let val = ['"', '\'']
+            .iter()
+            .find_map(|q| val.strip_prefix(*q)?.strip_suffix(*q))
+            .unwrap_or(val);
+        result.push(format!("{}={}", name.trim_end(), val));
+    }
+    Ok(result)
+}
+
+/// The command line options that apply to every crate.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// The NAME=value environment variables for clippy, set in order, so later ones
+    /// can refer to (and override) earlier ones.
+    pub env_args: Vec<String>,
+    /// Forward cargo's progress output as it happens.
+    pub stream: bool,
+    /// Show every line of cargo's progress output, rather than just a spinner and
+    /// a summary (when streaming) or nothing at all.
+    pub verbose: bool,
+    /// When not empty, only clippy lints in these groups (e.g. `correctness`) block
+    /// the commit.
+    pub only_groups: Vec<String>,
+    /// Clippy lints in these groups (e.g. `pedantic`) don't block the commit.
+    pub skip_groups: Vec<String>,
+    /// Cargo's reports that dependencies will stop compiling on a future Rust
+    /// block the commit, rather than just being forwarded.
+    pub deny_future_incompat: bool,
+}
+
+impl Options {
+    /// Should the diagnostic block the commit, as far as the lint groups go?  Only
+    /// clippy's lints are in groups, so everything else always counts.
+    fn counts(&self, diagnostic: &Diagnostic) -> bool {
+        let lint = match diagnostic.code.as_deref() {
+            Some(code) if code.starts_with("clippy::") => code,
+            _ => return true,
+        };
+        let known = lint_groups();
+        let in_any = |groups: &[String]| {
+            groups
+                .iter()
+                .filter_map(|g| known.get(g))
+                .any(|lints| lints.contains(lint))
+        };
+        (self.only_groups.is_empty() || in_any(&self.only_groups)) && !in_any(&self.skip_groups)
+    }
+}
+
+/// The frames of the spinner shown while cargo works.
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Runs the command, showing cargo's progress on stderr as it happens (prefixed
+/// with the crate name), and returns everything it wrote to stdout, the rest of
+/// what it wrote to stderr, and how it exited.  With `verbose` every progress line
+/// is forwarded, otherwise a terminal just gets a spinner, and there's a summary
+/// at the end.
+fn stream_stderr(
+    cmd: &mut Command,
+    prefix: &str,
+    verbose: bool,
+) -> io::Result<(String, String, ExitStatus)> {
+    let start = Instant::now();
+    let spin = !verbose && io::stderr().is_terminal();
+    let (mut lines, mut built) = (0, 0);
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    // The diagnostics come out on stdout, which has to be drained at the same time
+    // so cargo never blocks on a full pipe
+    // unwrap here is safe since stdout was piped above
+    let mut stdout = child.stdout.take().unwrap();
+    let collector = thread::spawn(move || {
+        let mut collected = String::new();
+        stdout.read_to_string(&mut collected).map(|_| collected)
+    });
+    let mut rest = String::new();
+    // unwrap here is safe since stderr was piped above
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut bytes = Vec::new();
+    // Lines are read as bytes, since cargo's aren't necessarily valid UTF-8
+    while stderr.read_until(b'\n', &mut bytes)? > 0 {
+        let line = output::clean(&bytes);
+        let line = line.trim_end_matches('\n');
+        if CARGO_PROGRESS.is_match(line) {
+            let line = line.trim_start();
+            lines += 1;
+            if line.starts_with("Compiling") || line.starts_with("Checking") {
+                built += 1;
+            }
+            if verbose {
+                eprintln!("[{}] {}", prefix, line);
+            } else if spin {
+                // Redrawn in place, so it has to fit on one line
+                let status: String = format!("[{}] {} {}", prefix, SPINNER[lines % 4], line)
+                    .chars()
+                    .take(78)
+                    .collect();
+                eprint!("\r{:<79}", status);
+                drop(io::stderr().flush());
+            }
+        } else {
+            rest.push_str(line);
+            rest.push('\n');
+        }
+        bytes.clear();
+    }
+    let status = child.wait()?;
+    if spin {
+        eprint!("\r{:<79}\r", "");
+    }
+    if !verbose {
+        eprintln!(
+            "[{}] built {} crate(s) in {:.1}s, --verbose shows cargo's progress",
+            prefix,
+            built,
+            start.elapsed().as_secs_f64()
+        );
+    }
+    let stdout = collector
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::other("unable to read cargo's output")))?;
+    Ok((stdout, rest, status))
+}
+
+/// Splits what cargo itself wrote to stderr (as opposed to the diagnostics, which
+/// are on stdout) into its messages, e.g. warnings about unused manifest keys or
+/// future incompatibilities, leaving out the progress lines and the "could not
+/// compile" that just repeats the diagnostics.
+pub fn cargo_messages(stderr: &str) -> Vec<String> {
+    let mut result: Vec<String> = Vec::new();
+    for line in stderr.lines() {
+        if CARGO_PROGRESS.is_match(line) || line.trim().is_empty() {
+            continue;
+        }
+        let starts_message = line.starts_with("warning:") || line.starts_with("error:");
+        match result.last_mut() {
+            Some(message) if !starts_message => {
+                message.push('\n');
+                message.push_str(line);
+            }
+            _ => result.push(line.to_string()),
+        }
+    }
+    result.retain(|m| !m.starts_with("error: could not compile"));
+    result
+}
+
+/// Is the message cargo's report that some packages will be rejected by a future
+/// version of Rust?
+fn is_future_incompat(message: &str) -> bool {
+    message.contains("future version of Rust") || message.contains("future-incompat")
+}
+
+/// Turns the diagnostics clippy found in the given files into violations.  Lints
+/// whose level is set in the crate's `[lints]` table are treated the way
+/// `cargo clippy` would treat them: `deny` and `forbid` block the commit, `warn`
+/// is only advisory, and `allow` isn't reported at all.  Lints left out by the
+/// options' groups are advisory too.  Anything else blocks.  The violations point
+/// at their files relative to the repo root, like the rest of the hooks do.
+fn violations_in(
+    cargo_toml: &str,
+    diagnostics: &[Diagnostic],
+    files: &BTreeSet<String>,
+    options: &Options,
+) -> Vec<Violation> {
+    let levels = lint_levels(cargo_toml);
+    let workspace_root = find_workspace_root(cargo_toml);
+    let mut result = Vec::new();
+    for diagnostic in diagnostics.iter().filter(|d| d.is_in(files)) {
+        let level = diagnostic.code.as_ref().and_then(|c| levels.get(c));
+        let diagnostic = diagnostic.clone().relative_to_repo(&workspace_root);
+        let file = diagnostic
+            .location
+            .as_ref()
+            .map(|(file, _, _)| file.as_str());
+        let mut violation = Violation::new(file.unwrap_or(cargo_toml), diagnostic.rendered.clone());
+        if let Some(code) = &diagnostic.code {
+            violation = violation.for_lint(code);
+        }
+        match level.map(String::as_str) {
+            _ if diagnostic.level == "error" => result.push(violation),
+            Some("allow") => (),
+            Some("warn") => result.push(violation.advisory()),
+            _ if !options.counts(&diagnostic) => result.push(violation.advisory()),
+            _ => result.push(violation),
+        }
+    }
+    result
+}
+
+/// The `--lib` and `--bin`s that the files are in, so that clippy doesn't build
+/// the binaries (or library) of a crate that none of them are in.  That's None,
+/// for clippy's usual targets, when that can't be told for some file (or it's
+/// not a Rust file, e.g. the manifest, which affects them all).  Tests, examples,
+/// and benches aren't linted either way, so they're ignored, and so are bins whose
+/// `required-features` aren't on by default, which cargo won't build when asked
+/// for by name (and skips otherwise).
+fn targets_of(files: &BTreeSet<String>) -> Option<Vec<String>> {
+    let mut result = BTreeSet::new();
+    for file in files {
+        if !file.ends_with(".rs") {
+            return None;
+        }
+        match cargo_target(file)? {
+            ("lib", _) if is_only_in_lib(Path::new(file)) => {
+                result.insert("--lib".to_string());
+            }
+            ("lib", _) => return None,
+            ("bin", name) => {
+                let name = name?;
+                if has_required_features(Path::new(file), &name) {
+                    result.insert(format!("--bin={}", name));
+                }
+            }
+            _ => (),
+        }
+    }
+    if result.is_empty() {
+        None
+    } else {
+        Some(result.into_iter().collect())
+    }
+}
+
+/// Is the file, which [`cargo_target`] puts in the library, sure to be in it?  Any
+/// file under src/ is, by its path, so it's only sure when the crate has a library
+/// and no binary's root is right in src/ too (like src/main.rs), whose modules
+/// could be anywhere in there.
+fn is_only_in_lib(file: &Path) -> bool {
+    let manifest = match find_manifest(file) {
+        Some(manifest) => manifest,
+        None => return false,
+    };
+    let root = match library_of(&manifest) {
+        Some((_, root)) => root,
+        None => return false,
+    };
+    let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if canonical(file) == canonical(&root) {
+        return true;
+    }
+    let dir = manifest.parent().unwrap_or_else(|| Path::new(""));
+    let table = read_manifest(&manifest);
+    let bins = table
+        .as_ref()
+        .and_then(|t| t.get("bin")?.as_array().cloned());
+    let mut bin_roots = bins
+        .into_iter()
+        .flatten()
+        .filter_map(|b| b.get("path")?.as_str().map(PathBuf::from));
+    !dir.join("src/main.rs").is_file()
+        && !bin_roots.any(|root| root.parent() == Some(Path::new("src")))
+}
+
+/// Are the `required-features` of the file's crate's bin of the given name (if it
+/// has any) all on by default?
+fn has_required_features(file: &Path, bin: &str) -> bool {
+    let manifest = match find_manifest(file) {
+        Some(manifest) => manifest,
+        None => return true,
+    };
+    let table = read_manifest(&manifest);
+    let bins = table
+        .as_ref()
+        .and_then(|t| t.get("bin")?.as_array().cloned());
+    let required = bins
+        .into_iter()
+        .flatten()
+        .find(|b| b.get("name").and_then(|n| n.as_str()) == Some(bin))
+        .and_then(|b| b.get("required-features")?.as_array().cloned());
+    let defaults = default_features(&manifest);
+    required.into_iter().flatten().all(|feature| {
+        // A dependency's feature (`x/y`) is on when the dependency is
+        let feature = feature.as_str().unwrap_or_default();
+        defaults.contains(feature.split('/').next().unwrap_or_default())
+    })
+}
+
+/// Runs Clippy on a crate, and returns the lints for files in the given set.
+/// `config` is the repo-wide config, the crate's own is looked up from it.
+pub fn lint_crate(
+    cargo_toml: &str,
+    files: &BTreeSet<String>,
+    options: &Options,
+    config: &Config,
+) -> Vec<Violation> {
+    run_on_crate("clippy", cargo_toml, files, options, config)
+}
+
+/// Runs `cargo check` on a crate, and returns the errors and warnings for files in
+/// the given set, the way [`lint_crate`] does clippy's.  This is `salt-check`.
+pub fn check_crate(
+    cargo_toml: &str,
+    files: &BTreeSet<String>,
+    options: &Options,
+    config: &Config,
+) -> Vec<Violation> {
+    run_on_crate("check", cargo_toml, files, options, config)
+}
+
+/// Runs the cargo subcommand (`clippy` or `check`) on a crate, with everything
+/// that goes with it, and returns the diagnostics for files in the given set.
+fn run_on_crate(
+    subcommand: &str,
+    cargo_toml: &str,
+    files: &BTreeSet<String>,
+    options: &Options,
+    config: &Config,
+) -> Vec<Violation> {
+    let clippy = subcommand == "clippy";
+    let manifest = Path::new(cargo_toml);
+    let policy = config.policy_for(manifest);
+    let config = config.for_crate(manifest).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        config.clone()
+    });
+
+    let mut result = Vec::new();
+    let mut cmd = toolchain::cargo_for(manifest, &config);
+    if let Err(e) = load_env_args(&mut cmd, &options.env_args, &config.clip.env_policy) {
+        let violation = Violation::new(
+            cargo_toml,
+            format!("Unable to set up cargo {}'s environment: {}", subcommand, e),
+        );
+        return vec![if policy.advisory {
+            violation.advisory()
+        } else {
+            violation
+        }];
+    }
+    cmd.arg(subcommand);
+    if clippy {
+        cmd.arg("--no-deps");
+    }
+    // The colors in the rendered diagnostics are only wanted where they'll be kept
+    let format = if output::keeps_color() {
+        "--message-format=json-diagnostic-rendered-ansi"
+    } else {
+        "--message-format=json"
+    };
+    cmd.args([format, "--manifest-path"])
+        .arg(std::path::absolute(manifest).unwrap_or_else(|_| manifest.to_path_buf()));
+    if let Some(jobs) = jobs::limit() {
+        cmd.args(["--jobs", &jobs.to_string()]);
+    }
+    let mut rustflags = Vec::new();
+    let mut triple: Option<&str> = None;
+    if is_proc_macro(cargo_toml) {
+        // Proc-macro crates can't be linked into tests, examples, or doctests the
+        // usual way, so linting those targets is mostly noise (or failures).
+        if config.proc_macro.lib_only {
+            cmd.arg("--lib");
+        }
+        rustflags.extend(config.proc_macro.rustflags.as_deref());
+    } else if let Some(target) = config.cross_target_for(manifest) {
+        // Lints depend on the target (cfgs, pointer widths, and so on)
+        cmd.args(["--target", &target.triple]);
+        triple = Some(&target.triple);
+        rustflags.extend(target.rustflags.as_deref());
+    }
+    // Building every binary of a crate for a change to one of them (or to its
+    // library alone) can take much longer than linting the change
+    if !(is_proc_macro(cargo_toml) && config.proc_macro.lib_only) {
+        if let Some(targets) = targets_of(files) {
+            cmd.args(targets);
+        }
+    }
+    if !rustflags.is_empty() {
+        let extra = rustflags.join(" ");
+        let encoded = toolchain::encoded_flags(manifest, &config, triple, RUSTFLAGS, &extra);
+        cmd.env("CARGO_ENCODED_RUSTFLAGS", encoded);
+    }
+    // Publishable crates can be held to a higher standard
+    let publish_args = if is_publishable(cargo_toml) {
+        config.publish.clippy_args.as_slice()
+    } else {
+        &[]
+    };
+    let clippy_args = [&config.clippy_args[..], &policy.clippy_args, publish_args].concat();
+    if clippy && !clippy_args.is_empty() {
+        cmd.arg("--").args(clippy_args);
+    }
+
+    let output = if options.stream {
+        let name = package_name(cargo_toml).unwrap_or_else(|| cargo_toml.to_string());
+        stream_stderr(&mut cmd, &name, options.verbose)
+    } else {
+        cmd.output().map(
+            |Output {
+                 status,
+                 stdout,
+                 stderr,
+             }| {
+                let stderr = output::clean(&stderr);
+                if options.verbose {
+                    let name = package_name(cargo_toml).unwrap_or_else(|| cargo_toml.to_string());
+                    for line in stderr.lines().filter(|l| CARGO_PROGRESS.is_match(l)) {
+                        eprintln!("[{}] {}", name, line.trim_start());
+                    }
+                }
+                (String::from_utf8_lossy(&stdout).to_string(), stderr, status)
+            },
+        )
+    };
+
+    match output {
+        Ok((stdout, stderr, status)) => {
+            let messages = cargo_messages(&stderr);
+            if !messages.is_empty() {
+                eprintln!("\n-- cargo's own messages about {} --", cargo_toml);
+                for message in messages.iter() {
+                    eprintln!("{}", message);
+                }
+                eprintln!("-- end of cargo's messages --");
+            }
+            if options.deny_future_incompat {
+                let reports = messages.into_iter().filter(|m| is_future_incompat(m));
+                result.extend(reports.map(|m| {
+                    Violation::new(
+                        cargo_toml,
+                        format!(
+                            "{}: depends on code a future Rust will reject\n{}",
+                            cargo_toml, m
+                        ),
+                    )
+                }));
+            }
+            let diagnostics = parse_messages(&stdout);
+            result.extend(violations_in(cargo_toml, &diagnostics, files, options));
+            // e.g. when there's no such target, cargo fails before rustc says a thing
+            if !status.success() && !diagnostics.iter().any(|d| d.level == "error") {
+                result.push(Violation::new(
+                    cargo_toml,
+                    format!(
+                        "cargo {} failed on {} ({}) without any errors from rustc:\n{}",
+                        subcommand,
+                        cargo_toml,
+                        status,
+                        cargo_messages(&stderr).join("\n")
+                    ),
+                ));
+            }
+            let unparsed = unparsed_lines(&stdout).len();
+            if unparsed > 0 {
+                let message = format!(
+                    "Unable to parse {} of the messages cargo printed about {}, so some of its \
+                     diagnostics may be missing",
+                    unparsed, cargo_toml
+                );
+                let dump_name = format!("{}-output.json", subcommand);
+                let message = debug::with_dump(message, &dump_name, &stdout);
+                result.push(Violation::new(cargo_toml, message));
+            }
+        }
+        e => result.push(Violation::new(cargo_toml, format!("{:?}", e))),
+    }
+    if policy.advisory && !result.is_empty() {
+        eprintln!(
+            "{} is advisory, so its {} {} won't block the commit.",
+            cargo_toml,
+            result.len(),
+            if clippy { "lint(s)" } else { "diagnostic(s)" }
+        );
+        result = result.into_iter().map(Violation::advisory).collect();
+    }
+    result
+}
+
+/// How linting one crate went, for the report `salt-clip --report-file=` writes.
+#[derive(Clone, Debug)]
+pub struct CrateRun {
+    /// The crate's Cargo.toml.
+    pub manifest: String,
+    /// The files it was linted for.
+    pub files: BTreeSet<String>,
+    /// How long linting it took.
+    pub duration: Duration,
+    /// What clippy found, after the baseline (if there is one) had its say.
+    pub violations: Vec<Violation>,
+}
+
+impl CrateRun {
+    fn status(&self) -> &'static str {
+        if self.violations.iter().all(|v| v.advisory) {
+            "passed"
+        } else {
+            "failed"
+        }
+    }
+}
+
+/// Writes the report of a run over many crates to the path (relative to the repo
+/// root, unless it's absolute), replacing what was there: a JSON document with the
+/// run's summary and, for each crate, its status, how long it took, and its
+/// diagnostics (as the `json-lines` sink writes them, see [`crate::sink`]).  Since
+/// each run replaces the report, it only covers a whole commit when the commit's
+/// files all go to one run, i.e. when salt-clip runs serially.
+pub fn write_report(path: &str, runs: &[CrateRun], summary: &Summary) -> Result<(), String> {
+    let path = match find_repo_root() {
+        Some(root) if Path::new(path).is_relative() => root.join(path),
+        _ => PathBuf::from(path),
+    };
+    let crates: Vec<serde_json::Value> = runs
+        .iter()
+        .map(|run| {
+            serde_json::json!({
+                "manifest": run.manifest,
+                "package": package_name(&run.manifest),
+                "status": run.status(),
+                "seconds": run.duration.as_secs_f64(),
+                "files": run.files,
+                "diagnostics": run.violations.iter().map(Violation::to_json).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    let status = if runs.iter().all(|run| run.status() == "passed") {
+        "passed"
+    } else {
+        "failed"
+    };
+    let report = serde_json::json!({
+        "tool": summary.tool(),
+        "status": status,
+        "summary": {
+            "crates": summary.crates,
+            "files": summary.files,
+            "violations": summary.violations,
+            "seconds": summary.elapsed().as_secs_f64(),
+        },
+        "crates": crates,
+    });
+    // unwrap here is safe since the report is made of strings, numbers, and lists
+    let mut contents = serde_json::to_string_pretty(&report).unwrap();
+    contents.push('\n');
+    if let Some(parent) = path.parent() {
+        drop(fs::create_dir_all(parent));
+    }
+    fs::write(&path, contents).map_err(|e| format!("Unable to write {}: {}", path.display(), e))
+}
+
+/// Where the baseline of clippy's diagnostics lives.
+pub const BASELINE: &str = ".clippy-baseline.yaml";
+
+/// How many of each lint the files of each crate had when they were last linted,
+/// so that a crate can adopt clippy without first fixing every warning it already
+/// has.  A file may keep up to as many diagnostics of a lint as its baseline says,
+/// which are shown but don't block the commit, while a file with more of them than
+/// that has every one of them block it (there's no telling which is new).  Fixing
+/// them lowers the baseline, and a crate that isn't in it yet is added as it is.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Baseline {
+    /// The counts of each lint in each (repo relative) file, by package name.
+    crates: BTreeMap<String, BTreeMap<String, BTreeMap<String, usize>>>,
+    #[serde(skip)]
+    updated: bool,
+}
+
+impl Baseline {
+    /// Loads the baseline from the working directory, or an empty one if there
+    /// isn't one yet.
+    pub fn load() -> Result<Baseline, String> {
+        match fs::read_to_string(BASELINE) {
+            Ok(contents) => {
+                serde_yaml::from_str(&contents).map_err(|e| format!("{}: {}", BASELINE, e))
+            }
+            Err(_) => Ok(Default::default()),
+        }
+    }
+
+    /// Writes the baseline out, if it changed.  Returns whether it did.
+    pub fn save(&self) -> Result<bool, String> {
+        if !self.updated {
+            return Ok(false);
+        }
+        // unwrap here is safe since the baseline is just maps of strings and numbers
+        let contents = serde_yaml::to_string(self).unwrap();
+        fs::write(BASELINE, contents)
+            .map_err(|e| format!("Unable to write {}: {}", BASELINE, e))?;
+        Ok(true)
+    }
+
+    /// Holds the crate's violations in the given files up to the baseline, making
+    /// the ones it allows advisory, and updates it to match the files.  Only lints
+    /// are baselined, compiler errors always block.
+    pub fn apply(
+        &mut self,
+        cargo_toml: &str,
+        files: &BTreeSet<String>,
+        violations: Vec<Violation>,
+    ) -> Vec<Violation> {
+        let is_lint =
+            |lint: &str| !(lint.starts_with('E') && lint[1..].chars().all(|c| c.is_ascii_digit()));
+        let mut observed: BTreeMap<String, BTreeMap<String, usize>> = files
+            .iter()
+            .map(|f| {
+                (
+                    repo_relative(f).to_string_lossy().replace('\\', "/"),
+                    Default::default(),
+                )
+            })
+            .collect();
+        for violation in violations.iter() {
+            if let (Some(file), Some(lint)) = (&violation.file, &violation.lint) {
+                if is_lint(lint) {
+                    *observed
+                        .entry(file.clone())
+                        .or_default()
+                        .entry(lint.clone())
+                        .or_default() += 1;
+                }
+            }
+        }
+
+        let name = package_name(cargo_toml).unwrap_or_else(|| cargo_toml.to_string());
+        let update_anyway = env::var("UPDATE_ANYWAY").map(|v| v == "1").unwrap_or(false);
+        let known = match self.crates.get_mut(&name) {
+            Some(known) => known,
+            None => {
+                let count: usize = observed.values().flat_map(|l| l.values()).sum();
+                say!(
+                    "Started a clippy baseline for {} with {} diagnostic(s)",
+                    name,
+                    count
+                );
+                observed.retain(|_, lints| !lints.is_empty());
+                self.crates.insert(name, observed);
+                self.updated = true;
+                // What it started with is what it allows, which is only ever lints
+                return violations
+                    .into_iter()
+                    .map(|v| match (&v.file, &v.lint) {
+                        (Some(_), Some(lint)) if is_lint(lint) => v.advisory(),
+                        _ => v,
+                    })
+                    .collect();
+            }
+        };
+
+        let allowed =
+            |file: &str, lint: &str, known: &BTreeMap<String, BTreeMap<String, usize>>| {
+                let was = known
+                    .get(file)
+                    .and_then(|l| l.get(lint))
+                    .copied()
+                    .unwrap_or(0);
+                let is = observed
+                    .get(file)
+                    .and_then(|l| l.get(lint))
+                    .copied()
+                    .unwrap_or(0);
+                is <= was
+            };
+        let result = violations
+            .into_iter()
+            .map(|v| match (&v.file, &v.lint) {
+                (Some(file), Some(lint)) if is_lint(lint) && allowed(file, lint, known) => {
+                    v.advisory()
+                }
+                (Some(file), Some(lint)) if is_lint(lint) && !v.advisory => {
+                    let was = known
+                        .get(file)
+                        .and_then(|l| l.get(lint))
+                        .copied()
+                        .unwrap_or(0);
+                    let message = format!(
+                        "{}\n{} has more {} than the {} it has in {}",
+                        v.message, file, lint, was, BASELINE
+                    );
+                    Violation { message, ..v }
+                }
+                _ => v,
+            })
+            .collect();
+
+        for (file, lints) in observed.into_iter() {
+            let entry = known.entry(file).or_default();
+            let count =
+                |l: &str, counts: &BTreeMap<String, usize>| counts.get(l).copied().unwrap_or(0);
+            let shrunk = entry.iter().any(|(l, n)| count(l, &lints) < *n);
+            let grew = lints.iter().any(|(l, n)| count(l, entry) < *n);
+            if grew && update_anyway {
+                *entry = lints;
+                self.updated = true;
+            } else if shrunk {
+                for (lint, n) in entry.iter_mut() {
+                    *n = min(*n, count(lint, &lints));
+                }
+                entry.retain(|_, n| *n > 0);
+                self.updated = true;
+            }
+        }
+        known.retain(|_, lints| !lints.is_empty());
+        result
+    }
+}
+
+/// The parts of `cargo metadata --no-deps` needed to find reverse dependencies.
+#[cfg(feature = "metadata")]
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<MetadataPackage>,
+}
+
+#[cfg(feature = "metadata")]
+#[derive(Deserialize)]
+struct MetadataPackage {
+    manifest_path: PathBuf,
+    dependencies: Vec<MetadataDependency>,
+}
+
+#[cfg(feature = "metadata")]
+#[derive(Deserialize)]
+struct MetadataDependency {
+    /// Only set for path dependencies, which is what workspace members use.
+    path: Option<PathBuf>,
+}
+
+/// Finds the crates in the workspace that depend on the given ones, directly or
+/// not, according to `cargo metadata`.  They're returned the way
+/// `group_by_crate` would, with all of their files, so that lints a change causes
+/// in its dependents get reported too.  A workspace whose graph can't be had just
+/// gets told so, and has no reverse dependencies.
+#[cfg(feature = "metadata")]
+pub fn reverse_dependencies(
+    workspace: &Path,
+    manifests: &BTreeSet<String>,
+    config: &Config,
+) -> Result<BTreeMap<String, BTreeSet<String>>, String> {
+    let output = toolchain::cargo()
+        .args([
+            "metadata",
+            "--no-deps",
+            "--format-version",
+            "1",
+            "--manifest-path",
+        ])
+        .arg(workspace.join("Cargo.toml"))
+        .stderr(Stdio::inherit())
+        .output();
+    let metadata: Metadata = match output.map(|o| serde_json::from_slice(&o.stdout)) {
+        Ok(Ok(metadata)) => metadata,
+        _ => {
+            eprintln!(
+                "Unable to get the dependency graph of {}, so its reverse dependencies won't be linted",
+                workspace.display()
+            );
+            return Ok(BTreeMap::new());
+        }
+    };
+
+    // Which crate directories depend on which
+    let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+    let mut dependents: BTreeMap<PathBuf, BTreeSet<PathBuf>> = BTreeMap::new();
+    for package in metadata.packages.iter() {
+        let dir = canonical(package.manifest_path.parent().unwrap_or(Path::new("")));
+        for path in package.dependencies.iter().filter_map(|d| d.path.as_ref()) {
+            dependents
+                .entry(canonical(path))
+                .or_default()
+                .insert(dir.clone());
+        }
+    }
+
+    let changed: BTreeSet<PathBuf> = manifests
+        .iter()
+        .filter_map(|m| Path::new(m).parent())
+        .map(|d| {
+            canonical(if d.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                d
+            })
+        })
+        .collect();
+    let mut pending: Vec<PathBuf> = changed.iter().cloned().collect();
+    let mut found = BTreeSet::new();
+    while let Some(dir) = pending.pop() {
+        for dependent in dependents.get(&dir).into_iter().flatten() {
+            if !changed.contains(dependent) && found.insert(dependent.clone()) {
+                pending.push(dependent.clone());
+            }
+        }
+    }
+
+    // Paths are handed back relative to the current directory, like the rest
+    let cwd = env::current_dir()
+        .map(|d| canonical(&d))
+        .unwrap_or_default();
+    let mut result = BTreeMap::new();
+    for dir in found {
+        let dir = dir.strip_prefix(&cwd).map(Path::to_path_buf).unwrap_or(dir);
+        let files = crate::rust_files_in(&dir)
+            .into_iter()
+            .filter(|f| !config.is_excluded(f))
+            .map(|f| f.to_string_lossy().to_string())
+            .collect();
+        result.insert(dir.join("Cargo.toml").to_string_lossy().to_string(), files);
+    }
+    Ok(result)
+}
+
+/// Without the `metadata` feature the dependency graph can't be had, so asking for
+/// reverse dependencies is an error.
+#[cfg(not(feature = "metadata"))]
+pub fn reverse_dependencies(
+    workspace: &Path,
+    _manifests: &BTreeSet<String>,
+    _config: &Config,
+) -> Result<BTreeMap<String, BTreeSet<String>>, String> {
+    Err(format!(
+        "Unable to find the reverse dependencies in {}, since this build doesn't have the \
+         metadata feature",
+        workspace.display()
+    ))
+}
+
+/// Clippy as a [`Check`].
+#[derive(Debug, Default)]
+pub struct ClippyCheck {
+    /// How to run clippy.
+    pub options: Options,
+}
+
+impl Check for ClippyCheck {
+    fn name(&self) -> &str {
+        "clippy"
+    }
+
+    fn run(&self, files: &[String]) -> Vec<Violation> {
+        let config = Config::load().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            Default::default()
+        });
+        let mut result = Vec::new();
+        for (cargo_toml, files) in group_by_crate(files).iter() {
+            result.extend(lint_crate(cargo_toml, files, &self.options, &config));
+        }
+        result
+    }
+}
@@ -0,0 +1,1305 @@
+//! Configuration shared by all of the hooks, read from `salt-spray.toml` at the
+//! root of the repo.
+//!
+//! The main thing configured here is the tier system, which lets a monorepo hold
+//! different areas of the code to different standards.  A config like
+//!
+//! ```toml
+//! [tiers]
+//! tier1 = ["core/**"]
+//! tier3 = ["experimental/**"]
+//! ```
+//!
+//! puts every crate whose Cargo.toml lives under `core/` into tier1 (clippy
+//! pedantic, no lint suppressions allowed) and everything under `experimental/`
+//! into tier3 (findings are reported but never fail the commit).  Crates that
+//! don't match any tier get the default policy, which is how the hooks behaved
+//! before tiers existed.
+//!
+//! Member crates can have a `salt-spray.toml` of their own, next to their
+//! Cargo.toml.  Any keys set there override the repo-wide file for that crate
+//! (tables are merged key by key, everything else is replaced outright).
+//!
+//! Generated files are left alone by every hook, as if they were excluded.  Most
+//! are recognized by themselves (see [`crate::looks_generated`]), and the rest can
+//! be listed:
+//!
+//! ```toml
+//! [generated]
+//! paths = ["proto/src/gen/**"]
+//! ```
+//!
+//! Config files are checked as they're read, so a misspelled key, a value of the
+//! wrong type, or a glob that can't match anything is reported with its line and
+//! column rather than being silently ignored.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::{closest, find_repo_root, find_workspace_root, repo_relative, Memo};
+
+static GLOB_REGEX: Memo<String, Arc<Regex>> = Memo::new();
+static UNKNOWN_FIELD: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^unknown field `([^`]*)`, expected (.*)$").unwrap());
+
+/// The name of the config file, expected at the repo root.
+pub const CONFIG_FILE: &str = "salt-spray.toml";
+
+/// Everything that can be set in `salt-spray.toml`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Globs (relative to the repo root) of files that none of the hooks should touch.
+    pub exclude: Vec<String>,
+    /// Extra arguments handed to clippy, in addition to those from the tier policy.
+    pub clippy_args: Vec<String>,
+    /// Environment variables set whenever cargo runs for the crate.  This mostly
+    /// makes sense in the config at the root of a workspace, to give it its own
+    /// environment.
+    pub env: BTreeMap<String, String>,
+    /// When set, used for the crate instead of the policy of its tier.  This mostly
+    /// makes sense in a crate level config.
+    pub policy: Option<TierPolicy>,
+    /// Maps the name of a tier to globs (relative to the repo root) matching the
+    /// Cargo.toml files of the crates in that tier.
+    pub tiers: BTreeMap<String, Vec<String>>,
+    /// Replaces the built-in policy of a tier (or defines the policy of a custom one).
+    pub tier_policy: BTreeMap<String, TierPolicy>,
+    /// Settings for `salt-spray` itself.
+    pub fmt: FmtConfig,
+    /// Settings for `salt-fmt-imports`.
+    pub fmt_imports: FmtImportsConfig,
+    /// Settings for `salt-derive-guard`.
+    pub derive_guard: DeriveGuardConfig,
+    /// Settings for `salt-cfg-guard`.
+    pub cfg_guard: CfgGuardConfig,
+    /// Settings for `salt-cfg-test-guard`.
+    pub cfg_test_guard: CfgTestGuardConfig,
+    /// Settings for `salt-test-name-guard`.
+    pub test_name_guard: TestNameGuardConfig,
+    /// Settings for `salt-enum-guard`.
+    pub enum_guard: EnumGuardConfig,
+    /// Settings for `salt-ownership`.
+    pub ownership: OwnershipConfig,
+    /// Settings for `salt-submodule-guard`.
+    pub submodule_guard: SubmoduleGuardConfig,
+    /// Settings for `salt-changelog-guard`.
+    pub changelog_guard: ChangelogGuardConfig,
+    /// Settings for `salt-bin-size-guard`.
+    pub bin_size_guard: BinSizeGuardConfig,
+    /// Settings for `salt-workspace-dep-guard`.
+    pub workspace_dep_guard: WorkspaceDepGuardConfig,
+    /// Settings for `salt-error-guard`.
+    pub error_guard: ErrorGuardConfig,
+    /// Settings for `salt-feature-flag-guard`.
+    pub feature_flag_guard: FeatureFlagGuardConfig,
+    /// Settings for `salt-stability-guard`.
+    pub stability_guard: StabilityGuardConfig,
+    /// Settings for `salt-dupe-guard`.
+    pub dupe_guard: DupeGuardConfig,
+    /// Settings for `warning-ratchet`.
+    pub ratchet: RatchetConfig,
+    /// Settings for `salt-clip`.
+    pub clip: ClipConfig,
+    /// How `salt-clip` treats proc-macro crates.
+    pub proc_macro: ProcMacroConfig,
+    /// The targets `salt-clip` lints crates for, by the path (relative to the repo
+    /// root) their Cargo.toml is under.  The longest path that matches wins.
+    pub cross: BTreeMap<String, CrossTarget>,
+    /// How `salt-clip` treats crates depending on whether they can be published.
+    pub publish: PublishConfig,
+    /// Where the hooks report metrics about their runs.
+    pub metrics: MetricsConfig,
+    /// Where the hooks send their findings.
+    pub output: OutputConfig,
+    /// Which files are generated, and so left alone by all of the hooks.
+    pub generated: GeneratedConfig,
+    /// How much the hooks keep on disk, see [`crate::cache`].
+    pub cache: CacheConfig,
+    /// What the config was parsed from, kept around so it can be merged with crate
+    /// level configs.
+    #[serde(skip)]
+    raw: toml::Table,
+    /// The crate level configs looked up so far, by manifest, since the same few
+    /// get looked up for every file in a commit.
+    #[serde(skip)]
+    crate_configs: Arc<Mutex<HashMap<PathBuf, Option<Arc<Config>>>>>,
+}
+
+/// Settings for `salt-spray` itself.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct FmtConfig {
+    /// Macros (e.g. `my_crate::routes`) whose brace-delimited bodies should be
+    /// formatted even though rustfmt won't touch them.  Only bodies that parse as
+    /// statements are formatted, anything else (e.g. `quote!`) is left as is.
+    pub macros: Vec<String>,
+    /// Only format the crates that are in their workspace's `default-members`,
+    /// leaving the rest (typically legacy crates) alone.
+    pub default_members_only: bool,
+    /// Normalize the dependency tables of the Cargo.toml files in the commit too,
+    /// see [`crate::manifest`].
+    pub manifests: bool,
+    /// Extra rustfmt settings for the files of each kind of target, e.g.
+    ///
+    /// ```toml
+    /// [fmt.targets]
+    /// bin = ["max_width=120"]
+    /// ```
+    pub targets: FmtTargets,
+    /// When a commit touches at least this fraction (e.g. `0.8`) of a workspace's
+    /// crates, the whole workspace is formatted with one `cargo fmt` of its members
+    /// instead of file by file, which is much faster for sweeping changes like
+    /// edition migrations.  It formats files outside of the commit too, so this is
+    /// off unless it's set.
+    pub whole_workspace_fraction: Option<f64>,
+}
+
+/// Extra rustfmt settings (`key=value`, like `--rustfmt-config`) for the files of
+/// each kind of cargo target, see [`crate::target_kind`].
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FmtTargets {
+    /// For the library.
+    pub lib: Vec<String>,
+    /// For binaries.
+    pub bin: Vec<String>,
+    /// For integration tests.
+    pub test: Vec<String>,
+    /// For examples.
+    pub example: Vec<String>,
+    /// For benchmarks.
+    pub bench: Vec<String>,
+    /// For build scripts.
+    pub build: Vec<String>,
+}
+
+impl FmtTargets {
+    /// The settings for the given kind of target.
+    pub fn settings_for(&self, kind: &str) -> &[String] {
+        match kind {
+            "lib" => &self.lib,
+            "bin" => &self.bin,
+            "test" => &self.test,
+            "example" => &self.example,
+            "bench" => &self.bench,
+            "build" => &self.build,
+            _ => &[],
+        }
+    }
+
+    /// Are there no settings for any kind of target?
+    pub fn is_empty(&self) -> bool {
+        ["lib", "bin", "test", "example", "bench", "build"]
+            .iter()
+            .all(|kind| self.settings_for(kind).is_empty())
+    }
+}
+
+/// Settings for `salt-fmt-imports`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct FmtImportsConfig {
+    /// The order of the groups, which are separated by blank lines.  Any groups
+    /// left out go after the others, in the default order.
+    pub groups: Vec<ImportGroup>,
+    /// How `use` trees are merged or split.
+    pub granularity: ImportGranularity,
+}
+
+impl Default for FmtImportsConfig {
+    fn default() -> Self {
+        FmtImportsConfig {
+            groups: ImportGroup::ALL.to_vec(),
+            granularity: ImportGranularity::Preserve,
+        }
+    }
+}
+
+/// Where an import belongs, going by the first segment of its path.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImportGroup {
+    /// `std`, `core`, `alloc`, and `proc_macro`.
+    Std,
+    /// Crates from outside the workspace.
+    External,
+    /// Other crates in the same workspace.
+    Workspace,
+    /// `crate`, `self`, and `super`.
+    Crate,
+}
+
+impl ImportGroup {
+    /// Every group, in the default order.
+    pub const ALL: [ImportGroup; 4] = [
+        ImportGroup::Std,
+        ImportGroup::External,
+        ImportGroup::Workspace,
+        ImportGroup::Crate,
+    ];
+}
+
+/// How `salt-fmt-imports` merges or splits `use` trees, like rustfmt's nightly
+/// `imports_granularity`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImportGranularity {
+    /// Leave each `use` as it was written, only moving it into its group.
+    Preserve,
+    /// One `use` per crate, e.g. `use std::{fs, io::Read};`.
+    Crate,
+    /// One `use` per module, e.g. `use std::io::{Read, Write};`.
+    Module,
+    /// One `use` per imported item, e.g. `use std::io::Read;`.
+    Item,
+}
+
+/// Settings for `salt-cfg-guard`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CfgGuardConfig {
+    /// Custom cfgs (e.g. `tokio_unstable`) that are expected, on top of the ones
+    /// rustc knows about and the ones declared in a crate's
+    /// `[lints.rust.unexpected_cfgs]` check-cfg list.
+    pub allowed: Vec<String>,
+}
+
+/// Settings for `salt-cfg-test-guard`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CfgTestGuardConfig {
+    /// When set, new public functions without tests are reported but don't fail
+    /// the commit.
+    pub advisory: bool,
+    /// A comment containing this text just above a function exempts it from the check.
+    pub marker: String,
+}
+
+impl Default for CfgTestGuardConfig {
+    fn default() -> Self {
+        CfgTestGuardConfig {
+            advisory: false,
+            marker: "salt-cfg-test-guard: skip".to_string(),
+        }
+    }
+}
+
+/// Settings for `salt-test-name-guard`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TestNameGuardConfig {
+    /// A regex the names of test functions have to match, e.g. `^(it_|test_)`.
+    pub pattern: Option<String>,
+    /// When set, misplaced and misnamed tests are reported but don't fail the commit.
+    pub advisory: bool,
+}
+
+/// Settings for `salt-enum-guard`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct EnumGuardConfig {
+    /// Whether new public structs with public fields have to be `#[non_exhaustive]`
+    /// too, not just new public enums.
+    pub structs: bool,
+    /// Leave the crates that can't be published alone, since nobody outside of the
+    /// repo can depend on them.
+    pub publishable_only: bool,
+    /// A comment containing this text just above a type exempts it from the check.
+    pub marker: String,
+    /// When set, exhaustive types are reported but don't fail the commit.
+    pub advisory: bool,
+}
+
+impl Default for EnumGuardConfig {
+    fn default() -> Self {
+        EnumGuardConfig {
+            structs: true,
+            publishable_only: false,
+            marker: "salt-enum-guard: exhaustive".to_string(),
+            advisory: false,
+        }
+    }
+}
+
+/// Settings for `salt-ownership`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct OwnershipConfig {
+    /// The CODEOWNERS file (relative to the repo root) to use, instead of looking
+    /// in the places GitHub and GitLab do.
+    pub codeowners: Option<String>,
+    /// When set, new files without an owner are reported but don't fail the commit.
+    pub advisory: bool,
+}
+
+/// Settings for `salt-submodule-guard`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SubmoduleGuardConfig {
+    /// The branch submodule commits must be on, for submodules whose entry in
+    /// .gitmodules doesn't name one.  Defaults to the branch the submodule's
+    /// remote has checked out.
+    pub branch: Option<String>,
+    /// Globs (relative to the repo root) of the changelogs a bump has to add an
+    /// entry to.
+    pub changelog: Vec<String>,
+    /// Fetch the branch before checking it, rather than trusting what the
+    /// submodule last fetched.
+    pub fetch: bool,
+    /// When set, bad bumps are reported but don't fail the commit.
+    pub advisory: bool,
+}
+
+impl Default for SubmoduleGuardConfig {
+    fn default() -> Self {
+        SubmoduleGuardConfig {
+            branch: None,
+            changelog: vec!["**/CHANGELOG.md".to_string()],
+            fetch: false,
+            advisory: false,
+        }
+    }
+}
+
+/// Settings for `salt-changelog-guard`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ChangelogGuardConfig {
+    /// Globs (relative to the repo root) of changeset files, which count as a
+    /// changelog entry for the crates they mention by name.
+    pub changesets: Vec<String>,
+    /// A commit whose message contains this doesn't need changelog entries.
+    pub skip_tag: String,
+    /// When set, missing entries are reported but don't fail the commit.
+    pub advisory: bool,
+}
+
+impl Default for ChangelogGuardConfig {
+    fn default() -> Self {
+        ChangelogGuardConfig {
+            changesets: vec![".changeset/*.md".to_string()],
+            skip_tag: "[skip changelog]".to_string(),
+            advisory: false,
+        }
+    }
+}
+
+/// Settings for `salt-bin-size-guard`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BinSizeGuardConfig {
+    /// How much (as a fraction of its size in the baseline) a binary may grow
+    /// before it counts as a regression.
+    pub tolerance: f64,
+    /// The cargo profile the binaries are built with.
+    pub profile: String,
+    /// A file (relative to the repo root) to read the binaries' sizes from, rather
+    /// than building them, for CI jobs that have built them already.
+    pub sizes: Option<String>,
+    /// When set, regressions are reported but don't fail the commit.
+    pub advisory: bool,
+}
+
+impl Default for BinSizeGuardConfig {
+    fn default() -> Self {
+        BinSizeGuardConfig {
+            tolerance: 0.02,
+            profile: "release".to_string(),
+            sizes: None,
+            advisory: false,
+        }
+    }
+}
+
+/// Settings for `salt-workspace-dep-guard`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct WorkspaceDepGuardConfig {
+    /// How many of a workspace's members have to depend on a crate before they
+    /// have to inherit it from `[workspace.dependencies]`.
+    pub min_members: usize,
+    /// Dependencies (by name) that members may spell out for themselves.
+    pub ignore: Vec<String>,
+    /// When set, problems are reported but don't fail the commit.
+    pub advisory: bool,
+}
+
+impl Default for WorkspaceDepGuardConfig {
+    fn default() -> Self {
+        WorkspaceDepGuardConfig {
+            min_members: 2,
+            ignore: Vec::new(),
+            advisory: false,
+        }
+    }
+}
+
+/// Settings for `salt-error-guard`, which mostly make sense in a crate level
+/// config, since each crate has its own error type.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ErrorGuardConfig {
+    /// The error type (e.g. `crate::Error`) that the crate's public functions have
+    /// to return their `Result`s with.  Without one, any error type will do.
+    pub error_type: Option<String>,
+    /// Whether the crate is kept free of `anyhow` in its public signatures.
+    pub strict: bool,
+    /// Where the error handling conventions are written down, which the problems
+    /// point to.
+    pub doc: Option<String>,
+    /// When set, problems are reported but don't fail the commit.
+    pub advisory: bool,
+}
+
+/// Settings for `salt-feature-flag-guard`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct FeatureFlagGuardConfig {
+    /// Where the registry of feature flags is, relative to the repo root.
+    pub registry: String,
+    /// Globs of the Cargo features that are feature flags (e.g. `["flag-*"]`), and
+    /// so have to be registered.  Custom cfgs always do.
+    pub features: Vec<String>,
+    /// Custom cfgs that aren't feature flags, e.g. `docsrs` or `loom`.
+    pub ignore: Vec<String>,
+    /// How many days before a flag expires it starts getting warned about.
+    pub warn_days: i64,
+}
+
+impl Default for FeatureFlagGuardConfig {
+    fn default() -> Self {
+        FeatureFlagGuardConfig {
+            registry: ".feature-flags.yaml".to_string(),
+            features: Vec::new(),
+            ignore: Vec::new(),
+            warn_days: 14,
+        }
+    }
+}
+
+/// Settings for `salt-stability-guard`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct StabilityGuardConfig {
+    /// The Cargo features that unstable API goes behind.
+    pub unstable_features: Vec<String>,
+}
+
+impl Default for StabilityGuardConfig {
+    fn default() -> Self {
+        StabilityGuardConfig {
+            unstable_features: vec!["unstable".to_string()],
+        }
+    }
+}
+
+/// Settings for `salt-dupe-guard`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct DupeGuardConfig {
+    /// How alike (from 0 to 1, the share of their fingerprints they have in
+    /// common) a new function and existing code have to be to reject the commit.
+    pub threshold: f64,
+    /// Functions shorter than this many tokens are left alone, since short ones
+    /// look alike without having been copied.
+    pub min_tokens: usize,
+    /// Globs of the names of the functions that may look like others, e.g.
+    /// `*::fmt` for every `fmt` method, or `parse_*`.
+    pub allow: Vec<String>,
+    /// When set, duplicates are reported but don't fail the commit.
+    pub advisory: bool,
+}
+
+impl Default for DupeGuardConfig {
+    fn default() -> Self {
+        DupeGuardConfig {
+            threshold: 0.8,
+            min_tokens: 50,
+            allow: Vec::new(),
+            advisory: false,
+        }
+    }
+}
+
+/// Settings for `salt-clip`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ClipConfig {
+    /// Where to write the JSON report of every run, as `--report-file=` would.
+    pub report_file: Option<String>,
+    /// How each of the variables from `--env-args=` and `--env-file=` is merged
+    /// with what it already is, by name.  The ones not named are overridden.
+    pub env_policy: BTreeMap<String, EnvPolicy>,
+}
+
+/// How `salt-clip` sets a variable that's already set, in the environment (or
+/// `env`, or by an earlier assignment).
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EnvPolicy {
+    /// Replace it with the new value.
+    #[default]
+    Override,
+    /// Keep it, only setting the new value when it isn't set.
+    Preserve,
+    /// Add the new value to the end of it as a list of paths, like `PATH`.
+    AppendPath,
+}
+
+/// How `salt-clip` treats crates with `proc-macro = true`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ProcMacroConfig {
+    /// Only lint the library target, skipping tests, examples, and benches.
+    pub lib_only: bool,
+    /// Extra RUSTFLAGS to use when linting proc-macro crates.
+    pub rustflags: Option<String>,
+}
+
+impl Default for ProcMacroConfig {
+    fn default() -> Self {
+        ProcMacroConfig {
+            lib_only: true,
+            rustflags: None,
+        }
+    }
+}
+
+/// The target `salt-clip` lints some crates for instead of the host, e.g. the
+/// firmware of an embedded monorepo.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CrossTarget {
+    /// The target triple handed to `--target`, e.g. `thumbv7em-none-eabihf`.  It
+    /// has no default, since linting for an empty one would only fail.
+    pub triple: String,
+    /// Extra RUSTFLAGS the target needs.  Like `RUSTFLAGS`, setting them means
+    /// cargo ignores the `rustflags` under `[target.<triple>]` in its config.
+    pub rustflags: Option<String>,
+}
+
+/// How `salt-clip` treats crates depending on whether they can be published, see
+/// [`crate::is_publishable`].
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct PublishConfig {
+    /// Don't lint crates with `publish = false` at all, e.g. internal tools and
+    /// test fixtures.
+    pub skip_unpublished: bool,
+    /// Extra arguments handed to clippy for the crates that can be published, in
+    /// addition to those from the tier policy, e.g. `["-D", "missing_docs"]`.
+    pub clippy_args: Vec<String>,
+}
+
+/// Which files the hooks treat as generated, and so leave alone like excluded ones.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct GeneratedConfig {
+    /// Recognize generated files by themselves, see [`crate::looks_generated`].
+    pub detect: bool,
+    /// Globs (relative to the repo root) of more generated files, e.g. the ones a
+    /// build script writes somewhere it doesn't name outright.
+    pub paths: Vec<String>,
+}
+
+impl Default for GeneratedConfig {
+    fn default() -> Self {
+        GeneratedConfig {
+            detect: true,
+            paths: Vec::new(),
+        }
+    }
+}
+
+/// How much the hooks keep in their cache directory, see [`crate::cache`].
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CacheConfig {
+    /// The most the cache may hold, e.g. `100M`, past which the oldest files go.
+    pub max_size: String,
+    /// How old the files in the cache may get, e.g. `30d`, if there's a limit.
+    pub max_age: Option<String>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            max_size: "100M".to_string(),
+            max_age: None,
+        }
+    }
+}
+
+/// Settings for `warning-ratchet`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RatchetConfig {
+    /// Repo-wide limits on the total number of suppressions of a lint, e.g.
+    ///
+    /// ```toml
+    /// [ratchet.budget.dead_code]
+    /// max = 40
+    /// start = 40
+    /// since = "2026-10-01"
+    /// decrease-per-week = 1
+    /// ```
+    pub budget: BTreeMap<String, Budget>,
+    /// How the ratchet treats clippy's lints.
+    pub clippy: ClippyRatchetConfig,
+    /// Who may approve increases, which then need their approval rather than
+    /// `UPDATE_ANYWAY`.
+    pub approval: RatchetApprovalConfig,
+    /// Strict mode: reject new `#![allow(...)]`s that cover a whole file or module,
+    /// so suppressions have to go on the items that need them.
+    pub forbid_inner_allows: bool,
+    /// Derive and attribute macros (e.g. `Builder` or `serde_with::serde_as`) that
+    /// need allows on the items they're used on.  Allows on those items aren't
+    /// counted.
+    pub ignore_allows_on: Vec<String>,
+    /// Files longer than this many lines may not grow any longer than they are in
+    /// the shamefile, and other files may not grow past it.
+    pub max_lines: Option<usize>,
+    /// Which branches ratchet against another branch's shamefile.
+    pub branches: RatchetBranchesConfig,
+    /// How much each lint's suppressions weigh, when the ratchet weighs them.
+    pub weights: RatchetWeightsConfig,
+    /// Renamed lints, from the old name to the new one, on top of the renames the
+    /// ratchet already knows about.  Mapping a lint to itself undoes a rename.
+    pub renamed_lints: BTreeMap<String, String>,
+    /// The commit message trailer (e.g. `Lint-Debt`) that records what a commit
+    /// pays down, see `warning-ratchet --prepare-commit-msg`.  None records nothing.
+    pub debt_trailer: Option<String>,
+}
+
+/// Which branches `warning-ratchet` compares against another branch's shamefile
+/// rather than their own, see `salt_spray::ratchet`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RatchetBranchesConfig {
+    /// Globs of branch names, e.g. `experimental/*`.
+    pub patterns: Vec<String>,
+    /// The branch whose shamefile they ratchet against.
+    pub reference: String,
+}
+
+impl Default for RatchetBranchesConfig {
+    fn default() -> Self {
+        RatchetBranchesConfig {
+            patterns: Vec::new(),
+            reference: "main".to_string(),
+        }
+    }
+}
+
+/// How much suppressing each lint counts for, which (when any are given) has
+/// `warning-ratchet` compare the weighted totals of each file or crate rather than
+/// each lint's count, see `salt_spray::ratchet`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RatchetWeightsConfig {
+    /// The weight of each lint, by name, e.g. `{ unsafe_code = 10, dead_code = 1 }`.
+    pub lints: BTreeMap<String, usize>,
+    /// The weight of the lints that `lints` doesn't list.
+    pub default: usize,
+    /// Whether the weighted totals are compared file by file or crate by crate.
+    pub per: WeightScope,
+}
+
+impl Default for RatchetWeightsConfig {
+    fn default() -> Self {
+        RatchetWeightsConfig {
+            lints: BTreeMap::new(),
+            default: 1,
+            per: WeightScope::File,
+        }
+    }
+}
+
+impl RatchetWeightsConfig {
+    /// Does the ratchet weigh lints at all?
+    pub fn enabled(&self) -> bool {
+        !self.lints.is_empty()
+    }
+
+    /// What one suppression of the lint weighs.
+    pub fn weight_of(&self, lint: &str) -> usize {
+        self.lints.get(lint).copied().unwrap_or(self.default)
+    }
+}
+
+/// What the warning ratchet compares weighted totals for.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WeightScope {
+    /// Each file on its own.
+    File,
+    /// Each crate as a whole, so suppressions can move between its files.
+    Crate,
+}
+
+/// How `warning-ratchet` treats `allow(clippy::...)`s.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ClippyRatchetConfig {
+    /// Ratchet clippy's lints too, counting every allow wherever it is (even on a
+    /// single statement or expression).
+    pub track: bool,
+    /// Clippy lints, or lint groups like `restriction`, that may never be allowed.
+    pub forbidden: Vec<String>,
+}
+
+/// Who may approve increases to the warning ratchet's baseline, see
+/// `salt_spray::ratchet`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RatchetApprovalConfig {
+    /// The approvers, as names, emails, or `Name <email>`s.  When empty increases
+    /// are accepted the usual way.
+    pub approvers: Vec<String>,
+    /// The commit message trailer that names the approver.
+    pub trailer: String,
+}
+
+impl Default for RatchetApprovalConfig {
+    fn default() -> Self {
+        RatchetApprovalConfig {
+            approvers: Vec::new(),
+            trailer: "Ratchet-Approved-By".to_string(),
+        }
+    }
+}
+
+/// A repo-wide limit on how many times a lint may be suppressed.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Budget {
+    /// The total may never exceed this.
+    pub max: Option<usize>,
+    /// The total allowed on the `since` date, which then shrinks every week.
+    pub start: Option<usize>,
+    /// When the schedule started, as YYYY-MM-DD.
+    pub since: Option<String>,
+    /// How much the allowed total shrinks each week after `since`.
+    pub decrease_per_week: usize,
+}
+
+impl Budget {
+    /// How many suppressions are allowed on the given day (counted in days since
+    /// the unix epoch), or None if the budget doesn't limit anything.
+    pub fn allowed_on(&self, today: i64) -> Option<usize> {
+        let since = self.since.as_deref().and_then(days_since_epoch);
+        let scheduled = match (self.start, since) {
+            (Some(start), Some(since)) => {
+                let weeks = (today - since).max(0) as usize / 7;
+                Some(start.saturating_sub(weeks.saturating_mul(self.decrease_per_week)))
+            }
+            _ => None,
+        };
+        match (self.max, scheduled) {
+            (Some(max), Some(scheduled)) => Some(max.min(scheduled)),
+            (max, scheduled) => max.or(scheduled),
+        }
+    }
+}
+
+/// Converts a YYYY-MM-DD date into a count of days since 1970-01-01.
+pub fn days_since_epoch(date: &str) -> Option<i64> {
+    let mut parts = date.trim().splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (y, m, d) = (parts.next()??, parts.next()??, parts.next()??);
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return None;
+    }
+    // Howard Hinnant's days_from_civil
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some(era * 146097 + doe - 719468)
+}
+
+/// Where the hooks report metrics about their runs, see [`crate::metrics`].
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct MetricsConfig {
+    /// A file for Prometheus' node exporter textfile collector to pick up, relative
+    /// to the repo root unless it's absolute.
+    pub textfile: Option<String>,
+    /// The `host:port` of a statsd server to send the metrics to over UDP.
+    pub statsd: Option<String>,
+    /// What every metric's name starts with.
+    pub prefix: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            textfile: None,
+            statsd: None,
+            prefix: "salt_spray".to_string(),
+        }
+    }
+}
+
+/// Where the hooks send their findings, see [`crate::sink`].
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct OutputConfig {
+    /// The sinks every hook writes to, e.g. `["terminal", "tap=results.tap"]`.
+    pub sinks: Vec<String>,
+    /// The sinks particular hooks write to instead, by the hook's name.
+    pub tools: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            sinks: vec!["terminal".to_string()],
+            tools: BTreeMap::new(),
+        }
+    }
+}
+
+/// Which derives `salt-derive-guard` requires on new public types.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct DeriveGuardConfig {
+    /// The traits every new public struct and enum must derive.
+    pub required: Vec<String>,
+    /// A comment containing this text just above a type exempts it from the check.
+    pub marker: String,
+}
+
+impl Default for DeriveGuardConfig {
+    fn default() -> Self {
+        DeriveGuardConfig {
+            required: vec!["Debug".to_string()],
+            marker: "salt-derive-guard: skip".to_string(),
+        }
+    }
+}
+
+/// How strictly the hooks treat the crates in a tier.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TierPolicy {
+    /// Extra arguments handed to clippy (after the `--`), e.g. `["-W", "clippy::pedantic"]`.
+    pub clippy_args: Vec<String>,
+    /// The most suppressed lints a single file may contain, regardless of its baseline.
+    pub max_suppressions: Option<usize>,
+    /// When set, clippy lints and ratchet increases are printed but don't fail the commit.
+    pub advisory: bool,
+    /// Whether salt-spray should format the crates in this tier at all.
+    pub format: bool,
+    /// When set, `salt-geiger` rejects commits that add unsafe code to the crate.
+    pub forbid_unsafe_growth: bool,
+    /// When set, `salt-panic-guard` rejects commits that add panics, unwraps,
+    /// expects, or asserts to the crate's library.
+    pub forbid_panic_growth: bool,
+    /// When set, `salt-changelog-guard` rejects commits that change the crate without
+    /// adding to its CHANGELOG.md (or a changeset).
+    pub changelog: bool,
+    /// When set, `salt-bin-size-guard` rejects commits that make the crate's
+    /// binaries noticeably bigger.
+    pub track_binary_size: bool,
+    /// When set, `salt-doc-example-guard` rejects commits that add public functions
+    /// or types to the crate's library without an example in their docs.
+    pub require_doc_examples: bool,
+    /// When set, `salt-deprecation-guard` rejects commits that make the crate use
+    /// deprecated items more.
+    pub track_deprecated: bool,
+    /// When set, `salt-stability-guard` rejects commits that add public items to
+    /// the crate's library that are hidden from its docs (or `__`-prefixed) without
+    /// being behind an unstable feature.
+    pub stable_api: bool,
+}
+
+impl Default for TierPolicy {
+    fn default() -> Self {
+        TierPolicy {
+            clippy_args: Vec::new(),
+            max_suppressions: None,
+            advisory: false,
+            format: true,
+            forbid_unsafe_growth: false,
+            forbid_panic_growth: false,
+            changelog: false,
+            track_binary_size: false,
+            require_doc_examples: false,
+            track_deprecated: false,
+            stable_api: false,
+        }
+    }
+}
+
+impl TierPolicy {
+    /// The policy a tier gets when `salt-spray.toml` doesn't override it.
+    pub fn builtin(tier: &str) -> Self {
+        match tier {
+            "tier1" => TierPolicy {
+                clippy_args: vec!["-W".to_string(), "clippy::pedantic".to_string()],
+                max_suppressions: Some(0),
+                ..Default::default()
+            },
+            "tier3" => TierPolicy {
+                advisory: true,
+                ..Default::default()
+            },
+            _ => Default::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads `salt-spray.toml` from the repo root, or the default config if there
+    /// isn't one.
+    pub fn load() -> Result<Config, String> {
+        match find_repo_root().map(|root| root.join(CONFIG_FILE)) {
+            Some(path) if path.exists() => Config::load_from(&path),
+            _ => Ok(Default::default()),
+        }
+    }
+
+    /// Loads the config at the given path.
+    pub fn load_from(path: &Path) -> Result<Config, String> {
+        Config::from_table(read_table(path)?).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn from_table(table: toml::Table) -> Result<Config, String> {
+        let mut config: Config = table.clone().try_into().map_err(|e| format!("{}", e))?;
+        config.raw = table;
+        Ok(config)
+    }
+
+    /// Returns the config for the crate with the given manifest, which is this one
+    /// with the `salt-spray.toml` at the root of the crate's workspace and then the
+    /// crate's own (if they have them) merged over it.
+    pub fn for_crate(&self, manifest: &Path) -> Result<Config, String> {
+        let root_config = find_repo_root()
+            .map(|root| root.join(CONFIG_FILE))
+            .and_then(|r| r.canonicalize().ok());
+        let mut seen: Vec<PathBuf> = root_config.into_iter().collect();
+        let mut merged = self.raw.clone();
+        let workspace_config = find_workspace_root(manifest).join(CONFIG_FILE);
+        for config in [workspace_config, manifest.with_file_name(CONFIG_FILE)] {
+            // The repo's config is already in self, and a crate can be its own workspace
+            match config.canonicalize() {
+                Ok(canonical) if !seen.contains(&canonical) => seen.push(canonical),
+                _ => continue,
+            }
+            merge_tables(&mut merged, read_table(&config)?);
+            Config::from_table(merged.clone())
+                .map_err(|e| format!("{}: {}", config.display(), e))?;
+        }
+        Config::from_table(merged)
+    }
+
+    /// Like `for_crate`, but complains and returns None if the crate's config is
+    /// broken, so callers can fall back to this one.
+    fn crate_config_of(&self, manifest: &Path) -> Option<Arc<Config>> {
+        let lock = || self.crate_configs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(config) = lock().get(manifest) {
+            return config.clone();
+        }
+        let config = match self.for_crate(manifest) {
+            Ok(config) => Some(Arc::new(config)),
+            Err(e) => {
+                eprintln!("{}", e);
+                None
+            }
+        };
+        lock().insert(manifest.to_path_buf(), config.clone());
+        config
+    }
+
+    /// Should the hooks leave the given file alone?  Excluded files are, and so are
+    /// generated ones.
+    pub fn is_excluded(&self, filename: &Path) -> bool {
+        let relative = repo_relative(filename);
+        let crate_config = crate::find_manifest(filename).and_then(|m| self.crate_config_of(&m));
+        let config = crate_config.as_deref().unwrap_or(self);
+        let generated = &config.generated;
+        config
+            .exclude
+            .iter()
+            .chain(generated.paths.iter())
+            .any(|g| glob_matches(g, &relative))
+            || (generated.detect && crate::looks_generated(filename))
+    }
+
+    /// The extra rustfmt settings for the file, from the `[fmt.targets]` of its
+    /// crate's config for the kind of target it's in.
+    pub fn rustfmt_settings_for(&self, filename: &Path) -> Vec<String> {
+        let kind = match crate::target_kind(filename) {
+            Some(kind) => kind,
+            None => return Vec::new(),
+        };
+        let crate_config = crate::find_manifest(filename).and_then(|m| self.crate_config_of(&m));
+        let config = crate_config.as_deref().unwrap_or(self);
+        config.fmt.targets.settings_for(kind).to_vec()
+    }
+
+    /// Returns the name of the tier the crate with the given manifest belongs to.
+    /// Tiers are tried in name order, so if a crate matches both tier1 and tier3
+    /// it is in tier1.
+    pub fn tier_of(&self, manifest: &Path) -> Option<&str> {
+        let manifest = repo_relative(manifest);
+        self.tiers
+            .iter()
+            .find(|(_, globs)| globs.iter().any(|g| glob_matches(g, &manifest)))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Returns the policy for the crate with the given manifest, taking its crate
+    /// level config into account.
+    pub fn policy_for(&self, manifest: &Path) -> TierPolicy {
+        match self.crate_config_of(manifest) {
+            Some(config) => config.own_policy_for(manifest),
+            None => self.own_policy_for(manifest),
+        }
+    }
+
+    fn own_policy_for(&self, manifest: &Path) -> TierPolicy {
+        if let Some(policy) = &self.policy {
+            return policy.clone();
+        }
+        match self.tier_of(manifest) {
+            Some(tier) => self
+                .tier_policy
+                .get(tier)
+                .cloned()
+                .unwrap_or_else(|| TierPolicy::builtin(tier)),
+            None => Default::default(),
+        }
+    }
+
+    /// The target the crate with the given manifest is linted for, if `[cross]`
+    /// names one for its path.
+    pub fn cross_target_for(&self, manifest: &Path) -> Option<&CrossTarget> {
+        let relative = repo_relative(manifest);
+        self.cross
+            .iter()
+            .filter(|(prefix, _)| relative.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, target)| target)
+    }
+
+    /// Returns the policy for the crate containing the given file.
+    pub fn policy_for_file(&self, filename: &Path) -> TierPolicy {
+        match crate::find_manifest(filename) {
+            Some(manifest) => self.policy_for(&manifest),
+            None => Default::default(),
+        }
+    }
+}
+
+fn read_table(path: &Path) -> Result<toml::Table, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    validate(path, &contents)?;
+    contents
+        .parse()
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Just the globs of a config file, with where they are in it.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Globs {
+    exclude: Vec<Spanned<String>>,
+    tiers: BTreeMap<String, Vec<Spanned<String>>>,
+    generated: GeneratedGlobs,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct GeneratedGlobs {
+    paths: Vec<Spanned<String>>,
+}
+
+/// Just the schedules of a config file's lint budgets, with where they are in it.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Schedules {
+    ratchet: RatchetSchedules,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct RatchetSchedules {
+    budget: BTreeMap<String, Schedule>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Schedule {
+    start: Option<Spanned<usize>>,
+    since: Option<Spanned<String>>,
+}
+
+/// Checks the contents of a config file, describing the first problem found in
+/// terms of where it is in the file.
+fn validate(path: &Path, contents: &str) -> Result<(), String> {
+    let located = |span: Option<Range<usize>>, message: &str| match span {
+        Some(span) => {
+            let (line, column) = line_and_column(contents, span.start);
+            format!("{}:{}:{}: {}", path.display(), line, column, message)
+        }
+        None => format!("{}: {}", path.display(), message),
+    };
+    let error = |e: toml::de::Error| located(e.span(), &explain(e.message().trim()));
+    toml::from_str::<Config>(contents).map_err(error)?;
+    let globs: Globs = toml::from_str(contents).map_err(error)?;
+    let tiers = globs.tiers.values().flatten();
+    for glob in globs
+        .exclude
+        .iter()
+        .chain(tiers)
+        .chain(globs.generated.paths.iter())
+    {
+        if let Some(problem) = glob_problem(glob.get_ref()) {
+            let message = format!("the glob `{}` {}", glob.get_ref(), problem);
+            return Err(located(Some(glob.span()), &message));
+        }
+    }
+    // A schedule that can't be worked out would quietly stop limiting anything
+    let schedules: Schedules = toml::from_str(contents).map_err(error)?;
+    for (lint, schedule) in schedules.ratchet.budget.iter() {
+        match (&schedule.start, &schedule.since) {
+            (_, Some(since)) if days_since_epoch(since.get_ref()).is_none() => {
+                let message = format!(
+                    "the budget for {} starts on `{}`, which isn't a YYYY-MM-DD date",
+                    lint,
+                    since.get_ref()
+                );
+                return Err(located(Some(since.span()), &message));
+            }
+            (Some(start), None) => {
+                let message = format!(
+                    "the budget for {} has a `start` but no `since` date to start shrinking from",
+                    lint
+                );
+                return Err(located(Some(start.span()), &message));
+            }
+            (None, Some(since)) => {
+                let message = format!(
+                    "the budget for {} has a `since` date but no `start` total to shrink from",
+                    lint
+                );
+                return Err(located(Some(since.span()), &message));
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// The (1-based) line and column of the given byte offset.
+pub(crate) fn line_and_column(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset.min(contents.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Rewords serde's complaint about an unknown key to suggest the key that was
+/// probably meant, since the list of every valid key isn't much help with a typo.
+pub(crate) fn explain(message: &str) -> String {
+    let captures = match UNKNOWN_FIELD.captures(message) {
+        Some(captures) => captures,
+        None => return message.to_string(),
+    };
+    // unwrap here is safe since both capture groups are mandatory
+    let (key, expected) = (captures.get(1).unwrap(), captures.get(2).unwrap());
+    let candidates = expected.as_str().split('`').skip(1).step_by(2);
+    match closest(key.as_str(), candidates) {
+        Some(suggestion) => format!(
+            "unknown key `{}`, did you mean `{}`?",
+            key.as_str(),
+            suggestion
+        ),
+        None => format!(
+            "unknown key `{}`, expected {}",
+            key.as_str(),
+            expected.as_str()
+        ),
+    }
+}
+
+/// What's wrong with a glob, if it's something that can never match as intended.
+fn glob_problem(glob: &str) -> Option<&'static str> {
+    if glob.is_empty() {
+        Some("is empty, so it doesn't match anything")
+    } else if glob.starts_with('/') {
+        Some("starts with /, but globs are relative to the repo root")
+    } else if glob.contains('\\') {
+        Some("contains \\, but globs always separate directories with /")
+    } else if glob.contains(['[', ']', '{', '}']) {
+        Some("uses [] or {}, which (unlike * and ?) aren't supported")
+    } else if glob.contains("***") {
+        Some("contains ***, which should be either * or **")
+    } else {
+        None
+    }
+}
+
+/// Merges `overrides` into `base`, recursing into tables that are in both.
+fn merge_tables(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overrides)) => {
+                merge_tables(base, overrides)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Translates a glob into an anchored regex.  `**` matches across directories,
+/// `*` and `?` stay within one path component.
+fn glob_to_regex(glob: &str) -> Regex {
+    let mut pattern = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    pattern.push_str("(?:.*/)?");
+                } else {
+                    pattern.push_str(".*");
+                }
+            }
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    // unwrap here is safe because everything other than the wildcards was escaped
+    Regex::new(&pattern).unwrap()
+}
+
+/// Does the (repo relative) path match the glob?
+pub fn glob_matches(glob: &str, path: &Path) -> bool {
+    let path: Vec<_> = path
+        .components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    let regex = GLOB_REGEX.get_or_insert_with(glob.to_string(), || Arc::new(glob_to_regex(glob)));
+    regex.is_match(&path.join("/"))
+}
@@ -0,0 +1,55 @@
+//! Keeping whatever the hooks couldn't parse, so that it can be attached to a bug
+//! report.  When salt-clip can't make sense of cargo's output, or the warning
+//! ratchet can't read a shamefile or a source file, what it was given goes into a
+//! timestamped file under `target/salt-spray/debug/` (in the repo root), and the
+//! error says which one.  Rerunning with extra logging is no help when the problem
+//! was a one-off, e.g. output mangled by a crashing compiler.
+//!
+//! That's part of the hooks' cache, so the oldest dumps go once it's full (see
+//! [`crate::cache`]), and `salt cache clear debug` or `cargo clean` gets rid of them.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cache;
+
+/// Where the dumps go, relative to the repo root.
+pub const DEBUG_DIR: &str = "target/salt-spray/debug";
+
+/// Writes `contents` to a new file under [`DEBUG_DIR`], named after `name` with a
+/// timestamp (and the process id, so that hooks running at once don't clash)
+/// before its extension, e.g. `shamefile-1760400000123-4242.yaml`.  Returns the
+/// file's path, or None if it couldn't be written.
+pub fn dump(name: &str, contents: &str) -> Option<PathBuf> {
+    let dir = cache::dir(cache::DEBUG);
+    fs::create_dir_all(&dir).ok()?;
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let name = Path::new(name);
+    let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+    let mut filename = format!("{}-{}-{}", stem, millis, process::id());
+    if let Some(extension) = name.extension() {
+        filename = format!("{}.{}", filename, extension.to_string_lossy());
+    }
+    let path = dir.join(filename);
+    fs::write(&path, contents).ok()?;
+    cache::trim(&path);
+    Some(path)
+}
+
+/// Dumps `contents` (see [`dump`]) and adds where to the error message, which is
+/// returned as it was if the dump couldn't be written.
+pub fn with_dump(message: String, name: &str, contents: &str) -> String {
+    match dump(name, contents) {
+        Some(path) => format!(
+            "{}\n(what couldn't be parsed is in {}, please attach it to any bug report)",
+            message,
+            path.display()
+        ),
+        None => message,
+    }
+}
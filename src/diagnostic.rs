@@ -0,0 +1,144 @@
+//! Reading the diagnostics out of cargo's `--message-format=json` output, which
+//! (unlike its human readable output) says which lint each diagnostic is for.
+//! Only what the hooks need is kept; see the "JSON Messages" chapter of the cargo
+//! book for everything else in there.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::{output, repo_relative};
+
+static LOCATION_LINE: Lazy<Regex> = Lazy::new(|| {
+    // Lines like "  --> src/lib.rs:3:5" that say where a (sub)diagnostic points
+    Regex::new(r"^(\s*(?:-->|:::) )(.+):(\d+):(\d+)$").unwrap()
+});
+
+/// One line of cargo's JSON output.  Everything but compiler messages is ignored.
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    message: Option<RustcMessage>,
+}
+
+#[derive(Deserialize)]
+struct RustcMessage {
+    message: String,
+    code: Option<RustcCode>,
+    level: String,
+    #[serde(default)]
+    spans: Vec<RustcSpan>,
+    rendered: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RustcCode {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct RustcSpan {
+    file_name: String,
+    line_start: usize,
+    column_start: usize,
+    is_primary: bool,
+}
+
+/// A diagnostic from rustc or clippy.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The lint (e.g. `clippy::needless_return` or `dead_code`) or error code (e.g.
+    /// `E0308`) behind the diagnostic, if it has one.
+    pub code: Option<String>,
+    /// `error`, `warning`, and so on.
+    pub level: String,
+    /// The one line description of the problem.
+    pub message: String,
+    /// The diagnostic the way cargo would have printed it.
+    pub rendered: String,
+    /// Where the diagnostic points: its primary span's file (relative to the
+    /// workspace root, unless it's been made relative to the repo), line, and
+    /// column.
+    pub location: Option<(String, usize, usize)>,
+}
+
+impl Diagnostic {
+    /// Does the diagnostic point into any of the given files?
+    pub fn is_in(&self, files: &BTreeSet<String>) -> bool {
+        match &self.location {
+            Some((file, _, _)) => files.iter().any(|f| f.ends_with(file.as_str())),
+            None => false,
+        }
+    }
+
+    /// The same diagnostic, but with its paths (which rustc gives relative to the
+    /// directory cargo ran it in, usually the workspace root) made relative to the
+    /// repo root, so that its `file:line:col` locations can be clicked on.
+    pub fn relative_to_repo(self, dir: &Path) -> Diagnostic {
+        let relocate = |file: &str| {
+            repo_relative(&dir.join(file))
+                .to_string_lossy()
+                .replace('\\', "/")
+        };
+        let rendered: Vec<String> = self
+            .rendered
+            .lines()
+            .map(|line| match LOCATION_LINE.captures(line) {
+                Some(c) => format!("{}{}:{}:{}", &c[1], relocate(&c[2]), &c[3], &c[4]),
+                None => line.to_string(),
+            })
+            .collect();
+        Diagnostic {
+            rendered: rendered.join("\n"),
+            location: self
+                .location
+                .map(|(file, line, column)| (relocate(&file), line, column)),
+            ..self
+        }
+    }
+}
+
+/// The lines of cargo's JSON output that look like JSON, but that `parse_messages`
+/// can't make sense of (and so skips).
+pub fn unparsed_lines(stdout: &str) -> Vec<&str> {
+    stdout
+        .lines()
+        .filter(|l| l.starts_with('{'))
+        .filter(|l| serde_json::from_str::<CargoMessage>(l).is_err())
+        .collect()
+}
+
+/// Pulls the compiler messages out of cargo's JSON output, skipping lines that
+/// aren't JSON (or aren't compiler messages).
+pub fn parse_messages(stdout: &str) -> Vec<Diagnostic> {
+    let mut result = Vec::new();
+    for line in stdout.lines().filter(|l| l.starts_with('{')) {
+        let message = match serde_json::from_str::<CargoMessage>(line) {
+            Ok(CargoMessage {
+                reason,
+                message: Some(message),
+            }) if reason == "compiler-message" => message,
+            _ => continue,
+        };
+        let location = message
+            .spans
+            .iter()
+            .find(|s| s.is_primary)
+            .map(|s| (s.file_name.clone(), s.line_start, s.column_start));
+        result.push(Diagnostic {
+            code: message.code.map(|c| c.code),
+            level: message.level,
+            // The source lines quoted in it may hold anything at all
+            rendered: message
+                .rendered
+                .map(|r| output::sanitize(r.trim_end(), output::keeps_color()))
+                .unwrap_or_else(|| message.message.clone()),
+            message: message.message,
+            location,
+        });
+    }
+    result
+}
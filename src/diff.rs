@@ -0,0 +1,185 @@
+//! Unified diffs between two versions of a file, the way `git diff` shows them,
+//! for showing what formatting would change before it's kept.
+//!
+//! Lines are matched with Myers' O(ND) algorithm, so a shortest diff is found in
+//! time proportional to how much changed rather than to the size of the file, and
+//! each hunk gets three lines of context.  Colors (red for removed lines, green
+//! for added ones) are only used when asked for.
+
+/// How many unchanged lines are shown around each change.
+const CONTEXT: usize = 3;
+
+const RESET: &str = "\u{1b}[0m";
+const BOLD: &str = "\u{1b}[1m";
+const RED: &str = "\u{1b}[31m";
+const GREEN: &str = "\u{1b}[32m";
+const CYAN: &str = "\u{1b}[36m";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Edit {
+    Same,
+    Delete,
+    Insert,
+}
+
+/// One line of the diff, with where it is in the old and the new version (for an
+/// inserted line `old` is where it goes, and likewise `new` for a deleted one).
+#[derive(Clone, Copy, Debug)]
+struct Step {
+    edit: Edit,
+    old: usize,
+    new: usize,
+}
+
+/// The furthest x reached on diagonal k, out of a window of the diagonals
+/// -(d - 1)..=(d - 1) as they were before step d.
+fn furthest(window: &[isize], d: isize, k: isize) -> isize {
+    window[(k + d - 1) as usize]
+}
+
+/// A shortest edit script turning `old` into `new`.
+fn edits(old: &[&str], new: &[&str]) -> Vec<Step> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = n + m;
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    // Only the diagonals that step d could have reached are kept from each step,
+    // so the trace is O(D²) rather than O(D(N + M))
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    'search: for d in 0..=max {
+        if d > 0 {
+            trace.push(v[(offset - d + 1) as usize..(offset + d) as usize].to_vec());
+        }
+        for k in (-d..=d).step_by(2) {
+            let i = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut steps = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, window) in trace.iter().enumerate().rev() {
+        let d = d as isize + 1;
+        let k = x - y;
+        let down = k == -d || (k != d && furthest(window, d, k - 1) < furthest(window, d, k + 1));
+        let prev_k = if down { k + 1 } else { k - 1 };
+        let prev_x = furthest(window, d, prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            steps.push((Edit::Same, x, y));
+        }
+        if down {
+            steps.push((Edit::Insert, prev_x, prev_y));
+        } else {
+            steps.push((Edit::Delete, prev_x, prev_y));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        steps.push((Edit::Same, x, y));
+    }
+    steps
+        .into_iter()
+        .rev()
+        .map(|(edit, old, new)| Step {
+            edit,
+            old: old as usize,
+            new: new as usize,
+        })
+        .collect()
+}
+
+/// Where a hunk starts and how many lines it covers, as `@@` lines give them:
+/// one-based, but a hunk with no lines starts at the line before it.
+fn range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+/// A unified diff from `old` to `new`, with `name` in the headers, or the empty
+/// string if they're the same.
+pub fn unified(name: &str, old: &str, new: &str, color: bool) -> String {
+    if old == new {
+        return String::new();
+    }
+    let paint = |code: &str, text: String| {
+        if color {
+            format!("{}{}{}", code, text, RESET)
+        } else {
+            text
+        }
+    };
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let steps = edits(&old_lines, &new_lines);
+    let changes: Vec<usize> = (0..steps.len())
+        .filter(|&i| steps[i].edit != Edit::Same)
+        .collect();
+
+    let mut result = format!(
+        "{}\n{}\n",
+        paint(BOLD, format!("--- a/{}", name)),
+        paint(BOLD, format!("+++ b/{}", name))
+    );
+    let mut i = 0;
+    while i < changes.len() {
+        // Changes close enough together for their context to touch share a hunk
+        let mut j = i;
+        while j + 1 < changes.len() && changes[j + 1] - changes[j] <= 2 * CONTEXT + 1 {
+            j += 1;
+        }
+        let start = changes[i].saturating_sub(CONTEXT);
+        let end = (changes[j] + CONTEXT + 1).min(steps.len());
+        let hunk = &steps[start..end];
+        let old_len = hunk.iter().filter(|s| s.edit != Edit::Insert).count();
+        let new_len = hunk.iter().filter(|s| s.edit != Edit::Delete).count();
+        result.push_str(&paint(
+            CYAN,
+            format!(
+                "@@ -{} +{} @@",
+                range(hunk[0].old, old_len),
+                range(hunk[0].new, new_len)
+            ),
+        ));
+        result.push('\n');
+        for step in hunk {
+            let (sign, line, code) = match step.edit {
+                Edit::Same => (' ', old_lines[step.old], None),
+                Edit::Delete => ('-', old_lines[step.old], Some(RED)),
+                Edit::Insert => ('+', new_lines[step.new], Some(GREEN)),
+            };
+            let text = format!("{}{}", sign, line.strip_suffix('\n').unwrap_or(line));
+            match code {
+                Some(code) => result.push_str(&paint(code, text)),
+                None => result.push_str(&text),
+            }
+            result.push('\n');
+            if !line.ends_with('\n') {
+                result.push_str("\\ No newline at end of file\n");
+            }
+        }
+        i = j + 1;
+    }
+    result
+}
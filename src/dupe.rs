@@ -0,0 +1,114 @@
+//! Telling how alike two pieces of code are, for catching copy-pasted code (see
+//! salt-dupe-guard).
+//!
+//! The comparison is of tokens, not text, with identifiers and literals made all
+//! the same, so a copy that's been reformatted or had its variables renamed still
+//! counts.  The tokens are boiled down to fingerprints by winnowing (hashing each
+//! run of tokens, and keeping the smallest hash of each few runs in a row), and how
+//! alike two pieces of code are is the share of their fingerprints they have in
+//! common.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeSet;
+use std::hash::{Hash, Hasher};
+
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+
+/// How many tokens each hashed run is.  Shorter runs turn up everywhere (e.g.
+/// `let x = y.z(w);`), longer ones miss copies with small edits.
+const RUN: usize = 12;
+
+/// How many runs in a row winnowing keeps (at least) one fingerprint of, so any
+/// shared stretch of `RUN + WINDOW - 1` tokens gives a shared fingerprint.
+const WINDOW: usize = 8;
+
+/// The keywords, which are kept as they are rather than made the same as the
+/// other identifiers, since they're the shape of the code.
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while",
+];
+
+/// A piece of code, boiled down to its fingerprints.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Fingerprints {
+    /// How many tokens the code is, after normalizing.
+    pub tokens: usize,
+    hashes: BTreeSet<u64>,
+}
+
+impl Fingerprints {
+    /// Boils the code down.
+    pub fn of(tokens: TokenStream) -> Self {
+        let mut normalized = Vec::new();
+        normalize(tokens, &mut normalized);
+        Fingerprints {
+            tokens: normalized.len(),
+            hashes: winnow(&normalized),
+        }
+    }
+
+    /// How alike the two are, from 0 (nothing in common) to 1.
+    pub fn similarity(&self, other: &Fingerprints) -> f64 {
+        let shared = self.hashes.intersection(&other.hashes).count();
+        let all = self.hashes.len() + other.hashes.len() - shared;
+        if all == 0 {
+            0.0
+        } else {
+            shared as f64 / all as f64
+        }
+    }
+}
+
+/// Flattens the tokens, with every identifier other than a keyword as `$id` and
+/// every literal as `$lit`, so renaming things doesn't hide a copy.
+fn normalize(tokens: TokenStream, result: &mut Vec<String>) {
+    for token in tokens {
+        match token {
+            TokenTree::Ident(ident) => {
+                let ident = ident.to_string();
+                if KEYWORDS.contains(&ident.as_str()) {
+                    result.push(ident);
+                } else {
+                    result.push("$id".to_string());
+                }
+            }
+            TokenTree::Literal(_) => result.push("$lit".to_string()),
+            TokenTree::Punct(punct) => result.push(punct.as_char().to_string()),
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::Bracket => ("[", "]"),
+                    // Invisible groups (from macro expansion) aren't in the source
+                    Delimiter::None => {
+                        normalize(group.stream(), result);
+                        continue;
+                    }
+                };
+                result.push(open.to_string());
+                normalize(group.stream(), result);
+                result.push(close.to_string());
+            }
+        }
+    }
+}
+
+/// Winnows the tokens: hashes each run of `RUN` of them, and keeps the smallest
+/// hash of each `WINDOW` runs in a row.
+fn winnow(tokens: &[String]) -> BTreeSet<u64> {
+    let hashes: Vec<u64> = tokens
+        .windows(RUN)
+        .map(|run| {
+            let mut hasher = DefaultHasher::new();
+            run.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect();
+    hashes
+        .windows(WINDOW.min(hashes.len()).max(1))
+        .filter_map(|window| window.iter().min().copied())
+        .collect()
+}
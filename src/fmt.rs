@@ -0,0 +1,890 @@
+//! Formatting the files in a commit with `cargo fmt`, which is the guts of
+//! `salt-spray` itself.  When asked to, the commit's Cargo.toml files are
+//! normalized in the same run (see [`crate::manifest`]).
+//!
+//! Most files in most commits are formatted already, so before any of that a
+//! single `rustfmt --check` (see [`already_formatted`]) sorts out the files that
+//! need it, and only those get a `cargo fmt` of their own.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(feature = "syn")]
+use proc_macro2::{LineColumn, TokenStream};
+#[cfg(feature = "syn")]
+use syn::visit::{self, Visit};
+
+use crate::check::{Check, Violation};
+use crate::config::Config;
+use crate::diff;
+use crate::manifest::{self, is_manifest};
+use crate::output;
+#[cfg(feature = "syn")]
+use crate::parse_source;
+use crate::toolchain;
+use crate::{find_manifest, find_workspace_root, package_name, read_committed, read_manifest, say};
+
+/// What the bodies of macros are wrapped in so rustfmt will format them.
+#[cfg(feature = "syn")]
+static MACRO_WRAPPER: &str = "fn __salt_spray_macro_body() {";
+
+/// What to do with files that have both staged and unstaged changes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PartiallyStaged {
+    /// Warn about the file and leave it alone.
+    #[default]
+    Skip,
+    /// Format the staged version and re-apply the unstaged changes on top of it.
+    Staged,
+    /// Format the working tree file, unstaged changes and all.
+    Whole,
+}
+
+/// The command line options that apply to every file.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// key=value overrides for the project's rustfmt.toml
+    pub rustfmt_config: Vec<String>,
+    /// What to do with partially staged files.
+    pub partially_staged: PartiallyStaged,
+    /// Only report what would change, without touching any files.
+    pub check: bool,
+    /// Macros whose bodies should be formatted too, see [`format_macro_bodies`].
+    pub macros: Vec<String>,
+    /// Also report files that rustfmt would change again after formatting them,
+    /// see [`verify_idempotent`].
+    pub verify_idempotent: bool,
+    /// Normalize Cargo.toml files instead of skipping them.
+    pub manifests: bool,
+}
+
+impl Options {
+    /// The options for one file, with the rustfmt settings the config has for its
+    /// kind of target.  Those from the command line still win.
+    pub fn for_file(&self, filename: &str, config: &Config) -> Options {
+        let mut rustfmt_config = config.rustfmt_settings_for(Path::new(filename));
+        if rustfmt_config.is_empty() {
+            return self.clone();
+        }
+        rustfmt_config.extend(self.rustfmt_config.iter().cloned());
+        Options {
+            rustfmt_config,
+            ..self.clone()
+        }
+    }
+}
+
+fn git<I, S>(args: I) -> io::Result<Output>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    Command::new("git").args(args).output()
+}
+
+/// Does the file have changes in the index as well as different changes in the
+/// working tree?
+fn is_partially_staged(filename: &str) -> bool {
+    let differs = |extra: &[&str]| {
+        let mut cmd = Command::new("git");
+        cmd.arg("diff")
+            .args(extra)
+            .args(["--quiet", "--", filename]);
+        cmd.status().map(|s| s.code() == Some(1)).unwrap_or(false)
+    };
+    differs(&["--cached"]) && differs(&[])
+}
+
+/// Formats the staged version of a partially staged file, stages the result, and
+/// then merges the unstaged changes back into the working tree file.  If they
+/// conflict with the formatting the working tree file is left the way it was, and
+/// so it is when anything along the way fails.
+pub fn format_staged(filename: &str, options: &Options) -> io::Result<Output> {
+    let not_found = || io::Error::new(ErrorKind::NotFound, format!("{} isn't staged", filename));
+    let worktree = fs::read(filename)?;
+    // The empty revision makes git show read from the index
+    let staged = read_committed("", filename).ok_or_else(not_found)?;
+    format_staged_over(filename, &staged, &worktree, options).map_err(|e| {
+        // The unstaged changes are what this is all careful about, so they go back
+        match fs::write(filename, &worktree) {
+            Ok(()) => e,
+            Err(restoring) => io::Error::new(
+                e.kind(),
+                format!(
+                    "{}, and then unable to put back the unstaged changes to {}: {}",
+                    e, filename, restoring
+                ),
+            ),
+        }
+    })
+}
+
+/// The part of [`format_staged`] after the working tree file has been read, which
+/// may leave it overwritten when it fails.
+fn format_staged_over(
+    filename: &str,
+    staged: &str,
+    worktree: &[u8],
+    options: &Options,
+) -> io::Result<Output> {
+    fs::write(filename, staged)?;
+    let output = format_file(filename, options);
+    let formatted = fs::read(filename)?;
+    if formatted == staged.as_bytes() {
+        fs::write(filename, worktree)?;
+        return output;
+    }
+    let add = git(["add", "--", filename])?;
+    if !add.status.success() {
+        return Err(io::Error::other(format!(
+            "Unable to stage the formatted {}: {}",
+            filename,
+            String::from_utf8_lossy(&add.stderr).trim()
+        )));
+    }
+
+    // git merge-file wants all three versions on disk
+    let scratch =
+        |name: &str| env::temp_dir().join(format!("salt-spray-{}-{}", process::id(), name));
+    let (ours, base, theirs) = (scratch("formatted"), scratch("staged"), scratch("worktree"));
+    let written = fs::write(&ours, &formatted)
+        .and_then(|_| fs::write(&base, staged))
+        .and_then(|_| fs::write(&theirs, worktree));
+    let merge = written.and_then(|_| {
+        Command::new("git")
+            .args(["merge-file", "-p"])
+            .args([&ours, &base, &theirs])
+            .output()
+    });
+    for f in [&ours, &base, &theirs] {
+        drop(fs::remove_file(f));
+    }
+
+    match merge? {
+        Output { status, stdout, .. } if status.success() => fs::write(filename, stdout)?,
+        _ => {
+            fs::write(filename, worktree)?;
+            eprintln!(
+                "Formatted and staged {}, but your unstaged changes conflict with the formatting, \
+                 so the working tree copy was left as it was.",
+                filename
+            );
+        }
+    }
+    output
+}
+
+/// A file as it was before formatting, both in the working tree and in the index
+/// (which [`format_staged`] changes too), so that the formatting can be shown and
+/// then taken back.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    filename: String,
+    worktree: Option<String>,
+    staged: Option<String>,
+}
+
+impl Snapshot {
+    /// Remembers the file as it is now.
+    pub fn take(filename: &str) -> Snapshot {
+        Snapshot {
+            filename: filename.to_string(),
+            worktree: fs::read_to_string(filename).ok(),
+            // The empty revision makes git show read from the index
+            staged: read_committed("", filename),
+        }
+    }
+
+    /// Remembers just the working tree copy of the file, for when the index won't
+    /// be touched.
+    pub fn of_worktree(filename: &str) -> Snapshot {
+        Snapshot {
+            filename: filename.to_string(),
+            worktree: fs::read_to_string(filename).ok(),
+            staged: None,
+        }
+    }
+
+    /// The file the snapshot is of.
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// Has the file changed since, in the working tree or (if the snapshot has it)
+    /// in the index?
+    pub fn changed(&self) -> bool {
+        fs::read_to_string(&self.filename).ok() != self.worktree
+            || (self.staged.is_some() && read_committed("", &self.filename) != self.staged)
+    }
+
+    /// What's changed in the file since, as a unified diff of the working tree
+    /// copy (or of the staged one, if only that changed), or the empty string if
+    /// nothing has.
+    pub fn diff(&self, color: bool) -> String {
+        let now = fs::read_to_string(&self.filename).ok();
+        let (was, is) = match (&self.worktree, &now) {
+            (Some(was), Some(is)) if was != is => (was.clone(), is.clone()),
+            _ => match (&self.staged, read_committed("", &self.filename)) {
+                (Some(was), Some(is)) => (was.clone(), is),
+                _ => return String::new(),
+            },
+        };
+        diff::unified(&self.filename, &was, &is, color)
+    }
+
+    /// Puts the file back the way it was, restaging what was staged if that's
+    /// changed.
+    pub fn restore(&self) -> io::Result<()> {
+        if let Some(staged) = &self.staged {
+            if read_committed("", &self.filename).as_ref() != Some(staged) {
+                fs::write(&self.filename, staged)?;
+                git(["add", "--", &self.filename])?;
+            }
+        }
+        match &self.worktree {
+            Some(worktree) => fs::write(&self.filename, worktree),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Is `cargo fmt` known not to work (see [`format_file`])?
+static CARGO_FMT_MISSING: AtomicBool = AtomicBool::new(false);
+
+/// Did `cargo fmt` fail because there's no cargo, or no rustfmt component for it?
+fn is_cargo_fmt_missing(result: &io::Result<Output>) -> bool {
+    match result {
+        Err(e) => e.kind() == ErrorKind::NotFound,
+        Ok(Output { status, stderr, .. }) if !status.success() => {
+            let stderr = String::from_utf8_lossy(stderr);
+            stderr.contains("no such command") || stderr.contains("is not installed")
+        }
+        Ok(_) => false,
+    }
+}
+
+/// What rustfmt's `--color` should be for output that's passed on (rather than
+/// parsed), see [`crate::output`].
+fn color() -> &'static str {
+    if output::keeps_color() {
+        "always"
+    } else {
+        "never"
+    }
+}
+
+/// Format a single file using `cargo fmt`.  Where that isn't available (e.g. in a
+/// minimal docker image) rustfmt is run directly instead, with the edition taken
+/// from the crate's Cargo.toml.
+pub fn format_file<S: AsRef<OsStr> + ?Sized>(
+    filename: &S,
+    options: &Options,
+) -> io::Result<Output> {
+    if let Some(manifest_path) = find_manifest(filename) {
+        if CARGO_FMT_MISSING.load(Ordering::Relaxed) {
+            return format_file_with_rustfmt(filename.as_ref(), options);
+        }
+        let mut cmd = toolchain::cargo();
+        cmd.arg("fmt");
+        if options.check {
+            cmd.arg("--check");
+        }
+        cmd.arg("--manifest-path")
+            .arg(&manifest_path)
+            .args(["--", "--color", color()]);
+        if !options.rustfmt_config.is_empty() {
+            cmd.args(["--config", &options.rustfmt_config.join(",")]);
+        }
+        cmd.arg(filename.as_ref());
+        say!("{:?}", cmd);
+        let result = cmd.output();
+        if !is_cargo_fmt_missing(&result) {
+            return result;
+        }
+        if !CARGO_FMT_MISSING.swap(true, Ordering::Relaxed) {
+            eprintln!(
+                "`cargo fmt` isn't available, so falling back to running rustfmt directly \
+                 (with the edition from each crate's Cargo.toml)."
+            );
+        }
+        format_file_with_rustfmt(filename.as_ref(), options)
+    } else {
+        Err(io::Error::new(
+            ErrorKind::NotFound,
+            format!("No workspace found for {}", Path::new(filename).display()),
+        ))
+    }
+}
+
+/// Formats the workspace's members (see [`crate::workspace_members`]) with a
+/// single `cargo fmt -p <member> ...`, returning whether that worked.  Unlike
+/// `cargo fmt --all` that leaves alone the path dependencies outside of the
+/// workspace, which aren't this commit's to format.  If it didn't work (e.g. some
+/// file doesn't parse, or cargo fmt isn't there) formatting the files one at a
+/// time still can, and says why not.
+/// Partially staged files would get their unstaged changes formatted too, so
+/// unless the options say to format them whole, a workspace with any of them
+/// among `files` isn't attempted.
+pub fn format_workspace(
+    workspace: &Path,
+    members: &[PathBuf],
+    files: &[String],
+    options: &Options,
+) -> bool {
+    if CARGO_FMT_MISSING.load(Ordering::Relaxed) {
+        return false;
+    }
+    let whole = options.check || options.partially_staged == PartiallyStaged::Whole;
+    if !whole && files.iter().any(|f| is_partially_staged(f)) {
+        return false;
+    }
+    let packages: Option<Vec<String>> = members.iter().map(package_name).collect();
+    let packages = match packages {
+        Some(packages) if !packages.is_empty() => packages,
+        _ => return false,
+    };
+    let mut cmd = toolchain::cargo();
+    cmd.arg("fmt");
+    for package in packages.iter() {
+        cmd.args(["-p", package]);
+    }
+    if options.check {
+        cmd.arg("--check");
+    }
+    cmd.arg("--manifest-path")
+        .arg(workspace.join("Cargo.toml"))
+        .args(["--", "--color", color()]);
+    if !options.rustfmt_config.is_empty() {
+        cmd.args(["--config", &options.rustfmt_config.join(",")]);
+    }
+    say!("{:?}", cmd);
+    cmd.output().is_ok_and(|o| o.status.success())
+}
+
+/// Finds the Rust files among `files` that rustfmt has nothing to change in, with
+/// one `rustfmt --check` per edition (and set of settings) rather than a
+/// `cargo fmt` per file.  Partially staged files are left out unless they're
+/// formatted (or checked) whole, since rustfmt only sees the working tree, and so
+/// is every file in a batch that rustfmt couldn't check (e.g. because one doesn't
+/// parse).
+pub fn already_formatted(files: &[String], config: &Config, options: &Options) -> BTreeSet<String> {
+    let whole = match options.partially_staged {
+        PartiallyStaged::Whole => true,
+        PartiallyStaged::Staged => false,
+        PartiallyStaged::Skip => options.check,
+    };
+    let mut batches: BTreeMap<(String, Vec<String>), Vec<&String>> = BTreeMap::new();
+    for file in files.iter().filter(|f| f.ends_with(".rs")) {
+        if find_manifest(file).is_none() || (!whole && is_partially_staged(file)) {
+            continue;
+        }
+        let settings = options.for_file(file, config).rustfmt_config;
+        batches
+            .entry((edition_of(file), settings))
+            .or_default()
+            .push(file);
+    }
+
+    let mut result = BTreeSet::new();
+    for ((edition, settings), batch) in batches {
+        let mut cmd = toolchain::rustfmt();
+        cmd.args([
+            "--check",
+            "--files-with-diff",
+            "--color",
+            "never",
+            "--edition",
+            &edition,
+        ]);
+        if !settings.is_empty() {
+            cmd.args(["--config", &settings.join(",")]);
+        }
+        cmd.args(&batch);
+        say!("{:?}", cmd);
+        let output = match cmd.output() {
+            Ok(output) => output,
+            Err(_) => continue,
+        };
+        // Anything on stderr means some file couldn't be checked
+        if !output.stderr.is_empty() || output.status.code().is_none_or(|c| c > 1) {
+            continue;
+        }
+        // The files with a diff are listed by their absolute paths
+        let unformatted: BTreeSet<PathBuf> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|l| Path::new(l.trim()).canonicalize().ok())
+            .collect();
+        result.extend(
+            batch
+                .into_iter()
+                .filter(|f| {
+                    Path::new(f)
+                        .canonicalize()
+                        .is_ok_and(|c| !unformatted.contains(&c))
+                })
+                .cloned(),
+        );
+    }
+    result
+}
+
+/// Format a single file by running rustfmt on it directly.
+fn format_file_with_rustfmt(filename: &OsStr, options: &Options) -> io::Result<Output> {
+    let edition = edition_of(&filename.to_string_lossy());
+    let mut cmd = toolchain::rustfmt();
+    if options.check {
+        cmd.arg("--check");
+    }
+    cmd.args(["--color", color(), "--edition", &edition]);
+    if !options.rustfmt_config.is_empty() {
+        cmd.args(["--config", &options.rustfmt_config.join(",")]);
+    }
+    cmd.arg(filename);
+    say!("{:?}", cmd);
+    cmd.output()
+}
+
+/// Finds the brace-delimited invocations of the given macros, returning the
+/// positions of their opening and closing braces.
+#[cfg(feature = "syn")]
+struct MacroFinder<'a> {
+    macros: &'a [String],
+    found: Vec<(LineColumn, LineColumn)>,
+}
+
+#[cfg(feature = "syn")]
+impl<'a, 'ast> Visit<'ast> for MacroFinder<'a> {
+    fn visit_macro(&mut self, node: &'ast syn::Macro) {
+        let segments: Vec<String> = node
+            .path
+            .segments
+            .iter()
+            .map(|s| s.ident.to_string())
+            .collect();
+        let path = segments.join("::");
+        let wanted = self.macros.iter().any(|m| {
+            *m == path || path.ends_with(&format!("::{}", m)) || m.ends_with(&format!("::{}", path))
+        });
+        if let (true, syn::MacroDelimiter::Brace(brace)) = (wanted, &node.delimiter) {
+            self.found.push((brace.span.start(), brace.span.end()));
+        }
+        visit::visit_macro(self, node);
+    }
+}
+
+/// Turns a line (1-based) and column (0-based, in characters) into a byte offset.
+#[cfg(feature = "syn")]
+fn offset_of(contents: &str, line_starts: &[usize], at: LineColumn) -> Option<usize> {
+    let start = *line_starts.get(at.line.checked_sub(1)?)?;
+    let line = contents[start..].split('\n').next()?;
+    let within = line
+        .char_indices()
+        .nth(at.column)
+        .map(|(i, _)| i)
+        .unwrap_or(line.len());
+    Some(start + within)
+}
+
+/// Runs rustfmt over the given source, returning the formatted source (or None if
+/// rustfmt failed).
+fn rustfmt_stdin(
+    mut cmd: Command,
+    edition: &str,
+    source: &str,
+    options: &Options,
+) -> Option<String> {
+    cmd.args(["--emit", "stdout", "--edition", edition]);
+    if !options.rustfmt_config.is_empty() {
+        cmd.args(["--config", &options.rustfmt_config.join(",")]);
+    }
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    // unwrap here is safe since stdin was piped above
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(source.as_bytes()).ok()?;
+    drop(stdin);
+    let output = child.wait_with_output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+/// Runs rustfmt over the body of a macro as if it were the body of a function,
+/// and returns it re-indented to sit inside the macro.
+#[cfg(feature = "syn")]
+fn format_macro_body(body: &str, indent: &str, edition: &str, options: &Options) -> Option<String> {
+    let wrapped = format!("{}\n{}\n}}\n", MACRO_WRAPPER, body);
+    let formatted = rustfmt_stdin(toolchain::rustfmt(), edition, &wrapped, options)?;
+    let mut lines: Vec<&str> = formatted.trim_end().lines().collect();
+    if lines.len() < 3 || lines.first() != Some(&MACRO_WRAPPER) || lines.last() != Some(&"}") {
+        return None;
+    }
+    lines.remove(0);
+    lines.pop();
+    let mut result = String::from("\n");
+    for line in lines {
+        let line = line.strip_prefix("    ").unwrap_or(line);
+        if !line.is_empty() {
+            result.push_str(indent);
+            result.push_str("    ");
+            result.push_str(line);
+        }
+        result.push('\n');
+    }
+    result.push_str(indent);
+    Some(result)
+}
+
+/// The edition of the crate the file belongs to, which rustfmt needs to know.
+fn edition_of(filename: &str) -> String {
+    let manifest = match find_manifest(filename) {
+        Some(manifest) => manifest,
+        None => return "2015".to_string(),
+    };
+    let edition = read_manifest(&manifest).and_then(|t| t.get("package")?.get("edition").cloned());
+    let edition = match edition {
+        // edition.workspace = true means it comes from the workspace's [workspace.package]
+        Some(toml::Value::Table(_)) => {
+            read_manifest(&find_workspace_root(&manifest).join("Cargo.toml"))
+                .and_then(|t| t.get("workspace")?.get("package")?.get("edition").cloned())
+        }
+        edition => edition,
+    };
+    edition
+        .and_then(|e| e.as_str().map(str::to_string))
+        .unwrap_or_else(|| "2015".to_string())
+}
+
+/// Formats the bodies of brace-delimited invocations of the given macros, which
+/// rustfmt leaves alone.  Bodies that don't parse as statements (like those of
+/// `tokio::select!` or `quote!`) are left alone too, as is anything whose tokens
+/// the formatting would change.  Returns the new contents if anything changed.
+#[cfg(feature = "syn")]
+pub fn format_macro_bodies(filename: &str, contents: &str, options: &Options) -> Option<String> {
+    let ast = parse_source(contents).ok()?;
+    let mut finder = MacroFinder {
+        macros: &options.macros,
+        found: Vec::new(),
+    };
+    finder.visit_file(&ast);
+
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(contents.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let edition = edition_of(filename);
+    let mut result = contents.to_string();
+    // Going backwards keeps the earlier offsets valid
+    for (open, close) in finder.found.into_iter().rev() {
+        let open = offset_of(contents, &line_starts, open)?;
+        let close = offset_of(contents, &line_starts, close)?;
+        if !contents[open..].starts_with('{') || !contents[..close].ends_with('}') {
+            continue;
+        }
+        let body = &contents[open + 1..close - 1];
+        if syn::parse_str::<syn::Block>(&format!("{{{}}}", body)).is_err() {
+            continue;
+        }
+        let line_start = contents[..open].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let indent: String = contents[line_start..]
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect();
+        if let Some(formatted) = format_macro_body(body, &indent, &edition, options) {
+            // Re-indenting must never change what the macro sees (e.g. multi-line strings)
+            let tokens = |s: &str| s.parse::<TokenStream>().map(|t| t.to_string()).ok();
+            if tokens(&formatted) == tokens(body) {
+                result.replace_range(open + 1..close - 1, &formatted);
+            }
+        }
+    }
+    if result == contents {
+        None
+    } else {
+        Some(result)
+    }
+}
+
+/// Runs rustfmt over the given source the way it would run over the file, i.e.
+/// with the file's edition and rustfmt.toml, returning the formatted source (or
+/// None if rustfmt failed).
+fn rustfmt_as(filename: &str, source: &str, options: &Options) -> Option<String> {
+    // rustfmt looks for its config starting from where it runs
+    let mut cmd = toolchain::rustfmt();
+    match Path::new(filename).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => drop(cmd.current_dir(dir)),
+        _ => (),
+    }
+    rustfmt_stdin(cmd, &edition_of(filename), source, options)
+}
+
+/// What formatting the file would change, as a unified diff (empty if nothing),
+/// without touching it.  That's what rustfmt would change, and then the macro
+/// pass, or for manifests normalizing them.  Files that can't be formatted at all
+/// (e.g. because they don't parse) give why not instead.  This is
+/// `salt-spray --check`.  With `--partially-staged=staged` it's the staged copy of
+/// a partially staged file that's checked, since that's what would be formatted.
+pub fn formatting_diff(filename: &str, options: &Options, color: bool) -> Result<String, String> {
+    let options = Options {
+        check: true,
+        ..options.clone()
+    };
+    let contents = if options.partially_staged == PartiallyStaged::Staged
+        && is_partially_staged(filename)
+    {
+        // The empty revision makes git show read from the index
+        read_committed("", filename).ok_or_else(|| format!("{} isn't staged", filename))?
+    } else {
+        fs::read_to_string(filename).map_err(|e| format!("Unable to read {}: {}", filename, e))?
+    };
+    let formatted = if is_manifest(filename) {
+        if !options.manifests {
+            return Ok(String::new());
+        }
+        manifest::normalize_manifest(&contents)
+            .map_err(|e| format!("Unable to parse {}: {}", filename, e))?
+            .unwrap_or_else(|| contents.clone())
+    } else {
+        match rustfmt_as(filename, &contents, &options) {
+            Some(formatted) => formatted,
+            // rustfmt only says why it can't format the file when it's run on the file
+            None => {
+                let why = format_rustfmt(filename, &options).map(|v| v.message);
+                return Err(why.unwrap_or_else(|| format!("rustfmt can't format {}", filename)));
+            }
+        }
+    };
+    #[cfg(feature = "syn")]
+    let formatted = if options.macros.is_empty() || is_manifest(filename) {
+        formatted
+    } else {
+        format_macro_bodies(filename, &formatted, &options).unwrap_or(formatted)
+    };
+    if formatted == contents {
+        return Ok(String::new());
+    }
+    Ok(diff::unified(filename, &contents, &formatted, color))
+}
+
+/// Checks that formatting the file is stable, i.e. that once rustfmt has formatted
+/// it, formatting it again changes nothing.  Files where it isn't (usually because
+/// of rustfmt bugs around macros or long lines) make the hook flap, and are better
+/// off excluded.
+pub fn verify_idempotent(filename: &str, options: &Options) -> Option<Violation> {
+    let contents = fs::read_to_string(filename).ok()?;
+    let first = rustfmt_as(filename, &contents, options)?;
+    let second = rustfmt_as(filename, &first, options)?;
+    if first == second {
+        return None;
+    }
+    let message = format!(
+        "rustfmt keeps changing {} on every pass, so it won't ever be formatted.  Consider \
+         adding it to `exclude` in salt-spray.toml.",
+        filename
+    );
+    Some(Violation::new(filename, message))
+}
+
+/// Runs the macro formatting pass over a file that rustfmt has already formatted.
+#[cfg(feature = "syn")]
+fn format_macros_in(filename: &str, options: &Options) -> Option<Violation> {
+    let contents = fs::read_to_string(filename).ok()?;
+    let formatted = format_macro_bodies(filename, &contents, options)?;
+    if options.check {
+        return Some(Violation::new(
+            filename,
+            "the bodies of some macros aren't formatted",
+        ));
+    }
+    fs::write(filename, formatted)
+        .err()
+        .map(|e| Violation::new(filename, format!("Unable to write {}: {}", filename, e)))
+}
+
+/// Finding the macros takes parsing the file, so without the `syn` feature they're
+/// left alone.
+#[cfg(not(feature = "syn"))]
+fn format_macros_in(_filename: &str, _options: &Options) -> Option<Violation> {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    if !WARNED.swap(true, Ordering::Relaxed) {
+        eprintln!("This build doesn't have the syn feature, so macro bodies aren't formatted");
+    }
+    None
+}
+
+/// Normalizes a manifest, if the options say to.  Partially staged manifests are
+/// only normalized as a whole.
+fn format_manifest(filename: &str, options: &Options) -> Option<Violation> {
+    if !options.manifests {
+        return None;
+    } else if !options.check
+        && options.partially_staged != PartiallyStaged::Whole
+        && is_partially_staged(filename)
+    {
+        eprintln!(
+            "Skipping {} since it is only partially staged (see --partially-staged=whole)",
+            filename
+        );
+        return None;
+    }
+    manifest::normalize(filename, options.check)
+}
+
+/// Formats one file, dealing with partial staging as the options say, and returns
+/// what went wrong if anything did.
+pub fn format(filename: &str, options: &Options) -> Option<Violation> {
+    if is_manifest(filename) {
+        return format_manifest(filename, options);
+    }
+    match format_rustfmt(filename, options) {
+        None => format_after_rustfmt(filename, options),
+        violation => violation,
+    }
+}
+
+/// Like [`format`], but for a Rust file that rustfmt has nothing to change in (see
+/// [`already_formatted`]), so only the passes after it are left.
+pub fn format_after_rustfmt(filename: &str, options: &Options) -> Option<Violation> {
+    // Partially staged files only get rustfmt, the macro pass works on whole files
+    let format_macros = !options.macros.is_empty()
+        && (options.check
+            || options.partially_staged == PartiallyStaged::Whole
+            || !is_partially_staged(filename));
+    let violation = if format_macros {
+        format_macros_in(filename, options)
+    } else {
+        None
+    };
+    match violation {
+        None if options.verify_idempotent => verify_idempotent(filename, options),
+        violation => violation,
+    }
+}
+
+/// Did rustfmt crash (panic, or get killed by a signal like SIGSEGV) rather than
+/// just fail on the file?  Exit codes above 128 are how shells (and cargo fmt)
+/// pass on a signal.
+fn crash_of(status: &ExitStatus, stderr: &str) -> Option<&'static str> {
+    if stderr.contains("panicked at") || stderr.contains("internal compiler error") {
+        Some("panicked")
+    } else if status.code().is_none_or(|code| code > 128) {
+        Some("crashed")
+    } else {
+        None
+    }
+}
+
+fn format_rustfmt(filename: &str, options: &Options) -> Option<Violation> {
+    let result = match options.partially_staged {
+        _ if options.check => format_file(filename, options),
+        PartiallyStaged::Whole => format_file(filename, options),
+        _ if !is_partially_staged(filename) => format_file(filename, options),
+        PartiallyStaged::Staged => format_staged(filename, options),
+        PartiallyStaged::Skip => {
+            eprintln!(
+                "Skipping {} since it is only partially staged (see --partially-staged=staged)",
+                filename
+            );
+            return None;
+        }
+    };
+    match result {
+        Ok(Output { status, .. }) if status.code() == Some(0) => None,
+        Ok(Output {
+            status,
+            stdout,
+            stderr,
+        }) => {
+            let stderr = output::clean(&stderr);
+            let message = match crash_of(&status, &stderr) {
+                Some(how) => format!(
+                    "rustfmt {} ({}) on {}, so it was left as it was.  The other files were \
+                     still formatted, but this one needs working around (or adding to `exclude` \
+                     in salt-spray.toml):\n{}",
+                    how,
+                    status,
+                    filename,
+                    stderr.trim_end()
+                ),
+                // In check mode the diff of what would change ends up on stdout
+                None => format!("{}{}", output::clean(&stdout), stderr),
+            };
+            Some(Violation::new(filename, message))
+        }
+        r => Some(Violation::new(filename, format!("{:?}", r))),
+    }
+}
+
+/// rustfmt as a [`Check`].  Running it reports files that aren't formatted,
+/// fixing it formats them.
+#[derive(Debug, Default)]
+pub struct FmtCheck {
+    /// How to run rustfmt.
+    pub options: Options,
+}
+
+impl FmtCheck {
+    /// Formats (or checks) the files whose tier says to format them.
+    fn format_all(&self, files: &[String], check: bool) -> Vec<Violation> {
+        let config = Config::load().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            Default::default()
+        });
+        let mut options = Options {
+            check,
+            ..self.options.clone()
+        };
+        options.macros.extend(config.fmt.macros.iter().cloned());
+        options.manifests |= config.fmt.manifests;
+        let files: Vec<String> = files
+            .iter()
+            .filter(|f| config.policy_for_file(Path::new(f)).format)
+            .cloned()
+            .collect();
+        let formatted = already_formatted(&files, &config, &options);
+        files
+            .iter()
+            .filter_map(|f| {
+                let options = options.for_file(f, &config);
+                if formatted.contains(f) {
+                    format_after_rustfmt(f, &options)
+                } else {
+                    format(f, &options)
+                }
+            })
+            .collect()
+    }
+}
+
+impl Check for FmtCheck {
+    fn name(&self) -> &str {
+        "fmt"
+    }
+
+    fn wants(&self, file: &Path) -> bool {
+        file.extension().is_some_and(|e| e == "rs")
+            || file.file_name() == Some("Cargo.toml".as_ref())
+    }
+
+    fn run(&self, files: &[String]) -> Vec<Violation> {
+        self.format_all(files, true)
+    }
+
+    fn can_fix(&self) -> bool {
+        true
+    }
+
+    fn fix(&self, files: &[String]) -> Vec<Violation> {
+        self.format_all(files, false)
+    }
+}
@@ -0,0 +1,34 @@
+//! How much the hooks do at once.  `--jobs=N` caps it at N (the same as cargo's
+//! `--jobs`, which salt-clip passes it on to), otherwise it's however many CPUs
+//! there are.  The warning ratchet parses that many files at a time, so huge
+//! commits don't wait on syn going through them one by one.
+
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// The flag that caps how much is done at once.
+pub const JOBS: &str = "--jobs=";
+
+/// The cap from `--jobs=`, or 0 when there isn't one.
+static LIMIT: AtomicUsize = AtomicUsize::new(0);
+
+/// Caps how much is done at once for the rest of the process, from the value of
+/// `--jobs=`, or says why the value isn't a cap.
+pub fn set_jobs(value: &str) -> Result<(), String> {
+    let jobs: NonZeroUsize = value
+        .parse()
+        .map_err(|_| format!("{} needs a positive number, not {}", JOBS, value))?;
+    LIMIT.store(jobs.get(), Ordering::Relaxed);
+    Ok(())
+}
+
+/// The cap `--jobs=` set, if it did.
+pub fn limit() -> Option<usize> {
+    Some(LIMIT.load(Ordering::Relaxed)).filter(|&jobs| jobs > 0)
+}
+
+/// How many things to do at once.
+pub fn jobs() -> usize {
+    limit().unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZeroUsize::get))
+}
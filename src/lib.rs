@@ -5,39 +5,1206 @@
 //! Why salt spray?  Simple, salt spray is what you use when you're ready to commit
 //! to rust.
 //!
+//! The heavier parts of salt-spray are cargo features, which are all on by default:
+//!
+//! - `syn` parses Rust source (many files at once, with rayon), which the warning
+//!   ratchet, the formatting of macro bodies, and the guards that look at the code
+//!   need,
+//! - `json` reads cargo's JSON output, which salt-clip, salt-check, the clippy
+//!   check, salt-bin-size-guard, and salt-deprecation-guard need, and writes the
+//!   warning ratchet's SARIF reports and the `json-lines` output sink (see
+//!   [`sink`]), and
+//! - `metadata` asks `cargo metadata` about the workspace, for `--with-reverse-deps`
+//!   and for telling the workspace's own crates apart in salt-fmt-imports.
+//!
+//! Binaries that need a feature aren't built without it, so a slim static build
+//! (e.g. for running the hooks in Docker) can be had with
+//!
+//! ```sh
+//! cargo build --release --target x86_64-unknown-linux-musl --no-default-features --features json
+//! ```
+//!
+//! Every binary's `--version` says which features it was built with, and
+//! `--version --features` lists just them, one per line.
+//!
 
 #![deny(missing_docs)]
 #![forbid(unsafe_code)]
 
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::env;
-use std::ffi::OsStr;
-use std::path::{Path, PathBuf};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::hash::Hash;
+use std::io::{BufRead, BufReader};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+pub mod summary;
+
+pub mod args;
+pub mod cache;
+pub mod check;
+#[cfg(feature = "json")]
+pub mod clippy;
+pub mod config;
+pub mod debug;
+#[cfg(feature = "json")]
+pub mod diagnostic;
+pub mod diff;
+#[cfg(feature = "syn")]
+pub mod dupe;
+pub mod fmt;
+pub mod jobs;
+pub mod manifest;
+pub mod metrics;
+pub mod output;
+#[cfg(feature = "syn")]
+pub mod ratchet;
+pub mod render;
+#[cfg(feature = "json")]
+pub mod sarif;
+pub mod selftest;
+pub mod sink;
+pub mod toolchain;
+pub mod watch;
+
+static VERSION: &str = "--version";
+static FEATURES_ONLY: &str = "--features";
+
+/// The optional features, each with whether this build has it.
+pub const FEATURES: [(&str, bool); 3] = [
+    ("json", cfg!(feature = "json")),
+    ("metadata", cfg!(feature = "metadata")),
+    ("syn", cfg!(feature = "syn")),
+];
+
+/// Handles `--version` for the named binary: when it's one of the arguments, the
+/// version and the features this build has are printed and the process exits.
+/// With `--features` too only the features are printed, one per line.
+pub fn exit_if_version_asked(tool: &str) {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.iter().any(|a| a == VERSION) {
+        return;
+    }
+    let features: Vec<&str> = FEATURES.iter().filter(|(_, on)| *on).map(|(f, _)| *f).collect();
+    if args.iter().any(|a| a == FEATURES_ONLY) {
+        features.iter().for_each(|f| println!("{}", f));
+    } else if features.is_empty() {
+        println!("{} {} (no optional features)", tool, env!("CARGO_PKG_VERSION"));
+    } else {
+        println!("{} {} (features: {})", tool, env!("CARGO_PKG_VERSION"), features.join(", "));
+    }
+    std::process::exit(0);
+}
+
+static DIAGNOSTIC_FILE_IDENTIFICATION: Lazy<Regex> = Lazy::new(|| {
+    // The filename ends up in capture group #1
+    Regex::new(r"-->\s+([^:]+)").unwrap()
+});
+
+/// Splits cargo's (human readable) stderr into individual diagnostics, and returns
+/// the ones that point into any of the given files.
+pub fn diagnostics_for<'a>(stderr: &'a str, files: &BTreeSet<String>) -> Vec<&'a str> {
+    let mut result = Vec::new();
+    for diagnostic in stderr.split("\n\n") {
+        if let Some(captures) = DIAGNOSTIC_FILE_IDENTIFICATION.captures(diagnostic) {
+            // unwrap here is safe since the capture group is mandatory
+            let project_relative_filename = captures.get(1).unwrap().as_str();
+            if files.iter().any(|s| s.ends_with(project_relative_filename)) {
+                result.push(diagnostic);
+            }
+        }
+    }
+    result
+}
+
+/// A process wide memo of an expensive lookup.  The hooks ask the same questions
+/// about the same few directories and manifests for every file in a commit, and
+/// the answers don't change during a run.  Since most of the paths involved are
+/// relative, the memo starts over whenever the working directory changes.
+pub(crate) struct Memo<K, V>(Lazy<Mutex<MemoState<K, V>>>);
+
+/// The working directory the answers are for, and the answers.
+type MemoState<K, V> = (Option<PathBuf>, HashMap<K, V>);
+
+impl<K: Eq + Hash, V: Clone> Memo<K, V> {
+    pub(crate) const fn new() -> Self {
+        Memo(Lazy::new(Default::default))
+    }
+
+    pub(crate) fn get_or_insert_with<F: FnOnce() -> V>(&self, key: K, f: F) -> V {
+        let cwd = env::current_dir().ok();
+        {
+            // A poisoned lock only means another thread panicked mid-insert
+            let mut memo = self.0.lock().unwrap_or_else(|e| e.into_inner());
+            if memo.0 != cwd {
+                *memo = (cwd.clone(), HashMap::new());
+            } else if let Some(value) = memo.1.get(&key) {
+                return value.clone();
+            }
+        }
+        // The lock isn't held while computing, since f may well recurse
+        let value = f();
+        let mut memo = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if memo.0 == cwd {
+            memo.1.insert(key, value.clone());
+        }
+        value
+    }
+
+    pub(crate) fn clear(&self) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).1.clear();
+    }
+}
+
+static MANIFEST_OF_DIR: Memo<PathBuf, Option<PathBuf>> = Memo::new();
+static TABLE_OF: Memo<PathBuf, Option<Arc<toml::Table>>> = Memo::new();
+static WORKSPACE_ROOT_OF: Memo<PathBuf, PathBuf> = Memo::new();
+static PACKAGE_NAME_OF: Memo<PathBuf, Option<String>> = Memo::new();
+static REPO_ROOT: Memo<(), Option<PathBuf>> = Memo::new();
+static BUILD_SCRIPT_OF: Memo<PathBuf, Option<String>> = Memo::new();
+/// The repo root given with `--root=`, which overrides finding it.
+static ROOT_OVERRIDE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Forgets everything learned about manifests and workspaces so far, for long
+/// running processes that see Cargo.toml files change.
+pub fn forget_manifests() {
+    MANIFEST_OF_DIR.clear();
+    TABLE_OF.clear();
+    WORKSPACE_ROOT_OF.clear();
+    PACKAGE_NAME_OF.clear();
+    BUILD_SCRIPT_OF.clear();
+}
+
+/// The given Cargo.toml, parsed, if it can be.  Lookups like [`cargo_target`]
+/// are made for every file in a commit, but there are only so many manifests.
+pub fn read_manifest(manifest: &Path) -> Option<Arc<toml::Table>> {
+    TABLE_OF.get_or_insert_with(manifest.to_path_buf(), || {
+        let contents = fs::read_to_string(manifest).ok()?;
+        contents.parse().ok().map(Arc::new)
+    })
+}
+
+fn find_manifest_from_dir(dir: &Path) -> Option<PathBuf> {
+    MANIFEST_OF_DIR.get_or_insert_with(dir.to_path_buf(), || {
+        let cargo = dir.join("Cargo.toml");
+        if cargo.exists() {
+            Some(cargo)
+        } else if dir.ends_with("..") {
+            // The parent of .. is the working directory, which is back down the tree
+            None
+        } else {
+            dir.parent().and_then(find_manifest_from_dir)
+        }
+    })
+}
 
 /// `find_manifest` starts from a given filename and walks up the directory
 /// tree until it finds a Cargo.toml file.  It then returns the path to that
 /// manifest (including the "Cargo.toml" filename).
 pub fn find_manifest<S: AsRef<OsStr> + ?Sized>(filename: &S) -> Option<PathBuf> {
     let filename = Path::new(filename);
-    for parent in filename.ancestors() {
-        let cargo = parent.join("Cargo.toml");
+    // Rust files can't contain a Cargo.toml, so there's no need to look
+    if filename.extension().map(|e| e != "rs").unwrap_or(true) {
+        let cargo = filename.join("Cargo.toml");
         if cargo.exists() {
             return Some(cargo);
         }
     }
-    None
+    filename.parent().and_then(find_manifest_from_dir)
 }
 
-/// Starting from $PWD, search up until a .git directory is found, and return
-/// that as the repo root.
-pub fn find_repo_root() -> Option<PathBuf> {
-    env::current_dir().or_else(|_| {
-        env::var("PWD").map(PathBuf::from)
-    }).ok().and_then(|p| {
-        for parent in p.ancestors() {
-            if parent.join(".git").exists() {
-                return Some(parent.to_path_buf());
+/// Groups the files by the Cargo.toml of the crate each belongs to, dropping any
+/// that aren't in a crate.  Clippy and friends can only operate on whole crates at
+/// a time, so this lets them run once per crate rather than once per file.
+pub fn group_by_crate(files: &[String]) -> BTreeMap<String, BTreeSet<String>> {
+    let mut result: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for file in files {
+        if let Some(manifest_path) = find_manifest(file) {
+            let manifest_path = manifest_path.to_string_lossy().to_string();
+            result.entry(manifest_path).or_default().insert(file.clone());
+        }
+    }
+    result
+}
+
+/// Finds the root of the workspace the crate with the given manifest belongs to.
+/// That's the closest directory at or above the crate (but not above the repo
+/// root) whose Cargo.toml has a `[workspace]` table, or the crate's own directory
+/// if none of them do.
+pub fn find_workspace_root<P: AsRef<Path> + ?Sized>(manifest: &P) -> PathBuf {
+    let manifest = manifest.as_ref();
+    WORKSPACE_ROOT_OF.get_or_insert_with(manifest.to_path_buf(), || workspace_root_of(manifest))
+}
+
+fn workspace_root_of(manifest: &Path) -> PathBuf {
+    let crate_dir = match manifest.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let repo_root = find_repo_root().and_then(|r| r.canonicalize().ok());
+    // Relative paths run out with "", which is the current directory
+    let ancestors = crate_dir
+        .ancestors()
+        .map(|d| if d.as_os_str().is_empty() { Path::new(".") } else { d });
+    for dir in ancestors {
+        let cargo = dir.join("Cargo.toml");
+        let is_workspace = read_manifest(&cargo)
+            .map(|t| t.contains_key("workspace"))
+            .unwrap_or(false);
+        if is_workspace {
+            return dir.to_path_buf();
+        }
+        if dir.canonicalize().ok() == repo_root {
+            break;
+        }
+    }
+    crate_dir.to_path_buf()
+}
+
+/// Like `group_by_crate`, but with the crates grouped by workspace as well, so
+/// that each workspace can be checked in isolation.
+pub fn group_by_workspace(
+    files: &[String],
+) -> BTreeMap<PathBuf, BTreeMap<String, BTreeSet<String>>> {
+    let mut result: BTreeMap<PathBuf, BTreeMap<String, BTreeSet<String>>> = BTreeMap::new();
+    for (manifest, files) in group_by_crate(files) {
+        let workspace = find_workspace_root(&manifest);
+        result.entry(workspace).or_default().insert(manifest, files);
+    }
+    result
+}
+
+/// Is the crate with the given manifest one of its workspace's `default-members`?
+/// Workspaces that don't list any count every member as a default one.
+pub fn is_default_member<P: AsRef<Path> + ?Sized>(manifest: &P) -> bool {
+    // The workspace may well be above the current directory
+    let manifest = manifest.as_ref();
+    let manifest = manifest.canonicalize().unwrap_or_else(|_| manifest.to_path_buf());
+    let root = find_workspace_root(&manifest);
+    let default_members = read_manifest(&root.join("Cargo.toml"))
+        .and_then(|t| t.get("workspace")?.get("default-members")?.as_array().cloned());
+    let default_members = match default_members {
+        Some(default_members) => default_members,
+        None => return true,
+    };
+    let crate_dir = manifest.parent().unwrap_or_else(|| Path::new(""));
+    let relative = crate_dir.strip_prefix(&root).unwrap_or(crate_dir);
+    default_members.iter().filter_map(|m| m.as_str()).any(|member| {
+        let member = member.trim_start_matches("./").trim_end_matches('/');
+        match member {
+            "" | "." => relative.as_os_str().is_empty(),
+            _ => config::glob_matches(member, relative),
+        }
+    })
+}
+
+/// The manifests of the crates in the workspace with the given root, i.e. those
+/// its `members` (globs and all) name, less its `exclude`s, and the root package
+/// if there is one.  A directory without a `[workspace]` is a workspace of one.
+pub fn workspace_members<P: AsRef<Path> + ?Sized>(root: &P) -> Vec<PathBuf> {
+    let root = root.as_ref();
+    let manifest = root.join("Cargo.toml");
+    let table = read_manifest(&manifest).unwrap_or_default();
+    let mut result = Vec::new();
+    if table.contains_key("package") {
+        result.push(manifest.clone());
+    }
+    let workspace = match table.get("workspace") {
+        Some(workspace) => workspace,
+        None => return result,
+    };
+    let list = |key: &str| -> Vec<String> {
+        workspace
+            .get(key)
+            .and_then(|l| l.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|m| m.as_str())
+            .map(|m| m.trim_start_matches("./").trim_end_matches('/').to_string())
+            .collect()
+    };
+    let (members, excluded) = (list("members"), list("exclude"));
+    let mut dirs = Vec::new();
+    find_crate_dirs(root, Path::new(""), &mut dirs);
+    for dir in dirs {
+        let member = members.iter().any(|m| config::glob_matches(m, &dir));
+        let excluded = excluded.iter().any(|e| dir.starts_with(e));
+        if member && !excluded {
+            result.push(root.join(dir).join("Cargo.toml"));
+        }
+    }
+    result
+}
+
+/// Collects the (relative) directories under `root` that have a Cargo.toml,
+/// leaving out build output and hidden directories.
+fn find_crate_dirs(root: &Path, relative: &Path, result: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(root.join(relative)) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name();
+        let skipped = name == "target" || name.to_string_lossy().starts_with('.');
+        if skipped || !entry.path().is_dir() {
+            continue;
+        }
+        let dir = relative.join(&name);
+        if entry.path().join("Cargo.toml").is_file() {
+            result.push(dir.clone());
+        }
+        find_crate_dirs(root, &dir, result);
+    }
+}
+
+/// Reads the package name out of the given Cargo.toml, if it has one.
+pub fn package_name<P: AsRef<Path> + ?Sized>(manifest: &P) -> Option<String> {
+    let manifest = manifest.as_ref();
+    PACKAGE_NAME_OF.get_or_insert_with(manifest.to_path_buf(), || {
+        let manifest = read_manifest(manifest)?;
+        manifest.get("package")?.get("name")?.as_str().map(str::to_string)
+    })
+}
+
+/// The crate's name in paths (e.g. `my_crate`) and its library root, if it has a
+/// library target.
+pub fn library_of(manifest: &Path) -> Option<(String, PathBuf)> {
+    let table = read_manifest(manifest)?;
+    let dir = manifest.parent()?;
+    let lib = table.get("lib");
+    let root = match lib.and_then(|l| l.get("path")).and_then(|p| p.as_str()) {
+        Some(path) => dir.join(path),
+        None => dir.join("src/lib.rs"),
+    };
+    let name = lib
+        .and_then(|l| l.get("name"))
+        .and_then(|n| n.as_str())
+        .map(str::to_string)
+        .or_else(|| package_name(manifest))?;
+    if root.is_file() {
+        Some((name.replace('-', "_"), root))
+    } else {
+        None
+    }
+}
+
+/// Reads the lint levels out of the `[lints]` table of the given Cargo.toml
+/// (following `workspace = true` to the workspace's `[workspace.lints]`).  The
+/// keys are lint names the way rustc reports them, e.g. `dead_code` and
+/// `clippy::needless_return`, and the values are levels like `warn` or `deny`.
+pub fn lint_levels<P: AsRef<Path> + ?Sized>(manifest: &P) -> BTreeMap<String, String> {
+    let table = match read_manifest(manifest.as_ref()) {
+        Some(table) => table,
+        None => return BTreeMap::new(),
+    };
+    let lints = match table.get("lints") {
+        Some(lints) if lints.get("workspace").and_then(|w| w.as_bool()) == Some(true) => {
+            let workspace = find_workspace_root(manifest).join("Cargo.toml");
+            let workspace = read_manifest(&workspace);
+            workspace.and_then(|w| w.get("workspace")?.get("lints").cloned())
+        }
+        lints => lints.cloned(),
+    };
+    lint_table_levels(lints.as_ref())
+}
+
+/// The lint levels set by a `[lints]` (or `[workspace.lints]`) table, keyed the
+/// same way as [`lint_levels`].  Doesn't follow `workspace = true`.
+pub(crate) fn lint_table_levels(lints: Option<&toml::Value>) -> BTreeMap<String, String> {
+    let mut result = BTreeMap::new();
+    let tools = lints.and_then(|l| l.as_table()).into_iter().flatten();
+    for (tool, lints) in tools {
+        for (lint, level) in lints.as_table().into_iter().flatten() {
+            // Levels are either "warn" or { level = "warn", priority = -1 }
+            let level = level.as_str().or_else(|| level.get("level")?.as_str());
+            if let Some(level) = level {
+                let lint = lint.replace('-', "_");
+                let name = match tool.as_str() {
+                    "rust" => lint,
+                    tool => format!("{}::{}", tool, lint),
+                };
+                result.insert(name, level.to_string());
+            }
+        }
+    }
+    result
+}
+
+/// Is the crate with the given Cargo.toml a proc-macro crate?
+pub fn is_proc_macro<P: AsRef<Path> + ?Sized>(manifest: &P) -> bool {
+    let manifest = read_manifest(manifest.as_ref());
+    let lib = manifest.as_ref().and_then(|m| m.get("lib"));
+    // Cargo accepts both spellings
+    ["proc-macro", "proc_macro"]
+        .iter()
+        .any(|key| lib.and_then(|l| l.get(key)).and_then(|v| v.as_bool()) == Some(true))
+}
+
+/// Can the crate with the given Cargo.toml be published, i.e. does it not have
+/// `publish = false` (or an empty list of registries)?  `publish.workspace = true`
+/// is followed to the workspace's `[workspace.package]`.
+pub fn is_publishable<P: AsRef<Path> + ?Sized>(manifest: &P) -> bool {
+    let table = read_manifest(manifest.as_ref());
+    let publish = table
+        .as_ref()
+        .and_then(|t| t.get("package")?.get("publish"));
+    let publish = match publish {
+        Some(p) if p.get("workspace").and_then(|w| w.as_bool()) == Some(true) => {
+            let workspace = read_manifest(&find_workspace_root(manifest).join("Cargo.toml"));
+            workspace.and_then(|w| w.get("workspace")?.get("package")?.get("publish").cloned())
+        }
+        publish => publish.cloned(),
+    };
+    match publish {
+        Some(toml::Value::Boolean(publish)) => publish,
+        Some(toml::Value::Array(registries)) => !registries.is_empty(),
+        _ => true,
+    }
+}
+
+/// The features of the crate with the given Cargo.toml that are on by default,
+/// i.e. `default` and whatever it turns on.
+pub fn default_features(manifest: &Path) -> BTreeSet<String> {
+    let table = read_manifest(manifest);
+    let features = table
+        .as_ref()
+        .and_then(|t| t.get("features"))
+        .and_then(|f| f.as_table());
+    let mut result = BTreeSet::new();
+    let mut todo = vec!["default".to_string()];
+    while let Some(feature) = todo.pop() {
+        let enables = match features.and_then(|f| f.get(&feature)) {
+            Some(enables) if result.insert(feature) => enables,
+            _ => continue,
+        };
+        // Optional dependencies (`dep:x`) and their features (`x/y`) aren't ours
+        let enables = enables.as_array().into_iter().flatten();
+        for enabled in enables.filter_map(|e| e.as_str()) {
+            if !enabled.contains(':') && !enabled.contains('/') {
+                todo.push(enabled.to_string());
+            }
+        }
+    }
+    result
+}
+
+/// The marker generated files carry near their top, the same one rustfmt's
+/// `format_generated_files` looks for.
+static GENERATED_MARKER: &str = "@generated";
+
+/// The contents of the build script of the crate with the given Cargo.toml, if it
+/// has one.
+fn build_script_of(manifest: &Path) -> Option<String> {
+    BUILD_SCRIPT_OF.get_or_insert_with(manifest.to_path_buf(), || {
+        let table = read_manifest(manifest)?;
+        let build = table.get("package").and_then(|p| p.get("build"));
+        let script = match build {
+            Some(toml::Value::String(script)) => script.as_str(),
+            Some(toml::Value::Boolean(false)) => return None,
+            _ => "build.rs",
+        };
+        fs::read_to_string(manifest.with_file_name(script)).ok()
+    })
+}
+
+/// Does the file look like it was generated rather than written?  That's any file
+/// under a build script's `OUT_DIR` (i.e. `target/*/build/<crate>-<hash>/out`),
+/// one marked `@generated` in its first few lines, and one that its crate's build
+/// script writes to (with `fs::write`, `File::create`, and the like), which is
+/// how build scripts that copy what they generate into `OUT_DIR` back into the
+/// tree give themselves away.  Files the build script only reads, or reruns when
+/// they change, aren't.  Files `include!`d from `OUT_DIR` are never in a commit,
+/// but the copies would otherwise be formatted, and have their allows counted,
+/// like any other.
+pub fn looks_generated<P: AsRef<Path> + ?Sized>(filename: &P) -> bool {
+    let path = filename.as_ref();
+    let components: Vec<_> = path.components().map(|c| c.as_os_str()).collect();
+    let in_out_dir = components
+        .windows(3)
+        .any(|w| w[0] == "build" && w[2] == "out");
+    if in_out_dir {
+        return true;
+    }
+    let marked = fs::File::open(path)
+        .map(|f| {
+            BufReader::new(f)
+                .lines()
+                .take(5)
+                .map_while(Result::ok)
+                .any(|l| l.contains(GENERATED_MARKER))
+        })
+        .unwrap_or(false);
+    if marked {
+        return true;
+    }
+    let manifest = match find_manifest(path) {
+        Some(manifest) => manifest,
+        None => return false,
+    };
+    let within = manifest
+        .parent()
+        .and_then(|dir| path.strip_prefix(dir).ok())
+        .map(|w| {
+            w.iter()
+                .map(|c| c.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/")
+        });
+    match (within, build_script_of(&manifest)) {
+        (Some(within), Some(script)) => {
+            let quoted = format!("\"{}\"", within);
+            script.lines().any(|l| writes_to(l, &quoted))
+        }
+        _ => false,
+    }
+}
+
+/// The calls a build script writes a file with, taking where it goes first.
+static WRITES: &[&str] = &[
+    "fs::write",
+    "File::create",
+    "create_new",
+    "write_to_file",
+    "OpenOptions",
+];
+
+/// The calls that take where a file goes second, after where it comes from.
+static COPIES: &[&str] = &["fs::copy", "fs::rename", "fs::hard_link"];
+
+/// Does the line of a build script write to the quoted path?  That's passing it
+/// to `fs::write`, `File::create` and the like, or as where `fs::copy` puts a
+/// file, but not reading it (or telling cargo to rerun when it changes).
+fn writes_to(line: &str, quoted: &str) -> bool {
+    let at = match line.find(quoted) {
+        Some(at) => at,
+        None => return false,
+    };
+    let before = &line[..at];
+    WRITES.iter().any(|w| before.contains(w))
+        || COPIES.iter().any(|c| {
+            before
+                .find(c)
+                .is_some_and(|call| before[call..].contains(','))
+        })
+}
+
+/// The kind of cargo target the file belongs to: `lib`, `bin`, `test`, `example`,
+/// `bench`, or `build` (for build scripts).  Files are placed the way cargo finds
+/// targets on its own (e.g. `src/bin/**` is `bin`), except for the ones under the
+/// directory of a target declared elsewhere in Cargo.toml (e.g. `[[bin]]` with
+/// `path = "cli/main.rs"`).  Files outside of any crate have no kind.
+pub fn target_kind<P: AsRef<Path> + ?Sized>(filename: &P) -> Option<&'static str> {
+    cargo_target(filename).map(|(kind, _)| kind)
+}
+
+/// Like [`target_kind`], but with the name of the target too, where it has one
+/// that can be told from the file's path or the manifest (e.g. `foo` for
+/// `src/bin/foo/args.rs`, or the package's name for `src/main.rs`).
+pub fn cargo_target<P: AsRef<Path> + ?Sized>(
+    filename: &P,
+) -> Option<(&'static str, Option<String>)> {
+    let path = filename.as_ref();
+    let manifest = find_manifest(path)?;
+    let dir = manifest.parent()?;
+    let within = path.strip_prefix(dir).ok()?;
+    let table = read_manifest(&manifest)?;
+
+    let build = table
+        .get("package")
+        .and_then(|p| p.get("build"))
+        .and_then(|b| b.as_str());
+    if within == Path::new(build.unwrap_or("build.rs")) {
+        return Some(("build", None));
+    }
+    for kind in ["lib", "bin", "test", "example", "bench"] {
+        let targets = match table.get(kind) {
+            Some(toml::Value::Array(targets)) => targets.iter().collect(),
+            Some(target) => vec![target],
+            None => Vec::new(),
+        };
+        for target in targets {
+            let root = match target.get("path").and_then(|p| p.as_str()) {
+                Some(root) => Path::new(root),
+                None => continue,
+            };
+            // A root directly in src/ (or the crate) shares its directory with others
+            let root_dir = root
+                .parent()
+                .filter(|d| !d.as_os_str().is_empty() && *d != Path::new("src"));
+            if within == root || root_dir.is_some_and(|d| within.starts_with(d)) {
+                let name = target.get("name").and_then(|n| n.as_str());
+                return Some((kind, name.map(str::to_string)));
             }
         }
+    }
+    // Below src/bin, tests, and the like, each file (or directory) is a target
+    let name_under = |dir: &str| {
+        let first = within.strip_prefix(dir).ok()?.components().next()?;
+        let first = Path::new(first.as_os_str());
+        Some(first.file_stem()?.to_string_lossy().into_owned())
+    };
+    let target = if within == Path::new("src/main.rs") {
+        ("bin", package_name(&manifest))
+    } else if within.starts_with("src/bin") {
+        ("bin", name_under("src/bin"))
+    } else if within.starts_with("tests") {
+        ("test", name_under("tests"))
+    } else if within.starts_with("examples") {
+        ("example", name_under("examples"))
+    } else if within.starts_with("benches") {
+        ("bench", name_under("benches"))
+    } else {
+        ("lib", None)
+    };
+    Some(target)
+}
+
+/// Returns the root of the working tree $PWD is in.  That's whatever git says
+/// it is, which copes with worktrees and submodules (where .git is a file that
+/// points at the real git dir), or without git the closest directory holding a
+/// .git directory or gitdir file.
+pub fn find_repo_root() -> Option<PathBuf> {
+    let root = ROOT_OVERRIDE.lock().unwrap_or_else(|e| e.into_inner());
+    if root.is_some() {
+        return root.clone();
+    }
+    drop(root);
+    REPO_ROOT.get_or_insert_with((), || {
+        let cwd = env::current_dir()
+            .or_else(|_| env::var("PWD").map(PathBuf::from))
+            .ok()?;
+        // git resolves symlinks, but paths are compared against the cwd as we see it
+        let toplevel = git_toplevel(&cwd).and_then(|t| t.canonicalize().ok());
+        let mut ancestors = cwd.ancestors();
+        match toplevel {
+            Some(toplevel) => ancestors.find(|d| d.canonicalize().ok().as_ref() == Some(&toplevel)),
+            None => ancestors.find(|d| is_git_root(d)),
+        }
+        .map(Path::to_path_buf)
+    })
+}
+
+/// Makes `root` the repo root for the rest of the process, whether or not git
+/// thinks it is one, and moves there so that everything else that's found
+/// relative to the working directory is found relative to it (see
+/// [`args`](crate::args)'s `--root=`).
+pub fn set_repo_root(root: &Path) -> Result<(), String> {
+    let unusable =
+        |e: std::io::Error| format!("Unable to use {} as the repo root: {}", root.display(), e);
+    env::set_current_dir(root).map_err(unusable)?;
+    // As in find_repo_root, the root is compared against the cwd as we see it
+    let cwd = env::current_dir().map_err(unusable)?;
+    *ROOT_OVERRIDE.lock().unwrap_or_else(|e| e.into_inner()) = Some(cwd);
+    Ok(())
+}
+
+fn git_toplevel(dir: &Path) -> Option<PathBuf> {
+    let output = Command::new("git")
+        .args(["rev-parse", "--show-toplevel"])
+        .current_dir(dir)
+        .output()
+        .ok()
+        .filter(|o| o.status.success())?;
+    let toplevel = String::from_utf8(output.stdout).ok()?;
+    Some(PathBuf::from(toplevel.trim_end_matches(['\n', '\r'])))
+}
+
+/// Does the directory have a .git directory, or a .git file pointing at one (as
+/// worktrees and submodules do)?
+fn is_git_root(dir: &Path) -> bool {
+    let git = dir.join(".git");
+    git.is_dir()
+        || fs::read_to_string(&git)
+            .map(|c| c.starts_with("gitdir:"))
+            .unwrap_or(false)
+}
+
+/// Returns the contents of a file as of the given git revision, or None if it
+/// didn't exist then (or git isn't available).
+pub fn read_committed<P: AsRef<Path> + ?Sized>(rev: &str, path: &P) -> Option<String> {
+    // Git wants forward slashes, even on Windows, and the path may not be UTF-8
+    let mut spec = OsString::from(format!("{}:", rev));
+    for (i, component) in repo_relative(path).iter().enumerate() {
+        if i > 0 {
+            spec.push("/");
+        }
+        spec.push(component);
+    }
+    let output = Command::new("git")
+        .arg("show")
+        .arg(spec)
+        .current_dir(find_repo_root()?)
+        .output()
+        .ok()?;
+    if output.status.success() {
+        String::from_utf8(output.stdout).ok()
+    } else {
         None
+    }
+}
+
+/// Returns the given path relative to the repo root if it is inside the repo,
+/// otherwise returns it unchanged.
+pub fn repo_relative<P: AsRef<Path> + ?Sized>(path: &P) -> PathBuf {
+    let path = path.as_ref();
+    let joined = match env::current_dir() {
+        Ok(cwd) if path.is_relative() => cwd.join(path),
+        _ => path.to_path_buf(),
+    };
+    // Resolve any ..s, so that e.g. ../other/src/lib.rs is still inside the repo
+    let mut absolute = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::ParentDir => drop(absolute.pop()),
+            Component::CurDir => (),
+            c => absolute.push(c),
+        }
+    }
+    find_repo_root()
+        .and_then(|root| absolute.strip_prefix(root).ok().map(Path::to_path_buf))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// The cfgs rustc sets on its own that stand alone, like `#[cfg(test)]`, and the
+/// ones clippy and rustfmt do.
+pub static BUILTIN_CFG_NAMES: &[&str] = &[
+    "clippy",
+    "debug_assertions",
+    "doc",
+    "doctest",
+    "miri",
+    "overflow_checks",
+    "proc_macro",
+    "rustfmt",
+    "target_thread_local",
+    "test",
+    "ub_checks",
+    "unix",
+    "windows",
+];
+
+/// The cfgs rustc sets on its own that take a value, like `target_os = "linux"`.
+pub static BUILTIN_CFG_KEYS: &[&str] = &[
+    "feature",
+    "fmt_debug",
+    "panic",
+    "relocation_model",
+    "sanitize",
+    "target_abi",
+    "target_arch",
+    "target_endian",
+    "target_env",
+    "target_family",
+    "target_feature",
+    "target_has_atomic",
+    "target_os",
+    "target_pointer_width",
+    "target_vendor",
+];
+
+/// Levenshtein distance, which is plenty for catching typos in short names.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + (ca != *cb) as usize;
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The candidate closest to the given name, if one is close enough that the name
+/// is probably a typo of it.
+pub fn closest<'a, I: Iterator<Item = &'a str>>(name: &str, candidates: I) -> Option<String> {
+    candidates
+        .map(|c| (distance(name, c), c))
+        .filter(|(d, _)| *d <= 2)
+        .min()
+        .map(|(_, c)| c.to_string())
+}
+
+/// Parses the contents of a Rust source file.  Unlike `syn::parse_file` this
+/// also copes with cargo-script style files, whose `#!` line may be followed by
+/// a `---` frontmatter block.  The BOM, shebang, and frontmatter are blanked out
+/// rather than removed so that spans still point at the right lines.
+#[cfg(feature = "syn")]
+pub fn parse_source(contents: &str) -> syn::Result<syn::File> {
+    let contents = contents.strip_prefix('\u{feff}').unwrap_or(contents);
+    let mut lines = contents.split_inclusive('\n').peekable();
+    let mut cleaned = String::with_capacity(contents.len());
+
+    // A shebang is a first line starting with #! that isn't an inner attribute
+    if let Some(first) = lines.peek() {
+        if first.starts_with("#!") && !first[2..].trim_start().starts_with('[') {
+            lines.next();
+            cleaned.push('\n');
+        }
+    }
+
+    // Frontmatter may only be preceded by blank lines
+    while let Some(line) = lines.peek() {
+        if line.trim().is_empty() {
+            cleaned.push_str(line);
+            lines.next();
+        } else {
+            break;
+        }
+    }
+    if let Some(opening) = lines.peek().map(|l| l.trim_end().to_string()) {
+        let dashes = opening.len() - opening.trim_start_matches('-').len();
+        if dashes >= 3 {
+            let fence = &opening[..dashes];
+            lines.next();
+            cleaned.push('\n');
+            for line in lines.by_ref() {
+                cleaned.push('\n');
+                if line.trim_end() == fence {
+                    break;
+                }
+            }
+        }
+    }
+
+    lines.for_each(|line| cleaned.push_str(line));
+    syn::parse_file(&cleaned)
+}
+
+/// Where the files of a module's child modules live.
+pub fn child_dir(file: &Path) -> PathBuf {
+    let dir = file.parent().unwrap_or_else(|| Path::new(""));
+    match file.file_stem().and_then(|s| s.to_str()) {
+        Some("lib" | "main" | "mod") | None => dir.to_path_buf(),
+        Some(stem) => dir.join(stem),
+    }
+}
+
+/// Finds the file behind a `mod foo;`, honoring `#[path = "..."]`.
+#[cfg(feature = "syn")]
+pub fn module_file(dir: &Path, m: &syn::ItemMod) -> Option<PathBuf> {
+    for attr in m.attrs.iter().filter(|a| a.path.is_ident("path")) {
+        if let Ok(syn::Meta::NameValue(nv)) = attr.parse_meta() {
+            if let syn::Lit::Str(path) = nv.lit {
+                return Some(dir.join(path.value()));
+            }
+        }
+    }
+    let name = m.ident.to_string();
+    [
+        dir.join(format!("{}.rs", name)),
+        dir.join(&name).join("mod.rs"),
+    ]
+    .into_iter()
+    .find(|f| f.is_file())
+}
+
+/// Is the predicate of a `cfg` only true when testing, e.g. `test` or
+/// `all(test, feature = "x")`?  `not(test)` isn't, and nor is `any(test, unix)`,
+/// since both are true in plenty of builds that aren't tests.
+#[cfg(feature = "syn")]
+pub fn needs_test(predicate: &syn::NestedMeta) -> bool {
+    use syn::{Meta, NestedMeta};
+    match predicate {
+        NestedMeta::Meta(Meta::Path(path)) => path.is_ident("test"),
+        NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("all") => {
+            list.nested.iter().any(needs_test)
+        }
+        NestedMeta::Meta(Meta::List(list)) if list.path.is_ident("any") => {
+            !list.nested.is_empty() && list.nested.iter().all(needs_test)
+        }
+        _ => false,
+    }
+}
+
+/// Is the item with these attributes test code?  That's `#[test]` (or an
+/// attribute macro like `#[tokio::test]`), or a `#[cfg]` that [`needs_test`].
+#[cfg(feature = "syn")]
+pub fn is_test_code(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        if attr.path.segments.last().is_some_and(|s| s.ident == "test") {
+            return true;
+        } else if !attr.path.is_ident("cfg") {
+            return false;
+        }
+        match attr.parse_meta() {
+            Ok(syn::Meta::List(list)) => list.nested.first().is_some_and(needs_test),
+            _ => false,
+        }
     })
 }
+
+/// Is the visibility plain `pub`?  `pub(crate)` and the like don't reach other
+/// crates.
+#[cfg(feature = "syn")]
+pub fn is_public(vis: &syn::Visibility) -> bool {
+    matches!(vis, syn::Visibility::Public(_))
+}
+
+/// The item's attributes.
+#[cfg(feature = "syn")]
+pub fn attrs_of(item: &syn::Item) -> &[syn::Attribute] {
+    use syn::Item;
+    match item {
+        Item::Const(i) => &i.attrs,
+        Item::Enum(i) => &i.attrs,
+        Item::ExternCrate(i) => &i.attrs,
+        Item::Fn(i) => &i.attrs,
+        Item::ForeignMod(i) => &i.attrs,
+        Item::Impl(i) => &i.attrs,
+        Item::Macro(i) => &i.attrs,
+        Item::Macro2(i) => &i.attrs,
+        Item::Mod(i) => &i.attrs,
+        Item::Static(i) => &i.attrs,
+        Item::Struct(i) => &i.attrs,
+        Item::Trait(i) => &i.attrs,
+        Item::TraitAlias(i) => &i.attrs,
+        Item::Type(i) => &i.attrs,
+        Item::Union(i) => &i.attrs,
+        Item::Use(i) => &i.attrs,
+        _ => &[],
+    }
+}
+
+/// The public structs and enums among the items (and those of their inline
+/// modules), each with the path of the module it's in, e.g. `foo::bar::`.
+#[cfg(feature = "syn")]
+pub fn public_types_in(items: &[syn::Item]) -> Vec<(String, &syn::Item)> {
+    fn collect<'a>(
+        prefix: &str,
+        items: &'a [syn::Item],
+        result: &mut Vec<(String, &'a syn::Item)>,
+    ) {
+        for item in items {
+            match item {
+                syn::Item::Struct(s) if is_public(&s.vis) => {
+                    result.push((prefix.to_string(), item))
+                }
+                syn::Item::Enum(e) if is_public(&e.vis) => result.push((prefix.to_string(), item)),
+                syn::Item::Mod(m) => {
+                    if let Some((_, items)) = &m.content {
+                        collect(&format!("{}{}::", prefix, m.ident), items, result);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+    let mut result = Vec::new();
+    collect("", items, &mut result);
+    result
+}
+
+/// A public item, as [`public_items_in`] finds them.
+#[cfg(feature = "syn")]
+pub struct PublicItem<'a> {
+    /// What it is, e.g. `struct`, `mod`, or `use` (and `fn` for methods too).
+    pub kind: &'static str,
+    /// e.g. "pub fn foo::Bar::parse", or "pub use foo::{a, b as c}"
+    pub description: String,
+    /// Its name, or for a `use` the names it brings in.
+    pub names: Vec<String>,
+    /// Its own attributes.
+    pub attrs: &'a [syn::Attribute],
+    /// The inline modules (and the impl) it's in, outermost first.
+    pub within: Vec<&'a syn::Item>,
+    /// Where its name is, or for a `use` its tree.
+    pub span: check::Span,
+}
+
+/// Every public item among the items: the `pub` ones (modules and re-exports
+/// included) there and in their inline modules, and the `pub fn`s of inherent
+/// impls.  Trait impls are left out, since they're part of the trait's API.  What
+/// counts (tests, hidden items, and such) is up to the caller, which has what
+/// each item is in to go by.
+#[cfg(feature = "syn")]
+pub fn public_items_in(items: &[syn::Item]) -> Vec<PublicItem<'_>> {
+    let mut result = Vec::new();
+    collect_public_items("", items, &mut Vec::new(), &mut result);
+    result
+}
+
+#[cfg(feature = "syn")]
+fn collect_public_items<'a>(
+    prefix: &str,
+    items: &'a [syn::Item],
+    within: &mut Vec<&'a syn::Item>,
+    result: &mut Vec<PublicItem<'a>>,
+) {
+    use quote::ToTokens;
+    use syn::spanned::Spanned;
+    use syn::{ImplItem, Item};
+    for item in items {
+        let (kind, ident, attrs) = match item {
+            Item::Fn(f) if is_public(&f.vis) => ("fn", &f.sig.ident, &f.attrs),
+            Item::Struct(s) if is_public(&s.vis) => ("struct", &s.ident, &s.attrs),
+            Item::Enum(e) if is_public(&e.vis) => ("enum", &e.ident, &e.attrs),
+            Item::Union(u) if is_public(&u.vis) => ("union", &u.ident, &u.attrs),
+            Item::Trait(t) if is_public(&t.vis) => ("trait", &t.ident, &t.attrs),
+            Item::Type(t) if is_public(&t.vis) => ("type", &t.ident, &t.attrs),
+            Item::Const(c) if is_public(&c.vis) => ("const", &c.ident, &c.attrs),
+            Item::Static(s) if is_public(&s.vis) => ("static", &s.ident, &s.attrs),
+            Item::Use(u) if is_public(&u.vis) => {
+                // Spaces are left around `as`, e.g. `a::{b as c}`
+                let tree = u.tree.to_token_stream().to_string();
+                let tree = [(" :: ", "::"), ("{ ", "{"), (" }", "}"), (" ,", ",")]
+                    .iter()
+                    .fold(tree, |tree, (from, to)| tree.replace(from, to));
+                let mut names = Vec::new();
+                names_in(&u.tree, &mut names);
+                result.push(PublicItem {
+                    kind: "use",
+                    description: format!("pub use {}{}", prefix, tree),
+                    names,
+                    attrs: &u.attrs,
+                    within: within.clone(),
+                    span: u.tree.span().into(),
+                });
+                continue;
+            }
+            Item::Impl(i) if i.trait_.is_none() => {
+                let ty = i.self_ty.to_token_stream().to_string().replace(' ', "");
+                within.push(item);
+                for m in i.items.iter().filter_map(|i| match i {
+                    ImplItem::Method(m) if is_public(&m.vis) => Some(m),
+                    _ => None,
+                }) {
+                    result.push(PublicItem {
+                        kind: "fn",
+                        description: format!("pub fn {}{}::{}", prefix, ty, m.sig.ident),
+                        names: vec![m.sig.ident.to_string()],
+                        attrs: &m.attrs,
+                        within: within.clone(),
+                        span: m.sig.ident.span().into(),
+                    });
+                }
+                within.pop();
+                continue;
+            }
+            Item::Mod(m) => {
+                if is_public(&m.vis) {
+                    result.push(PublicItem {
+                        kind: "mod",
+                        description: format!("pub mod {}{}", prefix, m.ident),
+                        names: vec![m.ident.to_string()],
+                        attrs: &m.attrs,
+                        within: within.clone(),
+                        span: m.ident.span().into(),
+                    });
+                }
+                if let Some((_, items)) = &m.content {
+                    within.push(item);
+                    collect_public_items(
+                        &format!("{}{}::", prefix, m.ident),
+                        items,
+                        within,
+                        result,
+                    );
+                    within.pop();
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        result.push(PublicItem {
+            kind,
+            description: format!("pub {} {}{}", kind, prefix, ident),
+            names: vec![ident.to_string()],
+            attrs,
+            within: within.clone(),
+            span: ident.span().into(),
+        });
+    }
+}
+
+/// The names a `use` brings in, e.g. `b` and `d` out of `a::{b, c as d}`.
+#[cfg(feature = "syn")]
+fn names_in(tree: &syn::UseTree, result: &mut Vec<String>) {
+    use syn::UseTree;
+    match tree {
+        UseTree::Path(p) => names_in(&p.tree, result),
+        UseTree::Name(n) => result.push(n.ident.to_string()),
+        UseTree::Rename(r) => result.push(r.rename.to_string()),
+        UseTree::Glob(_) => (),
+        UseTree::Group(g) => g.items.iter().for_each(|t| names_in(t, result)),
+    }
+}
+
+/// Reads a hook's baseline (e.g. `.geiger.yaml`), or the empty one if there isn't
+/// one yet.  One that doesn't parse is an error, rather than a fresh start.
+pub fn load_baseline<T: serde::de::DeserializeOwned + Default>(path: &Path) -> Result<T, String> {
+    match fs::read_to_string(path) {
+        Ok(contents) => serde_yaml::from_str(&contents)
+            .map_err(|e| format!("Unable to parse {}: {}", path.display(), e)),
+        Err(_) => Ok(Default::default()),
+    }
+}
+
+/// Writes a hook's baseline.
+pub fn save_baseline<T: serde::Serialize>(path: &Path, baseline: &T) -> Result<(), String> {
+    let contents = serde_yaml::to_string(baseline)
+        .map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+    fs::write(path, contents).map_err(|e| format!("Unable to write {}: {}", path.display(), e))
+}
+
+/// Finds every Rust file under the directory, leaving out build output and
+/// hidden directories, and when `nested` isn't set, any crates nested inside of
+/// it too.
+fn walk_rust_files(dir: &Path, nested: bool, result: &mut Vec<PathBuf>) {
+    // A crate at the root of the repo has a manifest of just Cargo.toml, and its
+    // directory is empty
+    let dir = Some(dir)
+        .filter(|d| !d.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() {
+            let skipped = name == "target" || name.starts_with('.');
+            if !skipped && (nested || !path.join("Cargo.toml").exists()) {
+                walk_rust_files(&path, nested, result);
+            }
+        } else if name.ends_with(".rs") {
+            result.push(path);
+        }
+    }
+}
+
+/// Finds every Rust file under the directory (nested crates included), leaving
+/// out build output and hidden directories.
+pub fn rust_files_in(dir: &Path) -> Vec<PathBuf> {
+    let mut result = Vec::new();
+    walk_rust_files(dir, true, &mut result);
+    result
+}
+
+/// Finds every Rust file of the crate in the directory, like [`rust_files_in`]
+/// but leaving out any crates nested inside of it.
+pub fn crate_files_in(dir: &Path) -> Vec<PathBuf> {
+    let mut result = Vec::new();
+    walk_rust_files(dir, false, &mut result);
+    result
+}
+
+/// Finds every Rust file of the library of the crate in the directory, like
+/// [`crate_files_in`] but leaving out its binaries (`main.rs` and `bin/`).
+pub fn library_files_in(dir: &Path) -> Vec<PathBuf> {
+    let mut result = crate_files_in(dir);
+    result.retain(|path| {
+        let within = path.strip_prefix(dir).unwrap_or(path);
+        path.file_name() != Some(OsStr::new("main.rs"))
+            && !within.components().any(|c| c.as_os_str() == "bin")
+    });
+    result
+}
@@ -2,18 +2,89 @@
 //! how it handles monorepos (and other situations where Cargo workspaces are used)
 //! than some other rustfmt pre-commit wrappers.
 //!
+//! With `--default-members-only` (or `default-members-only = true` in the `[fmt]`
+//! table of salt-spray.toml) files in crates that aren't in their workspace's
+//! `default-members` are left alone.
+//!
+//! Files can get extra rustfmt settings for the kind of target they're in (e.g.
+//! longer lines in binaries) from `[fmt.targets]`, see `salt_spray::target_kind`.
+//!
+//! With `whole-workspace-fraction = 0.8` in `[fmt]`, a commit that touches at
+//! least that fraction of a workspace's crates gets the whole workspace formatted
+//! with a single `cargo fmt` of its members, files outside of the commit included.
+//! That's only done where it formats the same way going file by file would, so not
+//! with excludes, per-target settings, macros, or `--verify-idempotent`.
+//!
+//! Only the files that a quick `rustfmt --check` over the whole commit finds
+//! something to change in get a `cargo fmt` of their own, so commits that are
+//! mostly formatted already don't wait on cargo for every file.
+//!
+//! With `--manifests` (or `manifests = true` in `[fmt]`) the Cargo.toml files in
+//! the commit are normalized in the same run, which sorts `[workspace.dependencies]`
+//! and `[patch]` tables and spells inline specs and paths the same way everywhere.
+//!
+//! With `--interactive` each file's formatting is shown as a diff first, and kept
+//! or taken back as you answer (like `git add -p`), so a quick commit can leave
+//! the big mechanical reformatting of files it barely touched for later.  The
+//! answers are read from the terminal, since pre-commit doesn't pass hooks its
+//! stdin, and whole workspaces are never formatted at once.
+//!
+//! With `--check` (or `SALT_SPRAY_CHECK=1` in the environment, e.g. for CI) no
+//! file is touched: what formatting would change in each one is printed as a
+//! unified diff instead, and the exit code says which files it would have
+//! formatted.  It doesn't go with `--interactive`, and never formats whole
+//! workspaces at once.  With `--partially-staged=staged` the diff of a partially
+//! staged file is of its staged copy, which is what would have been formatted.
+//!
+//! Files of dependencies' sources are refused, since formatting them only makes
+//! a diff against upstream that's gone again the next time they're fetched:
+//! anything under cargo's registry or git checkouts (in `$CARGO_HOME`, usually
+//! ~/.cargo), and crates under a `vendor/` directory or with the
+//! .cargo-checksum.json that `cargo vendor` leaves.  A commit with any of them in
+//! it exits with -1, unless `--allow-vendored` says to format them anyway.
+//!
+//! Files that aren't formatted go to the terminal, and to any other sinks
+//! `--output=` (or salt-spray.toml) picks, see `salt_spray::sink`.
+//!
+//! The exit code says how it went, and so does the last thing it prints: 0 when
+//! everything was formatted already, 1 when it formatted some of the files (which
+//! then need to be staged again) or with `--check` would have, and 2 when it
+//! couldn't format some of them, e.g. because they don't parse or rustfmt
+//! crashed, whatever else it formatted.  A command line or config that doesn't
+//! make sense exits with -1 before anything is touched.
+//!
+//! What rustfmt prints is passed on sanitized, keeping its colors only as
+//! `--color=auto|always|never` says (see `salt_spray::output`).
+//!
 //! Why salt spray?  Simple, salt spray is what you use when you're ready to commit
 //! to rust.
 //!
 
 #![forbid(unsafe_code)]
 
+use std::collections::BTreeSet;
 use std::env;
-use std::ffi::OsStr;
-use std::io::{self, ErrorKind};
-use std::process::{Command, Output};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process;
 
-use salt_spray::find_manifest;
+use salt_spray::args::parse_args;
+use salt_spray::check::Violation;
+use salt_spray::config::Config;
+use salt_spray::fmt::{
+    already_formatted, format, format_after_rustfmt, format_workspace, formatting_diff,
+    verify_idempotent, Options, PartiallyStaged, Snapshot,
+};
+use salt_spray::manifest::is_manifest;
+use salt_spray::output::{self, COLOR};
+use salt_spray::sink::{Report, OUTPUT};
+use salt_spray::summary::{self, Summary, PORCELAIN};
+use salt_spray::toolchain::{self, CARGO_PATH};
+use salt_spray::{
+    find_manifest, find_workspace_root, group_by_workspace, is_default_member, repo_relative, say,
+    workspace_members,
+};
 
 // /// Split a given file path into the path of the file's workspace and the relative
 // /// path from the workspace to the file.
@@ -42,40 +113,395 @@ use salt_spray::find_manifest;
 //     })
 // }
 
-/// Format a single file using `cargo fmt`
-fn format_file<S: AsRef<OsStr> + ?Sized>(filename: &S) -> io::Result<Output> {
-    if let Some(manifest_path) = find_manifest(filename) {
-        let mut cmd = Command::new("cargo");
-        cmd.args([
-            "fmt",
-            "--manifest-path",
-            manifest_path.to_str().unwrap(),
-            "--",
-            "--color",
-            "never",
-            filename.as_ref().to_str().unwrap(),
-        ]);
-        println!("{:?}", cmd);
-        cmd.output()
-    } else {
-        Err(io::Error::new(
-            ErrorKind::NotFound,
-            format!("No workspace found for {:?}", filename.as_ref().to_str()),
-        ))
+static RUSTFMT_CONFIG: &str = "--rustfmt-config=";
+static PARTIALLY_STAGED: &str = "--partially-staged=";
+static VERIFY_IDEMPOTENT: &str = "--verify-idempotent";
+static DEFAULT_MEMBERS_ONLY: &str = "--default-members-only";
+static MANIFESTS: &str = "--manifests";
+static INTERACTIVE: &str = "--interactive";
+static ALLOW_VENDORED: &str = "--allow-vendored";
+static CHECK: &str = "--check";
+
+/// Exit codes, see the module docs.
+const FORMATTED: i32 = 1;
+const FAILED: i32 = 2;
+
+static INTERACTIVE_HELP: &str = "y - keep the formatting of this file
+n - leave this file as it was
+a - keep the formatting of this file and all the rest
+q - leave this file and all the rest as they were
+? - print help";
+
+/// What to do with a file whose formatting was shown, see `--interactive`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Answer {
+    Keep,
+    Skip,
+    KeepRest,
+    Quit,
+}
+
+/// Where to ask about each file: the terminal, which pre-commit leaves hooks even
+/// though it doesn't pass them its stdin.
+fn open_terminal() -> Result<Box<dyn BufRead>, String> {
+    match File::open("/dev/tty") {
+        Ok(tty) => Ok(Box::new(BufReader::new(tty))),
+        Err(_) if io::stdin().is_terminal() => Ok(Box::new(io::stdin().lock())),
+        Err(e) => Err(format!(
+            "{} needs a terminal to ask on, but there isn't one ({})",
+            INTERACTIVE, e
+        )),
+    }
+}
+
+/// Asks whether to keep the formatting of a file until it gets an answer.
+fn ask(terminal: &mut dyn BufRead, filename: &str) -> Answer {
+    loop {
+        eprint!("Keep the formatting of {} [y,n,a,q,?]? ", filename);
+        drop(io::stderr().flush());
+        let mut line = String::new();
+        // Running out of input (e.g. ^D) is taken as quitting
+        if terminal.read_line(&mut line).unwrap_or(0) == 0 {
+            eprintln!();
+            return Answer::Quit;
+        }
+        match line.trim() {
+            "y" => return Answer::Keep,
+            "n" => return Answer::Skip,
+            "a" => return Answer::KeepRest,
+            "q" => return Answer::Quit,
+            _ => eprintln!("{}", INTERACTIVE_HELP),
+        }
     }
 }
 
+/// Where cargo keeps the sources of dependencies, i.e. the registry's and git's
+/// checkouts in CARGO_HOME.
+fn cargo_sources() -> Vec<PathBuf> {
+    let home = env::var_os("CARGO_HOME").map(PathBuf::from).or_else(|| {
+        let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+        Some(Path::new(&home).join(".cargo"))
+    });
+    home.into_iter()
+        .flat_map(|home| [home.join("registry"), home.join("git")])
+        .map(|dir| dir.canonicalize().unwrap_or(dir))
+        .collect()
+}
+
+/// Is the file in the source of a dependency, rather than the repo's own code?
+/// That's a checkout cargo made, or a crate under a `vendor/` directory (or with a
+/// .cargo-checksum.json, which `cargo vendor` leaves next to each Cargo.toml).
+fn is_vendored(filename: &str, cargo_sources: &[PathBuf]) -> bool {
+    let path = Path::new(filename);
+    let absolute = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if cargo_sources.iter().any(|dir| absolute.starts_with(dir)) {
+        return true;
+    }
+    find_manifest(path).is_some_and(|manifest| {
+        let under_vendor = repo_relative(&manifest)
+            .components()
+            .any(|c| c.as_os_str() == "vendor");
+        under_vendor || manifest.with_file_name(".cargo-checksum.json").exists()
+    })
+}
+
+/// Would a `cargo fmt` of the whole workspace format the files the config covers
+/// the same way going file by file would, i.e. does the config leave no files out
+/// and give none of them settings of their own?
+fn formats_as_one(config: &Config) -> bool {
+    config.exclude.is_empty() && config.generated.paths.is_empty() && config.fmt.targets.is_empty()
+}
+
+/// The workspaces that the commit touches enough of to format all of them at once
+/// (see `whole-workspace-fraction`), and did.
+fn format_whole_workspaces(
+    filenames: &[String],
+    config: &Config,
+    options: &Options,
+) -> BTreeSet<PathBuf> {
+    let mut result = BTreeSet::new();
+    let fraction = match config.fmt.whole_workspace_fraction {
+        // The macro pass and the idempotence check only work file by file
+        Some(_) if !options.macros.is_empty() || options.verify_idempotent => return result,
+        Some(_) if !formats_as_one(config) => return result,
+        Some(fraction) => fraction,
+        None => return result,
+    };
+    let rust_files: Vec<String> = filenames
+        .iter()
+        .filter(|f| f.ends_with(".rs"))
+        .cloned()
+        .collect();
+    for (workspace, files_by_crate) in group_by_workspace(&rust_files) {
+        let members = workspace_members(&workspace);
+        let uniform = members.iter().all(|m| {
+            let crate_config = config.for_crate(m).unwrap_or_else(|_| config.clone());
+            config.policy_for(m).format && formats_as_one(&crate_config)
+        });
+        let touched = files_by_crate.len();
+        if !uniform || members.is_empty() || (touched as f64) < fraction * members.len() as f64 {
+            continue;
+        }
+        say!(
+            "Formatting all of {}, since the commit touches {} of its {} crates",
+            workspace.display(),
+            touched,
+            members.len()
+        );
+        let files: Vec<String> = files_by_crate.into_values().flatten().collect();
+        if format_workspace(&workspace, &members, &files, options) {
+            result.insert(workspace);
+        }
+    }
+    result
+}
+
 /// Do the thing
 fn main() {
-    let mut args = env::args();
-    drop(args.next());
-
-    for arg in args {
-        println!("{:?}", arg);
-        match format_file(&arg) {
-            Ok(Output { status, .. }) if status.code() == Some(0) => {}
-            Ok(Output { stderr, .. }) => eprintln!("{}", String::from_utf8_lossy(&stderr)),
-            r => eprintln!("{:?}", r),
+    salt_spray::exit_if_version_asked("salt-spray");
+    let mut summary = Summary::new("salt-spray");
+    let known = [
+        RUSTFMT_CONFIG,
+        PARTIALLY_STAGED,
+        VERIFY_IDEMPOTENT,
+        DEFAULT_MEMBERS_ONLY,
+        MANIFESTS,
+        INTERACTIVE,
+        ALLOW_VENDORED,
+        CHECK,
+        CARGO_PATH,
+        COLOR,
+        OUTPUT,
+        PORCELAIN,
+    ];
+    let (flags, filenames) = parse_args("salt-spray", env::args().skip(1), &known);
+    summary::set_porcelain(flags.iter().any(|a| a == PORCELAIN));
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+
+    // Pull out the rustfmt overrides first, they apply to every file
+    let mut options = Options {
+        macros: config.fmt.macros.clone(),
+        manifests: config.fmt.manifests,
+        ..Default::default()
+    };
+    let mut default_members_only = config.fmt.default_members_only;
+    let mut terminal = None;
+    let mut allow_vendored = false;
+    let mut check = env::var("SALT_SPRAY_CHECK").is_ok_and(|v| v == "1");
+    for arg in flags.iter() {
+        if let Some(setting) = arg.strip_prefix(RUSTFMT_CONFIG) {
+            options.rustfmt_config.push(setting.to_string());
+        } else if arg == VERIFY_IDEMPOTENT {
+            options.verify_idempotent = true;
+        } else if arg == DEFAULT_MEMBERS_ONLY {
+            default_members_only = true;
+        } else if arg == MANIFESTS {
+            options.manifests = true;
+        } else if arg == INTERACTIVE {
+            terminal = Some(open_terminal().unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(-1);
+            }));
+        } else if arg == ALLOW_VENDORED {
+            allow_vendored = true;
+        } else if arg == CHECK {
+            check = true;
+        } else if let Some(path) = arg.strip_prefix(CARGO_PATH) {
+            toolchain::set_cargo_path(path);
+        } else if let Some(mode) = arg.strip_prefix(PARTIALLY_STAGED) {
+            options.partially_staged = match mode {
+                "skip" => PartiallyStaged::Skip,
+                "staged" => PartiallyStaged::Staged,
+                "whole" => PartiallyStaged::Whole,
+                _ => {
+                    eprintln!("{} must be one of skip, staged, or whole", PARTIALLY_STAGED);
+                    process::exit(-1);
+                }
+            };
         }
     }
+
+    if check && terminal.is_some() {
+        eprintln!(
+            "{} only shows what would change, so there's nothing for {} to ask about",
+            CHECK, INTERACTIVE
+        );
+        process::exit(-1);
+    }
+    options.check = check;
+
+    if !allow_vendored {
+        let sources = cargo_sources();
+        let vendored: Vec<&str> = filenames
+            .iter()
+            .filter(|f| f.ends_with(".rs") || (options.manifests && is_manifest(f)))
+            .filter(|f| !config.is_excluded(Path::new(f)) && is_vendored(f, &sources))
+            .map(String::as_str)
+            .collect();
+        if !vendored.is_empty() {
+            eprintln!(
+                "Refusing to format {} file(s) of dependencies' sources: {}.  Their formatting \
+                 is upstream's to change; unstage them, or pass {} to format them anyway.",
+                vendored.len(),
+                vendored.join(", "),
+                ALLOW_VENDORED
+            );
+            process::exit(-1);
+        }
+    }
+
+    let mut report = Report::from_flags("salt-spray", &flags, &config).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(-1);
+    });
+    // What the files were like before, to tell which ones got formatted
+    let snapshots: Vec<Snapshot> = filenames
+        .iter()
+        .filter(|f| f.ends_with(".rs") || (options.manifests && is_manifest(f)))
+        .map(|f| match options.partially_staged {
+            PartiallyStaged::Staged => Snapshot::take(f),
+            _ => Snapshot::of_worktree(f),
+        })
+        .collect();
+    // Formatting a whole workspace at once leaves nothing to say no to
+    let whole = if default_members_only || terminal.is_some() || check {
+        BTreeSet::new()
+    } else {
+        format_whole_workspaces(&filenames, &config, &options)
+    };
+    let mut crates = BTreeSet::new();
+    let mut pending = Vec::new();
+    for arg in filenames {
+        say!("{:?}", arg);
+        // The hook is sent every TOML file, but only manifests are ever touched
+        let wanted = arg.ends_with(".rs") || (options.manifests && is_manifest(&arg));
+        if !wanted {
+            continue;
+        }
+        let path = Path::new(&arg);
+        if config.is_excluded(path) || !config.policy_for_file(path).format {
+            continue;
+        }
+        let manifest = find_manifest(&arg);
+        if default_members_only && !manifest.as_ref().is_none_or(is_default_member) {
+            continue;
+        }
+        summary.files += 1;
+        let done = arg.ends_with(".rs")
+            && manifest
+                .as_ref()
+                .is_some_and(|m| whole.contains(&find_workspace_root(m)));
+        crates.extend(manifest);
+        if !done {
+            pending.push(arg);
+        }
+    }
+
+    let formatted = already_formatted(&pending, &config, &options);
+    let mut skipped = Vec::new();
+    let mut unformatted = Vec::new();
+    let mut quit = false;
+    for arg in pending {
+        if check {
+            let file_options = options.for_file(&arg, &config);
+            // Only the macro pass could change a file that rustfmt has nothing to change in
+            let diff = if formatted.contains(&arg) && file_options.macros.is_empty() {
+                Ok(String::new())
+            } else {
+                formatting_diff(&arg, &file_options, output::keeps_color())
+            };
+            let mut violation = match diff {
+                Ok(diff) if diff.is_empty() => None,
+                Ok(diff) => {
+                    eprint!("{}", diff);
+                    unformatted.push(arg.clone());
+                    None
+                }
+                Err(why) => Some(Violation::new(&arg, why)),
+            };
+            if violation.is_none() && file_options.verify_idempotent {
+                violation = verify_idempotent(&arg, &file_options);
+            }
+            if let Some(violation) = violation {
+                report.record(&violation);
+                summary.violations += 1;
+            }
+            continue;
+        } else if quit {
+            skipped.push(arg);
+            continue;
+        }
+        let snapshot = terminal.is_some().then(|| Snapshot::take(&arg));
+        let file_options = options.for_file(&arg, &config);
+        let mut violation = if formatted.contains(&arg) {
+            format_after_rustfmt(&arg, &file_options)
+        } else {
+            format(&arg, &file_options)
+        };
+        let diff = snapshot
+            .as_ref()
+            .map(|s| s.diff(output::keeps_color()))
+            .unwrap_or_default();
+        if let (Some(tty), Some(snapshot)) = (terminal.as_mut(), snapshot) {
+            if !diff.is_empty() {
+                eprint!("{}", diff);
+                let answer = ask(tty.as_mut(), &arg);
+                if answer == Answer::Skip || answer == Answer::Quit {
+                    if let Err(e) = snapshot.restore() {
+                        let message = format!("Unable to put {} back the way it was: {}", arg, e);
+                        violation = violation.or(Some(Violation::new(&arg, message)));
+                    }
+                    skipped.push(arg.clone());
+                }
+                quit = answer == Answer::Quit;
+                if answer == Answer::KeepRest {
+                    terminal = None;
+                }
+            }
+        }
+        if let Some(violation) = violation {
+            report.record(&violation);
+            summary.violations += 1;
+        }
+    }
+    for arg in skipped {
+        say!("Left {} unformatted, as asked", arg);
+    }
+    summary.crates = crates.len();
+    report.finish(&summary);
+    summary.print();
+    let formatted: Vec<&str> = snapshots
+        .iter()
+        .filter(|s| s.changed())
+        .map(Snapshot::filename)
+        .chain(unformatted.iter().map(String::as_str))
+        .collect();
+    if check && !formatted.is_empty() {
+        eprintln!(
+            "{} file(s) would be formatted: {}.  Run salt-spray without {} to format them.",
+            formatted.len(),
+            formatted.join(", "),
+            CHECK
+        );
+    } else if !formatted.is_empty() {
+        eprintln!(
+            "Formatted {} file(s): {}.  Look over the changes, `git add` them, and commit again.",
+            formatted.len(),
+            formatted.join(", ")
+        );
+    }
+    // Every file gets its turn even if some fail, but the failures still fail the hook
+    if summary.violations > 0 {
+        eprintln!(
+            "Unable to format {} file(s), see above.  Fix them (or add them to `exclude` in \
+             salt-spray.toml) and commit again.",
+            summary.violations
+        );
+        process::exit(FAILED);
+    } else if !formatted.is_empty() {
+        process::exit(FORMATTED);
+    }
 }
@@ -0,0 +1,164 @@
+//! Normalizing the parts of a Cargo.toml that tend to be written a little
+//! differently by everyone who touches them, so that `salt-spray` can format a
+//! commit's manifests along with its Rust code.
+//!
+//! Only the dependency tables are touched, and everything else (comments, blank
+//! lines, the order of the other tables) is left as it was:
+//!
+//! * the entries of `[workspace.dependencies]` and of each `[patch.<source>]` are
+//!   sorted by name, since they're lookup tables whose order means nothing,
+//! * inline dependency specs are spaced the same way, e.g.
+//!   `foo = { path = "../foo", version = "1" }`, and
+//! * `path`s use forward slashes, with no leading `./` or trailing `/`.
+
+use std::fs;
+
+use toml_edit::{DocumentMut, InlineTable, Item, Table, Value};
+
+use crate::check::Violation;
+
+/// The tables of a manifest that hold dependencies, other than those under
+/// `[target.'cfg(...)']`.
+const DEPENDENCY_TABLES: [&str; 5] = [
+    "dependencies",
+    "dev-dependencies",
+    "dev_dependencies",
+    "build-dependencies",
+    "build_dependencies",
+];
+
+/// Is the file a Cargo manifest?
+pub fn is_manifest(filename: &str) -> bool {
+    filename == "Cargo.toml" || filename.ends_with("/Cargo.toml")
+}
+
+/// Spells a dependency's path the one way.
+fn normalize_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut path = path.as_str();
+    while let Some(rest) = path.strip_prefix("./") {
+        path = rest;
+    }
+    let path = path.trim_end_matches('/');
+    if path.is_empty() {
+        ".".to_string()
+    } else {
+        path.to_string()
+    }
+}
+
+/// Normalizes the `path` of a dependency spec.
+fn normalize_path_in(path: Option<&mut Value>) {
+    let value = match path {
+        Some(Value::String(value)) => value,
+        _ => return,
+    };
+    let normalized = normalize_path(value.value());
+    if normalized == *value.value() {
+        return;
+    }
+    // Keep whatever comes before and after the value, e.g. a trailing comment
+    let decor = value.decor().clone();
+    *value = toml_edit::Formatted::new(normalized);
+    *value.decor_mut() = decor;
+}
+
+fn normalize_inline_spec(spec: &mut InlineTable) {
+    normalize_path_in(spec.get_mut("path"));
+    spec.fmt();
+}
+
+/// Normalizes every dependency spec in the table.
+fn normalize_specs(table: &mut Table) {
+    for (_, spec) in table.iter_mut() {
+        match spec {
+            Item::Value(Value::InlineTable(spec)) => normalize_inline_spec(spec),
+            Item::Table(spec) => {
+                normalize_path_in(spec.get_mut("path").and_then(Item::as_value_mut));
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Normalizes the dependency tables of a manifest, returning the new contents, or
+/// None if there was nothing to change.
+pub fn normalize_manifest(contents: &str) -> Result<Option<String>, String> {
+    let mut doc: DocumentMut = contents.parse().map_err(|e| format!("{}", e))?;
+
+    let mut tables: Vec<&mut Table> = Vec::new();
+    let (root, rest): (Vec<_>, Vec<_>) = doc
+        .iter_mut()
+        .partition(|(key, _)| DEPENDENCY_TABLES.contains(&key.get()));
+    // Tables under [workspace.dependencies] and [patch.*] are sorted as well
+    let mut sorted: Vec<&mut Table> = Vec::new();
+    for (key, item) in rest {
+        match key.get() {
+            "workspace" => {
+                let deps = item.as_table_mut().and_then(|t| t.get_mut("dependencies"));
+                sorted.extend(deps.and_then(Item::as_table_mut));
+            }
+            "patch" => {
+                if let Some(patch) = item.as_table_mut() {
+                    sorted.extend(patch.iter_mut().filter_map(|(_, t)| t.as_table_mut()));
+                }
+            }
+            "target" => {
+                if let Some(targets) = item.as_table_mut() {
+                    for (_, target) in targets.iter_mut() {
+                        if let Some(target) = target.as_table_mut() {
+                            let deps = target
+                                .iter_mut()
+                                .filter(|(key, _)| DEPENDENCY_TABLES.contains(&key.get()));
+                            tables.extend(deps.filter_map(|(_, t)| t.as_table_mut()));
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    tables.extend(root.into_iter().filter_map(|(_, t)| t.as_table_mut()));
+    for table in sorted {
+        table.sort_values();
+        normalize_specs(table);
+    }
+    for table in tables {
+        normalize_specs(table);
+    }
+
+    let result = doc.to_string();
+    if result == contents {
+        Ok(None)
+    } else {
+        Ok(Some(result))
+    }
+}
+
+/// Normalizes a manifest in place (or, when checking, only reports that it needs
+/// it), returning what went wrong if anything did.
+pub fn normalize(filename: &str, check: bool) -> Option<Violation> {
+    let contents = match fs::read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(e) => {
+            return Some(Violation::new(
+                filename,
+                format!("Unable to read {}: {}", filename, e),
+            ))
+        }
+    };
+    match normalize_manifest(&contents) {
+        Ok(None) => None,
+        Ok(Some(_)) if check => Some(Violation::new(
+            filename,
+            "its dependency tables aren't normalized",
+        )),
+        Ok(Some(normalized)) => fs::write(filename, normalized)
+            .err()
+            .map(|e| Violation::new(filename, format!("Unable to write {}: {}", filename, e))),
+        Err(e) => Some(Violation::new(
+            filename,
+            format!("Unable to parse {}: {}", filename, e),
+        )),
+    }
+}
@@ -0,0 +1,236 @@
+//! Exporting what each run of a hook did, so that whoever looks after the hooks
+//! for a whole organization can see how they're doing (how long they take, how
+//! often they block commits) and how the lints trend over time.  Nothing is
+//! exported unless salt-spray.toml says where to:
+//!
+//! ```toml
+//! [metrics]
+//! textfile = "/var/lib/node_exporter/textfile/salt-spray.prom"
+//! statsd = "127.0.0.1:8125"
+//! prefix = "salt_spray"
+//! ```
+//!
+//! The textfile is in Prometheus' text format, for node exporter's textfile
+//! collector.  Each hook updates its own series in it, labeled with the hook's
+//! name: counters of runs and of violations (in total and by lint), and gauges of
+//! how the last run went.  The statsd server is sent the same things as
+//! `<prefix>.<hook>.*` counters, gauges, and timers.
+//!
+//! Metrics are best effort, problems exporting them are reported but never fail
+//! the hook.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::UdpSocket;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{Config, MetricsConfig};
+use crate::find_repo_root;
+use crate::summary::Summary;
+
+/// The most that goes into one statsd packet, which keeps them under the usual MTU.
+const STATSD_PACKET: usize = 1400;
+
+/// What each family of series in the textfile is, as (name, type, help).
+const FAMILIES: [(&str, &str, &str); 8] = [
+    ("runs_total", "counter", "How many times the hook has run."),
+    (
+        "violations_total",
+        "counter",
+        "How many blocking violations the hook has found.",
+    ),
+    (
+        "lint_violations_total",
+        "counter",
+        "How many blocking violations each lint has caused.",
+    ),
+    (
+        "last_run_duration_seconds",
+        "gauge",
+        "How long the hook's last run took.",
+    ),
+    (
+        "last_run_crates",
+        "gauge",
+        "How many crates the hook's last run examined.",
+    ),
+    (
+        "last_run_files",
+        "gauge",
+        "How many files the hook's last run examined.",
+    ),
+    (
+        "last_run_violations",
+        "gauge",
+        "How many blocking violations the hook's last run found.",
+    ),
+    (
+        "last_run_timestamp_seconds",
+        "gauge",
+        "When the hook last ran, in seconds since the epoch.",
+    ),
+];
+
+/// Quotes a label value the way Prometheus' text format wants.
+fn label(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+/// Reads the samples in a textfile, as a map from each series (its name and
+/// labels) to its value.
+fn read_samples(contents: &str) -> BTreeMap<String, f64> {
+    contents
+        .lines()
+        .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+        .filter_map(|l| {
+            let (series, value) = l.trim().rsplit_once(' ')?;
+            Some((series.to_string(), value.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Writes the samples out again, grouped into families.
+fn write_samples(samples: &BTreeMap<String, f64>, prefix: &str) -> String {
+    let family_of = |series: &str| series.split('{').next().unwrap_or(series).to_string();
+    let mut families: BTreeMap<String, Vec<(&String, &f64)>> = BTreeMap::new();
+    for sample in samples.iter() {
+        families
+            .entry(family_of(sample.0))
+            .or_default()
+            .push(sample);
+    }
+    let mut result = String::new();
+    for (family, samples) in families.iter() {
+        let described = FAMILIES
+            .iter()
+            .find(|(name, _, _)| format!("{}_{}", prefix, name) == *family);
+        if let Some((_, kind, help)) = described {
+            result.push_str(&format!("# HELP {} {}\n", family, help));
+            result.push_str(&format!("# TYPE {} {}\n", family, kind));
+        }
+        for (series, value) in samples {
+            result.push_str(&format!("{} {}\n", series, value));
+        }
+    }
+    result
+}
+
+/// Folds the run into the samples: the counters go up, the gauges are replaced.
+fn update_samples(samples: &mut BTreeMap<String, f64>, summary: &Summary, prefix: &str) {
+    let tool = format!("tool={}", label(summary.tool()));
+    let series = |name: &str| format!("{}_{}{{{}}}", prefix, name, tool);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    *samples.entry(series("runs_total")).or_default() += 1.0;
+    *samples.entry(series("violations_total")).or_default() += summary.violations as f64;
+    for (lint, count) in summary.lints.iter() {
+        let series = format!(
+            "{}_lint_violations_total{{{},lint={}}}",
+            prefix,
+            tool,
+            label(lint)
+        );
+        *samples.entry(series).or_default() += *count as f64;
+    }
+    let gauges = [
+        ("last_run_duration_seconds", summary.elapsed().as_secs_f64()),
+        ("last_run_crates", summary.crates as f64),
+        ("last_run_files", summary.files as f64),
+        ("last_run_violations", summary.violations as f64),
+        ("last_run_timestamp_seconds", now as f64),
+    ];
+    for (name, value) in gauges {
+        samples.insert(series(name), value);
+    }
+}
+
+/// Updates the hook's series in the textfile.  The new file is written next to
+/// it then renamed into place, so the collector never reads half of one.
+fn export_textfile(path: &Path, summary: &Summary, prefix: &str) -> Result<(), String> {
+    let mut samples = read_samples(&fs::read_to_string(path).unwrap_or_default());
+    update_samples(&mut samples, summary, prefix);
+    let mut scratch = path.as_os_str().to_owned();
+    scratch.push(format!(".{}.tmp", process::id()));
+    let scratch = PathBuf::from(scratch);
+    fs::write(&scratch, write_samples(&samples, prefix))
+        .and_then(|_| fs::rename(&scratch, path))
+        .map_err(|e| {
+            drop(fs::remove_file(&scratch));
+            e.to_string()
+        })
+}
+
+/// The statsd lines for the run.
+fn statsd_lines(summary: &Summary, prefix: &str) -> Vec<String> {
+    // Dots separate the parts of a statsd name, and colons its value
+    let clean = |s: &str| s.replace("::", ".").replace([' ', '-', ':'], "_");
+    let name = format!("{}.{}", clean(prefix), clean(summary.tool()));
+    let mut result = vec![
+        format!("{}.runs:1|c", name),
+        format!("{}.duration:{}|ms", name, summary.elapsed().as_millis()),
+        format!("{}.crates:{}|g", name, summary.crates),
+        format!("{}.files:{}|g", name, summary.files),
+        format!("{}.violations:{}|c", name, summary.violations),
+    ];
+    for (lint, count) in summary.lints.iter() {
+        result.push(format!("{}.lint.{}:{}|c", name, clean(lint), count));
+    }
+    result
+}
+
+/// Sends the run's metrics to the statsd server, packing as many lines into each
+/// packet as fit.
+fn export_statsd(address: &str, summary: &Summary, prefix: &str) -> Result<(), String> {
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| e.to_string())?;
+    let mut packets: Vec<String> = Vec::new();
+    for line in statsd_lines(summary, prefix) {
+        match packets.last_mut() {
+            Some(packet) if packet.len() + 1 + line.len() <= STATSD_PACKET => {
+                packet.push('\n');
+                packet.push_str(&line);
+            }
+            _ => packets.push(line),
+        }
+    }
+    for packet in packets {
+        socket
+            .send_to(packet.as_bytes(), address)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Exports the run according to the given config.
+pub fn export_to(summary: &Summary, config: &MetricsConfig) {
+    if let Some(textfile) = &config.textfile {
+        let path = match find_repo_root() {
+            Some(root) => root.join(textfile),
+            None => PathBuf::from(textfile),
+        };
+        if let Err(e) = export_textfile(&path, summary, &config.prefix) {
+            eprintln!("Unable to write metrics to {}: {}", path.display(), e);
+        }
+    }
+    if let Some(address) = &config.statsd {
+        if let Err(e) = export_statsd(address, summary, &config.prefix) {
+            eprintln!("Unable to send metrics to {}: {}", address, e);
+        }
+    }
+}
+
+/// Exports the run wherever salt-spray.toml says to, if anywhere.  A config that
+/// doesn't load is left for the hook itself to complain about.
+pub fn export(summary: &Summary) {
+    if let Ok(config) = Config::load() {
+        export_to(summary, &config.metrics);
+    }
+}
@@ -0,0 +1,106 @@
+//! Passing on what other tools (cargo, clippy, rustfmt) print, without garbling
+//! the terminal or pre-commit's logs.
+//!
+//! Their output is decoded leniently (invalid UTF-8 becomes `�`), CRLFs become
+//! plain newlines, and a line that was redrawn with a bare carriage return (as
+//! progress bars do) keeps only what it was redrawn to.  ANSI escapes are dropped,
+//! apart from colors when they're wanted: `--color=always` keeps them, `never`
+//! drops them, and `auto` (the default) keeps them only when stderr is a terminal.
+//! Anything else that moves the cursor, clears the screen, or sets the window
+//! title never makes it through, and kept colors are always reset at the end.
+
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// The flag that says whether to keep colors, `--color=auto|always|never`.
+pub const COLOR: &str = "--color=";
+
+/// Whether to keep the colors in other tools' output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Color {
+    /// Only when stderr is a terminal.
+    #[default]
+    Auto,
+    /// Always, e.g. for CI logs that render colors.
+    Always,
+    /// Never.
+    Never,
+}
+
+static COLOR_MODE: AtomicU8 = AtomicU8::new(0);
+
+/// Sets whether to keep colors for the rest of the process, from the value of
+/// `--color=`, or says why the value isn't one.
+pub fn set_color(value: &str) -> Result<(), String> {
+    let color = match value {
+        "auto" => Color::Auto,
+        "always" => Color::Always,
+        "never" => Color::Never,
+        _ => {
+            return Err(format!(
+                "{} must be one of auto, always, or never, not {}",
+                COLOR, value
+            ))
+        }
+    };
+    COLOR_MODE.store(color as u8, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Should colors be kept?
+pub fn keeps_color() -> bool {
+    match COLOR_MODE.load(Ordering::Relaxed) {
+        1 => true,
+        2 => false,
+        _ => io::stderr().is_terminal(),
+    }
+}
+
+/// Decodes and sanitizes another tool's output, see the module docs.
+pub fn clean(bytes: &[u8]) -> String {
+    sanitize(&String::from_utf8_lossy(bytes), keeps_color())
+}
+
+/// Sanitizes text, keeping its colors (and nothing else ANSI) if asked to.
+pub fn sanitize(text: &str, keep_color: bool) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut colored = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' if chars.peek() == Some(&'\n') => (),
+            // What the terminal would show is whatever the line was redrawn to
+            '\r' => result.truncate(result.rfind('\n').map_or(0, |i| i + 1)),
+            '\u{1b}' if chars.peek() == Some(&'[') => {
+                chars.next();
+                let mut sequence = String::from("\u{1b}[");
+                for c in chars.by_ref() {
+                    sequence.push(c);
+                    if ('\u{40}'..='\u{7e}').contains(&c) {
+                        break;
+                    }
+                }
+                if keep_color && sequence.ends_with('m') {
+                    result.push_str(&sequence);
+                    colored = true;
+                }
+            }
+            '\u{1b}' if chars.peek() == Some(&']') => {
+                // Operating system commands (e.g. window titles) end with BEL or ESC \
+                while let Some(c) = chars.next() {
+                    if c == '\u{7}' || (c == '\u{1b}' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            '\u{1b}' => drop(chars.next()),
+            '\n' | '\t' => result.push(c),
+            c if c.is_control() => (),
+            c => result.push(c),
+        }
+    }
+    if colored {
+        result.push_str("\u{1b}[0m");
+    }
+    result
+}
@@ -0,0 +1,2233 @@
+//! The Warning Ratchet is a linter that ensures that the number of allowed warnings
+//! does not increase.
+//!
+//! It does that by parsing every file in the change, and counting each warning listed
+//! in an #[allow(lint)] block, and then comparing those totals to the previous totals
+//! stored in .therug.yaml .  If the totals match the ratchet does nothing.  If the
+//! new total of some lint has increased, the ratchet rejects that commit and lets the
+//! user know why.  If OTOH the new total has decreased the ratchet clicks and updates
+//! .therug.yaml to have fewer warnings swept under it.
+//!
+//! Files are tracked in .therug.yaml by the name of the package they belong to and
+//! their path within it (e.g. `my-crate:src/lib.rs`), so that crates with
+//! identically named files can't collide.  Shamefiles from before that are
+//! converted the first time the ratchet sees them.
+//!
+//! Suppressions are counted per item: each lint an item allows counts once, no
+//! matter how many of its `#[allow(...)]`s (or `#[cfg_attr(..., allow(...))]`s)
+//! list it, so `#[allow(dead_code, dead_code)]` and two `#[allow(dead_code)]`s on
+//! the same fn both count as one.  Allowing a lint on a module, impl, extern
+//! block, or the whole file counts once for every item it covers, and items in an
+//! inline module are counted in their own right on top of that.
+//!
+//! Clippy's lints are only counted when `[ratchet.clippy]` in salt-spray.toml has
+//! `track = true`, in which case every `allow(clippy::...)` counts, including the
+//! ones on single statements and expressions, once per attribute.  Independently
+//! of that, its `forbidden` list names clippy lints (or whole groups, like
+//! `restriction`) that may never be allowed anywhere:
+//!
+//! ```toml
+//! [ratchet.clippy]
+//! track = true
+//! forbidden = ["clippy::unwrap_used", "clippy::correctness"]
+//! ```
+//!
+//! Both apply just the same when `salt` runs the ratchet along with clippy.
+//!
+//! Some derive and attribute macros generate code that needs an allow on the item
+//! they're used on, which the ratchet would otherwise count against whoever used
+//! the macro.  Allows on items using the macros in `ignore-allows-on` aren't
+//! counted at all:
+//!
+//! ```toml
+//! [ratchet]
+//! ignore-allows-on = ["Builder", "builder", "serde_with::serde_as"]
+//! ```
+//!
+//! In strict mode (`forbid-inner-allows = true` under `[ratchet]`, or
+//! `warning-ratchet --strict`) a file or inline module may not gain any new
+//! `#![allow(...)]`, since those cover far more than the code that needs them.
+//! Allow the lint on the item (or statement) instead.  Inner allows of lints the
+//! file's entry in .therug.yaml already has are grandfathered.
+//!
+//! Allowing a lint for a whole crate is the same as suppressing it everywhere, so
+//! the ratchet counts those too.  A Cargo.toml's `[lints]` and `[workspace.lints]`
+//! entries set to `allow` (or `expect`) and the `-A`s in the rustflags of cargo's
+//! .cargo/config.toml each count once, in their own file's entry, and a
+//! `--cap-lints` among those rustflags counts as allowing `cap-lints`, since it
+//! downgrades every lint at once.  (`RUSTFLAGS` in the environment is never
+//! committed, so it's not the ratchet's business.)
+//!
+//! Organizations that want a say in who adds suppressions can name approvers, in
+//! which case `--accept` and `UPDATE_ANYWAY` no longer do anything on their own.
+//! Increases are accepted when the commit message has a trailer naming one of the
+//! approvers instead, e.g. `Ratchet-Approved-By: Jo Doe <jo@example.com>`:
+//!
+//! ```toml
+//! [ratchet.approval]
+//! approvers = ["jo@example.com", "Sam Roe"]
+//! trailer = "Ratchet-Approved-By"
+//! ```
+//!
+//! Since the hook has to see the message, it then runs at pre-commit's
+//! `commit-msg` stage (see `warning-ratchet --commit-msg`).  In CI, with `--since`,
+//! each commit since that revision that grew the shamefile needs to carry the
+//! trailer itself, which catches commits that never ran the hook (or edited the
+//! shamefile by hand).  The trailer is only as trustworthy as the
+//! review that lets it through, so pair this with protected branches.
+//!
+//! Paying down lint debt can leave a trail that release tooling adds up.  With a
+//! trailer named, `warning-ratchet --prepare-commit-msg` (in pre-commit's
+//! `prepare-commit-msg` stage) adds one to the message of each commit whose staged
+//! shamefile has fewer suppressions of a lint than HEAD's, e.g. `Lint-Debt:
+//! dead_code -2`, see [`debt_paid`]:
+//!
+//! ```toml
+//! [ratchet]
+//! debt-trailer = "Lint-Debt"
+//! ```
+//!
+//! The ratchet can hold the size of files back too, so that oversized ones get
+//! split up bit by bit rather than growing forever.  With `max-lines` set no file
+//! may grow past that many lines, and the ones that already have are tracked under
+//! `sizes` in .therug.yaml, which they may shrink but not outgrow.  Files that were
+//! over it at HEAD before the shamefile tracked them (e.g. when `max-lines` is
+//! first set) start out tracked at their size then, rather than failing:
+//!
+//! ```toml
+//! [ratchet]
+//! max-lines = 1000
+//! ```
+//!
+//! Long-lived branches can ratchet against another branch's shamefile rather than
+//! their own, so that they don't have to keep up with every suppression main
+//! gains or loses.  On a branch matching one of the patterns, the baseline is
+//! what `git show main:.therug.yaml` says (and the working tree's when main has
+//! none), and that's what clicking or accepting then writes back, with the
+//! branch's changes:
+//!
+//! ```toml
+//! [ratchet.branches]
+//! patterns = ["experimental/*"]
+//! reference = "main"
+//! ```
+//!
+//! Not every suppression is as bad as every other, so lints can be given weights,
+//! in which case the ratchet compares what each file's suppressions weigh all
+//! together rather than each lint's count.  A file can then trade suppressions,
+//! e.g. swap an `allow(unsafe_code)` for a few `allow(dead_code)`s, but not the
+//! other way around.  Lints that aren't listed weigh `default`, and with `per =
+//! "crate"` the totals are compared crate by crate, so suppressions can move
+//! between the files of a crate as well:
+//!
+//! ```toml
+//! [ratchet.weights]
+//! lints = { unsafe_code = 10, dead_code = 1 }
+//! default = 1
+//! per = "file"
+//! ```
+//!
+//! Lints get renamed now and then (`clippy::stutter` became
+//! `clippy::module_name_repetitions`, `bare_trait_object` became
+//! `bare_trait_objects`), and swapping an old name for the new one isn't a new
+//! suppression.  The ratchet counts and compares every lint under its current
+//! name, and writes that name into .therug.yaml the next time it updates it.  It
+//! knows the renames of rustc's and clippy's lints, and `renamed-lints` adds more
+//! (or, mapping a lint to itself, undoes one):
+//!
+//! ```toml
+//! [ratchet.renamed-lints]
+//! "clippy::old_name" = "clippy::new_name"
+//! ```
+//!
+//! .therug.yaml is a good place to say why suppressions are there, so comments in
+//! it survive the ratchet rewriting it.  A comment stays with the entry it's on
+//! the line of or just above (and goes when the entry does), and comments after
+//! the last entry stay at the end:
+//!
+//! ```yaml
+//! lints:
+//!   my-crate:src/ffi.rs:
+//!     # The bindings are generated, see #123
+//!     non_camel_case_types: 4
+//!     dead_code: 1  # Only used on Windows
+//! ```
+//!
+
+use std::cmp::{max, min};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use serde::{Deserialize, Serialize};
+use syn::spanned::Spanned;
+use syn::visit::{self, Visit};
+use syn::{AttrStyle, Attribute, Item, Meta, NestedMeta};
+
+use crate::check::{Check, Violation};
+use crate::config::{explain, glob_matches, Config, WeightScope};
+use crate::debug;
+use crate::jobs;
+use crate::manifest::is_manifest;
+use crate::toolchain::lint_groups;
+use crate::{
+    attrs_of, find_manifest, find_repo_root, lint_table_levels, package_name, parse_source,
+    read_committed, say,
+};
+
+/// Where the baseline of suppressed lints lives.
+#[allow(dead_code)]
+pub const SHAMEFILE: &str = ".therug.yaml";
+
+/// Lints that rustc and clippy have renamed, from the old name to the new one.
+static RENAMED_LINTS: [(&str, &str); 44] = [
+    ("async_idents", "keyword_idents"),
+    ("bare_trait_object", "bare_trait_objects"),
+    (
+        "disjoint_capture_migration",
+        "rust_2021_incompatible_closure_captures",
+    ),
+    ("exceeding_bitshifts", "arithmetic_overflow"),
+    ("non_fmt_panic", "non_fmt_panics"),
+    (
+        "or_patterns_back_compat",
+        "rust_2021_incompatible_or_patterns",
+    ),
+    ("overlapping_patterns", "overlapping_range_endpoints"),
+    ("redundant_semicolon", "redundant_semicolons"),
+    ("unstable_name_collision", "unstable_name_collisions"),
+    ("unused_doc_comment", "unused_doc_comments"),
+    ("unused_tuple_struct_fields", "dead_code"),
+    (
+        "clippy::almost_complete_letter_range",
+        "clippy::almost_complete_range",
+    ),
+    (
+        "clippy::blocks_in_if_conditions",
+        "clippy::blocks_in_conditions",
+    ),
+    ("clippy::box_vec", "clippy::box_collection"),
+    (
+        "clippy::const_static_lifetime",
+        "clippy::redundant_static_lifetimes",
+    ),
+    (
+        "clippy::cyclomatic_complexity",
+        "clippy::cognitive_complexity",
+    ),
+    (
+        "clippy::derive_hash_xor_eq",
+        "clippy::derived_hash_with_manual_eq",
+    ),
+    ("clippy::disallowed_method", "clippy::disallowed_methods"),
+    ("clippy::disallowed_type", "clippy::disallowed_types"),
+    ("clippy::drop_copy", "dropping_copy_types"),
+    ("clippy::drop_ref", "dropping_references"),
+    (
+        "clippy::eval_order_dependence",
+        "clippy::mixed_read_write_in_expression",
+    ),
+    ("clippy::forget_copy", "forgetting_copy_types"),
+    ("clippy::forget_ref", "forgetting_references"),
+    ("clippy::identity_conversion", "clippy::useless_conversion"),
+    (
+        "clippy::incorrect_clone_impl_on_copy_type",
+        "clippy::non_canonical_clone_impl",
+    ),
+    (
+        "clippy::integer_arithmetic",
+        "clippy::arithmetic_side_effects",
+    ),
+    ("clippy::into_iter_on_array", "array_into_iter"),
+    ("clippy::invalid_ref", "invalid_value"),
+    ("clippy::logic_bug", "clippy::overly_complex_bool_expr"),
+    ("clippy::maybe_misused_cfg", "unexpected_cfgs"),
+    ("clippy::mem_uninitialized", "invalid_value"),
+    ("clippy::mismatched_target_os", "unexpected_cfgs"),
+    (
+        "clippy::new_without_default_derive",
+        "clippy::new_without_default",
+    ),
+    ("clippy::option_expect_used", "clippy::expect_used"),
+    ("clippy::option_unwrap_used", "clippy::unwrap_used"),
+    ("clippy::panic_params", "non_fmt_panics"),
+    ("clippy::result_expect_used", "clippy::expect_used"),
+    ("clippy::result_unwrap_used", "clippy::unwrap_used"),
+    (
+        "clippy::single_char_push_str",
+        "clippy::single_char_add_str",
+    ),
+    ("clippy::stutter", "clippy::module_name_repetitions"),
+    (
+        "clippy::to_string_in_display",
+        "clippy::recursive_format_impl",
+    ),
+    ("clippy::unknown_clippy_lints", "unknown_lints"),
+    ("clippy::zero_width_space", "clippy::invisible_characters"),
+];
+
+/// What the lint is called now, following its renames, from salt-spray.toml's
+/// `renamed-lints` and then [`RENAMED_LINTS`].  A clippy lint that became one of
+/// rustc's keeps its old name when clippy's lints aren't tracked, since it's then
+/// not counted either way.
+fn current_name(lint: &str, config: &Config) -> String {
+    let renamed = &config.ratchet.renamed_lints;
+    let mut name = lint.to_string();
+    let mut seen = BTreeSet::new();
+    while let Some(next) = renamed.get(&name).cloned().or_else(|| {
+        RENAMED_LINTS
+            .iter()
+            .find(|(old, _)| *old == name)
+            .map(|(_, new)| new.to_string())
+    }) {
+        // A hand-written rename can go around in circles
+        if !seen.insert(name.clone()) {
+            break;
+        }
+        name = next;
+    }
+    if lint.starts_with("clippy::") && !name.starts_with("clippy::") && !config.ratchet.clippy.track
+    {
+        return lint.to_string();
+    }
+    name
+}
+
+#[allow(dead_code, unused_mut, unused_imports)]
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SupressedLints {
+    lints: BTreeMap<String, BTreeMap<String, usize>>,
+    /// The repo-wide count of each lint, as of the last time the rug was swept.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    totals: BTreeMap<String, usize>,
+    /// How many lines each file that's over `max-lines` may have.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    sizes: BTreeMap<String, usize>,
+    /// How many lines each of the files we looked at has, by key.
+    #[serde(skip)]
+    line_counts: BTreeMap<String, usize>,
+    /// Where the file behind each key in lints actually is, for the files we looked at.
+    #[serde(skip)]
+    paths: BTreeMap<String, PathBuf>,
+    /// Every clippy lint allowed anywhere in the files we looked at, with the line
+    /// it's allowed on, by key.
+    #[serde(skip)]
+    clippy_allows: BTreeMap<String, Vec<(String, usize)>>,
+    /// Every lint the ratchet counts that's allowed anywhere in the files we looked
+    /// at, with its line, by key.
+    #[serde(skip)]
+    allows: BTreeMap<String, Vec<(String, usize)>>,
+    /// Every lint allowed by an inner attribute (`#![allow(...)]`) of a file or
+    /// inline module in the files we looked at, with its line, by key.
+    #[serde(skip)]
+    inner_allows: BTreeMap<String, Vec<(String, usize)>>,
+    /// The lints in the entries that were renamed, old name to new.
+    #[serde(skip)]
+    renames: BTreeMap<String, String>,
+}
+
+enum Relationship {
+    Expected,
+    ProperSubset,
+    NotASubset,
+}
+
+impl Relationship {
+    /// How the whole relates, given how two of its parts do.
+    fn and(self, other: Relationship) -> Relationship {
+        match (self, other) {
+            (Relationship::NotASubset, _) | (_, Relationship::NotASubset) => {
+                Relationship::NotASubset
+            }
+            (Relationship::ProperSubset, _) | (_, Relationship::ProperSubset) => {
+                Relationship::ProperSubset
+            }
+            _ => Relationship::Expected,
+        }
+    }
+}
+
+fn read_file<S: AsRef<OsStr>>(filename: S) -> Option<String> {
+    File::open(filename.as_ref())
+        .map(|mut file| {
+            let mut result = String::new();
+            file.read_to_string(&mut result).unwrap();
+            result
+        })
+        .ok()
+}
+
+#[allow(unused_mut)]
+fn look_under_therug() -> Result<SupressedLints, String> {
+    // TODO (mrd): this should probably only be a default, test an env var first
+    read_therug(SHAMEFILE, read_file(SHAMEFILE))
+}
+
+/// The branch whose shamefile the current branch ratchets against, if
+/// `[ratchet.branches]` covers it.
+fn reference_branch(config: &Config) -> Option<&str> {
+    let branches = &config.ratchet.branches;
+    if branches.patterns.is_empty() {
+        return None;
+    }
+    let output = Command::new("git")
+        .args(["symbolic-ref", "--short", "-q", "HEAD"])
+        .output()
+        .ok()?;
+    let branch = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let covered = branches
+        .patterns
+        .iter()
+        .any(|g| glob_matches(g, Path::new(&branch)));
+    if output.status.success() && covered && branch != branches.reference {
+        Some(&branches.reference)
+    } else {
+        None
+    }
+}
+
+/// The shamefile to ratchet against, which is the reference branch's on the
+/// branches `[ratchet.branches]` covers, and otherwise the one in the working tree.
+fn baseline_under_therug(config: &Config) -> Result<SupressedLints, String> {
+    if let Some(reference) = reference_branch(config) {
+        match read_committed(reference, SHAMEFILE) {
+            Some(contents) => {
+                say!("Ratcheting against {}'s {}", reference, SHAMEFILE);
+                return read_therug(&format!("{}:{}", reference, SHAMEFILE), Some(contents));
+            }
+            None => say!(
+                "{} has no {}, so ratcheting against the working tree's",
+                reference,
+                SHAMEFILE
+            ),
+        }
+    }
+    look_under_therug()
+}
+
+/// Parses a shamefile, dumping it for a bug report when it doesn't parse (see
+/// `crate::debug`).
+fn read_therug(name: &str, contents: Option<String>) -> Result<SupressedLints, String> {
+    let contents = contents.as_deref();
+    let dump_name = SHAMEFILE.trim_start_matches('.');
+    parse_therug(name, contents)
+        .map_err(|e| debug::with_dump(e, dump_name, contents.unwrap_or_default()))
+}
+
+/// Parses a shamefile, which `name` says where it came from in errors.
+fn parse_therug(name: &str, contents: Option<&str>) -> Result<SupressedLints, String> {
+    let contents = match contents {
+        Some(contents) => contents,
+        None => return Ok(Default::default()),
+    };
+    serde_yaml::from_str(contents).map_err(|e| {
+        let location = e
+            .location()
+            .map(|l| format!(":{}:{}", l.line(), l.column()))
+            .unwrap_or_default();
+        // serde_yaml tacks the location onto the message, but we lead with it
+        let message = e.to_string();
+        let message = message.split(" at line ").next().unwrap_or(&message);
+        format!("{}{}: {}", name, location, explain(message))
+    })
+}
+
+/// The key a file is tracked under in the shamefile, which is its owning package's
+/// name and its path within that package.  Files outside of any package are
+/// tracked by their path alone.
+fn lint_key(filename: &str) -> String {
+    let path = Path::new(filename);
+    find_manifest(filename)
+        .and_then(|manifest| {
+            let name = package_name(&manifest)?;
+            let within = path.strip_prefix(manifest.parent()?).ok()?;
+            let within: Vec<_> = within.iter().map(|c| c.to_string_lossy()).collect();
+            Some(format!("{}:{}", name, within.join("/")))
+        })
+        .unwrap_or_else(|| filename.to_string())
+}
+
+/// The comments in a shamefile, by the path of keys to the entry they belong to.
+#[derive(Debug, Default)]
+struct Comments {
+    /// The comment lines just above each entry, with the blank lines after them.
+    above: BTreeMap<Vec<String>, Vec<String>>,
+    /// The comment at the end of each entry's line.
+    beside: BTreeMap<Vec<String>, String>,
+    /// The comments after the last entry.
+    end: Vec<String>,
+}
+
+impl Comments {
+    /// Moves the comments on lints that were renamed, old name to new, over to
+    /// the new name.
+    fn rename(&mut self, renames: &BTreeMap<String, String>) {
+        let renamed = |mut keys: Vec<String>| {
+            let counted = matches!(keys.first().map(String::as_str), Some("lints" | "totals"));
+            if let Some(new) = keys.last().and_then(|k| renames.get(k)).filter(|_| counted) {
+                // unwrap here is safe since the key was just looked at
+                *keys.last_mut().unwrap() = new.clone();
+            }
+            keys
+        };
+        self.above = std::mem::take(&mut self.above)
+            .into_iter()
+            .map(|(keys, comments)| (renamed(keys), comments))
+            .collect();
+        self.beside = std::mem::take(&mut self.beside)
+            .into_iter()
+            .map(|(keys, comment)| (renamed(keys), comment))
+            .collect();
+    }
+}
+
+/// Splits a line of a shamefile into its indentation, the key on it (unquoted),
+/// and the comment at the end of it, if the line has a key on it at all.
+fn split_entry(line: &str) -> Option<(usize, String, Option<String>)> {
+    let rest = line.trim_start();
+    let indent = line.len() - rest.len();
+    if rest.is_empty() || rest.starts_with('#') {
+        return None;
+    }
+    let (key, after) = match rest.chars().next() {
+        Some(quote @ ('\'' | '"')) => {
+            let end = rest[1..].find(quote)? + 1;
+            (rest[1..end].to_string(), rest[end + 1..].strip_prefix(':')?)
+        }
+        _ => match rest.find(": ") {
+            Some(i) => (rest[..i].to_string(), &rest[i + 1..]),
+            None => (rest.strip_suffix(':')?.to_string(), ""),
+        },
+    };
+    let comment = after.find('#').map(|i| after[i..].to_string());
+    Some((indent, key, comment))
+}
+
+/// Gathers up the comments in a shamefile.
+fn read_comments(contents: &str) -> Comments {
+    let mut result = Comments::default();
+    let mut path: Vec<(usize, String)> = Vec::new();
+    let mut pending: Vec<String> = Vec::new();
+    for line in contents.lines() {
+        let (indent, key, comment) = match split_entry(line) {
+            Some(entry) => entry,
+            None => {
+                // Blank lines only matter between comments
+                if line.trim().starts_with('#') || !pending.is_empty() {
+                    pending.push(line.trim().to_string());
+                }
+                continue;
+            }
+        };
+        while path.last().is_some_and(|(i, _)| *i >= indent) {
+            path.pop();
+        }
+        path.push((indent, key));
+        let keys: Vec<String> = path.iter().map(|(_, k)| k.clone()).collect();
+        if !pending.is_empty() {
+            result
+                .above
+                .insert(keys.clone(), std::mem::take(&mut pending));
+        }
+        if let Some(comment) = comment {
+            result.beside.insert(keys, comment);
+        }
+    }
+    while pending.last().is_some_and(String::is_empty) {
+        pending.pop();
+    }
+    result.end = pending;
+    result
+}
+
+/// Puts the comments back into a freshly written shamefile, next to the entries
+/// they belong to.
+fn restore_comments(contents: &str, comments: &Comments) -> String {
+    let mut result = String::new();
+    let mut path: Vec<(usize, String)> = Vec::new();
+    for line in contents.lines() {
+        if let Some((indent, key, _)) = split_entry(line) {
+            while path.last().is_some_and(|(i, _)| *i >= indent) {
+                path.pop();
+            }
+            path.push((indent, key));
+            let keys: Vec<String> = path.iter().map(|(_, k)| k.clone()).collect();
+            for comment in comments.above.get(&keys).into_iter().flatten() {
+                if comment.is_empty() {
+                    result.push('\n');
+                } else {
+                    result.push_str(&format!("{:indent$}{}\n", "", comment, indent = indent));
+                }
+            }
+            match comments.beside.get(&keys) {
+                Some(comment) => result.push_str(&format!("{}  {}\n", line, comment)),
+                None => result.push_str(&format!("{}\n", line)),
+            }
+        } else {
+            result.push_str(&format!("{}\n", line));
+        }
+    }
+    for comment in comments.end.iter() {
+        result.push_str(&format!("{}\n", comment));
+    }
+    result
+}
+
+/// The shamefile as the ratchet writes it, totals and all, with the comments of
+/// the one it replaces.
+fn swept(lints: &mut SupressedLints, old: &str) -> String {
+    lints.update_totals();
+    let contents = serde_yaml::to_string(&lints).unwrap();
+    let mut comments = read_comments(old);
+    comments.rename(&lints.renames);
+    restore_comments(&contents, &comments)
+}
+
+/// Reads a shamefile and writes it back out the way the ratchet would after a run
+/// that didn't change any of its counts, or says why it can't be read.
+pub fn rewrite_shamefile(contents: &str) -> Result<String, String> {
+    let mut lints = parse_therug(SHAMEFILE, Some(contents))?;
+    Ok(swept(&mut lints, contents))
+}
+
+fn sweep_under_therug(lints: &mut SupressedLints) {
+    let contents = swept(lints, &read_file(SHAMEFILE).unwrap_or_default());
+    // TODO (mrd): this should probably only be a default, test an env var first
+    let mut file = File::create(SHAMEFILE).unwrap();
+    file.write_all(contents.as_bytes()).unwrap();
+}
+
+/// Parses the Rust files, as many at once as `--jobs` allows (see
+/// [`crate::jobs`]), each into lints of its own.  They come back in the order
+/// they were given, so merging them goes the same way every time.
+fn parse_in_parallel(filenames: &[String], config: &Config) -> Vec<SupressedLints> {
+    let parse = |filename: &String| {
+        let mut lints = SupressedLints::default();
+        lints.load_suppressed_lints_from(filename, config);
+        lints
+    };
+    match ThreadPoolBuilder::new().num_threads(jobs::jobs()).build() {
+        Ok(pool) => pool.install(|| filenames.par_iter().map(parse).collect()),
+        // Without threads it's slower, but no different
+        Err(_) => filenames.iter().map(parse).collect(),
+    }
+}
+
+fn find_supressed_lints<S: AsRef<OsStr>>(filenames: &[S], config: &Config) -> SupressedLints {
+    let mut result = SupressedLints::default();
+    let mut rust_files = Vec::new();
+    let mut names: Vec<String> = Vec::new();
+    for name in filenames {
+        let path = Path::new(&name);
+        if path.is_dir() && !config.is_excluded(path) {
+            let found = files_under(path);
+            let rust = found.iter().filter(|f| f.ends_with(".rs")).count();
+            say!(
+                "Scanning the {} Rust file(s) under {}",
+                rust,
+                path.display()
+            );
+            names.extend(found);
+        } else {
+            names.push(name.as_ref().to_string_lossy().into_owned());
+        }
+    }
+    for filename in names {
+        let path = Path::new(&filename);
+        if config.is_excluded(path) {
+            continue;
+        } else if path.extension().map(|e| e == "rs").unwrap_or(false) {
+            rust_files.push(filename);
+        } else if is_lint_config(path) {
+            result.load_crate_wide_allows_from(&filename, config);
+        }
+    }
+    for lints in parse_in_parallel(&rust_files, config) {
+        result.absorb(lints);
+    }
+    if config.ratchet.clippy.track {
+        for (key, allows) in result.clippy_allows.iter() {
+            let lints = result.lints.entry(key.clone()).or_default();
+            for (lint, _) in allows {
+                *lints.entry(lint.clone()).or_default() += 1;
+            }
+        }
+    }
+    result.rename_lints(config);
+    result
+}
+
+/// The files under a directory handed to the ratchet (e.g. by a hook with
+/// `pass_filenames: false`), as git sees them, so that ignored ones are left out.
+/// Outside of a git repo the directory is walked instead, skipping build output
+/// and hidden directories other than .cargo.
+fn files_under(dir: &Path) -> Vec<String> {
+    let output = Command::new("git")
+        .args([
+            "ls-files",
+            "--cached",
+            "--others",
+            "--exclude-standard",
+            "-z",
+            "--",
+        ])
+        .arg(dir)
+        .output();
+    match output {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .split('\0')
+            .filter(|f| !f.is_empty() && Path::new(f).is_file())
+            .map(str::to_string)
+            .collect(),
+        _ => {
+            let mut result = Vec::new();
+            walk(dir, &mut result);
+            result
+        }
+    }
+}
+
+fn walk(dir: &Path, result: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() {
+            if name != "target" && (!name.starts_with('.') || name == ".cargo") {
+                walk(&path, result);
+            }
+        } else {
+            result.push(path.to_string_lossy().into_owned());
+        }
+    }
+}
+
+/// Does the file set lint levels for whole crates?  Manifests do, with their
+/// `[lints]`, and so does cargo's config, with its rustflags.
+fn is_lint_config(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default();
+    let in_dot_cargo = path
+        .parent()
+        .and_then(Path::file_name)
+        .is_some_and(|d| d == ".cargo");
+    is_manifest(&path.to_string_lossy())
+        || (in_dot_cargo && (name == "config.toml" || name == "config"))
+}
+
+/// What a `--cap-lints` is counted as, see the module docs.
+const CAP_LINTS: &str = "cap-lints";
+
+/// The value of the flag if it's the named one, whether it's given as `-Avalue`,
+/// `--name=value`, or followed by the value as the next flag.
+fn flag_value<'a, I: Iterator<Item = &'a str>>(
+    flag: &'a str,
+    name: &str,
+    rest: &mut I,
+) -> Option<&'a str> {
+    if flag == name {
+        return rest.next();
+    }
+    let value = flag.strip_prefix(name)?;
+    if name.starts_with("--") {
+        value.strip_prefix('=')
+    } else {
+        Some(value)
+    }
+}
+
+/// Adds the lints the rustflags (a string or an array, as cargo takes them) allow
+/// to the set.
+fn collect_rustflag_allows(rustflags: &toml::Value, lints: &mut BTreeSet<String>) {
+    let flags: Vec<&str> = match rustflags {
+        toml::Value::String(flags) => flags.split_whitespace().collect(),
+        toml::Value::Array(flags) => flags.iter().filter_map(|f| f.as_str()).collect(),
+        _ => Vec::new(),
+    };
+    let mut flags = flags.into_iter();
+    while let Some(flag) = flags.next() {
+        let allowed =
+            flag_value(flag, "-A", &mut flags).or_else(|| flag_value(flag, "--allow", &mut flags));
+        if let Some(lint) = allowed {
+            lints.insert(lint.replace('-', "_"));
+        } else if let Some(cap) = flag_value(flag, "--cap-lints", &mut flags) {
+            if cap != "forbid" {
+                lints.insert(CAP_LINTS.to_string());
+            }
+        }
+    }
+}
+
+/// The lints the manifest or cargo config allows for whole crates, with the line
+/// each is (first) mentioned on.
+fn crate_wide_allows(path: &Path, contents: &str) -> Vec<(String, usize)> {
+    let table: toml::Table = match contents.parse() {
+        Ok(table) => table,
+        // Whatever reads it for real will complain about it
+        Err(_) => return Vec::new(),
+    };
+    let mut lints = BTreeSet::new();
+    if is_manifest(&path.to_string_lossy()) {
+        let workspace = table.get("workspace").and_then(|w| w.get("lints"));
+        for levels in [table.get("lints"), workspace].map(lint_table_levels) {
+            let allowed = levels
+                .into_iter()
+                .filter(|(_, level)| level == "allow" || level == "expect");
+            lints.extend(allowed.map(|(lint, _)| lint));
+        }
+    } else {
+        let build = table.get("build").and_then(|b| b.get("rustflags"));
+        let targets = table.get("target").and_then(|t| t.as_table());
+        let targets = targets.into_iter().flatten();
+        for rustflags in build
+            .into_iter()
+            .chain(targets.filter_map(|(_, t)| t.get("rustflags")))
+        {
+            collect_rustflag_allows(rustflags, &mut lints);
+        }
+    }
+    lints
+        .into_iter()
+        .map(|lint| {
+            // Manifests may spell lints with dashes
+            let name = lint.rsplit("::").next().unwrap_or(&lint).to_string();
+            let dashed = name.replace('_', "-");
+            let line = contents
+                .lines()
+                .position(|l| l.contains(&name) || l.contains(&dashed))
+                .map_or(1, |i| i + 1);
+            (lint, line)
+        })
+        .collect()
+}
+
+/// Does the path (e.g. `serde_with::serde_as`) name one of the macros?  Either
+/// may be written out in full or not.
+fn is_one_of(path: &syn::Path, macros: &[String]) -> bool {
+    let segments: Vec<String> = path.segments.iter().map(|s| s.ident.to_string()).collect();
+    let path = segments.join("::");
+    macros.iter().any(|m| {
+        *m == path || path.ends_with(&format!("::{}", m)) || m.ends_with(&format!("::{}", path))
+    })
+}
+
+/// Do the attributes use (or derive) any of the macros?
+fn uses_macro(attrs: &[Attribute], macros: &[String]) -> bool {
+    if macros.is_empty() {
+        return false;
+    }
+    attrs.iter().any(|attr| {
+        if !attr.path.is_ident("derive") {
+            return is_one_of(&attr.path, macros);
+        }
+        match attr.parse_meta() {
+            Ok(Meta::List(list)) => list.nested.iter().any(|derived| match derived {
+                NestedMeta::Meta(Meta::Path(derived)) => is_one_of(derived, macros),
+                _ => false,
+            }),
+            _ => false,
+        }
+    })
+}
+
+// #[allow(unsafe_code)]
+fn count_lints_in_attrs(
+    result: &mut BTreeMap<String, usize>,
+    attrs: &[Attribute],
+    item_count: usize,
+) {
+    let item_count = max(item_count, 1);
+    let mut lints = BTreeSet::new();
+    for attr in attrs {
+        if let Ok(meta) = attr.parse_meta() {
+            collect_allowed_lints(&mut lints, &meta);
+        }
+    }
+    // Tool lints like clippy's are counted by Allows instead
+    for lint in lints.into_iter().filter(|l| !l.contains("::")) {
+        *result.entry(lint).or_default() += item_count;
+    }
+}
+
+/// Adds the lints an attribute allows to the set, looking inside `cfg_attr`s too.
+fn collect_allowed_lints(lints: &mut BTreeSet<String>, meta: &Meta) {
+    let list = match meta {
+        Meta::List(list) => list,
+        _ => return,
+    };
+    if list.path.is_ident("allow") {
+        for lint in list.nested.iter() {
+            // hooray metaprogramming  :-/
+            if let NestedMeta::Meta(Meta::Path(lint)) = lint {
+                let segments: Vec<String> =
+                    lint.segments.iter().map(|s| s.ident.to_string()).collect();
+                lints.insert(segments.join("::"));
+            }
+        }
+    } else if list.path.is_ident("cfg_attr") {
+        // The first argument is the predicate, the rest are the attributes
+        for attr in list.nested.iter().skip(1) {
+            if let NestedMeta::Meta(meta) = attr {
+                collect_allowed_lints(lints, meta);
+            }
+        }
+    }
+}
+
+fn count_lints_in_items(result: &mut BTreeMap<String, usize>, items: &[Item], ignored: &[String]) {
+    for item in items {
+        if uses_macro(attrs_of(item), ignored) {
+            // Anything in an inline module still counts
+            if let Item::Mod(syn::ItemMod {
+                content: Some((_, items)),
+                ..
+            }) = item
+            {
+                count_lints_in_items(result, items, ignored);
+            }
+            continue;
+        }
+        match item {
+            Item::Const(c) => count_lints_in_attrs(result, &c.attrs, 1),
+            Item::Enum(c) => count_lints_in_attrs(result, &c.attrs, 1),
+            Item::ExternCrate(c) => count_lints_in_attrs(result, &c.attrs, 1),
+            Item::Fn(c) => count_lints_in_attrs(result, &c.attrs, 1),
+            Item::ForeignMod(c) => count_lints_in_attrs(result, &c.attrs, c.items.len()),
+            Item::Impl(c) => count_lints_in_attrs(result, &c.attrs, c.items.len()),
+            Item::Macro(c) => count_lints_in_attrs(result, &c.attrs, 1),
+            Item::Macro2(c) => count_lints_in_attrs(result, &c.attrs, 1),
+            Item::Mod(c) => {
+                if let Some((_, items)) = &c.content {
+                    count_lints_in_attrs(result, &c.attrs, items.len());
+                    count_lints_in_items(result, items, ignored);
+                } else {
+                    count_lints_in_attrs(result, &c.attrs, 1);
+                }
+            }
+            Item::Static(c) => count_lints_in_attrs(result, &c.attrs, 1),
+            Item::Struct(c) => count_lints_in_attrs(result, &c.attrs, 1),
+            Item::Trait(c) => count_lints_in_attrs(result, &c.attrs, 1),
+            Item::TraitAlias(c) => count_lints_in_attrs(result, &c.attrs, 1),
+            Item::Type(c) => count_lints_in_attrs(result, &c.attrs, 1),
+            Item::Union(c) => count_lints_in_attrs(result, &c.attrs, 1),
+            Item::Use(c) => count_lints_in_attrs(result, &c.attrs, 1),
+            Item::Verbatim(_) => (),
+            _ => (),
+        }
+    }
+}
+
+fn count_suppressed_lints(ast: &syn::File, ignored: &[String]) -> BTreeMap<String, usize> {
+    let mut result = BTreeMap::<String, usize>::default();
+    count_lints_in_attrs(&mut result, &ast.attrs, ast.items.len());
+    count_lints_in_items(&mut result, &ast.items, ignored);
+    result
+}
+
+/// Collects the lints allowed by the inner attributes of the file and of the
+/// inline modules in it, with the line each is allowed on.
+fn collect_inner_allows(attrs: &[Attribute], items: &[Item], found: &mut Vec<(String, usize)>) {
+    for attr in attrs
+        .iter()
+        .filter(|a| matches!(a.style, AttrStyle::Inner(_)))
+    {
+        if let Ok(meta) = attr.parse_meta() {
+            let mut lints = BTreeSet::new();
+            collect_allowed_lints(&mut lints, &meta);
+            let line = attr.span().start().line;
+            found.extend(lints.into_iter().map(|lint| (lint, line)));
+        }
+    }
+    for item in items {
+        if let Item::Mod(m) = item {
+            if let Some((_, items)) = &m.content {
+                collect_inner_allows(&m.attrs, items, found);
+            }
+        }
+    }
+}
+
+/// Collects every `allow(...)` in a file, wherever it is, down to single
+/// statements and expressions, except on items using the ignored macros.
+#[derive(Default)]
+struct Allows<'a> {
+    found: Vec<(String, usize)>,
+    ignored: &'a [String],
+    /// Where the attributes of items using the ignored macros start.
+    skipped: BTreeSet<(usize, usize)>,
+}
+
+fn start_of(attr: &Attribute) -> (usize, usize) {
+    let start = attr.span().start();
+    (start.line, start.column)
+}
+
+impl<'a, 'ast> Visit<'ast> for Allows<'a> {
+    fn visit_item(&mut self, item: &'ast Item) {
+        if uses_macro(attrs_of(item), self.ignored) {
+            self.skipped.extend(attrs_of(item).iter().map(start_of));
+        }
+        visit::visit_item(self, item);
+    }
+
+    fn visit_attribute(&mut self, attr: &'ast Attribute) {
+        if self.skipped.contains(&start_of(attr)) {
+            return;
+        }
+        if let Ok(meta) = attr.parse_meta() {
+            let mut lints = BTreeSet::new();
+            collect_allowed_lints(&mut lints, &meta);
+            let line = attr.span().start().line;
+            self.found
+                .extend(lints.into_iter().map(|lint| (lint, line)));
+        }
+        visit::visit_attribute(self, attr);
+    }
+}
+
+/// Why the clippy lint may not be allowed at all, if the config forbids it.  The
+/// config may name the lint itself, or one of clippy's groups that it's in.
+fn forbidden_because(lint: &str, config: &Config) -> Option<String> {
+    let forbidden: Vec<&str> = config
+        .ratchet
+        .clippy
+        .forbidden
+        .iter()
+        .map(|f| f.trim_start_matches("clippy::"))
+        .collect();
+    if forbidden.is_empty() {
+        return None;
+    } else if forbidden.contains(&lint.trim_start_matches("clippy::")) {
+        return Some(String::new());
+    }
+    let groups = lint_groups();
+    forbidden
+        .iter()
+        .find(|f| groups.get(**f).is_some_and(|lints| lints.contains(lint)))
+        .map(|group| format!(" (it's in clippy::{})", group))
+}
+
+/// Counts the suppressed lints in the given source the way the ratchet does (see
+/// the module docs), or returns why it doesn't parse.
+pub fn count_suppressions(contents: &str) -> syn::Result<BTreeMap<String, usize>> {
+    parse_source(contents).map(|ast| count_suppressed_lints(&ast, &[]))
+}
+
+/// Compares the repo-wide totals against the budgets in the config, returning the
+/// budgets that were exceeded.
+fn check_budgets(totals: &BTreeMap<String, usize>, config: &Config) -> Vec<Violation> {
+    let today = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| (d.as_secs() / 86400) as i64)
+        .unwrap_or_default();
+    let mut result = Vec::new();
+    for (lint, budget) in config.ratchet.budget.iter() {
+        if let Some(allowed) = budget.allowed_on(today) {
+            let total = totals
+                .get(&current_name(lint, config))
+                .copied()
+                .unwrap_or_default();
+            if total > allowed {
+                result.push(Violation::general(format!(
+                    "allow({}) appears {} times repo-wide, but the budget only allows {}",
+                    lint, total, allowed
+                )));
+            } else {
+                say!(
+                    "allow({}): {} of {} allowed repo-wide",
+                    lint,
+                    total,
+                    allowed
+                );
+            }
+        }
+    }
+    result
+}
+
+/// Where the ratchet is running, which decides how strict it is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// As a pre-commit hook, which may update the shamefile.
+    #[default]
+    Commit,
+    /// In CI, where the shamefile is never touched and has to be exactly up to date,
+    /// which catches commits that skipped the hook with `--no-verify`.
+    Ci,
+}
+
+/// How to run the ratchet.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Rewrite the shamefile when the suppressions shrank (or it's in an old format).
+    pub click: bool,
+    /// Grow the shamefile to cover increases instead of only rejecting them.  Also
+    /// turned on by setting `UPDATE_ANYWAY=1`.
+    pub accept: bool,
+    /// In CI mode the two options above are ignored, and a shamefile that could be
+    /// shrunk is an error.
+    pub mode: Mode,
+    /// Forbid new inner allows, as if `forbid-inner-allows` were set in the config.
+    pub strict: bool,
+    /// The approver that the commit message names, see [`approver_in`].  When the
+    /// config names approvers, increases are accepted if and only if this is set.
+    pub approved_by: Option<String>,
+    /// The revision the files changed since.  In CI mode, with approvers in the
+    /// config, each commit since then that grew the shamefile needs an approval.
+    pub since: Option<String>,
+}
+
+/// The configured approver that a trailer in the commit message names, if any.
+/// Approvers are matched against the whole value of the trailer or against the
+/// email in its `<...>`, so `jo@example.com` approves as `Jo Doe <jo@example.com>`.
+pub fn approver_in(message: &str, config: &Config) -> Option<String> {
+    let approval = &config.ratchet.approval;
+    message
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            if !key.trim().eq_ignore_ascii_case(&approval.trailer) {
+                return None;
+            }
+            let value = value.trim();
+            let email = value
+                .rsplit_once('<')
+                .and_then(|(_, email)| email.strip_suffix('>'));
+            approval
+                .approvers
+                .iter()
+                .find(|a| a.as_str() == value || Some(a.as_str()) == email)
+                .cloned()
+        })
+}
+
+/// The commits since the revision, oldest first, as their (abbreviated) hashes
+/// and their messages.  Merges are left out, their changes are their parents'.
+fn commits_since(rev: &str) -> Result<Vec<(String, String)>, String> {
+    let root = find_repo_root().ok_or("The ratchet has to run inside of a git repo")?;
+    let output = Command::new("git")
+        .args(["log", "--reverse", "--no-merges", "--format=%h%n%B%x00"])
+        .arg(format!("{}..HEAD", rev))
+        .current_dir(root)
+        .output()
+        .map_err(|e| format!("Unable to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Unable to read the commits since {}: {}",
+            rev,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter_map(|commit| {
+            let (hash, message) = commit.trim_start().split_once('\n')?;
+            Some((hash.to_string(), message.to_string()))
+        })
+        .collect())
+}
+
+/// The shamefile as of the revision (`""` being the index), empty if it didn't
+/// have one.
+fn shamefile_at(rev: &str, config: &Config) -> SupressedLints {
+    let mut lints: SupressedLints = read_committed(rev, SHAMEFILE)
+        .and_then(|c| serde_yaml::from_str(&c).ok())
+        .unwrap_or_default();
+    lints.convert_legacy_keys();
+    lints.rename_lints(config);
+    lints
+}
+
+/// The (key, lint, was, is) of each suppression there are more of after than
+/// before.
+fn growth(before: &SupressedLints, after: &SupressedLints) -> Vec<(String, String, usize, usize)> {
+    let mut result = Vec::new();
+    for (key, lints) in after.lints.iter() {
+        for (lint, count) in lints {
+            let was = before.lints.get(key).and_then(|l| l.get(lint)).copied();
+            if *count > was.unwrap_or(0) {
+                result.push((key.clone(), lint.clone(), was.unwrap_or(0), *count));
+            }
+        }
+    }
+    result
+}
+
+/// The growth of the shamefile since the revision that wasn't approved: each
+/// commit since then that grew it has to be approved itself, an approval in one
+/// commit doesn't cover the others.
+fn unapproved_growth(rev: &str, current: &SupressedLints, config: &Config) -> Vec<Violation> {
+    let grown = growth(&shamefile_at(rev, config), current);
+    if grown.is_empty() {
+        return Vec::new();
+    }
+    let commits = match commits_since(rev) {
+        Ok(commits) => commits,
+        Err(e) => return vec![Violation::general(e)],
+    };
+    let approval = &config.ratchet.approval;
+    let mut result = Vec::new();
+    let mut explained = BTreeSet::new();
+    for (hash, message) in commits {
+        let before = shamefile_at(&format!("{}^", hash), config);
+        let grown_here: Vec<_> = growth(&before, &shamefile_at(&hash, config))
+            .into_iter()
+            // Only what's still grown counts, not what a later commit undid
+            .filter(|(key, lint, ..)| grown.iter().any(|(k, l, ..)| k == key && l == lint))
+            .collect();
+        if grown_here.is_empty() {
+            continue;
+        }
+        explained.extend(
+            grown_here
+                .iter()
+                .map(|(key, lint, ..)| (key.clone(), lint.clone())),
+        );
+        if let Some(approver) = approver_in(&message, config) {
+            say!(
+                "{} grew in {}, as approved by {}",
+                SHAMEFILE,
+                hash,
+                approver
+            );
+            continue;
+        }
+        result.extend(grown_here.into_iter().map(|(key, lint, was, count)| {
+            Violation::general(format!(
+                "{} grew from {} to {} allow({}) in {} in commit {}, which has no {}: trailer from {}",
+                SHAMEFILE,
+                was,
+                count,
+                lint,
+                key,
+                hash,
+                approval.trailer,
+                approval.approvers.join(", ")
+            ))
+        }));
+    }
+    // Growth that isn't in any of the commits, e.g. that isn't committed yet
+    for (key, lint, was, count) in grown {
+        if !explained.contains(&(key.clone(), lint.clone())) {
+            result.push(Violation::general(format!(
+                "{} grew from {} to {} allow({}) in {} since {}, but not in a commit with a {}: \
+                 trailer from {}",
+                SHAMEFILE,
+                was,
+                count,
+                lint,
+                key,
+                rev,
+                approval.trailer,
+                approval.approvers.join(", ")
+            )));
+        }
+    }
+    result
+}
+
+/// How many times each lint is suppressed in the shamefile as of the revision
+/// (`""` being the index), all files together.
+fn totals_at(rev: &str, config: &Config) -> BTreeMap<String, usize> {
+    let lints = shamefile_at(rev, config);
+    let mut result = BTreeMap::new();
+    for (lint, count) in lints.lints.values().flatten() {
+        *result.entry(lint.clone()).or_default() += count;
+    }
+    result
+}
+
+/// The lint debt the commit pays down, as the values of the trailers that
+/// `debt-trailer` asks for, e.g. `dead_code -2`: each lint that the staged
+/// shamefile has fewer suppressions of than HEAD's.
+pub fn debt_paid(config: &Config) -> Vec<String> {
+    let (before, after) = (totals_at("HEAD", config), totals_at("", config));
+    before
+        .iter()
+        .filter_map(|(lint, was)| {
+            let is = after.get(lint).copied().unwrap_or(0);
+            (is < *was).then(|| format!("{} -{}", lint, was - is))
+        })
+        .collect()
+}
+
+/// A suppression the shamefile doesn't allow.
+#[derive(Clone, Debug)]
+pub struct Increase {
+    /// The file's key in the shamefile, e.g. `my-crate:src/lib.rs`.
+    pub key: String,
+    /// The lint that is suppressed more than it used to be.
+    pub lint: String,
+    /// How many times the file suppresses it now.
+    pub observed: usize,
+    /// The file's entry in the shamefile, if it has one.
+    pub entry: Option<BTreeMap<String, usize>>,
+    /// How much the suppressions around it grew when the ratchet weighs lints,
+    /// which is what it was rejected for rather than the lint's count.
+    pub weighted: Option<WeightedGrowth>,
+}
+
+/// How much a file's (or a crate's) suppressions grew, weighing each lint the way
+/// `[ratchet.weights]` says.
+#[derive(Clone, Debug)]
+pub struct WeightedGrowth {
+    /// The file's key in the shamefile, or the crate's name.
+    pub scope: String,
+    /// What its suppressions weighed according to the shamefile.
+    pub was: usize,
+    /// What they weigh now.
+    pub is: usize,
+}
+
+impl Increase {
+    fn violation(&self) -> Violation {
+        if let Some(growth) = &self.weighted {
+            let example = if growth.scope == self.key {
+                format!("allow({})", self.lint)
+            } else {
+                format!("allow({}) in {}", self.lint, self.key)
+            };
+            let message = format!(
+                "Cannot let the suppressions in {} weigh more than they did ({} now, {} \
+                 before), e.g. {}",
+                growth.scope, growth.is, growth.was, example
+            );
+            return Violation::general(message).for_lint(&self.lint);
+        }
+        let message = match &self.entry {
+            Some(entry) if entry.contains_key(&self.lint) => format!(
+                "Cannot allow({}) count to increase in {}",
+                self.lint, self.key
+            ),
+            Some(_) => format!("Cannot add allow({}) to {}", self.lint, self.key),
+            None => format!("Cannot surpress new lints in {}", self.key),
+        };
+        Violation::general(message).for_lint(&self.lint)
+    }
+
+    /// A longer explanation of the rejection, for people who haven't met the
+    /// ratchet before.
+    pub fn explain(&self) -> String {
+        let baseline = match &self.entry {
+            Some(entry) => {
+                let mut lines = vec![format!("{}'s entry in {} is", self.key, SHAMEFILE)];
+                lines.push(format!("  {}:", self.key));
+                for (lint, count) in entry.iter() {
+                    lines.push(format!("    {}: {}", lint, count));
+                }
+                lines.join("\n")
+            }
+            None => format!("{} has no entry in {} at all.", self.key, SHAMEFILE),
+        };
+        let baseline = match &self.weighted {
+            Some(growth) => format!(
+                "{}\n\n\
+                 The lints are weighed as [ratchet.weights] in salt-spray.toml says, and all \
+                 together the suppressions in {} now weigh {}, up from {}.",
+                baseline, growth.scope, growth.is, growth.was
+            ),
+            None => baseline,
+        };
+        format!(
+            "The warning ratchet counts the #[allow(...)]s in each file being committed and \
+             compares them to {shamefile}, which records how many each file had before.  \
+             Counts may go down (the ratchet clicks), but not up.\n\
+             \n\
+             {key} now has {observed} allow({lint}), but {baseline}\n\
+             \n\
+             You can:\n  \
+             1. remove the new allow({lint}) and fix the lint instead,\n  \
+             2. get the suppression approved, then rerun with --accept (or UPDATE_ANYWAY=1) to add it to {shamefile}, or\n  \
+             3. split the commit, so the suppression can be reviewed on its own.",
+            shamefile = SHAMEFILE,
+            key = self.key,
+            observed = self.observed,
+            lint = self.lint,
+            baseline = baseline,
+        )
+    }
+}
+
+/// One `allow(...)` of a lint the ratchet counts, wherever it is.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Suppression {
+    /// The file's key in the shamefile, e.g. `my-crate:src/lib.rs`.
+    pub key: String,
+    /// The file, as it was given to the ratchet.
+    pub file: String,
+    /// The suppressed lint.
+    pub lint: String,
+    /// The line the lint is allowed on.
+    pub line: usize,
+}
+
+/// What a run of the ratchet found.
+#[derive(Debug, Default)]
+pub struct Outcome {
+    /// Everything the ratchet found, of which the ones that aren't advisory block
+    /// the commit.
+    pub violations: Vec<Violation>,
+    /// The suppressions that made the ratchet reject the files.
+    pub increases: Vec<Increase>,
+    /// Every suppression in the files that were examined, in file order.
+    pub suppressions: Vec<Suppression>,
+    /// Whether the shamefile was rewritten, and so needs to be staged.
+    pub updated: bool,
+    /// How many files were examined.
+    pub files: usize,
+    /// How many crates those files belong to.
+    pub crates: usize,
+}
+
+impl Outcome {
+    /// The suppressions behind the increases, i.e. every allow of an increased lint
+    /// in the file it increased in.  The ratchet only counts, so it can't tell which
+    /// of them is the new one.
+    pub fn new_suppressions(&self) -> Vec<&Suppression> {
+        self.suppressions
+            .iter()
+            .filter(|s| {
+                self.increases
+                    .iter()
+                    .any(|i| i.key == s.key && i.lint == s.lint)
+            })
+            .collect()
+    }
+
+    /// The exit code the hook should use: 1 if anything blocks the commit, 2 if the
+    /// shamefile needs to be staged, and 0 otherwise.
+    pub fn exit_code(&self) -> i32 {
+        if self.violations.iter().any(|v| !v.advisory) {
+            1
+        } else if self.updated {
+            2
+        } else {
+            0
+        }
+    }
+}
+
+/// Compares the suppressed lints in the given files against the shamefile.  Unless
+/// `options.click` is set the shamefile is left alone, and the chance to shrink it
+/// is reported as an advisory violation (or an error, in CI mode).
+pub fn ratchet(relevant_files: &[String], config: &Config, options: &Options) -> Outcome {
+    let ci = options.mode == Mode::Ci;
+    let click = options.click && !ci;
+    let mut outcome = Outcome::default();
+    let observed_supressed_lints = find_supressed_lints(relevant_files, config);
+    let mut expected_supressed_lints = match baseline_under_therug(config) {
+        Ok(lints) => lints,
+        Err(e) => {
+            outcome.violations.push(Violation::general(e));
+            return outcome;
+        }
+    };
+    let converted = expected_supressed_lints.convert_legacy_keys();
+    let renamed = expected_supressed_lints.rename_lints(config);
+    if converted && ci {
+        outcome.violations.push(Violation::general(format!(
+            "{} still tracks files the old way, run warning-ratchet and commit the result",
+            SHAMEFILE
+        )));
+    } else if converted && click {
+        sweep_under_therug(&mut expected_supressed_lints);
+        say!(
+            "Converted {} to track files by package, e.g. my-crate:src/lib.rs",
+            SHAMEFILE
+        );
+        outcome.updated = true;
+    }
+    if renamed && click {
+        sweep_under_therug(&mut expected_supressed_lints);
+        say!("Renamed the lints in {} to their current names", SHAMEFILE);
+        outcome.updated = true;
+    }
+    let seeded = expected_supressed_lints.seed_sizes(&observed_supressed_lints, config);
+    if seeded && click {
+        sweep_under_therug(&mut expected_supressed_lints);
+        say!(
+            "Started tracking the sizes of the files already over max-lines in {}",
+            SHAMEFILE
+        );
+        outcome.updated = true;
+    }
+
+    outcome.files = observed_supressed_lints.lints.len();
+    for (key, allows) in observed_supressed_lints.allows.iter() {
+        let file = observed_supressed_lints.path_of(key).to_string_lossy();
+        outcome
+            .suppressions
+            .extend(allows.iter().map(|(lint, line)| Suppression {
+                key: key.clone(),
+                file: file.to_string(),
+                lint: lint.clone(),
+                line: *line,
+            }));
+    }
+    outcome.crates = observed_supressed_lints
+        .paths
+        .values()
+        .filter_map(find_manifest)
+        .collect::<BTreeSet<_>>()
+        .len();
+    outcome
+        .violations
+        .extend(observed_supressed_lints.tier_violations(config));
+    outcome
+        .violations
+        .extend(observed_supressed_lints.forbidden_violations(config));
+    if options.strict || config.ratchet.forbid_inner_allows {
+        outcome
+            .violations
+            .extend(observed_supressed_lints.inner_violations(&expected_supressed_lints, config));
+    }
+    let projected_totals = expected_supressed_lints.projected_totals(&observed_supressed_lints);
+    outcome
+        .violations
+        .extend(check_budgets(&projected_totals, config));
+    // Advisory ones (e.g. inner allows in advisory tiers) don't stop the count
+    if outcome.violations.iter().any(|v| !v.advisory) {
+        return outcome;
+    }
+
+    let relationship = if config.ratchet.weights.enabled() {
+        observed_supressed_lints.weighted_vis_a_vis(
+            &expected_supressed_lints,
+            config,
+            &mut outcome.increases,
+        )
+    } else {
+        observed_supressed_lints.vis_a_vis(
+            &expected_supressed_lints,
+            config,
+            &mut outcome.increases,
+        )
+    };
+    outcome
+        .violations
+        .extend(outcome.increases.iter().map(Increase::violation));
+    let relationship = relationship.and(observed_supressed_lints.sizes_vis_a_vis(
+        &expected_supressed_lints,
+        config,
+        &mut outcome.violations,
+    ));
+    match relationship {
+        Relationship::Expected => (),
+        Relationship::ProperSubset if click => {
+            if config.ratchet.weights.enabled() {
+                // Lints may have traded places, so the entries take on the new counts
+                expected_supressed_lints.grow_around(&observed_supressed_lints);
+            }
+            expected_supressed_lints.shrink_around(&observed_supressed_lints, relevant_files);
+            expected_supressed_lints.shrink_sizes_around(&observed_supressed_lints, config);
+            sweep_under_therug(&mut expected_supressed_lints);
+            say!(
+                "Thanks for enabling more lints!  Please run `git add {}` and retry your commit.",
+                SHAMEFILE
+            );
+            outcome.updated = true;
+        }
+        Relationship::ProperSubset if ci => {
+            outcome.violations.push(Violation::general(format!(
+                "{} is out of date, since suppressions went away without it being updated.  \
+                 Run warning-ratchet on these files and commit the result.",
+                SHAMEFILE
+            )));
+        }
+        Relationship::ProperSubset => {
+            outcome.violations.push(
+                Violation::general(format!("{} can be shrunk, rerun with --fix", SHAMEFILE))
+                    .advisory(),
+            );
+        }
+        Relationship::NotASubset => {
+            // The reasons for NotASubset are already in the outcome's violations
+            let asked =
+                options.accept || env::var("UPDATE_ANYWAY").map(|v| v == "1").unwrap_or(false);
+            let approval = &config.ratchet.approval;
+            let accept = if approval.approvers.is_empty() {
+                asked
+            } else if let Some(approver) = &options.approved_by {
+                say!("Accepting the increases, as approved by {}", approver);
+                true
+            } else {
+                if asked {
+                    outcome.violations.push(Violation::general(format!(
+                        "Increases have to be approved by one of {}, with a `{}: ...` trailer \
+                         in the commit message, rather than with --accept or UPDATE_ANYWAY",
+                        approval.approvers.join(", "),
+                        approval.trailer
+                    )));
+                }
+                false
+            };
+            if accept && !ci {
+                expected_supressed_lints.grow_around(&observed_supressed_lints);
+                expected_supressed_lints.grow_sizes_around(&observed_supressed_lints, config);
+                sweep_under_therug(&mut expected_supressed_lints);
+            }
+        }
+    }
+    if let Some(since) = options.since.as_deref().filter(|_| ci) {
+        if !config.ratchet.approval.approvers.is_empty() {
+            outcome
+                .violations
+                .extend(unapproved_growth(since, &expected_supressed_lints, config));
+        }
+    }
+    if (converted || renamed || seeded) && click && matches!(relationship, Relationship::Expected) {
+        say!("Please run `git add {}` and retry your commit.", SHAMEFILE);
+    }
+    outcome
+}
+
+/// Which of the suppressions in the shamefile [`query`] should list.
+#[derive(Clone, Debug, Default)]
+pub struct Query {
+    /// Only suppressions of this lint.
+    pub lint: Option<String>,
+    /// Only suppressions in this file, given either as its key in the shamefile, its
+    /// path within its crate, or its path.
+    pub file: Option<String>,
+    /// Only the most suppressed ones.
+    pub top: Option<usize>,
+}
+
+/// How many times one file suppresses one lint, according to the shamefile.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// The file's key in the shamefile, e.g. `my-crate:src/lib.rs`.
+    pub key: String,
+    /// The suppressed lint.
+    pub lint: String,
+    /// How many times it's suppressed.
+    pub count: usize,
+}
+
+/// Lists the suppressions in the shamefile that match the query, the most
+/// suppressed first, or why the shamefile couldn't be read.
+pub fn query(query: &Query) -> Result<Vec<Entry>, String> {
+    let file_matches = |key: &str| match &query.file {
+        Some(f) => key == f || key.ends_with(&format!(":{}", f)) || lint_key(f) == key,
+        None => true,
+    };
+    let mut result: Vec<Entry> = Vec::new();
+    for (key, lints) in look_under_therug()?.lints.into_iter() {
+        if !file_matches(&key) {
+            continue;
+        }
+        for (lint, count) in lints.into_iter() {
+            if count > 0 && query.lint.as_ref().is_none_or(|l| *l == lint) {
+                result.push(Entry {
+                    key: key.clone(),
+                    lint,
+                    count,
+                });
+            }
+        }
+    }
+    result.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| (&a.key, &a.lint).cmp(&(&b.key, &b.lint)))
+    });
+    if let Some(top) = query.top {
+        result.truncate(top);
+    }
+    Ok(result)
+}
+
+/// The warning ratchet as a [`Check`].  Running it only compares, fixing it also
+/// clicks the ratchet (rewrites the shamefile) when suppressions went away.
+#[derive(Debug, Default)]
+pub struct RatchetCheck;
+
+impl Check for RatchetCheck {
+    fn name(&self) -> &str {
+        "ratchet"
+    }
+
+    fn run(&self, files: &[String]) -> Vec<Violation> {
+        ratchet(files, &load_config(), &Default::default()).violations
+    }
+
+    fn can_fix(&self) -> bool {
+        true
+    }
+
+    fn fix(&self, files: &[String]) -> Vec<Violation> {
+        let options = Options {
+            click: true,
+            ..Default::default()
+        };
+        ratchet(files, &load_config(), &options).violations
+    }
+}
+
+fn load_config() -> Config {
+    Config::load().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        Default::default()
+    })
+}
+
+impl SupressedLints {
+    fn path_of<'a>(&'a self, key: &'a str) -> &'a Path {
+        self.paths
+            .get(key)
+            .map(PathBuf::as_path)
+            .unwrap_or_else(|| Path::new(key))
+    }
+
+    /// Switches any keys from before the package prefix existed over to the new
+    /// style, returning whether there were any.
+    fn convert_legacy_keys(&mut self) -> bool {
+        let legacy: Vec<String> = self
+            .lints
+            .keys()
+            .filter(|k| !k.contains(':'))
+            .cloned()
+            .collect();
+        let mut converted = false;
+        for old in legacy {
+            let new = lint_key(&old);
+            if new != old {
+                // unwrap here is safe since the key came from the map
+                let lints = self.lints.remove(&old).unwrap();
+                self.lints.insert(new, lints);
+                converted = true;
+            }
+        }
+        converted
+    }
+
+    /// Switches every lint over to its current name (see [`current_name`]), adding
+    /// up the counts of names that turn out to be the same lint, and returns
+    /// whether any of the counted ones were renamed.
+    fn rename_lints(&mut self, config: &Config) -> bool {
+        let mut renamed = false;
+        for lints in self.lints.values_mut() {
+            let mut current = BTreeMap::new();
+            for (lint, count) in std::mem::take(lints) {
+                let name = current_name(&lint, config);
+                if name != lint {
+                    self.renames.insert(lint, name.clone());
+                    renamed = true;
+                }
+                let total: &mut usize = current.entry(name).or_default();
+                *total = total.saturating_add(count);
+            }
+            *lints = current;
+        }
+        for allows in self
+            .allows
+            .values_mut()
+            .chain(self.inner_allows.values_mut())
+        {
+            for (lint, _) in allows.iter_mut() {
+                *lint = current_name(lint, config);
+            }
+        }
+        // These are checked against clippy's groups, so they stay clippy's
+        for allows in self.clippy_allows.values_mut() {
+            for (lint, _) in allows.iter_mut() {
+                let name = current_name(lint, config);
+                if name.starts_with("clippy::") {
+                    *lint = name;
+                }
+            }
+        }
+        renamed
+    }
+
+    fn update_totals(&mut self) {
+        self.totals.clear();
+        for lints in self.lints.values() {
+            for (lint, count) in lints {
+                // Hand-edited counts can be anything at all
+                let total = self.totals.entry(lint.to_string()).or_default();
+                *total = total.saturating_add(*count);
+            }
+        }
+    }
+
+    /// The repo-wide totals we'd have if the observed files replaced their entries
+    /// in self.
+    fn projected_totals(&self, observed: &SupressedLints) -> BTreeMap<String, usize> {
+        let mut result = BTreeMap::new();
+        let unobserved = self
+            .lints
+            .iter()
+            .filter(|(f, _)| !observed.lints.contains_key(*f));
+        for (_, lints) in unobserved.chain(observed.lints.iter()) {
+            for (lint, count) in lints {
+                let total: &mut usize = result.entry(lint.to_string()).or_default();
+                *total = total.saturating_add(*count);
+            }
+        }
+        result
+    }
+
+    /// Checks each file against the `max-suppressions` of its crate's tier, which
+    /// applies no matter what the baseline says.
+    fn tier_violations(&self, config: &Config) -> Vec<Violation> {
+        let mut result = Vec::new();
+        for (file, lints) in self.lints.iter() {
+            if let Some(max) = config.policy_for_file(self.path_of(file)).max_suppressions {
+                let total: usize = lints.values().sum();
+                if total > max {
+                    result.push(Violation::new(
+                        file,
+                        format!(
+                            "may suppress at most {} lint(s) because of its tier, but it suppresses {}",
+                            max, total
+                        ),
+                    ));
+                }
+            }
+        }
+        result
+    }
+
+    /// The clippy lints allowed in the files that the config forbids allowing.
+    fn forbidden_violations(&self, config: &Config) -> Vec<Violation> {
+        let mut result = Vec::new();
+        for (key, allows) in self.clippy_allows.iter() {
+            let path = self.path_of(key).to_string_lossy();
+            for (lint, line) in allows {
+                if let Some(reason) = forbidden_because(lint, config) {
+                    result.push(Violation::new(
+                        &path,
+                        format!(
+                            "allow({}) on line {} is forbidden by salt-spray.toml{}, fix the lint instead",
+                            lint, line, reason
+                        ),
+                    ).for_lint(lint));
+                }
+            }
+        }
+        result
+    }
+
+    /// The inner allows in the files that the baseline doesn't grandfather in, i.e.
+    /// of lints the file's entry doesn't have.  Only lints the ratchet counts make it
+    /// into an entry, so untracked clippy lints are left to `forbidden`.
+    fn inner_violations(&self, baseline: &SupressedLints, config: &Config) -> Vec<Violation> {
+        let mut result = Vec::new();
+        for (key, allows) in self.inner_allows.iter() {
+            let path = self.path_of(key);
+            let advisory = config.policy_for_file(path).advisory;
+            let entry = baseline.lints.get(key);
+            for (lint, line) in allows {
+                let tracked = !lint.starts_with("clippy::") || config.ratchet.clippy.track;
+                let grandfathered = entry
+                    .and_then(|e| e.get(lint))
+                    .is_some_and(|count| *count > 0);
+                if !tracked || grandfathered {
+                    continue;
+                }
+                let violation = Violation::new(
+                    &path.to_string_lossy(),
+                    format!(
+                        "#![allow({})] on line {} covers the whole module, allow it on just the items that need it instead",
+                        lint, line
+                    ),
+                )
+                .for_lint(lint);
+                result.push(if advisory {
+                    violation.advisory()
+                } else {
+                    violation
+                });
+            }
+        }
+        result
+    }
+
+    /// Works out how self relates to other, pushing the reason onto `rejections`
+    /// when self isn't a subset.
+    fn vis_a_vis(
+        &self,
+        other: &SupressedLints,
+        config: &Config,
+        rejections: &mut Vec<Increase>,
+    ) -> Relationship {
+        let mut result = Relationship::Expected;
+
+        // Is everything in self also in other?
+        for (file, lints) in self.lints.iter() {
+            // Advisory tiers get told about increases, but they don't fail the commit
+            let advisory = config.policy_for_file(self.path_of(file)).advisory;
+            if let Some(olints) = other.lints.get(file) {
+                for (lint, count) in lints {
+                    if let Some(ocount) = olints.get(lint) {
+                        if *count < *ocount {
+                            result = Relationship::ProperSubset;
+                        } else if *count > *ocount && advisory {
+                            say!("allow({}) count increased in advisory file {}", lint, file);
+                        } else if *count > *ocount {
+                            rejections.push(Increase {
+                                key: file.clone(),
+                                lint: lint.clone(),
+                                observed: *count,
+                                entry: Some(olints.clone()),
+                                weighted: None,
+                            });
+                            return Relationship::NotASubset;
+                        }
+                    } else if advisory {
+                        say!("Added allow({}) to advisory file {}", lint, file);
+                    } else {
+                        rejections.push(Increase {
+                            key: file.clone(),
+                            lint: lint.clone(),
+                            observed: *count,
+                            entry: Some(olints.clone()),
+                            weighted: None,
+                        });
+                        return Relationship::NotASubset;
+                    }
+                }
+            } else if !lints.is_empty() && advisory {
+                say!("Suppressing new lints in advisory file {}", file);
+            } else if let Some((lint, count)) = lints.iter().next() {
+                rejections.push(Increase {
+                    key: file.clone(),
+                    lint: lint.clone(),
+                    observed: *count,
+                    entry: None,
+                    weighted: None,
+                });
+                return Relationship::NotASubset;
+            }
+        }
+
+        // Is there anything in other that is not in self?
+        for (ofile, olints) in other.lints.iter() {
+            if let Some(lints) = self.lints.get(ofile) {
+                for (lint, count) in olints {
+                    if !lints.contains_key(lint) && *count > 0 {
+                        say!("No longer have {} to worry about in {}.", lint, ofile);
+                        result = Relationship::ProperSubset;
+                    }
+                }
+            }
+            // No else here because pre-commit chunks the filenames before
+            // invoking us, so on the second invocation we expect to have
+            // lints that other does not.
+        }
+        result
+    }
+
+    /// Like `vis_a_vis`, but weighing the lints as `[ratchet.weights]` says and
+    /// comparing the weighted totals of each file (or crate), so that lints can
+    /// trade places as long as the total doesn't grow.
+    fn weighted_vis_a_vis(
+        &self,
+        other: &SupressedLints,
+        config: &Config,
+        rejections: &mut Vec<Increase>,
+    ) -> Relationship {
+        let weights = &config.ratchet.weights;
+        let weigh = |lints: &BTreeMap<String, usize>| {
+            lints
+                .iter()
+                .map(|(lint, count)| count.saturating_mul(weights.weight_of(lint)))
+                .fold(0, usize::saturating_add)
+        };
+        let scope_of = |key: &str| match weights.per {
+            WeightScope::File => key.to_string(),
+            WeightScope::Crate => key.split_once(':').map_or(key, |(p, _)| p).to_string(),
+        };
+        let nonzero = |lints: Option<&BTreeMap<String, usize>>| -> BTreeMap<String, usize> {
+            let lints = lints.into_iter().flatten();
+            lints
+                .filter(|(_, c)| **c > 0)
+                .map(|(l, c)| (l.clone(), *c))
+                .collect()
+        };
+
+        let mut scopes: BTreeMap<String, Vec<&String>> = BTreeMap::new();
+        for key in self.lints.keys() {
+            scopes.entry(scope_of(key)).or_default().push(key);
+        }
+        let mut result = Relationship::Expected;
+        for (scope, keys) in scopes {
+            let was = other
+                .lints
+                .iter()
+                .filter(|(key, _)| scope_of(key) == scope)
+                .map(|(_, lints)| weigh(lints))
+                .fold(0, usize::saturating_add);
+            // The baseline's entries for the scope, with the files we looked at replaced
+            let unobserved = other
+                .lints
+                .iter()
+                .filter(|(key, _)| scope_of(key) == scope && !self.lints.contains_key(*key));
+            let is = unobserved
+                .map(|(_, lints)| weigh(lints))
+                .chain(keys.iter().map(|key| weigh(&self.lints[*key])))
+                .fold(0, usize::saturating_add);
+            if is <= was {
+                let changed = keys
+                    .iter()
+                    .any(|key| nonzero(self.lints.get(*key)) != nonzero(other.lints.get(*key)));
+                if changed {
+                    result = Relationship::ProperSubset;
+                }
+                continue;
+            }
+            // Advisory tiers get told about increases, but they don't fail the commit
+            if config.policy_for_file(self.path_of(keys[0])).advisory {
+                say!(
+                    "Suppressions in advisory {} now weigh {}, up from {}",
+                    scope,
+                    is,
+                    was
+                );
+                continue;
+            }
+            // Blame whichever lint gained the most weight
+            let mut culprit = None;
+            for key in keys {
+                let before = other.lints.get(key);
+                for (lint, count) in self.lints[key].iter() {
+                    let old = before.and_then(|b| b.get(lint)).copied().unwrap_or(0);
+                    let gained = count
+                        .saturating_sub(old)
+                        .saturating_mul(weights.weight_of(lint));
+                    if gained > culprit.as_ref().map_or(0, |(g, _, _, _)| *g) {
+                        culprit = Some((gained, key, lint, *count));
+                    }
+                }
+            }
+            if let Some((_, key, lint, count)) = culprit {
+                rejections.push(Increase {
+                    key: key.clone(),
+                    lint: lint.clone(),
+                    observed: count,
+                    entry: other.lints.get(key).cloned(),
+                    weighted: Some(WeightedGrowth { scope, was, is }),
+                });
+                return Relationship::NotASubset;
+            }
+        }
+        result
+    }
+
+    /// Works out how the sizes of the files in self relate to the ones other allows,
+    /// pushing a violation for each file that grew too much.
+    fn sizes_vis_a_vis(
+        &self,
+        other: &SupressedLints,
+        config: &Config,
+        violations: &mut Vec<Violation>,
+    ) -> Relationship {
+        let mut result = Relationship::Expected;
+        let max_lines = match config.ratchet.max_lines {
+            Some(max_lines) => max_lines,
+            None => return result,
+        };
+        for (file, lines) in self.line_counts.iter() {
+            let allowed = other.sizes.get(file).copied();
+            if *lines <= allowed.unwrap_or(0).max(max_lines) {
+                if allowed.is_some_and(|allowed| *lines < allowed) {
+                    result = result.and(Relationship::ProperSubset);
+                }
+                continue;
+            }
+            let message = match allowed {
+                Some(allowed) => format!(
+                    "has grown to {} lines, but it's already over {} and may not grow past {}, split it up instead",
+                    lines, max_lines, allowed
+                ),
+                None => format!(
+                    "has grown to {} lines, but files may have at most {}, split it up instead",
+                    lines, max_lines
+                ),
+            };
+            if config.policy_for_file(self.path_of(file)).advisory {
+                say!("{} in advisory file {}", message, file);
+            } else {
+                violations.push(Violation::new(
+                    &self.path_of(file).to_string_lossy(),
+                    message,
+                ));
+                result = Relationship::NotASubset;
+            }
+        }
+        result
+    }
+
+    /// Lowers the sizes of any oversized files that shrank, forgetting the ones that
+    /// are no longer over the limit.
+    fn shrink_sizes_around(&mut self, other: &SupressedLints, config: &Config) {
+        let max_lines = config.ratchet.max_lines.unwrap_or(0);
+        for (key, lines) in other.line_counts.iter() {
+            if let Some(size) = self.sizes.get_mut(key) {
+                *size = min(*size, *lines);
+            }
+        }
+        self.sizes.retain(|_, size| *size > max_lines);
+    }
+
+    /// Starts tracking the sizes of the files in other that were already over
+    /// `max-lines` at HEAD, but that aren't tracked yet, at their size then.
+    /// Returns whether there were any.
+    fn seed_sizes(&mut self, other: &SupressedLints, config: &Config) -> bool {
+        let max_lines = match config.ratchet.max_lines {
+            Some(max_lines) => max_lines,
+            None => return false,
+        };
+        let mut seeded = false;
+        for key in other.line_counts.keys() {
+            if self.sizes.contains_key(key) {
+                continue;
+            }
+            let committed = read_committed("HEAD", other.path_of(key)).map(|c| c.lines().count());
+            if let Some(lines) = committed.filter(|lines| *lines > max_lines) {
+                self.sizes.insert(key.clone(), lines);
+                seeded = true;
+            }
+        }
+        seeded
+    }
+
+    /// Raises the sizes to cover the oversized files in other.
+    fn grow_sizes_around(&mut self, other: &SupressedLints, config: &Config) {
+        if let Some(max_lines) = config.ratchet.max_lines {
+            for (key, lines) in other.line_counts.iter().filter(|(_, l)| **l > max_lines) {
+                let size = self.sizes.entry(key.clone()).or_default();
+                *size = max(*size, *lines);
+            }
+        }
+    }
+
+    fn shrink_around(&mut self, other: &SupressedLints, examined_files: &[String]) {
+        let examined_files: Vec<String> = examined_files.iter().map(|f| lint_key(f)).collect();
+        // TODO: remove keys that are now missing?
+        for (key, val) in self.lints.iter_mut() {
+            if let Some(oval) = other.lints.get(key) {
+                for (lint, count) in val.iter_mut() {
+                    if let Some(ocount) = oval.get(lint) {
+                        *count = min(*count, *ocount);
+                    } else {
+                        *count = 0;
+                    }
+                }
+            } else if examined_files.contains(key) {
+                val.clear();
+            }
+        }
+    }
+
+    fn grow_around(&mut self, other: &SupressedLints) {
+        for (okey, oval) in other.lints.iter() {
+            if let Some(val) = self.lints.get_mut(okey) {
+                for (lint, ocount) in oval {
+                    if let Some(count) = val.get_mut(lint) {
+                        *count = max(*count, *ocount);
+                    } else {
+                        val.insert(lint.to_string(), *ocount);
+                    }
+                }
+            } else {
+                self.lints.insert(okey.to_string(), oval.clone());
+            }
+        }
+    }
+
+    /// Takes in what was found in other files, which wins where both looked at the
+    /// same one.
+    fn absorb(&mut self, other: SupressedLints) {
+        self.lints.extend(other.lints);
+        self.line_counts.extend(other.line_counts);
+        self.paths.extend(other.paths);
+        self.clippy_allows.extend(other.clippy_allows);
+        self.allows.extend(other.allows);
+        self.inner_allows.extend(other.inner_allows);
+    }
+
+    fn load_suppressed_lints_from(&mut self, filename: &str, config: &Config) {
+        let ignored = &config.ratchet.ignore_allows_on;
+        if let Some(contents) = read_file(filename) {
+            match parse_source(&contents) {
+                Ok(ast) => {
+                    let key = lint_key(filename);
+                    let mut allows = Allows {
+                        ignored,
+                        ..Default::default()
+                    };
+                    allows.visit_file(&ast);
+                    let (clippy, others): (Vec<_>, Vec<_>) = allows
+                        .found
+                        .into_iter()
+                        .partition(|(lint, _)| lint.starts_with("clippy::"));
+                    // Tool lints like rustdoc's aren't counted, so they aren't kept either
+                    let mut counted: Vec<_> = others
+                        .into_iter()
+                        .filter(|(l, _)| !l.contains("::"))
+                        .collect();
+                    if config.ratchet.clippy.track {
+                        counted.extend(clippy.iter().cloned());
+                    }
+                    counted.sort_by_key(|(_, line)| *line);
+                    self.allows.insert(key.clone(), counted);
+                    self.clippy_allows.insert(key.clone(), clippy);
+                    let mut inner_allows = Vec::new();
+                    collect_inner_allows(&ast.attrs, &ast.items, &mut inner_allows);
+                    self.inner_allows.insert(key.clone(), inner_allows);
+                    self.lints
+                        .insert(key.clone(), count_suppressed_lints(&ast, ignored));
+                    self.line_counts
+                        .insert(key.clone(), contents.lines().count());
+                    self.paths.insert(key, PathBuf::from(filename));
+                }
+                Err(e) => {
+                    let message = format!("Unable to parse {}, skipping it: {}", filename, e);
+                    let name = Path::new(filename).file_name().unwrap_or_default();
+                    let name = name.to_string_lossy();
+                    eprintln!("{}", debug::with_dump(message, &name, &contents));
+                }
+            }
+        }
+    }
+
+    /// Loads the lints the manifest or cargo config allows for whole crates, each of
+    /// which counts once.
+    fn load_crate_wide_allows_from(&mut self, filename: &str, config: &Config) {
+        if let Some(contents) = read_file(filename) {
+            let key = lint_key(filename);
+            let (clippy, others): (Vec<_>, Vec<_>) =
+                crate_wide_allows(Path::new(filename), &contents)
+                    .into_iter()
+                    .partition(|(lint, _)| lint.starts_with("clippy::"));
+            // As in source files, other tools' lints aren't counted
+            let mut counted: Vec<_> = others
+                .into_iter()
+                .filter(|(l, _)| !l.contains("::"))
+                .collect();
+            self.lints.insert(
+                key.clone(),
+                counted.iter().map(|(lint, _)| (lint.clone(), 1)).collect(),
+            );
+            if config.ratchet.clippy.track {
+                counted.extend(clippy.iter().cloned());
+            }
+            counted.sort_by_key(|(_, line)| *line);
+            self.allows.insert(key.clone(), counted);
+            self.clippy_allows.insert(key.clone(), clippy);
+            self.paths.insert(key, PathBuf::from(filename));
+        }
+    }
+}
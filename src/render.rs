@@ -0,0 +1,155 @@
+//! Showing violations the way rustc shows its diagnostics: what's wrong, where it
+//! is, and the offending source lines with carets under the part at fault.
+//!
+//! ```text
+//! error[enum-guard]: pub enum Mode must be #[non_exhaustive]
+//!   --> src/lib.rs:12:10
+//!    |
+//! 12 | pub enum Mode {
+//!    |          ^^^^
+//!    = help: add #[non_exhaustive] above it
+//! ```
+//!
+//! Advisory violations are warnings rather than errors.  Violations that aren't
+//! at a particular place in a file get the same heading (and help) without the
+//! source, unless they've nothing more to say than their message, and the ones
+//! that come rendered already (like clippy's) are shown as they are.  Colors
+//! follow `--color`, see [`crate::output`].
+
+use std::fs;
+
+use crate::check::{Span, Violation};
+use crate::output;
+
+/// The most lines of a span that are shown, the rest are left as `...`.
+const MAX_LINES: usize = 4;
+
+const RESET: &str = "\u{1b}[0m";
+const BOLD: &str = "\u{1b}[1m";
+const RED: &str = "\u{1b}[1;31m";
+const YELLOW: &str = "\u{1b}[1;33m";
+const BLUE: &str = "\u{1b}[1;34m";
+
+/// Renders the violation, reading the source from its file.
+pub fn render(violation: &Violation) -> String {
+    let source = violation
+        .file
+        .as_ref()
+        .filter(|_| violation.span.is_some())
+        .and_then(|f| fs::read_to_string(f).ok());
+    render_source(violation, source.as_deref(), output::keeps_color())
+}
+
+/// How wide the text is on a terminal, with tabs as four spaces.
+fn width(text: &str) -> usize {
+    text.chars().map(|c| if c == '\t' { 4 } else { 1 }).sum()
+}
+
+/// Renders the violation given the source of its file, in color if asked to.
+pub fn render_source(violation: &Violation, source: Option<&str>, color: bool) -> String {
+    let plain = violation.span.is_none() && violation.suggestion.is_none();
+    if plain || violation.message.contains('\n') {
+        return violation.to_string();
+    }
+    let paint = |code: &str, text: &str| {
+        if color {
+            format!("{}{}{}", code, text, RESET)
+        } else {
+            text.to_string()
+        }
+    };
+    let severity_color = if violation.advisory { YELLOW } else { RED };
+    let heading = match &violation.lint {
+        Some(lint) => format!("{}[{}]", violation.severity(), lint),
+        None => violation.severity().to_string(),
+    };
+
+    let lines: Vec<&str> = source.unwrap_or_default().lines().collect();
+    let span = violation.span.unwrap_or(Span::line(0));
+    let last = span.end_line.max(span.line);
+    let shown = span.line..=last.min(span.line + MAX_LINES.saturating_sub(1));
+    let gutter = " ".repeat(shown.end().to_string().len());
+    let bar = paint(BLUE, "|");
+
+    let mut result = format!(
+        "{}{}\n",
+        paint(severity_color, &heading),
+        paint(BOLD, &format!(": {}", violation.message))
+    );
+    match (&violation.file, &violation.span) {
+        (Some(file), Some(span)) => result.push_str(&format!(
+            "{}{} {}:{}:{}\n",
+            gutter,
+            paint(BLUE, "-->"),
+            file,
+            span.line,
+            span.column
+        )),
+        (Some(file), None) => {
+            result.push_str(&format!("{}{} {}\n", gutter, paint(BLUE, "-->"), file))
+        }
+        (None, _) => (),
+    }
+    if span.line > 0 && lines.get(span.line - 1).is_some() {
+        result.push_str(&format!("{} {}\n", gutter, bar));
+        for number in shown.clone() {
+            let text = match lines.get(number - 1) {
+                Some(text) => *text,
+                None => break,
+            };
+            let number_text = format!("{:>width$}", number, width = gutter.len());
+            result.push_str(&format!(
+                "{} {} {}\n",
+                paint(BLUE, &number_text),
+                bar,
+                text.replace('\t', "    ")
+            ));
+            // Carets go under the part of the line the span covers
+            let chars: Vec<char> = text.chars().collect();
+            let indent = chars.iter().take_while(|c| c.is_whitespace()).count() + 1;
+            let mut start = if number == span.line {
+                span.column
+            } else {
+                indent
+            };
+            let mut end = if number == last {
+                span.end_column
+            } else {
+                chars.len() + 1
+            };
+            if end <= start {
+                // A span without a length covers the rest of the line
+                start = start.max(indent);
+                end = chars.len() + 1;
+            }
+            let before: String = chars.iter().take(start.saturating_sub(1)).collect();
+            let covered: String = chars
+                .iter()
+                .skip(start.saturating_sub(1))
+                .take(end.saturating_sub(start))
+                .collect();
+            let carets = "^".repeat(width(&covered).max(1));
+            result.push_str(&format!(
+                "{} {} {}{}\n",
+                gutter,
+                bar,
+                " ".repeat(width(&before)),
+                paint(severity_color, &carets)
+            ));
+        }
+        if last > *shown.end() {
+            result.push_str(&format!("{} {} ...\n", gutter, bar));
+        }
+    }
+    if let Some(suggestion) = &violation.suggestion {
+        result.push_str(&format!(
+            "{} {} {}\n",
+            gutter,
+            paint(BLUE, "="),
+            paint(BOLD, &format!("help: {}", suggestion))
+        ));
+    }
+    // The caller adds the line ending, the same as for Display
+    result.pop();
+    result
+}
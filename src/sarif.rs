@@ -0,0 +1,63 @@
+//! Writing findings out as [SARIF](https://sarifweb.azurewebsites.net/) 2.1.0, the
+//! format GitHub code scanning (and most other code review tools) read, so that
+//! they can show the findings inline on pull requests.
+//!
+//! Only the parts of the format those tools actually need are written: one run,
+//! with a rule for each distinct lint, and a result for each finding pointing at
+//! its file and line.
+
+use std::collections::BTreeSet;
+
+use serde_json::{json, Value};
+
+/// Something to report, at a particular line of a particular file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    /// The lint (or other rule) behind the finding, e.g. `dead_code`.
+    pub rule: String,
+    /// A human readable description of the finding.
+    pub message: String,
+    /// The file it's in, relative to the root of the repo.
+    pub file: String,
+    /// The (1-based) line it's on.
+    pub line: usize,
+    /// Whether it's an error, as opposed to a note about something already there.
+    pub error: bool,
+}
+
+/// Renders the findings of the given tool as a SARIF log.
+pub fn render(tool: &str, findings: &[Finding]) -> String {
+    let rules: BTreeSet<&str> = findings.iter().map(|f| f.rule.as_str()).collect();
+    let results: Vec<Value> = findings
+        .iter()
+        .map(|f| {
+            json!({
+                "ruleId": f.rule,
+                "level": if f.error { "error" } else { "note" },
+                "message": { "text": f.message },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": f.file.trim_start_matches("./") },
+                        "region": { "startLine": f.line.max(1) },
+                    },
+                }],
+            })
+        })
+        .collect();
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": tool,
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules.iter().map(|r| json!({ "id": r })).collect::<Vec<_>>(),
+                },
+            },
+            "results": results,
+        }],
+    });
+    // unwrap here is safe since the log is built from strings and numbers
+    serde_json::to_string_pretty(&log).unwrap()
+}
@@ -0,0 +1,288 @@
+//! `salt selftest`, which checks that the hooks are installed and work, e.g.
+//! after `pre-commit try-repo` or when packaging them in a container.
+//!
+//! It makes a small crate in a fresh git repo under the temp directory, with
+//! everything it takes for a commit of it to be clean (a CODEOWNERS entry, a
+//! changelog, a test, and so on), stages it, and runs each hook on it the way pre-commit
+//! would: the `entry` from .pre-commit-hooks.yaml, with the staged files that the
+//! hook's `types` and `files` pick.  Every hook should pass, so any that fails
+//! has its output shown.  Hooks that aren't installed (e.g. in a build without
+//! the `syn` feature) are skipped, and in a repo whose .pre-commit-config.yaml
+//! lists some of the hooks only those are run.
+//!
+//! The crate is deleted afterwards, apart from when a hook fails, so that what
+//! went wrong can be looked into.
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output};
+use std::time::Instant;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::{find_repo_root, output};
+
+/// The hooks, as pre-commit sees them.
+static HOOKS: &str = include_str!("../.pre-commit-hooks.yaml");
+
+/// The files of the crate that the hooks are run on.
+static FILES: [(&str, &str); 6] = [
+    (
+        "Cargo.toml",
+        "[package]\nname = \"salt-selftest\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    ),
+    (
+        "src/lib.rs",
+        "//! A crate for `salt selftest` to run the hooks on.\n\n\
+         /// Adds two numbers.\n\
+         pub fn add(a: u64, b: u64) -> u64 {\n    a + b\n}\n\n\
+         #[cfg(test)]\n\
+         mod tests {\n    #[test]\n    fn adds() {\n        assert_eq!(super::add(1, 2), 3);\n    }\n}\n",
+    ),
+    ("CHANGELOG.md", "# Changelog\n\n- Added `add`.\n"),
+    ("CODEOWNERS", "* @salt-selftest\n"),
+    (
+        ".api-surface.yaml",
+        "crates:\n  salt-selftest:\n  - 'salt_selftest: pub fn add (a : u64 , b : u64) -> u64'\n",
+    ),
+    ("salt-spray.toml", ""),
+];
+
+/// What git sets for hooks, which would point git at the repo `salt selftest` is
+/// run from (when it's run from a hook) rather than the crate's.
+static GIT_ENV: [&str; 3] = ["GIT_DIR", "GIT_INDEX_FILE", "GIT_WORK_TREE"];
+
+/// How many lines of a failing hook's output are shown.
+const SHOWN_LINES: usize = 20;
+
+/// One hook from .pre-commit-hooks.yaml, with the parts of it that decide how it
+/// is run.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct Hook {
+    id: String,
+    entry: String,
+    #[serde(default)]
+    types: Vec<String>,
+    #[serde(default)]
+    types_or: Vec<String>,
+    files: Option<String>,
+    pass_filenames: Option<bool>,
+    #[serde(default)]
+    stages: Vec<String>,
+}
+
+impl Hook {
+    /// Would pre-commit hand the hook the file?
+    fn wants(&self, file: &str) -> bool {
+        let is = |kind: &String| match kind.as_str() {
+            "rust" => file.ends_with(".rs"),
+            "toml" => file.ends_with(".toml"),
+            _ => false,
+        };
+        let typed = (self.types.is_empty() || self.types.iter().all(is))
+            && (self.types_or.is_empty() || self.types_or.iter().any(is));
+        let matched = match &self.files {
+            Some(files) => Regex::new(files).is_ok_and(|r| r.is_match(file)),
+            None => true,
+        };
+        typed && matched
+    }
+
+    /// The files to hand the hook, as pre-commit would.
+    fn arguments(&self, dir: &Path) -> Vec<String> {
+        if self.stages.iter().any(|s| s.ends_with("commit-msg")) {
+            // Hooks at the (prepare-)commit-msg stage get the message instead
+            let message = dir.join(".git").join("COMMIT_EDITMSG");
+            return vec![message.to_string_lossy().into_owned()];
+        } else if self.pass_filenames == Some(false) {
+            return Vec::new();
+        }
+        FILES
+            .iter()
+            .map(|(file, _)| file.to_string())
+            .filter(|f| self.wants(f))
+            .collect()
+    }
+}
+
+/// The ids of the hooks that the repo's .pre-commit-config.yaml lists, if it has
+/// one.  They aren't told apart by which repo they come from, since salt-spray
+/// may be vendored or mirrored under any name.
+fn configured_hooks() -> Option<BTreeSet<String>> {
+    let path = find_repo_root()?.join(".pre-commit-config.yaml");
+    let config: serde_yaml::Value = serde_yaml::from_str(&fs::read_to_string(path).ok()?).ok()?;
+    let ids = config
+        .get("repos")?
+        .as_sequence()?
+        .iter()
+        .filter_map(|repo| repo.get("hooks")?.as_sequence())
+        .flatten()
+        .filter_map(|hook| hook.get("id")?.as_str())
+        .map(str::to_string)
+        .collect();
+    Some(ids)
+}
+
+/// Where a hook's binary is: next to this one if it's there, since that's where
+/// pre-commit (and cargo install) put them, otherwise wherever the PATH says.
+fn binary(name: &str) -> PathBuf {
+    env::current_exe()
+        .ok()
+        .and_then(|exe| {
+            Some(
+                exe.parent()?
+                    .join(name)
+                    .with_extension(env::consts::EXE_EXTENSION),
+            )
+        })
+        .filter(|path| path.is_file())
+        .unwrap_or_else(|| PathBuf::from(name))
+}
+
+/// A command that runs in the crate's repo.
+fn command_in(dir: &Path, program: &Path) -> Command {
+    let mut cmd = Command::new(program);
+    cmd.current_dir(dir);
+    for var in GIT_ENV {
+        cmd.env_remove(var);
+    }
+    cmd
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<(), String> {
+    let output = command_in(dir, Path::new("git"))
+        .args([
+            "-c",
+            "user.name=salt selftest",
+            "-c",
+            "user.email=selftest@localhost",
+        ])
+        .args(args)
+        .output()
+        .map_err(|e| format!("Unable to run git: {}", e))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            output::clean(&output.stderr).trim()
+        ))
+    }
+}
+
+/// Makes the crate, with its manifest committed and the rest of it staged, as if
+/// it were being committed for the first time.
+fn make_crate(dir: &Path) -> Result<(), String> {
+    for (file, contents) in FILES {
+        let path = dir.join(file);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+        }
+        fs::write(&path, contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    git(dir, &["init", "--quiet"])?;
+    git(dir, &["add", "Cargo.toml"])?;
+    git(
+        dir,
+        &["commit", "--quiet", "--message", "Start the selftest crate"],
+    )?;
+    git(dir, &["add", "."])?;
+    fs::write(dir.join(".git").join("COMMIT_EDITMSG"), "Add add\n")
+        .map_err(|e| format!("Unable to write the commit message: {}", e))
+}
+
+/// How running a hook went.
+enum Outcome {
+    Passed,
+    Failed(Output),
+    Missing,
+}
+
+fn run_hook(hook: &Hook, dir: &Path) -> Result<Outcome, String> {
+    let mut words = hook.entry.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    let result = command_in(dir, &binary(name))
+        .args(words)
+        .args(hook.arguments(dir))
+        // The crate's build goes in its own target directory, whatever the
+        // environment says
+        .env_remove("CARGO_TARGET_DIR")
+        .output();
+    match result {
+        Ok(output) if output.status.success() => Ok(Outcome::Passed),
+        Ok(output) => Ok(Outcome::Failed(output)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Outcome::Missing),
+        Err(e) => Err(format!("Unable to run {}: {}", name, e)),
+    }
+}
+
+/// Runs every hook (the ones `only` names, if it names any) on a new crate and
+/// says how each went, then exits with how many failed.
+pub fn selftest(only: Option<&[String]>) -> ! {
+    let hooks: Vec<Hook> = serde_yaml::from_str(HOOKS).unwrap_or_else(|e| {
+        eprintln!("Unable to read the hooks: {}", e);
+        process::exit(-1);
+    });
+    let configured = configured_hooks().filter(|ids| hooks.iter().any(|h| ids.contains(&h.id)));
+    let hooks: Vec<&Hook> = hooks
+        .iter()
+        .filter(|h| only.is_none_or(|o| o.contains(&h.id)))
+        .filter(|h| configured.as_ref().is_none_or(|ids| ids.contains(&h.id)))
+        .collect();
+
+    let dir = env::temp_dir().join(format!("salt-selftest-{}", process::id()));
+    if let Err(e) = make_crate(&dir) {
+        eprintln!("Unable to make a crate to test the hooks on: {}", e);
+        drop(fs::remove_dir_all(&dir));
+        process::exit(-1);
+    }
+    let width = hooks.iter().map(|h| h.id.len()).max().unwrap_or(0);
+    let mut failed = 0;
+    for hook in hooks {
+        let start = Instant::now();
+        let outcome = run_hook(hook, &dir).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(-1);
+        });
+        let elapsed = start.elapsed().as_secs_f64();
+        match outcome {
+            Outcome::Passed => println!("ok      {:width$}  {:.1}s", hook.id, elapsed),
+            Outcome::Missing => println!(
+                "skipped {:width$}  {} isn't installed",
+                hook.id,
+                hook.entry.split_whitespace().next().unwrap_or_default()
+            ),
+            Outcome::Failed(output) => {
+                failed += 1;
+                println!(
+                    "FAILED  {:width$}  {}, {:.1}s",
+                    hook.id, output.status, elapsed
+                );
+                let printed = format!(
+                    "{}{}",
+                    output::clean(&output.stdout),
+                    output::clean(&output.stderr)
+                );
+                for line in printed.lines().take(SHOWN_LINES) {
+                    println!("        | {}", line);
+                }
+            }
+        }
+    }
+    if failed > 0 {
+        println!(
+            "{} hook(s) failed, the crate they ran on is in {}",
+            failed,
+            dir.display()
+        );
+    } else {
+        drop(fs::remove_dir_all(&dir));
+    }
+    process::exit(failed);
+}
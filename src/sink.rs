@@ -0,0 +1,322 @@
+//! Where a hook's findings go.  By default they're only shown on the terminal, but
+//! the same run can also keep them for later, so CI can show people what a hook
+//! found and hand the results to other tools without running it twice:
+//!
+//! ```toml
+//! [output]
+//! sinks = ["terminal", "json-lines=.salt/results.jsonl"]
+//!
+//! [output.tools]
+//! salt-clip = ["terminal", "tap=.salt/{tool}.tap"]
+//! ```
+//!
+//! or for a single run, `--output=terminal,tap=clip.tap`, which every hook takes
+//! (along with `--color=`, see [`crate::output`]).  The sinks are
+//!
+//! - `terminal`, the findings on stderr, with the source they point at,
+//! - `json-lines=<path>`, a JSON object per finding and one per run with its
+//!   summary, appended to the file so that every hook in a run can share it (this
+//!   needs the `json` feature), and
+//! - `tap=<path>`, the run as a [TAP](https://testanything.org/) 13 document, a
+//!   test point per finding, with advisory ones marked `# TODO`.  Each run's
+//!   document is appended to the file, like the JSON lines are, since pre-commit
+//!   runs a hook's batches of files at the same time.
+//!
+//! Paths are relative to the repo root unless they're absolute, and `{tool}` in
+//! them becomes the name of the hook.  Other crates can add sinks of their own by
+//! implementing [`Sink`] and handing them to a [`Report`].
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::check::Violation;
+use crate::config::Config;
+use crate::find_repo_root;
+use crate::output::{self, COLOR};
+use crate::render::render;
+use crate::summary::Summary;
+
+/// The flag that picks the sinks for a single run, e.g.
+/// `--output=terminal,json-lines=results.jsonl`.
+pub const OUTPUT: &str = "--output=";
+
+/// Somewhere findings can go.
+pub trait Sink {
+    /// Takes in a finding, as soon as the hook has it.
+    fn record(&mut self, tool: &str, violation: &Violation);
+
+    /// Finishes off the run, which the summary describes.
+    fn finish(&mut self, summary: &Summary) -> Result<(), String>;
+}
+
+/// Shows findings on stderr, with the source they're about when they're about
+/// somewhere in particular (see [`crate::render`]).
+#[derive(Debug, Default)]
+pub struct Terminal;
+
+impl Sink for Terminal {
+    fn record(&mut self, _tool: &str, violation: &Violation) {
+        let rendered = render(violation);
+        // Multi-line messages (e.g. rustc diagnostics) read better with some space
+        if rendered.contains('\n') {
+            eprintln!("\n{}", rendered);
+        } else {
+            eprintln!("{}", rendered);
+        }
+    }
+
+    fn finish(&mut self, _summary: &Summary) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Appends findings to a file as JSON lines, see the module docs.
+#[cfg(feature = "json")]
+#[derive(Debug)]
+pub struct JsonLines {
+    path: PathBuf,
+    lines: Vec<String>,
+}
+
+#[cfg(feature = "json")]
+impl JsonLines {
+    /// A sink appending to the given file.
+    pub fn new(path: PathBuf) -> Self {
+        JsonLines {
+            path,
+            lines: Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl Sink for JsonLines {
+    fn record(&mut self, tool: &str, violation: &Violation) {
+        let mut line = violation.to_json();
+        line["tool"] = tool.into();
+        self.lines.push(line.to_string());
+    }
+
+    fn finish(&mut self, summary: &Summary) -> Result<(), String> {
+        let line = serde_json::json!({
+            "tool": summary.tool(),
+            "summary": {
+                "crates": summary.crates,
+                "files": summary.files,
+                "violations": summary.violations,
+                "seconds": summary.elapsed().as_secs_f64(),
+            },
+        });
+        self.lines.push(line.to_string());
+        let mut contents = self.lines.join("\n");
+        contents.push('\n');
+        append_to(&self.path, &contents)
+    }
+}
+
+/// Appends the run to a file as a TAP document, see the module docs.
+#[derive(Debug)]
+pub struct Tap {
+    path: PathBuf,
+    points: Vec<String>,
+}
+
+impl Tap {
+    /// A sink appending to the given file.
+    pub fn new(path: PathBuf) -> Self {
+        Tap {
+            path,
+            points: Vec::new(),
+        }
+    }
+}
+
+impl Sink for Tap {
+    fn record(&mut self, _tool: &str, violation: &Violation) {
+        // The file's no place for colors
+        let violation = &Violation {
+            message: output::sanitize(&violation.message, false),
+            ..violation.clone()
+        };
+        let number = self.points.len() + 1;
+        // A test point's description has to fit on its line, the rest goes below it
+        let description = violation.to_string();
+        let first = description
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .replace('#', "\\#");
+        let directive = if violation.advisory {
+            " # TODO advisory"
+        } else {
+            ""
+        };
+        let mut point = format!("not ok {} - {}{}\n", number, first, directive);
+        point.push_str("  ---\n");
+        if let Some(file) = &violation.file {
+            point.push_str(&format!("  file: {:?}\n", file));
+        }
+        if let Some(span) = &violation.span {
+            point.push_str(&format!(
+                "  line: {}\n  column: {}\n",
+                span.line, span.column
+            ));
+        }
+        if let Some(lint) = &violation.lint {
+            point.push_str(&format!("  lint: {:?}\n", lint));
+        }
+        point.push_str(&format!("  severity: {}\n", violation.severity()));
+        if let Some(suggestion) = &violation.suggestion {
+            point.push_str(&format!("  suggestion: {:?}\n", suggestion));
+        }
+        point.push_str("  message: |\n");
+        for line in violation.message.lines() {
+            point.push_str(&format!("    {}\n", line));
+        }
+        point.push_str("  ...\n");
+        self.points.push(point);
+    }
+
+    fn finish(&mut self, summary: &Summary) -> Result<(), String> {
+        let mut contents = String::from("TAP version 13\n");
+        if self.points.is_empty() {
+            contents.push_str(&format!("1..1\nok 1 - {}\n", summary.tool()));
+        } else {
+            contents.push_str(&format!("1..{}\n", self.points.len()));
+            contents.extend(self.points.iter().map(String::as_str));
+        }
+        contents.push_str(&format!("# {}\n", summary.line()));
+        append_to(&self.path, &contents)
+    }
+}
+
+/// Makes the directory a sink's file goes in, if it isn't there yet.
+fn create_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+/// Appends a run's worth of output to a sink's file.  It goes out in a single
+/// write, so runs sharing the file (other hooks, or other batches of this one)
+/// don't interleave.
+fn append_to(path: &Path, contents: &str) -> Result<(), String> {
+    create_parent(path)
+        .and_then(|_| OpenOptions::new().create(true).append(true).open(path))
+        .and_then(|mut f| f.write_all(contents.as_bytes()))
+        .map_err(|e| format!("Unable to write to {}: {}", path.display(), e))
+}
+
+/// Where a sink's file goes, see the module docs.
+fn sink_path(tool: &str, path: &str) -> PathBuf {
+    let path = PathBuf::from(path.replace("{tool}", tool));
+    match find_repo_root() {
+        Some(root) if path.is_relative() => root.join(path),
+        _ => path,
+    }
+}
+
+/// Makes the sink a spec like `terminal` or `tap=results.tap` describes, or says
+/// why it can't.
+pub fn parse_sink(tool: &str, spec: &str) -> Result<Box<dyn Sink>, String> {
+    let (kind, path) = match spec.trim().split_once('=') {
+        Some((kind, path)) => (kind, Some(sink_path(tool, path))),
+        None => (spec.trim(), None),
+    };
+    match (kind, path) {
+        ("terminal", None) => Ok(Box::new(Terminal)),
+        #[cfg(feature = "json")]
+        ("json-lines", Some(path)) => Ok(Box::new(JsonLines::new(path))),
+        #[cfg(not(feature = "json"))]
+        ("json-lines", Some(_)) => Err("The json-lines sink needs the json feature".to_string()),
+        ("tap", Some(path)) => Ok(Box::new(Tap::new(path))),
+        ("json-lines" | "tap", None) => Err(format!(
+            "The {} sink needs a path, e.g. {}=results",
+            kind, kind
+        )),
+        _ => Err(format!(
+            "{} isn't a sink, they're terminal, json-lines=<path>, and tap=<path>",
+            spec
+        )),
+    }
+}
+
+/// Sends a hook's findings to every one of its sinks.
+pub struct Report {
+    tool: &'static str,
+    sinks: Vec<Box<dyn Sink>>,
+}
+
+impl Report {
+    /// A report going to the given sinks.
+    pub fn new(tool: &'static str, sinks: Vec<Box<dyn Sink>>) -> Self {
+        Report { tool, sinks }
+    }
+
+    /// A report going to the sinks the `--output=` flag names, if it was given,
+    /// or else to those salt-spray.toml picks for the tool.
+    pub fn for_tool(
+        tool: &'static str,
+        flag: Option<&str>,
+        config: &Config,
+    ) -> Result<Self, String> {
+        let specs: Vec<String> = match flag {
+            Some(flag) => flag.split(',').map(str::to_string).collect(),
+            None => config
+                .output
+                .tools
+                .get(tool)
+                .unwrap_or(&config.output.sinks)
+                .clone(),
+        };
+        let sinks = specs
+            .iter()
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| parse_sink(tool, s))
+            .collect::<Result<_, _>>()?;
+        Ok(Report::new(tool, sinks))
+    }
+
+    /// A report going where the tool's flags say: to the sinks of `--output=`, as
+    /// [`Report::for_tool`] has it, with the colors `--color=` asks for (which
+    /// goes for the rest of the process, see [`crate::output`]).  The last of each
+    /// flag wins.
+    pub fn from_flags(
+        tool: &'static str,
+        flags: &[String],
+        config: &Config,
+    ) -> Result<Self, String> {
+        if let Some(color) = flags.iter().rev().find_map(|f| f.strip_prefix(COLOR)) {
+            output::set_color(color)?;
+        }
+        let sinks = flags.iter().rev().find_map(|f| f.strip_prefix(OUTPUT));
+        Report::for_tool(tool, sinks, config)
+    }
+
+    /// Sends a finding to every sink.
+    pub fn record(&mut self, violation: &Violation) {
+        for sink in self.sinks.iter_mut() {
+            sink.record(self.tool, violation);
+        }
+    }
+
+    /// Sends each of the findings to every sink.
+    pub fn record_all(&mut self, violations: &[Violation]) {
+        for violation in violations {
+            self.record(violation);
+        }
+    }
+
+    /// Finishes off every sink.  Sinks that fail are reported, but they don't fail
+    /// the hook, since the findings have already been shown (or at least kept
+    /// somewhere else).
+    pub fn finish(&mut self, summary: &Summary) {
+        for sink in self.sinks.iter_mut() {
+            if let Err(e) = sink.finish(summary) {
+                eprintln!("{}", e);
+            }
+        }
+    }
+}
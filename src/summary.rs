@@ -0,0 +1,119 @@
+//! Every hook ends its run with a single line like
+//!
+//! ```text
+//! salt-clip: 3 crates, 17 files, 4 violations, 12.3s
+//! ```
+//!
+//! so that scripts and log scanners have something stable to parse.  Passing
+//! `--porcelain` makes that line the only thing written to stdout; everything
+//! else the hook has to say goes to stderr instead.
+//!
+//! Printing the summary also exports it as metrics, if salt-spray.toml says where
+//! to (see [`crate::metrics`]).
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::check::Violation;
+
+/// The flag that makes the summary line the only output on stdout.
+pub const PORCELAIN: &str = "--porcelain";
+
+static PORCELAIN_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Turns porcelain mode on or off for the rest of the process.
+pub fn set_porcelain(porcelain: bool) {
+    PORCELAIN_MODE.store(porcelain, Ordering::Relaxed);
+}
+
+/// Is the process in porcelain mode?
+pub fn is_porcelain() -> bool {
+    PORCELAIN_MODE.load(Ordering::Relaxed)
+}
+
+/// Like `println!`, except that in porcelain mode it writes to stderr so stdout
+/// stays reserved for the summary line.
+#[macro_export]
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if $crate::summary::is_porcelain() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Tallies what a hook did, for the final status line.
+#[derive(Debug)]
+pub struct Summary {
+    tool: &'static str,
+    start: Instant,
+    /// How many crates were examined.
+    pub crates: usize,
+    /// How many files were examined.
+    pub files: usize,
+    /// How many problems were found.
+    pub violations: usize,
+    /// How many of the (blocking) problems were down to each lint.
+    pub lints: BTreeMap<String, usize>,
+}
+
+impl Summary {
+    /// Starts the clock on a run of the named tool.
+    pub fn new(tool: &'static str) -> Self {
+        Summary {
+            tool,
+            start: Instant::now(),
+            crates: 0,
+            files: 0,
+            violations: 0,
+            lints: BTreeMap::new(),
+        }
+    }
+
+    /// The name of the tool.
+    pub fn tool(&self) -> &'static str {
+        self.tool
+    }
+
+    /// How long the tool has been running.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// What the hook exits with: how many violations there were, but at most 255,
+    /// since exit codes wrap around past that and 256 of them would pass.
+    pub fn exit_code(&self) -> i32 {
+        self.violations.min(255) as i32
+    }
+
+    /// Tallies the lints behind the blocking violations.  This doesn't touch
+    /// `violations`, which the hooks count themselves.
+    pub fn count_lints(&mut self, violations: &[Violation]) {
+        for violation in violations.iter().filter(|v| !v.advisory) {
+            if let Some(lint) = &violation.lint {
+                *self.lints.entry(lint.clone()).or_default() += 1;
+            }
+        }
+    }
+
+    /// The status line itself.
+    pub fn line(&self) -> String {
+        format!(
+            "{}: {} crates, {} files, {} violations, {:.1}s",
+            self.tool,
+            self.crates,
+            self.files,
+            self.violations,
+            self.start.elapsed().as_secs_f64()
+        )
+    }
+
+    /// Prints the status line to stdout, and exports the metrics.
+    pub fn print(&self) {
+        println!("{}", self.line());
+        crate::metrics::export(self);
+    }
+}
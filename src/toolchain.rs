@@ -0,0 +1,316 @@
+//! Which `cargo` and `rustfmt` the hooks run.
+//!
+//! By default that's whatever `cargo` is on the PATH, but hermetic setups (Bazel,
+//! Nix, and the like) can point the hooks elsewhere.  In order of precedence:
+//!
+//! 1. `--cargo-path=/path/to/cargo` on the command line,
+//! 2. `RUSTUP_TOOLCHAIN`, which makes the hooks go through rustup's `cargo` proxy
+//!    (even if `CARGO` points at some other toolchain's cargo),
+//! 3. the `CARGO` environment variable, and finally
+//! 4. plain old `cargo`.
+//!
+//! `rustfmt` and `clippy-driver` are looked for next to the chosen cargo, falling
+//! back to the PATH.
+//!
+//! Anything cached on what the toolchain said (like clippy's lint groups) is keyed
+//! on [`fingerprint`], the versions of its tools, so that an upgrade in the middle
+//! of a long running process (e.g. `salt watch`) doesn't replay stale answers.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{self, Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::{Lazy, OnceCell};
+use regex::Regex;
+
+use crate::config::Config;
+use crate::{find_workspace_root, Memo};
+
+/// The flag that picks the cargo binary to run.
+pub const CARGO_PATH: &str = "--cargo-path=";
+
+static CARGO_PATH_OVERRIDE: OnceCell<PathBuf> = OnceCell::new();
+static FINGERPRINT: Lazy<Mutex<Option<String>>> = Lazy::new(Default::default);
+static LINT_GROUP: Lazy<Regex> = Lazy::new(|| {
+    // Lines like "  clippy::style  clippy::assertions-on-constants, ..."
+    Regex::new(r"^\s*clippy::([a-z_-]+)\s+(clippy::.*)$").unwrap()
+});
+
+/// Makes the hooks use the given cargo for the rest of the process.  Only the
+/// first call has any effect.
+pub fn set_cargo_path<P: Into<PathBuf>>(path: P) {
+    let path = path.into();
+    // Commands may run from elsewhere (see cargo_for), so only bare names are left
+    // for the PATH to resolve
+    let path = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => path::absolute(&path).unwrap_or(path),
+        _ => path,
+    };
+    drop(CARGO_PATH_OVERRIDE.set(path));
+}
+
+/// The cargo binary the hooks should run.
+pub fn cargo_path() -> PathBuf {
+    if let Some(path) = CARGO_PATH_OVERRIDE.get() {
+        return path.clone();
+    }
+    if env::var_os("RUSTUP_TOOLCHAIN").is_some() {
+        return PathBuf::from("cargo");
+    }
+    env::var_os("CARGO")
+        .filter(|c| !c.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("cargo"))
+}
+
+/// A command running the chosen cargo.
+pub fn cargo() -> Command {
+    Command::new(cargo_path())
+}
+
+/// A command running the chosen cargo for the crate with the given manifest,
+/// isolated from any other workspace in the commit: it runs from the root of the
+/// crate's workspace, with the workspace's own target directory, and with the
+/// `env` from the crate's config.  Since the working directory changes, paths
+/// handed to it should be absolute.
+pub fn cargo_for(manifest: &Path, config: &Config) -> Command {
+    let workspace = find_workspace_root(manifest);
+    let workspace = path::absolute(&workspace).unwrap_or(workspace);
+    let mut cmd = cargo();
+    cmd.current_dir(&workspace)
+        .env("CARGO_TARGET_DIR", workspace.join("target"))
+        .envs(&config.env);
+    cmd
+}
+
+/// The flags cargo hands to rustc, as they're spelled in its config files.
+pub const RUSTFLAGS: &str = "rustflags";
+/// The flags cargo hands to rustdoc, as they're spelled in its config files.
+pub const RUSTDOCFLAGS: &str = "rustdocflags";
+
+/// The flags of the given kind ([`RUSTFLAGS`] or [`RUSTDOCFLAGS`]) in cargo's
+/// config files (and their environment variables) for builds from the given
+/// directory for the given target: those of `target.<triple>` if there are any,
+/// otherwise those of `build`.  Like cargo, files further up the tree (and then
+/// CARGO_HOME's) come first.  The `target.'cfg(...)'` tables aren't worked out,
+/// so they're passed over.
+fn configured_flags(config: &Config, dir: &Path, triple: Option<&str>, kind: &str) -> Vec<String> {
+    let home = env::var_os("CARGO_HOME").map(PathBuf::from).or_else(|| {
+        let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+        Some(Path::new(&home).join(".cargo"))
+    });
+    let mut dirs: Vec<PathBuf> = dir.ancestors().map(|d| d.join(".cargo")).collect();
+    dirs.reverse();
+    if let Some(home) = home.filter(|home| !dirs.contains(home)) {
+        dirs.insert(0, home);
+    }
+    let flags_in = |value: Option<&toml::Value>| -> Option<Vec<String>> {
+        match value? {
+            toml::Value::String(flags) => {
+                Some(flags.split_whitespace().map(str::to_string).collect())
+            }
+            toml::Value::Array(flags) => Some(
+                flags
+                    .iter()
+                    .filter_map(|f| f.as_str())
+                    .map(str::to_string)
+                    .collect(),
+            ),
+            _ => None,
+        }
+    };
+    let (mut target, mut build): (Option<Vec<String>>, Option<Vec<String>>) = (None, None);
+    // When both are there cargo reads `config`, the name from before `config.toml`
+    let files = dirs.iter().filter_map(|d| {
+        let file = [d.join("config"), d.join("config.toml")]
+            .into_iter()
+            .find(|f| f.is_file())?;
+        fs::read_to_string(file).ok()?.parse::<toml::Table>().ok()
+    });
+    for table in files {
+        let for_target = triple.and_then(|triple| table.get("target")?.get(triple)?.get(kind));
+        if let Some(flags) = flags_in(for_target) {
+            target.get_or_insert_with(Vec::new).extend(flags);
+        }
+        if let Some(flags) = flags_in(table.get("build").and_then(|b| b.get(kind))) {
+            build.get_or_insert_with(Vec::new).extend(flags);
+        }
+    }
+    let var = |name: &str| {
+        config
+            .env
+            .get(name)
+            .cloned()
+            .or_else(|| env::var(name).ok())
+    };
+    let kind = kind.to_uppercase();
+    let triple_var = triple.map(|t| {
+        let t = t.to_uppercase().replace(['-', '.'], "_");
+        format!("CARGO_TARGET_{}_{}", t, kind)
+    });
+    if let Some(flags) = triple_var.as_deref().and_then(var) {
+        target
+            .get_or_insert_with(Vec::new)
+            .extend(flags.split_whitespace().map(str::to_string));
+    }
+    if let Some(flags) = var(&format!("CARGO_BUILD_{}", kind)) {
+        build
+            .get_or_insert_with(Vec::new)
+            .extend(flags.split_whitespace().map(str::to_string));
+    }
+    target.or(build).unwrap_or_default()
+}
+
+/// The flags of the given kind ([`RUSTFLAGS`] or [`RUSTDOCFLAGS`]) that cargo
+/// would use for the crate with the given manifest, built for the given target
+/// (or the host), plus the extra ones, in the form of `CARGO_ENCODED_RUSTFLAGS`
+/// (or `CARGO_ENCODED_RUSTDOCFLAGS`).  Setting that makes cargo ignore every other
+/// source of them, so they're all gathered here.  Cargo splits `RUSTFLAGS` on
+/// spaces, so existing flags with spaces in them (e.g. paths) only survive in the
+/// encoded form, which cargo prefers anyway.
+pub fn encoded_flags(
+    manifest: &Path,
+    config: &Config,
+    triple: Option<&str>,
+    kind: &str,
+    extra: &str,
+) -> String {
+    let var = |name: &str| {
+        config
+            .env
+            .get(name)
+            .cloned()
+            .or_else(|| env::var(name).ok())
+    };
+    let name = kind.to_uppercase();
+    let encoded = var(&format!("CARGO_ENCODED_{}", name));
+    let mut flags: Vec<String> = match (encoded, var(&name)) {
+        (Some(encoded), _) => encoded.split('\u{1f}').map(str::to_string).collect(),
+        (None, Some(flags)) => flags.split_whitespace().map(str::to_string).collect(),
+        (None, None) => {
+            // Cargo runs from the workspace (see cargo_for), which is where it
+            // looks for its config files from
+            let dir = find_workspace_root(manifest);
+            let dir = path::absolute(&dir).unwrap_or(dir);
+            let host = triple.is_none().then(host_triple).flatten();
+            configured_flags(config, &dir, triple.or(host.as_deref()), kind)
+        }
+    };
+    flags.extend(extra.split_whitespace().map(str::to_string));
+    flags.retain(|f| !f.is_empty());
+    flags.join("\u{1f}")
+}
+
+/// A command running the named tool from the same toolchain as the chosen cargo,
+/// falling back to the one on the PATH.
+fn beside_cargo(tool: &str) -> Command {
+    let cargo = cargo_path();
+    // A bare `cargo` has an empty parent, and is looked up on the PATH like the tool
+    let dir = cargo.parent().filter(|dir| !dir.as_os_str().is_empty());
+    let sibling = dir.map(|dir| {
+        let mut name = OsString::from(tool);
+        name.push(env::consts::EXE_SUFFIX);
+        dir.join(name)
+    });
+    match sibling {
+        Some(path) if path.is_file() => Command::new(path),
+        _ => Command::new(tool),
+    }
+}
+
+/// A command running the rustfmt that goes with the chosen cargo.
+pub fn rustfmt() -> Command {
+    beside_cargo("rustfmt")
+}
+
+/// A command running the clippy-driver that goes with the chosen cargo.
+pub fn clippy_driver() -> Command {
+    beside_cargo("clippy-driver")
+}
+
+/// A command running the rustc that goes with the chosen cargo.
+pub fn rustc() -> Command {
+    beside_cargo("rustc")
+}
+
+/// The target the toolchain's rustc builds for by default (e.g.
+/// `x86_64-unknown-linux-gnu`), or None if rustc couldn't be asked.
+pub fn host_triple() -> Option<String> {
+    static HOSTS: Memo<String, Option<String>> = Memo::new();
+    HOSTS.get_or_insert_with(fingerprint(), || {
+        let output = rustc().arg("-vV").stderr(Stdio::null()).output().ok()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let host = stdout.lines().find_map(|l| l.strip_prefix("host: "))?;
+        Some(host.trim().to_string())
+    })
+}
+
+/// Asks each of the toolchain's tools for its version.
+fn versions() -> String {
+    let tools = [cargo(), rustc(), clippy_driver(), rustfmt()];
+    let versions = tools.into_iter().map(|mut tool| {
+        let output = tool.arg("--version").stderr(Stdio::null()).output();
+        match output {
+            Ok(output) if output.status.success() => {
+                String::from_utf8_lossy(&output.stdout).trim().to_string()
+            }
+            _ => format!("{:?} is unavailable", tool.get_program()),
+        }
+    });
+    versions.collect::<Vec<_>>().join("\n")
+}
+
+/// The versions of the toolchain's cargo, rustc, clippy, and rustfmt, for keying
+/// anything cached on what they said.  They're only asked once, until
+/// [`refresh_fingerprint`].
+pub fn fingerprint() -> String {
+    let mut fingerprint = FINGERPRINT.lock().unwrap_or_else(|e| e.into_inner());
+    fingerprint.get_or_insert_with(versions).clone()
+}
+
+/// Asks the toolchain for its versions again, returning whether they changed
+/// (which makes everything keyed on the old fingerprint stale).
+pub fn refresh_fingerprint() -> bool {
+    let current = versions();
+    let mut fingerprint = FINGERPRINT.lock().unwrap_or_else(|e| e.into_inner());
+    let changed = fingerprint.as_ref().is_some_and(|f| *f != current);
+    *fingerprint = Some(current);
+    changed
+}
+
+/// Clippy's lint groups (e.g. `style`), each with the lints in it (e.g.
+/// `clippy::needless_return`).
+pub type LintGroups = BTreeMap<String, BTreeSet<String>>;
+
+/// Clippy's lint groups, as told by the toolchain's own clippy.  Empty if clippy
+/// couldn't be asked.  They're asked again whenever the toolchain changes.
+pub fn lint_groups() -> Arc<LintGroups> {
+    static GROUPS: Memo<String, Arc<LintGroups>> = Memo::new();
+    GROUPS.get_or_insert_with(fingerprint(), || {
+        let output = clippy_driver()
+            .args(["rustc", "-W", "help"])
+            .stderr(Stdio::null())
+            .output();
+        let stdout = match output {
+            Ok(Output { stdout, .. }) => String::from_utf8_lossy(&stdout).to_string(),
+            Err(e) => {
+                eprintln!("Unable to ask clippy-driver for its lint groups: {}", e);
+                return Default::default();
+            }
+        };
+        let mut result = BTreeMap::new();
+        for captures in stdout.lines().filter_map(|l| LINT_GROUP.captures(l)) {
+            // The help spells lints with dashes, diagnostics with underscores
+            let lints = captures[2]
+                .split(',')
+                .map(|lint| lint.trim().replace('-', "_"))
+                .collect();
+            result.insert(captures[1].replace('-', "_"), lints);
+        }
+        Arc::new(result)
+    })
+}
@@ -0,0 +1,137 @@
+//! `salt watch`, which gives instant local feedback by re-running the checks
+//! whenever a file is saved, with exactly the same rules as the commit hooks.
+//!
+//! Only the files that changed are checked (clippy lints the crates they're in),
+//! and since the process stays up, everything learned about manifests and
+//! workspaces along the way stays cached between runs (until a Cargo.toml
+//! changes), as does cargo's build cache.  The config is reread for every run,
+//! and the toolchain's versions are checked before every run, so that upgrading
+//! it (e.g. with `rustup update`) throws out anything learned from the old one.
+//! Changes under `target/` and `.git/` are ignored.
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+use std::{env, process};
+
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+use crate::check::{run_checks, Check};
+use crate::summary::Summary;
+use crate::toolchain;
+use crate::{find_manifest, find_repo_root, forget_manifests};
+
+/// How long to wait for things to settle after a change, since editors often
+/// write a file in several steps and a save can touch several files.
+const SETTLE: Duration = Duration::from_millis(200);
+
+/// Should a change to the given path be ignored?
+fn is_ignored(path: &Path) -> bool {
+    path.components().any(|c| match c {
+        Component::Normal(name) => name == "target" || name == ".git",
+        _ => false,
+    })
+}
+
+/// The changed paths of the event, relative to the current directory when they're
+/// inside of it.
+fn changed_paths(event: Event, cwd: &Path) -> Vec<PathBuf> {
+    if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+        return Vec::new();
+    }
+    event
+        .paths
+        .into_iter()
+        .map(|p| p.strip_prefix(cwd).map(Path::to_path_buf).unwrap_or(p))
+        .filter(|p| !is_ignored(p))
+        .collect()
+}
+
+/// Waits for a change, then collects everything else that changes until things
+/// settle down.
+fn next_changes(events: &Receiver<notify::Result<Event>>, cwd: &Path) -> Vec<PathBuf> {
+    let mut result = Vec::new();
+    while result.is_empty() {
+        match events.recv() {
+            Ok(Ok(event)) => result.extend(changed_paths(event, cwd)),
+            Ok(Err(e)) => eprintln!("Error while watching: {}", e),
+            Err(_) => process::exit(-1),
+        }
+    }
+    loop {
+        match events.recv_timeout(SETTLE) {
+            Ok(Ok(event)) => result.extend(changed_paths(event, cwd)),
+            Ok(Err(e)) => eprintln!("Error while watching: {}", e),
+            Err(RecvTimeoutError::Timeout) => break,
+            Err(RecvTimeoutError::Disconnected) => process::exit(-1),
+        }
+    }
+    result.sort();
+    result.dedup();
+    result
+}
+
+/// Watches the given directories (or the whole repo if there aren't any), running
+/// the checks over each batch of changed files.  Never returns.
+pub fn watch(checks: &[Box<dyn Check>], dirs: &[String]) -> ! {
+    let cwd = env::current_dir().unwrap_or_default();
+    let dirs: Vec<PathBuf> = if dirs.is_empty() {
+        find_repo_root().into_iter().collect()
+    } else {
+        dirs.iter().map(PathBuf::from).collect()
+    };
+    if dirs.is_empty() {
+        eprintln!("salt watch needs a directory to watch when run outside of a git repo");
+        process::exit(-1);
+    }
+
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).unwrap_or_else(|e| {
+        eprintln!("Unable to watch for changes: {}", e);
+        process::exit(-1);
+    });
+    for dir in dirs.iter() {
+        if let Err(e) = watcher.watch(dir, RecursiveMode::Recursive) {
+            eprintln!("Unable to watch {}: {}", dir.display(), e);
+            process::exit(-1);
+        }
+    }
+
+    // Ask for the versions now, so the first run has something to compare against
+    drop(toolchain::fingerprint());
+    eprintln!("Watching for changes...");
+    loop {
+        let changes = next_changes(&events, &cwd);
+        // A manifest changing can move files between crates and workspaces
+        if changes.iter().any(|p| p.ends_with("Cargo.toml")) {
+            forget_manifests();
+        }
+        let files: Vec<String> = changes
+            .iter()
+            .filter(|p| p.is_file())
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+        if files.is_empty() {
+            continue;
+        }
+        if toolchain::refresh_fingerprint() {
+            eprintln!("The toolchain changed, so forgetting everything learned from the old one");
+            forget_manifests();
+        }
+
+        let mut summary = Summary::new("salt watch");
+        let violations = run_checks(checks, &files, false);
+        for violation in violations.iter() {
+            eprintln!("{}", violation);
+        }
+        summary.files = files.len();
+        summary.crates = files
+            .iter()
+            .filter_map(find_manifest)
+            .collect::<std::collections::BTreeSet<_>>()
+            .len();
+        summary.violations = violations.iter().filter(|v| !v.advisory).count();
+        summary.count_lints(&violations);
+        summary.print();
+    }
+}
@@ -0,0 +1,53 @@
+//! Budget schedules that can't be worked out, which loading the config rejects.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use salt_spray::config::Config;
+
+/// Writes a config with just the given budget for `dead_code`, unique to the
+/// test, returning its path.
+fn config_with(test: &str, budget: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("salt-spray-{}-{}.toml", process::id(), test));
+    fs::write(&path, format!("[ratchet.budget.dead_code]\n{}", budget)).unwrap();
+    path
+}
+
+fn load_error(test: &str, budget: &str) -> String {
+    let path = config_with(test, budget);
+    let error = Config::load_from(&path).err().unwrap_or_default();
+    drop(fs::remove_file(&path));
+    error
+}
+
+#[test]
+fn malformed_since() {
+    let error = load_error("malformed-since", "start = 3\nsince = \"2026/01/01\"\n");
+    assert!(error.contains(":3:9: "), "{}", error);
+    assert!(error.contains("YYYY-MM-DD"), "{}", error);
+}
+
+#[test]
+fn start_without_since() {
+    let error = load_error("start-only", "start = 3\n");
+    assert!(error.contains(":2:9: "), "{}", error);
+}
+
+#[test]
+fn since_without_start() {
+    let error = load_error("since-only", "since = \"2026-01-01\"\n");
+    assert!(error.contains(":2:9: "), "{}", error);
+}
+
+#[test]
+fn whole_schedule() {
+    let path = config_with(
+        "whole",
+        "start = 3\nsince = \"2026-01-01\"\ndecrease-per-week = 1\n",
+    );
+    let config = Config::load_from(&path);
+    drop(fs::remove_file(&path));
+    assert!(config.is_ok(), "{:?}", config.err());
+}
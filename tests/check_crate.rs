@@ -0,0 +1,102 @@
+//! salt-check on the modules of a binary, which cargo has no `--lib` for, and on
+//! crates it passes extra rustflags for.
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use salt_spray::clippy::{check_crate, Options};
+use salt_spray::config::Config;
+
+/// Makes a crate with just a binary, whose `helper` module has the given code,
+/// unique to the test, returning the directory.
+fn bin_crate(test: &str, manifest_extra: &str, helper: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("salt-spray-{}-{}", process::id(), test));
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        format!(
+            "[package]\nname = \"checked\"\nversion = \"0.1.0\"\nedition = \"2021\"\n{}",
+            manifest_extra
+        ),
+    )
+    .unwrap();
+    fs::write(
+        dir.join("src").join("main.rs"),
+        "mod helper;\n\nfn main() {\n    helper::run();\n}\n",
+    )
+    .unwrap();
+    fs::write(dir.join("src").join("helper.rs"), helper).unwrap();
+    dir
+}
+
+fn check(dir: &Path) -> usize {
+    check_with(dir, "helper.rs")
+}
+
+fn check_with(dir: &Path, file: &str) -> usize {
+    let manifest = dir.join("Cargo.toml");
+    let files = BTreeSet::from([dir.join("src").join(file).display().to_string()]);
+    let violations = check_crate(
+        &manifest.display().to_string(),
+        &files,
+        &Options::default(),
+        &Config::default(),
+    );
+    violations.iter().filter(|v| !v.advisory).count()
+}
+
+#[test]
+fn errors_in_a_module_of_a_binary() {
+    let dir = bin_crate(
+        "bin-module",
+        "",
+        "pub fn run() {\n    let _x: u32 = \"s\";\n}\n",
+    );
+    assert_eq!(check(&dir), 1);
+    drop(fs::remove_dir_all(&dir));
+}
+
+#[test]
+fn cargo_failing_without_diagnostics() {
+    let dir = bin_crate(
+        "no-diagnostics",
+        "\n[dependencies]\nmissing = { path = \"nowhere\" }\n",
+        "pub fn run() {}\n",
+    );
+    assert_eq!(check(&dir), 1);
+    drop(fs::remove_dir_all(&dir));
+}
+
+#[test]
+fn extra_rustflags_keep_the_configured_ones() {
+    let dir = env::temp_dir().join(format!("salt-spray-{}-rustflags", process::id()));
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::create_dir_all(dir.join(".cargo")).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"flagged\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n\
+         [lib]\nproc-macro = true\n\n[lints.rust]\nunexpected_cfgs = \"allow\"\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("src").join("lib.rs"),
+        "#[cfg(not(configured))]\ncompile_error!(\"configured\");\n\
+         #[cfg(not(extra))]\ncompile_error!(\"extra\");\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join(".cargo").join("config.toml"),
+        "[build]\nrustflags = [\"--cfg\", \"configured\"]\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("salt-spray.toml"),
+        "[proc-macro]\nrustflags = \"--cfg extra\"\n",
+    )
+    .unwrap();
+    assert_eq!(check_with(&dir, "lib.rs"), 0);
+    drop(fs::remove_dir_all(&dir));
+}
@@ -0,0 +1,87 @@
+use salt_spray::ratchet::count_suppressions;
+
+fn count(contents: &str, lint: &str) -> usize {
+    let counts = count_suppressions(contents).expect(contents);
+    counts.get(lint).copied().unwrap_or(0)
+}
+
+#[test]
+fn each_listed_lint_counts() {
+    let contents = "#[allow(dead_code, unused_mut, unused_imports)]\nfn f() {}\n";
+    assert_eq!(count(contents, "dead_code"), 1);
+    assert_eq!(count(contents, "unused_mut"), 1);
+    assert_eq!(count(contents, "unused_imports"), 1);
+}
+
+#[test]
+fn repeated_lint_in_one_list() {
+    assert_eq!(
+        count("#[allow(dead_code, dead_code)]\nfn f() {}\n", "dead_code"),
+        1
+    );
+}
+
+#[test]
+fn repeated_attribute_on_one_item() {
+    let contents = "#[allow(dead_code)]\n#[allow(dead_code)]\nfn f() {}\n";
+    assert_eq!(count(contents, "dead_code"), 1);
+}
+
+#[test]
+fn same_lint_on_different_items() {
+    let contents = "#[allow(dead_code)]\nfn f() {}\n#[allow(dead_code)]\nfn g() {}\n";
+    assert_eq!(count(contents, "dead_code"), 2);
+}
+
+#[test]
+fn cfg_attr() {
+    let contents = "#[cfg_attr(test, allow(dead_code))]\nfn f() {}\n";
+    assert_eq!(count(contents, "dead_code"), 1);
+}
+
+#[test]
+fn cfg_attr_and_plain_allow_of_the_same_lint() {
+    let contents = "#[allow(dead_code)]\n#[cfg_attr(test, allow(dead_code, unused))]\nfn f() {}\n";
+    assert_eq!(count(contents, "dead_code"), 1);
+    assert_eq!(count(contents, "unused"), 1);
+}
+
+#[test]
+fn nested_cfg_attr() {
+    let contents = "#[cfg_attr(unix, cfg_attr(test, allow(dead_code)))]\nfn f() {}\n";
+    assert_eq!(count(contents, "dead_code"), 1);
+}
+
+#[test]
+fn cfg_predicate_is_not_a_lint() {
+    let contents = "#[cfg_attr(dead_code, allow(unused))]\nfn f() {}\n";
+    assert_eq!(count(contents, "dead_code"), 0);
+}
+
+#[test]
+fn module_counts_for_each_item() {
+    let contents = "#[allow(dead_code, dead_code)]\nmod m {\n    fn f() {}\n    fn g() {}\n}\n";
+    assert_eq!(count(contents, "dead_code"), 2);
+}
+
+#[test]
+fn nested_module_items_count_on_their_own() {
+    let contents = "#[allow(dead_code)]\n\
+                    mod m {\n\
+                        #[allow(dead_code)]\n\
+                        #[allow(dead_code)]\n\
+                        fn f() {}\n\
+                        mod n {\n\
+                            #[allow(dead_code)]\n\
+                            fn g() {}\n\
+                        }\n\
+                    }\n";
+    // Two for m's items, one for f, one for g
+    assert_eq!(count(contents, "dead_code"), 4);
+}
+
+#[test]
+fn inner_attribute_counts_for_each_item() {
+    let contents = "#![allow(dead_code)]\n#![allow(dead_code)]\nfn f() {}\nfn g() {}\n";
+    assert_eq!(count(contents, "dead_code"), 2);
+}
@@ -0,0 +1,92 @@
+use proc_macro2::TokenStream;
+
+use salt_spray::dupe::Fingerprints;
+
+fn fingerprints(code: &str) -> Fingerprints {
+    let tokens: TokenStream = code.parse().expect(code);
+    Fingerprints::of(tokens)
+}
+
+static ORIGINAL: &str = r#"
+fn total_weight(items: &[Item], limit: usize) -> Result<u64, String> {
+    let mut total = 0;
+    for item in items.iter().take(limit) {
+        if item.weight == 0 {
+            return Err(format!("{} has no weight", item.name));
+        }
+        total += item.weight;
+    }
+    Ok(total)
+}
+"#;
+
+#[test]
+fn identical_code() {
+    let original = fingerprints(ORIGINAL);
+    assert_eq!(original.similarity(&fingerprints(ORIGINAL)), 1.0);
+}
+
+#[test]
+fn renamed_and_reformatted_copy() {
+    let copy = r#"
+fn sum_of_masses(things: &[Thing], max: usize) -> Result<u64, String> {
+    let mut sum = 0;
+    for thing in things.iter().take(max) {
+        if thing.mass == 0 { return Err(format!("{} is massless", thing.label)); }
+        sum += thing.mass;
+    }
+    Ok(sum)
+}
+"#;
+    let original = fingerprints(ORIGINAL);
+    assert_eq!(original.similarity(&fingerprints(copy)), 1.0);
+}
+
+#[test]
+fn copy_with_a_line_added() {
+    let copy = r#"
+fn total_weight(items: &[Item], limit: usize) -> Result<u64, String> {
+    let mut total = 0;
+    for item in items.iter().take(limit) {
+        if item.weight == 0 {
+            return Err(format!("{} has no weight", item.name));
+        }
+        println!("{}", item.name);
+        total += item.weight;
+    }
+    Ok(total)
+}
+"#;
+    let similarity = fingerprints(ORIGINAL).similarity(&fingerprints(copy));
+    assert!(similarity > 0.5 && similarity < 1.0, "{}", similarity);
+}
+
+#[test]
+fn unrelated_code() {
+    let other = r#"
+fn parse_header(line: &str) -> Option<(String, String)> {
+    let (name, value) = line.split_once(':')?;
+    match name.trim() {
+        "" => None,
+        name => Some((name.to_lowercase(), value.trim().to_string())),
+    }
+}
+"#;
+    let similarity = fingerprints(ORIGINAL).similarity(&fingerprints(other));
+    assert!(similarity < 0.2, "{}", similarity);
+}
+
+#[test]
+fn token_count_ignores_formatting() {
+    let spread = "fn f(a: u8) -> u8 {\n    a\n        + 1\n}\n";
+    assert_eq!(
+        fingerprints(spread).tokens,
+        fingerprints("fn f(a: u8) -> u8 { a + 1 }").tokens
+    );
+}
+
+#[test]
+fn too_short_to_fingerprint() {
+    let short = fingerprints("fn f() {}");
+    assert_eq!(short.similarity(&fingerprints("fn g() {}")), 0.0);
+}
@@ -0,0 +1,46 @@
+//! Formatting the staged copy of a partially staged file, which mustn't lose the
+//! unstaged changes whatever goes wrong.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::{self, Command};
+
+use salt_spray::fmt::{format_staged, Options};
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .args(["-c", "user.name=t", "-c", "user.email=t@t"])
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .unwrap();
+    assert!(status.success(), "git {:?}", args);
+}
+
+#[test]
+fn failing_to_stage_keeps_the_unstaged_changes() {
+    let dir = env::temp_dir().join(format!("salt-spray-{}-format-staged", process::id()));
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"staged\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::write(dir.join("src").join("lib.rs"), "pub fn a() {}\n").unwrap();
+    git(&dir, &["init", "-q"]);
+    git(&dir, &["add", "-A"]);
+    git(&dir, &["commit", "-q", "-m", "first"]);
+    fs::write(dir.join("src").join("lib.rs"), "pub fn a()   {}\n").unwrap();
+    git(&dir, &["add", "src/lib.rs"]);
+    let worktree = "pub fn a()   {}\npub fn b() {}\n";
+    fs::write(dir.join("src").join("lib.rs"), worktree).unwrap();
+    // With the index locked the formatted copy can't be staged
+    fs::write(dir.join(".git").join("index.lock"), "").unwrap();
+
+    env::set_current_dir(&dir).unwrap();
+    let result = format_staged("src/lib.rs", &Options::default());
+    assert!(result.is_err(), "{:?}", result);
+    assert_eq!(fs::read_to_string("src/lib.rs").unwrap(), worktree);
+    drop(fs::remove_dir_all(&dir));
+}
@@ -0,0 +1,39 @@
+use salt_spray::is_test_code;
+
+/// Is the first item of the source test code?
+fn is_test(source: &str) -> bool {
+    let file = syn::parse_file(source).expect(source);
+    match &file.items[0] {
+        syn::Item::Fn(f) => is_test_code(&f.attrs),
+        syn::Item::Mod(m) => is_test_code(&m.attrs),
+        item => panic!("not a fn or mod: {:?}", item),
+    }
+}
+
+#[test]
+fn test_attributes() {
+    assert!(is_test("#[test]\nfn f() {}\n"));
+    assert!(is_test("#[tokio::test]\nasync fn f() {}\n"));
+    assert!(!is_test("#[inline]\nfn f() {}\n"));
+}
+
+#[test]
+fn cfg_test() {
+    assert!(is_test("#[cfg(test)]\nmod tests {}\n"));
+    assert!(is_test(
+        "#[cfg(all(test, feature = \"slow\"))]\nmod tests {}\n"
+    ));
+    assert!(is_test(
+        "#[cfg(any(test, all(test, unix)))]\nmod tests {}\n"
+    ));
+}
+
+#[test]
+fn cfg_true_outside_of_tests() {
+    assert!(!is_test("#[cfg(not(test))]\nfn f() {}\n"));
+    assert!(!is_test(
+        "#[cfg(any(test, feature = \"testing\"))]\nfn f() {}\n"
+    ));
+    assert!(!is_test("#[cfg(feature = \"test\")]\nfn f() {}\n"));
+    assert!(!is_test("#[cfg(all(unix, not(test)))]\nfn f() {}\n"));
+}
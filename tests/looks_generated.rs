@@ -0,0 +1,61 @@
+//! Which files a crate's build script gives away as generated.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use salt_spray::looks_generated;
+
+/// Makes a crate with the given build script, unique to the test, returning the
+/// directory.
+fn crate_with_build_script(test: &str, script: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("salt-spray-{}-{}", process::id(), test));
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"built\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::write(dir.join("build.rs"), script).unwrap();
+    for file in ["lib.rs", "gen.rs", "schema.rs"] {
+        fs::write(dir.join("src").join(file), "").unwrap();
+    }
+    dir
+}
+
+#[test]
+fn written_files_are_generated() {
+    let dir = crate_with_build_script(
+        "written",
+        "fn main() {\n    std::fs::write(\"src/gen.rs\", \"\").unwrap();\n}\n",
+    );
+    assert!(looks_generated(&dir.join("src").join("gen.rs")));
+    assert!(!looks_generated(&dir.join("src").join("lib.rs")));
+    drop(fs::remove_dir_all(&dir));
+}
+
+#[test]
+fn copy_destinations_are_generated() {
+    let dir = crate_with_build_script(
+        "copied",
+        "fn main() {\n    std::fs::copy(\"src/schema.rs\", \"src/gen.rs\").unwrap();\n}\n",
+    );
+    assert!(looks_generated(&dir.join("src").join("gen.rs")));
+    assert!(!looks_generated(&dir.join("src").join("schema.rs")));
+    drop(fs::remove_dir_all(&dir));
+}
+
+#[test]
+fn read_files_are_not_generated() {
+    let dir = crate_with_build_script(
+        "read",
+        "fn main() {\n    \
+         println!(\"cargo:rerun-if-changed={}\", \"src/schema.rs\");\n    \
+         let schema = std::fs::read_to_string(\"src/schema.rs\").unwrap();\n    \
+         let out = std::env::var(\"OUT_DIR\").unwrap();\n    \
+         std::fs::write(format!(\"{}/schema.rs\", out), schema).unwrap();\n}\n",
+    );
+    assert!(!looks_generated(&dir.join("src").join("schema.rs")));
+    drop(fs::remove_dir_all(&dir));
+}
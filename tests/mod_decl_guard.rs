@@ -0,0 +1,65 @@
+//! salt-mod-decl-guard on small crates, run the way pre-commit runs it.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output};
+
+/// Makes a crate (in a repo of its own) with the given files, unique to the test,
+/// returning the directory.
+fn crate_with(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = env::temp_dir().join(format!("salt-spray-{}-{}", process::id(), test));
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"modules\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    for (file, contents) in files {
+        fs::write(dir.join("src").join(file), contents).unwrap();
+    }
+    let init = Command::new("git")
+        .args(["init", "-q"])
+        .current_dir(&dir)
+        .status();
+    assert!(init.is_ok_and(|s| s.success()));
+    dir
+}
+
+fn guard(dir: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_salt-mod-decl-guard"))
+        .arg("src/lib.rs")
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn children_of_a_path_file_live_next_to_it() {
+    let dir = crate_with(
+        "path-children",
+        &[
+            ("lib.rs", "#[path = \"x.rs\"]\nmod foo;\n"),
+            ("x.rs", "mod bar;\n"),
+            ("bar.rs", ""),
+        ],
+    );
+    let output = guard(&dir);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    drop(fs::remove_dir_all(&dir));
+}
+
+#[test]
+fn orphans_and_missing_files() {
+    let dir = crate_with(
+        "orphans",
+        &[("lib.rs", "mod gone;\n"), ("forgotten.rs", "")],
+    );
+    let output = guard(&dir);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr);
+    assert!(stderr.contains("src/gone.rs"), "{}", stderr);
+    assert!(stderr.contains("forgotten.rs"), "{}", stderr);
+    drop(fs::remove_dir_all(&dir));
+}
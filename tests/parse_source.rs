@@ -0,0 +1,49 @@
+use salt_spray::parse_source;
+
+fn fn_count(contents: &str) -> usize {
+    let ast = parse_source(contents).expect(contents);
+    ast.items.iter().filter(|i| matches!(i, syn::Item::Fn(_))).count()
+}
+
+#[test]
+fn plain_file() {
+    assert_eq!(fn_count("fn main() {}\n"), 1);
+}
+
+#[test]
+fn byte_order_mark() {
+    assert_eq!(fn_count("\u{feff}fn main() {}\n"), 1);
+}
+
+#[test]
+fn shebang() {
+    assert_eq!(fn_count("#!/usr/bin/env run-cargo-script\nfn main() {}\n"), 1);
+}
+
+#[test]
+fn byte_order_mark_and_shebang() {
+    assert_eq!(fn_count("\u{feff}#!/usr/bin/env cargo\nfn main() {}\n"), 1);
+}
+
+#[test]
+fn inner_attribute_is_not_a_shebang() {
+    let ast = parse_source("#![allow(dead_code)]\nfn main() {}\n").unwrap();
+    assert_eq!(ast.attrs.len(), 1);
+    assert!(ast.shebang.is_none());
+}
+
+#[test]
+fn cargo_script_frontmatter() {
+    let contents = "#!/usr/bin/env -S cargo +nightly -Zscript\n\
+                    ---cargo\n\
+                    [dependencies]\n\
+                    regex = \"1\"\n\
+                    ---\n\
+                    fn main() {}\n";
+    assert_eq!(fn_count(contents), 1);
+}
+
+#[test]
+fn garbage_is_an_error_not_a_panic() {
+    assert!(parse_source("#!/bin/sh\necho hello\n").is_err());
+}
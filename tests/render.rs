@@ -0,0 +1,95 @@
+use salt_spray::check::{Span, Violation};
+use salt_spray::render::render_source;
+
+static SOURCE: &str = "use std::fmt;\n\npub enum Mode {\n    Fast,\n    Slow,\n}\n";
+
+fn mode() -> Violation {
+    Violation::new("src/lib.rs", "pub enum Mode must be #[non_exhaustive]")
+        .for_lint("enum-guard")
+        .at(Span::new(3, 10, 4))
+        .suggesting("add #[non_exhaustive] above it")
+}
+
+#[test]
+fn source_with_carets() {
+    let expected = "\
+error[enum-guard]: pub enum Mode must be #[non_exhaustive]
+ --> src/lib.rs:3:10
+  |
+3 | pub enum Mode {
+  |          ^^^^
+  = help: add #[non_exhaustive] above it";
+    assert_eq!(render_source(&mode(), Some(SOURCE), false), expected);
+}
+
+#[test]
+fn advisory_is_a_warning() {
+    let rendered = render_source(&mode().advisory(), Some(SOURCE), false);
+    assert!(
+        rendered.starts_with("warning[enum-guard]: "),
+        "{}",
+        rendered
+    );
+}
+
+#[test]
+fn spans_over_several_lines() {
+    let violation = Violation::new("src/lib.rs", "too slow").at(Span {
+        line: 4,
+        column: 5,
+        end_line: 5,
+        end_column: 9,
+    });
+    let expected = "\
+error: too slow
+ --> src/lib.rs:4:5
+  |
+4 |     Fast,
+  |     ^^^^^
+5 |     Slow,
+  |     ^^^^";
+    assert_eq!(render_source(&violation, Some(SOURCE), false), expected);
+}
+
+#[test]
+fn empty_span_covers_the_rest_of_the_line() {
+    let violation = Violation::new("src/lib.rs", "not here").at(Span::line(3));
+    let rendered = render_source(&violation, Some(SOURCE), false);
+    assert!(rendered.ends_with("  | ^^^^^^^^^^^^^^^"), "{}", rendered);
+}
+
+#[test]
+fn missing_source_leaves_out_the_lines() {
+    let expected = "\
+error[enum-guard]: pub enum Mode must be #[non_exhaustive]
+ --> src/lib.rs:3:10
+  = help: add #[non_exhaustive] above it";
+    assert_eq!(render_source(&mode(), None, false), expected);
+}
+
+#[test]
+fn plain_and_prerendered_messages_are_left_alone() {
+    let plain = Violation::new("src/lib.rs", "Unable to parse it");
+    assert_eq!(
+        render_source(&plain, Some(SOURCE), false),
+        "src/lib.rs: Unable to parse it"
+    );
+    let rendered =
+        Violation::new("src/lib.rs", "warning: unused\n --> src/lib.rs:1:5").at(Span::new(1, 5, 3));
+    assert_eq!(
+        render_source(&rendered, Some(SOURCE), true),
+        "warning: unused\n --> src/lib.rs:1:5"
+    );
+}
+
+#[test]
+fn colors_only_when_asked() {
+    assert!(!render_source(&mode(), Some(SOURCE), false).contains('\u{1b}'));
+    let colored = render_source(&mode(), Some(SOURCE), true);
+    assert!(
+        colored.starts_with("\u{1b}[1;31merror[enum-guard]"),
+        "{}",
+        colored
+    );
+    assert!(colored.contains("\u{1b}[1;31m^^^^\u{1b}[0m"), "{}", colored);
+}
@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+
+use salt_spray::clippy::resolve_env_vars;
+
+/// Resolves the value with the given assignments from earlier on the command line.
+fn resolve(value: &str, earlier: &[(&str, &str)]) -> Result<String, String> {
+    let earlier: BTreeMap<String, String> = earlier
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    resolve_env_vars(value, &earlier)
+}
+
+#[test]
+fn plain_and_braced_references() {
+    let earlier = [("SALT_SPRAY_A", "a")];
+    assert_eq!(resolve("$SALT_SPRAY_A/x", &earlier).unwrap(), "a/x");
+    assert_eq!(resolve("${SALT_SPRAY_A}x", &earlier).unwrap(), "ax");
+}
+
+#[test]
+fn default_for_unset() {
+    assert_eq!(resolve("${SALT_SPRAY_UNSET:-d}", &[]).unwrap(), "d");
+}
+
+#[test]
+fn default_for_empty() {
+    let earlier = [("SALT_SPRAY_EMPTY", "")];
+    assert_eq!(resolve("${SALT_SPRAY_EMPTY:-d}", &earlier).unwrap(), "d");
+}
+
+#[test]
+fn default_not_used_when_set() {
+    let earlier = [("SALT_SPRAY_A", "a")];
+    assert_eq!(resolve("${SALT_SPRAY_A:-d}", &earlier).unwrap(), "a");
+}
+
+#[test]
+fn default_can_refer_to_another_variable() {
+    let earlier = [("SALT_SPRAY_A", "a")];
+    assert_eq!(
+        resolve("${SALT_SPRAY_UNSET:-$SALT_SPRAY_A}", &earlier).unwrap(),
+        "a"
+    );
+}
+
+#[test]
+fn empty_value_is_not_unknown() {
+    let earlier = [("SALT_SPRAY_EMPTY", "")];
+    assert_eq!(resolve("[$SALT_SPRAY_EMPTY]", &earlier).unwrap(), "[]");
+}
+
+#[test]
+fn self_reference() {
+    let earlier = [("PATH", "/bin")];
+    assert_eq!(resolve("$PATH:/extra", &earlier).unwrap(), "/bin:/extra");
+}
+
+#[test]
+fn what_is_put_in_is_not_resolved_again() {
+    let earlier = [("SALT_SPRAY_A", "$SALT_SPRAY_UNSET")];
+    assert_eq!(
+        resolve("$SALT_SPRAY_A", &earlier).unwrap(),
+        "$SALT_SPRAY_UNSET"
+    );
+}
+
+#[test]
+fn unknown_variable() {
+    let error = resolve("x$SALT_SPRAY_UNSET", &[]).unwrap_err();
+    assert!(error.contains("$SALT_SPRAY_UNSET"), "{}", error);
+}
@@ -0,0 +1,64 @@
+//! Paths with spaces and non-ASCII characters in them have to reach the tools the
+//! hooks run intact, on every platform.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use salt_spray::fmt::{format_file, Options};
+use salt_spray::{find_manifest, group_by_crate};
+
+/// Makes a crate with a lib.rs in a directory with an awkward name, unique to the
+/// test, returning the directory.
+fn awkward_crate(test: &str, lib: &str) -> PathBuf {
+    let root = env::temp_dir().join(format!("salt spray {} {}", process::id(), test));
+    let dir = root.join("crate with spaces, ünïcødé & $HOME");
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        "[package]\nname = \"awkward\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::write(dir.join("src").join("lib.rs"), lib).unwrap();
+    dir
+}
+
+/// Removes what [`awkward_crate`] made.
+fn clean_up(dir: &Path) {
+    // unwrap here is safe since the crate is always two levels down
+    drop(fs::remove_dir_all(dir.parent().unwrap()));
+}
+
+#[test]
+fn finds_the_manifest() {
+    let dir = awkward_crate("manifest", "");
+    let lib = dir.join("src").join("lib.rs");
+    assert_eq!(find_manifest(&lib), Some(dir.join("Cargo.toml")));
+    clean_up(&dir);
+}
+
+#[test]
+fn groups_by_crate() {
+    let dir = awkward_crate("group", "");
+    let lib = dir.join("src").join("lib.rs").to_string_lossy().to_string();
+    let groups = group_by_crate(std::slice::from_ref(&lib));
+    let manifest = dir.join("Cargo.toml").to_string_lossy().to_string();
+    assert_eq!(groups.keys().collect::<Vec<_>>(), vec![&manifest]);
+    assert!(groups[&manifest].contains(&lib));
+    clean_up(&dir);
+}
+
+#[test]
+fn formats_the_file() {
+    let dir = awkward_crate("fmt", "fn  main( ) {  }\n");
+    let lib = dir.join("src").join("lib.rs");
+    let output = format_file(&lib, &Options::default()).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(fs::read_to_string(&lib).unwrap(), "fn main() {}\n");
+    clean_up(&dir);
+}